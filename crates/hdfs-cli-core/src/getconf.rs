@@ -0,0 +1,175 @@
+use hdfs_common::config::layered::{self, ConfigLoader, EffectiveConfig};
+use hdfs_common::error::{HdfsError, Result};
+use std::path::PathBuf;
+
+/// `getconf [-conf <file>] [-D key=value]... [key]...`
+///
+/// Prints the effective value of each requested key (or every key) together
+/// with the layer it came from.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GetConfArgs {
+    pub site_file: Option<PathBuf>,
+    pub defines: Vec<(String, String)>,
+    pub keys: Vec<String>,
+}
+
+impl GetConfArgs {
+    pub fn parse<I, S>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut out = GetConfArgs::default();
+        let mut it = args.into_iter();
+        while let Some(arg) = it.next() {
+            match arg.as_ref() {
+                "-conf" => {
+                    let path = it.next().ok_or_else(|| missing("-conf"))?;
+                    out.site_file = Some(PathBuf::from(path.as_ref()));
+                }
+                "-D" => {
+                    let kv = it.next().ok_or_else(|| missing("-D"))?;
+                    out.defines.push(layered::parse_define(kv.as_ref())?);
+                }
+                s if s.starts_with("-D") => out.defines.push(layered::parse_define(&s[2..])?),
+                s if s.starts_with('-') => {
+                    return Err(HdfsError::Config {
+                        key: "getconf".into(),
+                        msg: format!("unknown option '{s}'"),
+                    });
+                }
+                key => out.keys.push(key.to_string()),
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn loader(&self) -> ConfigLoader {
+        let mut loader = ConfigLoader::new();
+        if let Some(path) = &self.site_file {
+            loader = loader.site_file(path);
+        }
+        for (k, v) in &self.defines {
+            loader = loader.define(k, v);
+        }
+        loader
+    }
+}
+
+fn missing(opt: &str) -> HdfsError {
+    HdfsError::Config {
+        key: "getconf".into(),
+        msg: format!("{opt} requires an argument"),
+    }
+}
+
+/// Loads the layered config (including the process environment) and renders it.
+pub fn run(args: &GetConfArgs) -> Result<String> {
+    let eff = args.loader().process_env().load()?;
+    render(&eff, &args.keys)
+}
+
+/// One `key = value (origin)` line per key, preceded by any loader warnings.
+pub fn render(eff: &EffectiveConfig, keys: &[String]) -> Result<String> {
+    let mut out = String::new();
+    for w in eff.warnings() {
+        out.push_str(&format!("# warning: {w}\n"));
+    }
+
+    if keys.is_empty() {
+        for (key, value, origin) in eff.entries() {
+            out.push_str(&format!("{key} = {value} ({origin})\n"));
+        }
+        return Ok(out);
+    }
+
+    for key in keys {
+        let (value, origin) = eff.get(key).ok_or_else(|| HdfsError::Config {
+            key: "unknown".into(),
+            msg: format!("unknown key '{key}'"),
+        })?;
+        out.push_str(&format!("{key} = {value} ({origin})\n"));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_args() {
+        let args = GetConfArgs::parse([
+            "-conf",
+            "/etc/hdfs/site.toml",
+            "-D",
+            "default_replication=2",
+            "-Dclient.max_retries=4",
+            "default_block_size",
+            "client.max_retries",
+        ])
+        .unwrap();
+
+        assert_eq!(args.site_file, Some(PathBuf::from("/etc/hdfs/site.toml")));
+        assert_eq!(
+            args.defines,
+            vec![
+                ("default_replication".into(), "2".into()),
+                ("client.max_retries".into(), "4".into()),
+            ]
+        );
+        assert_eq!(args.keys, vec!["default_block_size", "client.max_retries"]);
+    }
+
+    #[test]
+    fn parse_args_errors() {
+        assert!(matches!(
+            GetConfArgs::parse(["-conf"]),
+            Err(HdfsError::Config { key, .. }) if key == "getconf"
+        ));
+        assert!(matches!(
+            GetConfArgs::parse(["-D", "nokey"]),
+            Err(HdfsError::Config { key, .. }) if key == "-D"
+        ));
+        assert!(matches!(
+            GetConfArgs::parse(["-x"]),
+            Err(HdfsError::Config { key, .. }) if key == "getconf"
+        ));
+    }
+
+    #[test]
+    fn render_selected_keys_with_origin() {
+        let args =
+            GetConfArgs::parse(["-D", "default_replication=2", "default_replication"]).unwrap();
+        let eff = args.loader().load().unwrap();
+
+        let out = render(&eff, &args.keys).unwrap();
+        assert_eq!(out, "default_replication = 2 (-D)\n");
+
+        let out = render(&eff, &["default_block_size".into()]).unwrap();
+        assert_eq!(out, "default_block_size = 128MiB (default)\n");
+
+        assert!(matches!(
+            render(&eff, &["bogus".into()]),
+            Err(HdfsError::Config { key, .. }) if key == "unknown"
+        ));
+    }
+
+    #[test]
+    fn render_all_keys_and_warnings() {
+        let eff = ConfigLoader::new()
+            .define("dfs.blocksize", "64MiB")
+            .load()
+            .unwrap();
+
+        let out = render(&eff, &[]).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines[0],
+            "# warning: config error (default_block_size): 'dfs.blocksize' is deprecated, use 'default_block_size'"
+        );
+        assert_eq!(lines.len(), 1 + layered::KEYS.len());
+        assert!(lines.contains(&"default_block_size = 64MiB (-D)"));
+        assert!(lines.contains(&"namenode.rpc_addr = 0.0.0.0:8020 (default)"));
+    }
+}
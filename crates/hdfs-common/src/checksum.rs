@@ -0,0 +1,337 @@
+//! Checksums of block data, as in Hadoop's `DataChecksum`: data is cut into
+//! chunks of `bytes_per_checksum` (the last one may be short) and each chunk
+//! gets a big-endian 32-bit checksum.
+//!
+//! CRC32C uses the SSE4.2 (x86_64) or CRC (aarch64) instructions and CRC32
+//! uses PCLMULQDQ when the CPU has them; both fall back to table lookups.
+
+use crate::consts::DEFAULT_CHECKSUM_CHUNK_SIZE;
+use crate::error::{HdfsError, Result};
+use crate::ids::BlockId;
+use serde::{Deserialize, Serialize};
+
+/// Ids as in Hadoop's `DataChecksum.Type`, which are also what block meta
+/// files record.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumType {
+    #[serde(alias = "NULL")]
+    Null,
+    #[serde(alias = "CRC32")]
+    Crc32,
+    #[default]
+    #[serde(alias = "CRC32C")]
+    Crc32c,
+}
+
+impl ChecksumType {
+    pub fn id(self) -> u8 {
+        match self {
+            ChecksumType::Null => 0,
+            ChecksumType::Crc32 => 1,
+            ChecksumType::Crc32c => 2,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(ChecksumType::Null),
+            1 => Some(ChecksumType::Crc32),
+            2 => Some(ChecksumType::Crc32c),
+            _ => None,
+        }
+    }
+
+    /// Bytes per checksum: 4, or 0 for `Null`.
+    pub fn size(self) -> usize {
+        match self {
+            ChecksumType::Null => 0,
+            ChecksumType::Crc32 | ChecksumType::Crc32c => 4,
+        }
+    }
+
+    /// Whether this CPU computes the checksum with dedicated instructions.
+    pub fn is_accelerated(self) -> bool {
+        match self {
+            ChecksumType::Null => false,
+            ChecksumType::Crc32 => crc32_in_hardware(),
+            ChecksumType::Crc32c => crc32c_in_hardware(),
+        }
+    }
+
+    fn checksum(self, chunk: &[u8]) -> u32 {
+        match self {
+            ChecksumType::Null => 0,
+            ChecksumType::Crc32 => crc32fast::hash(chunk),
+            ChecksumType::Crc32c => crc32c::crc32c(chunk),
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn crc32c_in_hardware() -> bool {
+    std::arch::is_x86_feature_detected!("sse4.2")
+}
+
+#[cfg(target_arch = "x86_64")]
+fn crc32_in_hardware() -> bool {
+    std::arch::is_x86_feature_detected!("pclmulqdq")
+        && std::arch::is_x86_feature_detected!("sse4.1")
+}
+
+#[cfg(target_arch = "aarch64")]
+fn crc32c_in_hardware() -> bool {
+    std::arch::is_aarch64_feature_detected!("crc")
+}
+
+#[cfg(target_arch = "aarch64")]
+fn crc32_in_hardware() -> bool {
+    std::arch::is_aarch64_feature_detected!("crc")
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn crc32c_in_hardware() -> bool {
+    false
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn crc32_in_hardware() -> bool {
+    false
+}
+
+impl core::fmt::Display for ChecksumType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ChecksumType::Null => "NULL",
+            ChecksumType::Crc32 => "CRC32",
+            ChecksumType::Crc32c => "CRC32C",
+        })
+    }
+}
+
+impl std::str::FromStr for ChecksumType {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "null" => Ok(ChecksumType::Null),
+            "crc32" => Ok(ChecksumType::Crc32),
+            "crc32c" => Ok(ChecksumType::Crc32c),
+            _ => Err(format!("unknown checksum type '{s}'")),
+        }
+    }
+}
+
+/// How the data of one block is checksummed. A writer picks it for the
+/// whole file; datanodes keep it in each block's meta file and hand it to
+/// readers.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct DataChecksum {
+    kind: ChecksumType,
+    bytes_per_checksum: u32,
+}
+
+impl DataChecksum {
+    /// type(1) bytes_per_checksum(4), as in Hadoop.
+    pub const HEADER_LEN: usize = 5;
+
+    pub fn new(kind: ChecksumType, bytes_per_checksum: u32) -> Self {
+        assert!(bytes_per_checksum > 0, "bytes_per_checksum must be > 0");
+        Self {
+            kind,
+            bytes_per_checksum,
+        }
+    }
+
+    pub fn kind(&self) -> ChecksumType {
+        self.kind
+    }
+
+    pub fn bytes_per_checksum(&self) -> u32 {
+        self.bytes_per_checksum
+    }
+
+    pub fn checksum_size(&self) -> usize {
+        self.kind.size()
+    }
+
+    /// Checksums covering `data_len` bytes; none for `Null`.
+    pub fn num_checksums(&self, data_len: usize) -> usize {
+        match self.kind {
+            ChecksumType::Null => 0,
+            _ => data_len.div_ceil(self.bytes_per_checksum as usize),
+        }
+    }
+
+    /// Bytes of checksums covering `data_len` bytes.
+    pub fn checksums_len(&self, data_len: usize) -> usize {
+        self.num_checksums(data_len) * self.checksum_size()
+    }
+
+    /// Appends the checksums of `data` to `out`.
+    pub fn compute(&self, data: &[u8], out: &mut Vec<u8>) {
+        if self.kind == ChecksumType::Null {
+            return;
+        }
+        out.reserve(self.checksums_len(data.len()));
+        for chunk in data.chunks(self.bytes_per_checksum as usize) {
+            out.extend_from_slice(&self.kind.checksum(chunk).to_be_bytes());
+        }
+    }
+
+    pub fn compute_to_vec(&self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.compute(data, &mut out);
+        out
+    }
+
+    /// Checks `data`, which starts `offset_in_block` bytes into `block`,
+    /// against `checksums`. The first bad chunk is reported by its index in
+    /// the block.
+    pub fn verify(
+        &self,
+        data: &[u8],
+        checksums: &[u8],
+        block: BlockId,
+        offset_in_block: u64,
+    ) -> Result<()> {
+        let expected_len = self.checksums_len(data.len());
+        if checksums.len() != expected_len {
+            return Err(HdfsError::Protocol {
+                op: "verify_checksum".into(),
+                details: format!(
+                    "{} checksum bytes for {} data bytes, expected {expected_len} ({self})",
+                    checksums.len(),
+                    data.len()
+                ),
+            });
+        }
+        if self.kind == ChecksumType::Null {
+            return Ok(());
+        }
+        let first_chunk = offset_in_block / u64::from(self.bytes_per_checksum);
+        let chunks = data.chunks(self.bytes_per_checksum as usize);
+        for (i, (chunk, sum)) in chunks.zip(checksums.chunks_exact(4)).enumerate() {
+            let expected = u32::from_be_bytes(sum.try_into().unwrap());
+            let got = self.kind.checksum(chunk);
+            if got != expected {
+                return Err(HdfsError::ChecksumMismatch {
+                    block,
+                    chunk_index: first_chunk + i as u64,
+                    expected,
+                    got,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_header(&self) -> [u8; Self::HEADER_LEN] {
+        let mut out = [0; Self::HEADER_LEN];
+        out[0] = self.kind.id();
+        out[1..].copy_from_slice(&self.bytes_per_checksum.to_be_bytes());
+        out
+    }
+
+    pub fn from_header(raw: &[u8; Self::HEADER_LEN]) -> Result<Self> {
+        let bad = |details: String| HdfsError::Protocol {
+            op: "checksum_header".into(),
+            details,
+        };
+        let kind = ChecksumType::from_id(raw[0])
+            .ok_or_else(|| bad(format!("unknown checksum type {}", raw[0])))?;
+        let bytes_per_checksum = u32::from_be_bytes(raw[1..].try_into().unwrap());
+        if bytes_per_checksum == 0 {
+            return Err(bad("bytes per checksum must be > 0".into()));
+        }
+        Ok(Self::new(kind, bytes_per_checksum))
+    }
+}
+
+impl Default for DataChecksum {
+    fn default() -> Self {
+        Self::new(ChecksumType::default(), DEFAULT_CHECKSUM_CHUNK_SIZE as u32)
+    }
+}
+
+impl core::fmt::Display for DataChecksum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.kind, self.bytes_per_checksum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_values() {
+        // the standard check values of both polynomials
+        assert_eq!(ChecksumType::Crc32.checksum(b"123456789"), 0xCBF4_3926);
+        assert_eq!(ChecksumType::Crc32c.checksum(b"123456789"), 0xE306_9283);
+
+        let c = DataChecksum::new(ChecksumType::Crc32, 4);
+        let sums = c.compute_to_vec(b"12345678a");
+        assert_eq!(sums.len(), 12);
+        assert_eq!(sums[..4], crc32fast::hash(b"1234").to_be_bytes());
+        assert_eq!(sums[8..], crc32fast::hash(b"a").to_be_bytes());
+    }
+
+    #[test]
+    fn verify_names_the_bad_chunk() {
+        let data: Vec<u8> = (0..2000u32).map(|i| i as u8).collect();
+        for kind in [ChecksumType::Crc32, ChecksumType::Crc32c] {
+            let c = DataChecksum::new(kind, 512);
+            let sums = c.compute_to_vec(&data);
+            assert_eq!(sums.len(), c.checksums_len(data.len()));
+            c.verify(&data, &sums, BlockId(7), 1024).unwrap();
+
+            let mut bad = data.clone();
+            bad[1500] ^= 1;
+            match c.verify(&bad, &sums, BlockId(7), 1024) {
+                Err(HdfsError::ChecksumMismatch {
+                    block, chunk_index, ..
+                }) => {
+                    assert_eq!(block, BlockId(7));
+                    assert_eq!(chunk_index, 2 + 2);
+                }
+                other => panic!("expected ChecksumMismatch, got {other:?}"),
+            }
+            assert!(matches!(
+                c.verify(&data, &sums[4..], BlockId(7), 0),
+                Err(HdfsError::Protocol { op, .. }) if op == "verify_checksum"
+            ));
+        }
+
+        let null = DataChecksum::new(ChecksumType::Null, 512);
+        assert!(null.compute_to_vec(&data).is_empty());
+        null.verify(&data, &[], BlockId(7), 0).unwrap();
+    }
+
+    #[test]
+    fn headers() {
+        let c = DataChecksum::new(ChecksumType::Crc32c, 512);
+        assert_eq!(c.to_header(), [2, 0, 0, 2, 0]);
+        assert_eq!(DataChecksum::from_header(&c.to_header()).unwrap(), c);
+        assert_eq!(c, DataChecksum::default());
+        assert_eq!(c.to_string(), "CRC32C/512");
+
+        assert!(DataChecksum::from_header(&[9, 0, 0, 2, 0]).is_err());
+        assert!(DataChecksum::from_header(&[1, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn type_names() {
+        for kind in [
+            ChecksumType::Null,
+            ChecksumType::Crc32,
+            ChecksumType::Crc32c,
+        ] {
+            assert_eq!(kind.to_string().parse::<ChecksumType>().unwrap(), kind);
+            assert_eq!(ChecksumType::from_id(kind.id()), Some(kind));
+        }
+        assert!("adler32".parse::<ChecksumType>().is_err());
+        // acceleration is a property of the machine; just make sure asking works
+        let _ = ChecksumType::Crc32c.is_accelerated();
+        assert!(!ChecksumType::Null.is_accelerated());
+    }
+}
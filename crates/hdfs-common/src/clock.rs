@@ -0,0 +1,196 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Source of time for everything that expires, times out or rolls over.
+/// Production code uses [`SystemClock`]; tests use [`ManualClock`] and move
+/// time forward by hand instead of sleeping.
+pub trait Clock: Send + Sync + std::fmt::Debug {
+    /// Monotonic time, for measuring intervals.
+    fn now(&self) -> Instant;
+
+    /// Wall-clock time, for timestamps that leave the process.
+    fn wall(&self) -> SystemTime;
+
+    fn wall_millis(&self) -> u64 {
+        self.wall()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| u64::try_from(d.as_millis()).unwrap_or(u64::MAX))
+            .unwrap_or(0)
+    }
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl SystemClock {
+    pub fn shared() -> SharedClock {
+        Arc::new(SystemClock)
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn wall(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves when told to. Monotonic and wall time advance
+/// together; `set_wall` lets tests simulate wall-clock jumps.
+#[derive(Debug)]
+pub struct ManualClock {
+    base: Instant,
+    state: Mutex<ManualState>,
+}
+
+#[derive(Debug)]
+struct ManualState {
+    elapsed: Duration,
+    wall: SystemTime,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::starting_at(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+    }
+
+    pub fn starting_at(wall: SystemTime) -> Self {
+        Self {
+            base: Instant::now(),
+            state: Mutex::new(ManualState {
+                elapsed: Duration::ZERO,
+                wall,
+            }),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        let mut s = self.state.lock().unwrap();
+        s.elapsed += by;
+        s.wall += by;
+    }
+
+    /// Moves only the wall clock; monotonic time is unaffected.
+    pub fn set_wall(&self, wall: SystemTime) {
+        self.state.lock().unwrap().wall = wall;
+    }
+
+    /// Monotonic time passed since the clock was created.
+    pub fn elapsed(&self) -> Duration {
+        self.state.lock().unwrap().elapsed
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.base + self.state.lock().unwrap().elapsed
+    }
+
+    fn wall(&self) -> SystemTime {
+        self.state.lock().unwrap().wall
+    }
+}
+
+/// Fires once per period, measured from the last time it was reset.
+#[derive(Debug)]
+pub struct IntervalTimer {
+    clock: SharedClock,
+    last: Instant,
+}
+
+impl IntervalTimer {
+    pub fn new(clock: SharedClock) -> Self {
+        let last = clock.now();
+        Self { clock, last }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.clock.now().saturating_duration_since(self.last)
+    }
+
+    pub fn is_due(&self, period: Duration) -> bool {
+        self.elapsed() >= period
+    }
+
+    pub fn remaining(&self, period: Duration) -> Duration {
+        period.saturating_sub(self.elapsed())
+    }
+
+    pub fn reset(&mut self) {
+        self.last = self.clock.now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn system_clock_moves_forward() {
+        let c = SystemClock;
+        let a = c.now();
+        let b = c.now();
+        assert!(b >= a);
+        assert!(c.wall_millis() > 1_600_000_000_000);
+    }
+
+    #[test]
+    fn manual_clock_only_moves_when_advanced() {
+        let c = ManualClock::new();
+        let t0 = c.now();
+        let w0 = c.wall();
+        assert_eq!(c.now(), t0);
+
+        c.advance(Duration::from_secs(5));
+        assert_eq!(c.now() - t0, Duration::from_secs(5));
+        assert_eq!(c.wall().duration_since(w0).unwrap(), Duration::from_secs(5));
+        assert_eq!(c.elapsed(), Duration::from_secs(5));
+        assert_eq!(c.wall_millis(), 1_700_000_005_000);
+    }
+
+    #[test]
+    fn wall_jumps_do_not_affect_monotonic() {
+        let c = ManualClock::starting_at(UNIX_EPOCH + Duration::from_secs(100));
+        let t0 = c.now();
+        c.set_wall(UNIX_EPOCH + Duration::from_secs(10));
+        assert_eq!(c.now(), t0);
+        assert_eq!(c.wall_millis(), 10_000);
+
+        c.set_wall(UNIX_EPOCH - Duration::from_secs(1));
+        assert_eq!(c.wall_millis(), 0);
+    }
+
+    #[test]
+    fn interval_timer() {
+        let c = Arc::new(ManualClock::new());
+        let mut t = IntervalTimer::new(c.clone());
+        let period = Duration::from_secs(10);
+
+        assert!(!t.is_due(period));
+        assert_eq!(t.remaining(period), period);
+
+        c.advance(Duration::from_secs(4));
+        assert!(!t.is_due(period));
+        assert_eq!(t.remaining(period), Duration::from_secs(6));
+
+        c.advance(Duration::from_secs(6));
+        assert!(t.is_due(period));
+        assert_eq!(t.remaining(period), Duration::ZERO);
+
+        t.reset();
+        assert!(!t.is_due(period));
+        assert_eq!(t.elapsed(), Duration::ZERO);
+    }
+}
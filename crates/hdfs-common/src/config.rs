@@ -0,0 +1,574 @@
+use crate::checksum::{ChecksumType, DataChecksum};
+use crate::consts::*;
+use crate::error::{HdfsError, Result};
+use crate::path::NamePolicy;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub mod layered;
+pub mod reconfig;
+
+pub use layered::{ConfigLoader, EffectiveConfig, Origin};
+
+/// A byte count that reads from TOML as either an integer or a human string
+/// like "128MiB" and is written back in the largest exact binary unit.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct ByteSize(pub u64);
+
+impl ByteSize {
+    pub const fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl From<u64> for ByteSize {
+    fn from(value: u64) -> Self {
+        ByteSize(value)
+    }
+}
+
+impl From<ByteSize> for u64 {
+    fn from(value: ByteSize) -> Self {
+        value.0
+    }
+}
+
+impl core::fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const UNITS: [(u64, &str); 5] = [
+            (1 << 50, "PiB"),
+            (1 << 40, "TiB"),
+            (GIB, "GiB"),
+            (MIB, "MiB"),
+            (KIB, "KiB"),
+        ];
+        if self.0 != 0 {
+            for (unit, suffix) in UNITS {
+                if self.0.is_multiple_of(unit) {
+                    return write!(f, "{}{}", self.0 / unit, suffix);
+                }
+            }
+        }
+        write!(f, "{}B", self.0)
+    }
+}
+
+impl std::str::FromStr for ByteSize {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let b = byte_unit::Byte::from_str(s).map_err(|e| e.to_string())?;
+        u64::try_from(b.get_bytes())
+            .map(ByteSize)
+            .map_err(|_| format!("'{s}' does not fit in 64 bits"))
+    }
+}
+
+impl Serialize for ByteSize {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        struct V;
+
+        impl serde::de::Visitor<'_> for V {
+            type Value = ByteSize;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a byte count or a size string like \"128MiB\"")
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> std::result::Result<ByteSize, E> {
+                Ok(ByteSize(v))
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> std::result::Result<ByteSize, E> {
+                u64::try_from(v)
+                    .map(ByteSize)
+                    .map_err(|_| E::custom(format!("negative size {v}")))
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> std::result::Result<ByteSize, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        d.deserialize_any(V)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClusterConfig {
+    pub default_block_size: ByteSize,
+    pub checksum_chunk_size: ByteSize,
+    /// What writers checksum new files with.
+    pub checksum_type: ChecksumType,
+    pub default_replication: u16,
+    pub namenode: NameNodeConfig,
+    pub datanode: DataNodeConfig,
+    pub client: ClientConfig,
+    pub name_policy: NamePolicy,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NameNodeConfig {
+    pub rpc_addr: String,
+    pub name_dirs: Vec<PathBuf>,
+    pub min_replication: u16,
+    pub max_replication: u16,
+    pub replication_max_streams: u32,
+    #[serde(with = "humantime_serde")]
+    pub heartbeat_recheck_interval: Duration,
+    pub safemode_threshold_pct: f64,
+    #[serde(with = "humantime_serde")]
+    pub safemode_extension: Duration,
+    #[serde(with = "humantime_serde")]
+    pub lease_soft_limit: Duration,
+    #[serde(with = "humantime_serde")]
+    pub lease_hard_limit: Duration,
+    #[serde(with = "humantime_serde")]
+    pub edit_log_roll_period: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DataNodeConfig {
+    pub data_addr: String,
+    pub ipc_addr: String,
+    pub data_dirs: Vec<PathBuf>,
+    #[serde(with = "humantime_serde")]
+    pub heartbeat_interval: Duration,
+    #[serde(with = "humantime_serde")]
+    pub block_report_interval: Duration,
+    pub max_xceivers: u32,
+    /// Bytes per second the balancer may use on this node.
+    pub balance_bandwidth: ByteSize,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientConfig {
+    pub namenodes: Vec<String>,
+    pub write_packet_size: ByteSize,
+    #[serde(with = "humantime_serde")]
+    pub socket_timeout: Duration,
+    pub max_retries: u32,
+    /// Wait before the first retry; doubled for each one after.
+    #[serde(with = "humantime_serde")]
+    pub retry_base_delay: Duration,
+    #[serde(with = "humantime_serde")]
+    pub retry_max_delay: Duration,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            default_block_size: ByteSize(DEFAULT_BLOCK_SIZE),
+            checksum_chunk_size: ByteSize(DEFAULT_CHECKSUM_CHUNK_SIZE),
+            checksum_type: ChecksumType::default(),
+            default_replication: DEFAULT_REPLICATION,
+            namenode: NameNodeConfig::default(),
+            datanode: DataNodeConfig::default(),
+            client: ClientConfig::default(),
+            name_policy: NamePolicy::default(),
+        }
+    }
+}
+
+impl Default for NameNodeConfig {
+    fn default() -> Self {
+        Self {
+            rpc_addr: DEFAULT_NN_RPC_ADDR.into(),
+            name_dirs: vec![PathBuf::from("/var/lib/hdfs/name")],
+            min_replication: DEFAULT_MIN_REPLICATION,
+            max_replication: DEFAULT_MAX_REPLICATION,
+            replication_max_streams: DEFAULT_REPLICATION_MAX_STREAMS,
+            heartbeat_recheck_interval: DEFAULT_HEARTBEAT_RECHECK_INTERVAL,
+            safemode_threshold_pct: DEFAULT_SAFEMODE_THRESHOLD_PCT,
+            safemode_extension: DEFAULT_SAFEMODE_EXTENSION,
+            lease_soft_limit: DEFAULT_LEASE_SOFT_LIMIT,
+            lease_hard_limit: DEFAULT_LEASE_HARD_LIMIT,
+            edit_log_roll_period: DEFAULT_EDIT_LOG_ROLL_PERIOD,
+        }
+    }
+}
+
+impl Default for DataNodeConfig {
+    fn default() -> Self {
+        Self {
+            data_addr: DEFAULT_DN_DATA_ADDR.into(),
+            ipc_addr: DEFAULT_DN_IPC_ADDR.into(),
+            data_dirs: vec![PathBuf::from("/var/lib/hdfs/data")],
+            heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+            block_report_interval: DEFAULT_BLOCK_REPORT_INTERVAL,
+            max_xceivers: DEFAULT_MAX_XCEIVERS,
+            balance_bandwidth: ByteSize(DEFAULT_BALANCE_BANDWIDTH),
+        }
+    }
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            namenodes: vec![DEFAULT_CLIENT_NAMENODE.into()],
+            write_packet_size: ByteSize(DEFAULT_WRITE_PACKET_SIZE),
+            socket_timeout: DEFAULT_SOCKET_TIMEOUT,
+            max_retries: DEFAULT_CLIENT_MAX_RETRIES,
+            retry_base_delay: DEFAULT_CLIENT_RETRY_BASE_DELAY,
+            retry_max_delay: DEFAULT_CLIENT_RETRY_MAX_DELAY,
+        }
+    }
+}
+
+fn invalid(key: &'static str, msg: impl Into<String>) -> HdfsError {
+    HdfsError::Config {
+        key: key.into(),
+        msg: msg.into(),
+    }
+}
+
+impl ClusterConfig {
+    /// Parses and validates a TOML document. Missing keys take their defaults.
+    pub fn from_toml_str(s: &str) -> Result<Self> {
+        let cfg: ClusterConfig = toml::from_str(s).map_err(|e| invalid("toml", e.message()))?;
+        cfg.validate()?;
+        Ok(cfg)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let s = std::fs::read_to_string(path)?;
+        Self::from_toml_str(&s)
+    }
+
+    pub fn to_toml_string(&self) -> Result<String> {
+        toml::to_string(self).map_err(|e| invalid("toml", e.to_string()))
+    }
+
+    pub fn data_checksum(&self) -> DataChecksum {
+        DataChecksum::new(self.checksum_type, self.checksum_chunk_size.0 as u32)
+    }
+
+    /// Checks the rules that span more than one field.
+    pub fn validate(&self) -> Result<()> {
+        let chunk = self.checksum_chunk_size.0;
+        if chunk == 0 || chunk > u64::from(u32::MAX) {
+            return Err(invalid("checksum_chunk_size", "must be > 0 and < 4GiB"));
+        }
+
+        let block = self.default_block_size.0;
+        if block == 0 || !block.is_multiple_of(chunk) {
+            return Err(invalid(
+                "default_block_size",
+                format!(
+                    "must be > 0 and multiple of checksum_chunk_size ({})",
+                    self.checksum_chunk_size
+                ),
+            ));
+        }
+
+        let nn = &self.namenode;
+        if nn.min_replication == 0 {
+            return Err(invalid("namenode.min_replication", "must be >= 1"));
+        }
+        if nn.max_replication < nn.min_replication {
+            return Err(invalid(
+                "namenode.max_replication",
+                format!(
+                    "must be >= namenode.min_replication ({})",
+                    nn.min_replication
+                ),
+            ));
+        }
+        if !(nn.min_replication..=nn.max_replication).contains(&self.default_replication) {
+            return Err(invalid(
+                "default_replication",
+                format!(
+                    "must be within [{}, {}]",
+                    nn.min_replication, nn.max_replication
+                ),
+            ));
+        }
+        if !(0.0..=1.0).contains(&nn.safemode_threshold_pct) {
+            return Err(invalid(
+                "namenode.safemode_threshold_pct",
+                "must be within [0.0, 1.0]",
+            ));
+        }
+        if nn.lease_soft_limit >= nn.lease_hard_limit {
+            return Err(invalid(
+                "namenode.lease_hard_limit",
+                format!(
+                    "must be greater than namenode.lease_soft_limit ({})",
+                    humantime::format_duration(nn.lease_soft_limit)
+                ),
+            ));
+        }
+        if nn.name_dirs.is_empty() {
+            return Err(invalid(
+                "namenode.name_dirs",
+                "at least one directory required",
+            ));
+        }
+
+        let dn = &self.datanode;
+        if dn.heartbeat_interval.is_zero() {
+            return Err(invalid("datanode.heartbeat_interval", "must be > 0"));
+        }
+        if nn.heartbeat_recheck_interval <= dn.heartbeat_interval {
+            return Err(invalid(
+                "namenode.heartbeat_recheck_interval",
+                format!(
+                    "must be greater than datanode.heartbeat_interval ({})",
+                    humantime::format_duration(dn.heartbeat_interval)
+                ),
+            ));
+        }
+        if dn.data_dirs.is_empty() {
+            return Err(invalid(
+                "datanode.data_dirs",
+                "at least one directory required",
+            ));
+        }
+        if dn.max_xceivers == 0 {
+            return Err(invalid("datanode.max_xceivers", "must be > 0"));
+        }
+
+        let np = &self.name_policy;
+        if np.reserved_chars.contains('/') {
+            return Err(invalid(
+                "name_policy.reserved_chars",
+                "must not contain the path separator",
+            ));
+        }
+
+        let cl = &self.client;
+        if cl.namenodes.is_empty() {
+            return Err(invalid("client.namenodes", "at least one address required"));
+        }
+        let packet = cl.write_packet_size.0;
+        if packet == 0 || !packet.is_multiple_of(chunk) || packet > block {
+            return Err(invalid(
+                "client.write_packet_size",
+                format!(
+                    "must be > 0, a multiple of checksum_chunk_size ({}) and <= default_block_size ({})",
+                    self.checksum_chunk_size, self.default_block_size
+                ),
+            ));
+        }
+        if cl.retry_max_delay < cl.retry_base_delay {
+            return Err(invalid(
+                "client.retry_max_delay",
+                "must be >= client.retry_base_delay",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_config_err(res: Result<ClusterConfig>, expected_key: &str) -> String {
+        match res {
+            Err(HdfsError::Config { key, msg }) => {
+                assert_eq!(key, expected_key, "unexpected key, msg: {msg}");
+                msg
+            }
+            other => panic!("expected Config error, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        let cfg = ClusterConfig::default();
+        cfg.validate().unwrap();
+        assert_eq!(cfg.default_block_size.as_u64(), 128 * MIB);
+        assert_eq!(cfg.checksum_chunk_size.as_u64(), 512);
+        assert_eq!(cfg.datanode.heartbeat_interval, Duration::from_secs(3));
+    }
+
+    #[test]
+    fn empty_document_yields_defaults() {
+        let cfg = ClusterConfig::from_toml_str("").unwrap();
+        assert_eq!(cfg, ClusterConfig::default());
+    }
+
+    #[test]
+    fn parses_sizes_and_durations() {
+        let cfg = ClusterConfig::from_toml_str(
+            r#"
+            default_block_size = "256MiB"
+            checksum_chunk_size = 1024
+
+            [datanode]
+            heartbeat_interval = "1s"
+            balance_bandwidth = "50MiB"
+
+            [namenode]
+            lease_soft_limit = "30s"
+            lease_hard_limit = "1h 30m"
+
+            [client]
+            write_packet_size = "128KiB"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(cfg.default_block_size, ByteSize(256 * MIB));
+        assert_eq!(cfg.checksum_chunk_size, ByteSize(1024));
+        assert_eq!(cfg.datanode.heartbeat_interval, Duration::from_secs(1));
+        assert_eq!(cfg.datanode.balance_bandwidth, ByteSize(50 * MIB));
+        assert_eq!(cfg.namenode.lease_soft_limit, Duration::from_secs(30));
+        assert_eq!(cfg.namenode.lease_hard_limit, Duration::from_secs(90 * 60));
+        assert_eq!(cfg.client.write_packet_size, ByteSize(128 * KIB));
+        // untouched keys keep their defaults
+        assert_eq!(cfg.default_replication, DEFAULT_REPLICATION);
+    }
+
+    #[test]
+    fn checksum_type_takes_hadoop_names() {
+        let cfg = ClusterConfig::from_toml_str("checksum_type = \"CRC32\"").unwrap();
+        assert_eq!(cfg.checksum_type, ChecksumType::Crc32);
+        let cfg = ClusterConfig::from_toml_str("checksum_type = \"null\"").unwrap();
+        assert_eq!(
+            cfg.data_checksum(),
+            DataChecksum::new(ChecksumType::Null, 512)
+        );
+        assert_config_err(
+            ClusterConfig::from_toml_str("checksum_type = \"adler32\""),
+            "toml",
+        );
+        assert_eq!(
+            ClusterConfig::default().data_checksum(),
+            DataChecksum::default()
+        );
+    }
+
+    #[test]
+    fn block_size_must_be_multiple_of_chunk() {
+        let msg = assert_config_err(
+            ClusterConfig::from_toml_str("default_block_size = 1000"),
+            "default_block_size",
+        );
+        assert_eq!(
+            msg,
+            "must be > 0 and multiple of checksum_chunk_size (512B)"
+        );
+
+        assert_config_err(
+            ClusterConfig::from_toml_str("default_block_size = 0"),
+            "default_block_size",
+        );
+        assert_config_err(
+            ClusterConfig::from_toml_str("checksum_chunk_size = 0"),
+            "checksum_chunk_size",
+        );
+    }
+
+    #[test]
+    fn cross_field_rules() {
+        assert_config_err(
+            ClusterConfig::from_toml_str("default_replication = 0"),
+            "default_replication",
+        );
+        assert_config_err(
+            ClusterConfig::from_toml_str("[namenode]\nmin_replication = 4\nmax_replication = 2"),
+            "namenode.max_replication",
+        );
+        assert_config_err(
+            ClusterConfig::from_toml_str(
+                "[namenode]\nlease_soft_limit = \"2h\"\nlease_hard_limit = \"1h\"",
+            ),
+            "namenode.lease_hard_limit",
+        );
+        assert_config_err(
+            ClusterConfig::from_toml_str("[namenode]\nheartbeat_recheck_interval = \"2s\""),
+            "namenode.heartbeat_recheck_interval",
+        );
+        assert_config_err(
+            ClusterConfig::from_toml_str("[namenode]\nsafemode_threshold_pct = 1.5"),
+            "namenode.safemode_threshold_pct",
+        );
+        assert_config_err(
+            ClusterConfig::from_toml_str("[client]\nwrite_packet_size = \"256MiB\""),
+            "client.write_packet_size",
+        );
+        assert_config_err(
+            ClusterConfig::from_toml_str("[client]\nnamenodes = []"),
+            "client.namenodes",
+        );
+        assert_config_err(
+            ClusterConfig::from_toml_str("[client]\nretry_base_delay = \"1m\""),
+            "client.retry_max_delay",
+        );
+    }
+
+    #[test]
+    fn syntax_and_unknown_keys_are_rejected() {
+        let msg = assert_config_err(
+            ClusterConfig::from_toml_str("[namenode]\nrpc_adr = \"x\""),
+            "toml",
+        );
+        assert!(msg.contains("rpc_adr"));
+
+        assert_config_err(
+            ClusterConfig::from_toml_str("default_block_size = \"lots\""),
+            "toml",
+        );
+        assert_config_err(ClusterConfig::from_toml_str("= broken"), "toml");
+    }
+
+    #[test]
+    fn byte_size_display_and_parse() {
+        assert_eq!(ByteSize(0).to_string(), "0B");
+        assert_eq!(ByteSize(512).to_string(), "512B");
+        assert_eq!(ByteSize(64 * KIB).to_string(), "64KiB");
+        assert_eq!(ByteSize(128 * MIB).to_string(), "128MiB");
+        assert_eq!(ByteSize(3 * GIB).to_string(), "3GiB");
+        assert_eq!(ByteSize(1536).to_string(), "1536B");
+
+        assert_eq!("128MiB".parse::<ByteSize>().unwrap(), ByteSize(128 * MIB));
+        assert_eq!("512 KiB".parse::<ByteSize>().unwrap(), ByteSize(512 * KIB));
+        assert_eq!("4096".parse::<ByteSize>().unwrap(), ByteSize(4096));
+        assert!("-1".parse::<ByteSize>().is_err());
+    }
+
+    #[test]
+    fn toml_roundtrip() {
+        let mut cfg = ClusterConfig {
+            default_block_size: ByteSize(64 * MIB),
+            ..Default::default()
+        };
+        cfg.datanode.block_report_interval = Duration::from_secs(90);
+
+        let s = cfg.to_toml_string().unwrap();
+        assert!(s.contains("default_block_size = \"64MiB\""));
+        assert!(s.contains("block_report_interval = \"1m 30s\""));
+
+        let back = ClusterConfig::from_toml_str(&s).unwrap();
+        assert_eq!(back, cfg);
+    }
+
+    #[test]
+    fn load_reads_file() {
+        let dir = std::env::temp_dir().join(format!("hdfs-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("hdfs-site.toml");
+        std::fs::write(&path, "default_replication = 2\n").unwrap();
+
+        let cfg = ClusterConfig::load(&path).unwrap();
+        assert_eq!(cfg.default_replication, 2);
+
+        let missing = ClusterConfig::load(dir.join("nope.toml"));
+        assert!(matches!(missing, Err(HdfsError::Io(_))));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,642 @@
+use super::ClusterConfig;
+use crate::error::{HdfsError, Result};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+pub const ENV_PREFIX: &str = "HDFS_";
+
+/// A known config key and whether a running daemon may change it without a
+/// restart.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct KeySpec {
+    pub name: &'static str,
+    pub reconfigurable: bool,
+}
+
+impl KeySpec {
+    const fn fixed(name: &'static str) -> Self {
+        Self {
+            name,
+            reconfigurable: false,
+        }
+    }
+
+    const fn live(name: &'static str) -> Self {
+        Self {
+            name,
+            reconfigurable: true,
+        }
+    }
+}
+
+/// Every key the effective config knows about, in dotted form.
+pub const KEYS: &[KeySpec] = &[
+    KeySpec::fixed("default_block_size"),
+    KeySpec::fixed("checksum_chunk_size"),
+    KeySpec::fixed("checksum_type"),
+    KeySpec::fixed("default_replication"),
+    KeySpec::fixed("namenode.rpc_addr"),
+    KeySpec::fixed("namenode.name_dirs"),
+    KeySpec::fixed("namenode.min_replication"),
+    KeySpec::fixed("namenode.max_replication"),
+    KeySpec::live("namenode.replication_max_streams"),
+    KeySpec::live("namenode.heartbeat_recheck_interval"),
+    KeySpec::fixed("namenode.safemode_threshold_pct"),
+    KeySpec::live("namenode.safemode_extension"),
+    KeySpec::fixed("namenode.lease_soft_limit"),
+    KeySpec::fixed("namenode.lease_hard_limit"),
+    KeySpec::live("namenode.edit_log_roll_period"),
+    KeySpec::fixed("datanode.data_addr"),
+    KeySpec::fixed("datanode.ipc_addr"),
+    KeySpec::fixed("datanode.data_dirs"),
+    KeySpec::live("datanode.heartbeat_interval"),
+    KeySpec::live("datanode.block_report_interval"),
+    KeySpec::live("datanode.max_xceivers"),
+    KeySpec::live("datanode.balance_bandwidth"),
+    KeySpec::fixed("client.namenodes"),
+    KeySpec::fixed("client.write_packet_size"),
+    KeySpec::fixed("client.socket_timeout"),
+    KeySpec::fixed("client.max_retries"),
+    KeySpec::fixed("client.retry_base_delay"),
+    KeySpec::fixed("client.retry_max_delay"),
+    KeySpec::fixed("name_policy.nfc"),
+    KeySpec::fixed("name_policy.reserved_chars"),
+    KeySpec::fixed("name_policy.max_depth"),
+    KeySpec::fixed("name_policy.case_insensitive"),
+];
+
+/// Old key names that are still accepted but rewritten to their replacement.
+pub const DEPRECATED_KEYS: &[(&str, &str)] = &[
+    ("dfs.blocksize", "default_block_size"),
+    ("dfs.bytes-per-checksum", "checksum_chunk_size"),
+    ("dfs.checksum.type", "checksum_type"),
+    ("dfs.replication", "default_replication"),
+    ("dfs.namenode.name.dir", "namenode.name_dirs"),
+    ("dfs.namenode.replication.min", "namenode.min_replication"),
+    ("dfs.replication.max", "namenode.max_replication"),
+    ("dfs.datanode.data.dir", "datanode.data_dirs"),
+    ("dfs.heartbeat.interval", "datanode.heartbeat_interval"),
+    ("dfs.datanode.max.transfer.threads", "datanode.max_xceivers"),
+];
+
+/// Where the effective value of a key came from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Origin {
+    Default,
+    SiteFile(PathBuf),
+    Env(String),
+    CommandLine,
+}
+
+impl core::fmt::Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::SiteFile(p) => write!(f, "site:{}", p.display()),
+            Origin::Env(name) => write!(f, "env:{name}"),
+            Origin::CommandLine => write!(f, "-D"),
+        }
+    }
+}
+
+/// Looks up a known key, following deprecated aliases.
+/// Returns the canonical key and whether the name was deprecated.
+pub fn resolve_key(name: &str) -> Option<(&'static str, bool)> {
+    if let Some(k) = KEYS.iter().find(|k| k.name == name) {
+        return Some((k.name, false));
+    }
+    DEPRECATED_KEYS
+        .iter()
+        .find(|(old, _)| *old == name)
+        .map(|(_, new)| (*new, true))
+}
+
+pub fn key_spec(key: &str) -> Option<&'static KeySpec> {
+    KEYS.iter().find(|k| k.name == key)
+}
+
+pub fn env_var_name(key: &str) -> String {
+    format!("{ENV_PREFIX}{}", key.to_ascii_uppercase().replace('.', "_"))
+}
+
+/// Splits a `-D key=value` argument.
+pub fn parse_define(arg: &str) -> Result<(String, String)> {
+    match arg.split_once('=') {
+        Some((k, v)) if !k.trim().is_empty() => Ok((k.trim().to_string(), v.trim().to_string())),
+        _ => Err(HdfsError::Config {
+            key: "-D".into(),
+            msg: format!("expected key=value, got '{arg}'"),
+        }),
+    }
+}
+
+/// Merges built-in defaults, a site file, `HDFS_*` environment variables and
+/// `-D` overrides, in that order, into one validated [`ClusterConfig`].
+///
+/// Unknown keys in the site file or on the command line are errors. Unknown
+/// `HDFS_*` variables only produce a warning since the environment is shared
+/// with other tools.
+#[derive(Clone, Debug, Default)]
+pub struct ConfigLoader {
+    site_file: Option<PathBuf>,
+    env: Vec<(String, String)>,
+    overrides: Vec<(String, String)>,
+}
+
+impl ConfigLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn site_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.site_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn env<I, K, V>(mut self, vars: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.env = vars
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .filter(|(k, _)| k.starts_with(ENV_PREFIX))
+            .collect();
+        self
+    }
+
+    pub fn process_env(self) -> Self {
+        self.env(std::env::vars())
+    }
+
+    pub fn define(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.overrides.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn load(&self) -> Result<EffectiveConfig> {
+        let defaults = flatten(&to_table(&ClusterConfig::default())?);
+        let mut layers = Layers {
+            values: defaults.clone(),
+            origins: KEYS.iter().map(|k| (k.name, Origin::Default)).collect(),
+            defaults,
+            warnings: Vec::new(),
+        };
+
+        if let Some(path) = &self.site_file {
+            let text = std::fs::read_to_string(path)?;
+            let table: Table = toml::from_str(&text).map_err(|e| HdfsError::Config {
+                key: "toml".into(),
+                msg: format!("{}: {}", path.display(), e.message()),
+            })?;
+            for (name, value) in flatten(&table) {
+                let key = layers.resolve(&name)?;
+                layers.set(key, value, Origin::SiteFile(path.clone()))?;
+            }
+        }
+
+        for (var, raw) in &self.env {
+            match KEYS.iter().find(|k| env_var_name(k.name) == *var) {
+                Some(&KeySpec { name: key, .. }) => {
+                    let value = layers.parse_raw(key, raw)?;
+                    layers.set(key, value, Origin::Env(var.clone()))?;
+                }
+                None => layers.warnings.push(HdfsError::Config {
+                    key: "env".into(),
+                    msg: format!("ignoring unknown variable {var}"),
+                }),
+            }
+        }
+
+        for (name, raw) in &self.overrides {
+            let key = layers.resolve(name)?;
+            let value = layers.parse_raw(key, raw)?;
+            layers.set(key, value, Origin::CommandLine)?;
+        }
+
+        let config: ClusterConfig =
+            Value::Table(unflatten(&layers.values))
+                .try_into()
+                .map_err(|e: toml::de::Error| HdfsError::Config {
+                    key: "toml".into(),
+                    msg: e.message().to_string(),
+                })?;
+        config.validate()?;
+
+        Ok(EffectiveConfig {
+            values: flatten(&to_table(&config)?),
+            config,
+            origins: layers.origins,
+            warnings: layers.warnings,
+        })
+    }
+}
+
+struct Layers {
+    defaults: BTreeMap<String, Value>,
+    values: BTreeMap<String, Value>,
+    origins: BTreeMap<&'static str, Origin>,
+    warnings: Vec<HdfsError>,
+}
+
+impl Layers {
+    fn resolve(&mut self, name: &str) -> Result<&'static str> {
+        match resolve_key(name) {
+            Some((key, false)) => Ok(key),
+            Some((key, true)) => {
+                self.warnings.push(HdfsError::Config {
+                    key: key.into(),
+                    msg: format!("'{name}' is deprecated, use '{key}'"),
+                });
+                Ok(key)
+            }
+            None => Err(HdfsError::Config {
+                key: "unknown".into(),
+                msg: format!("unknown key '{name}'"),
+            }),
+        }
+    }
+
+    /// Interprets a string from the environment or command line using the
+    /// type of the key's default value.
+    fn parse_raw(&self, key: &'static str, raw: &str) -> Result<Value> {
+        let bad = |what: &str| HdfsError::Config {
+            key: key.into(),
+            msg: format!("expected {what}, got '{raw}'"),
+        };
+        Ok(match self.defaults.get(key) {
+            Some(Value::Integer(_)) => Value::Integer(raw.parse().map_err(|_| bad("an integer"))?),
+            Some(Value::Float(_)) => Value::Float(raw.parse().map_err(|_| bad("a number"))?),
+            Some(Value::Boolean(_)) => Value::Boolean(raw.parse().map_err(|_| bad("a boolean"))?),
+            Some(Value::Array(_)) => Value::Array(
+                raw.split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(|s| Value::String(s.into()))
+                    .collect(),
+            ),
+            _ => Value::String(raw.into()),
+        })
+    }
+
+    /// Type-checks a single value against the config schema before taking it,
+    /// so the error names the offending key.
+    fn set(&mut self, key: &'static str, value: Value, origin: Origin) -> Result<()> {
+        let single = BTreeMap::from([(key.to_string(), value.clone())]);
+        Value::Table(unflatten(&single))
+            .try_into::<ClusterConfig>()
+            .map_err(|e| HdfsError::Config {
+                key: key.into(),
+                msg: format!("{origin}: {}", e.message()),
+            })?;
+        self.values.insert(key.to_string(), value);
+        self.origins.insert(key, origin);
+        Ok(())
+    }
+}
+
+/// The merged configuration plus the origin of every key.
+#[derive(Debug)]
+pub struct EffectiveConfig {
+    pub config: ClusterConfig,
+    values: BTreeMap<String, Value>,
+    origins: BTreeMap<&'static str, Origin>,
+    warnings: Vec<HdfsError>,
+}
+
+impl EffectiveConfig {
+    /// The rendered value and origin of a key (deprecated names resolve too).
+    pub fn get(&self, name: &str) -> Option<(String, &Origin)> {
+        let (key, _) = resolve_key(name)?;
+        Some((render(self.values.get(key)?), self.origins.get(key)?))
+    }
+
+    pub fn origin(&self, name: &str) -> Option<&Origin> {
+        self.origins.get(resolve_key(name)?.0)
+    }
+
+    /// All keys in sorted order with their rendered value and origin.
+    pub fn entries(&self) -> impl Iterator<Item = (&'static str, String, &Origin)> + '_ {
+        self.origins
+            .iter()
+            .map(|(k, o)| (*k, self.values.get(*k).map(render).unwrap_or_default(), o))
+    }
+
+    pub fn warnings(&self) -> &[HdfsError] {
+        &self.warnings
+    }
+
+    /// Keys whose effective value differs between `self` and `other`.
+    pub fn changed_keys(&self, other: &EffectiveConfig) -> Vec<&'static str> {
+        KEYS.iter()
+            .map(|k| k.name)
+            .filter(|k| self.values.get(*k) != other.values.get(*k))
+            .collect()
+    }
+
+    /// Fails the way [`EffectiveConfig::adopt`] would, without changing
+    /// anything.
+    pub(crate) fn can_adopt(&self, other: &EffectiveConfig, keys: &[&'static str]) -> Result<()> {
+        self.adopted(other, keys).map(|_| ())
+    }
+
+    /// Takes the value and origin of `keys` from `other` and rebuilds the
+    /// typed config. Nothing changes if the result would not validate.
+    pub(crate) fn adopt(&mut self, other: &EffectiveConfig, keys: &[&'static str]) -> Result<()> {
+        let (values, config) = self.adopted(other, keys)?;
+        for key in keys {
+            if let Some(o) = other.origins.get(*key) {
+                self.origins.insert(key, o.clone());
+            }
+        }
+        self.values = values;
+        self.config = config;
+        Ok(())
+    }
+
+    fn adopted(
+        &self,
+        other: &EffectiveConfig,
+        keys: &[&'static str],
+    ) -> Result<(BTreeMap<String, Value>, ClusterConfig)> {
+        let mut values = self.values.clone();
+        for key in keys {
+            if let Some(v) = other.values.get(*key) {
+                values.insert(key.to_string(), v.clone());
+            }
+        }
+        let config: ClusterConfig =
+            Value::Table(unflatten(&values))
+                .try_into()
+                .map_err(|e: toml::de::Error| HdfsError::Config {
+                    key: "toml".into(),
+                    msg: e.message().to_string(),
+                })?;
+        config.validate()?;
+        Ok((values, config))
+    }
+}
+
+fn to_table(cfg: &ClusterConfig) -> Result<Table> {
+    Table::try_from(cfg).map_err(|e| HdfsError::Config {
+        key: "toml".into(),
+        msg: e.to_string(),
+    })
+}
+
+fn flatten(table: &Table) -> BTreeMap<String, Value> {
+    fn walk(prefix: &str, table: &Table, out: &mut BTreeMap<String, Value>) {
+        for (k, v) in table {
+            let key = if prefix.is_empty() {
+                k.clone()
+            } else {
+                format!("{prefix}.{k}")
+            };
+            match v {
+                Value::Table(t) => walk(&key, t, out),
+                other => {
+                    out.insert(key, other.clone());
+                }
+            }
+        }
+    }
+    let mut out = BTreeMap::new();
+    walk("", table, &mut out);
+    out
+}
+
+fn unflatten(values: &BTreeMap<String, Value>) -> Table {
+    let mut root = Table::new();
+    for (key, value) in values {
+        let mut parts: Vec<&str> = key.split('.').collect();
+        let leaf = parts.pop().unwrap_or_default();
+        let mut cur = &mut root;
+        for p in parts {
+            cur = match cur.entry(p).or_insert_with(|| Value::Table(Table::new())) {
+                Value::Table(t) => t,
+                _ => unreachable!("config keys never nest under a scalar"),
+            };
+        }
+        cur.insert(leaf.to_string(), value.clone());
+    }
+    root
+}
+
+fn render(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        Value::Array(a) => a.iter().map(render).collect::<Vec<_>>().join(","),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ByteSize;
+    use crate::consts::MIB;
+    use std::time::Duration;
+
+    fn temp_site(name: &str, contents: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hdfs-layered-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("hdfs-site.toml");
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn assert_config_err(res: Result<EffectiveConfig>, expected_key: &str) -> String {
+        match res {
+            Err(HdfsError::Config { key, msg }) => {
+                assert_eq!(key, expected_key, "unexpected key, msg: {msg}");
+                msg
+            }
+            other => panic!("expected Config error, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn registry_matches_schema() {
+        let schema = flatten(&to_table(&ClusterConfig::default()).unwrap());
+        let mut keys: Vec<&str> = KEYS.iter().map(|k| k.name).collect();
+        keys.sort();
+        assert_eq!(keys, schema.keys().map(String::as_str).collect::<Vec<_>>());
+
+        for (old, new) in DEPRECATED_KEYS {
+            assert!(key_spec(new).is_some(), "{old} maps to unknown key {new}");
+        }
+    }
+
+    #[test]
+    fn defaults_only() {
+        let eff = ConfigLoader::new().load().unwrap();
+        assert_eq!(eff.config, ClusterConfig::default());
+        assert!(eff.entries().all(|(_, _, o)| *o == Origin::Default));
+        assert_eq!(eff.entries().count(), KEYS.len());
+        assert!(eff.warnings().is_empty());
+    }
+
+    #[test]
+    fn layers_apply_in_order_with_provenance() {
+        let site = temp_site(
+            "order",
+            r#"
+            default_block_size = "64MiB"
+            default_replication = 2
+
+            [datanode]
+            heartbeat_interval = "2s"
+            data_dirs = ["/d1", "/d2"]
+            "#,
+        );
+
+        let eff = ConfigLoader::new()
+            .site_file(&site)
+            .env([
+                ("HDFS_DEFAULT_REPLICATION", "1"),
+                ("HDFS_DATANODE_HEARTBEAT_INTERVAL", "4s"),
+                ("PATH", "/usr/bin"),
+            ])
+            .define("datanode.heartbeat_interval", "5s")
+            .load()
+            .unwrap();
+
+        assert_eq!(eff.config.default_block_size, ByteSize(64 * MIB));
+        assert_eq!(eff.config.default_replication, 1);
+        assert_eq!(
+            eff.config.datanode.heartbeat_interval,
+            Duration::from_secs(5)
+        );
+
+        let (v, o) = eff.get("default_block_size").unwrap();
+        assert_eq!(v, "64MiB");
+        assert_eq!(*o, Origin::SiteFile(site.clone()));
+
+        let (v, o) = eff.get("default_replication").unwrap();
+        assert_eq!(v, "1");
+        assert_eq!(o.to_string(), "env:HDFS_DEFAULT_REPLICATION");
+
+        let (v, o) = eff.get("datanode.heartbeat_interval").unwrap();
+        assert_eq!(v, "5s");
+        assert_eq!(*o, Origin::CommandLine);
+
+        assert_eq!(eff.get("datanode.data_dirs").unwrap().0, "/d1,/d2");
+        assert_eq!(*eff.origin("namenode.rpc_addr").unwrap(), Origin::Default);
+        assert!(eff.get("nope").is_none());
+
+        std::fs::remove_dir_all(site.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn env_and_define_values_are_typed() {
+        let eff = ConfigLoader::new()
+            .env([("HDFS_DATANODE_DATA_DIRS", "/a, /b,")])
+            .define("namenode.safemode_threshold_pct", "0.5")
+            .define("client.write_packet_size", "128KiB")
+            .load()
+            .unwrap();
+        assert_eq!(
+            eff.config.datanode.data_dirs,
+            vec![PathBuf::from("/a"), PathBuf::from("/b")]
+        );
+        assert_eq!(eff.config.namenode.safemode_threshold_pct, 0.5);
+        assert_eq!(eff.get("client.write_packet_size").unwrap().0, "128KiB");
+
+        let msg = assert_config_err(
+            ConfigLoader::new()
+                .define("default_replication", "three")
+                .load(),
+            "default_replication",
+        );
+        assert_eq!(msg, "expected an integer, got 'three'");
+
+        let msg = assert_config_err(
+            ConfigLoader::new()
+                .env([("HDFS_CLIENT_SOCKET_TIMEOUT", "soon")])
+                .load(),
+            "client.socket_timeout",
+        );
+        assert!(msg.starts_with("env:HDFS_CLIENT_SOCKET_TIMEOUT: "));
+    }
+
+    #[test]
+    fn unknown_and_deprecated_keys() {
+        let msg = assert_config_err(
+            ConfigLoader::new().define("namenode.rpc_adr", "x").load(),
+            "unknown",
+        );
+        assert_eq!(msg, "unknown key 'namenode.rpc_adr'");
+
+        let site = temp_site("unknown", "[client]\nretries = 3\n");
+        let msg = assert_config_err(ConfigLoader::new().site_file(&site).load(), "unknown");
+        assert_eq!(msg, "unknown key 'client.retries'");
+        std::fs::remove_dir_all(site.parent().unwrap()).unwrap();
+
+        let eff = ConfigLoader::new()
+            .define("dfs.replication", "2")
+            .env([("HDFS_CONF_DIR", "/etc/hdfs")])
+            .load()
+            .unwrap();
+        assert_eq!(eff.config.default_replication, 2);
+        assert_eq!(*eff.origin("dfs.replication").unwrap(), Origin::CommandLine);
+
+        let warnings: Vec<String> = eff.warnings().iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "config error (env): ignoring unknown variable HDFS_CONF_DIR",
+                "config error (default_replication): 'dfs.replication' is deprecated, use 'default_replication'",
+            ]
+        );
+    }
+
+    #[test]
+    fn cross_field_validation_runs_on_merged_result() {
+        let site = temp_site("merged", "default_block_size = \"1MiB\"\n");
+        // each layer is fine on its own, the combination is not
+        let msg = assert_config_err(
+            ConfigLoader::new()
+                .site_file(&site)
+                .define("checksum_chunk_size", "1000")
+                .load(),
+            "default_block_size",
+        );
+        assert_eq!(
+            msg,
+            "must be > 0 and multiple of checksum_chunk_size (1000B)"
+        );
+        std::fs::remove_dir_all(site.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn parse_define_splits_once() {
+        assert_eq!(
+            parse_define("client.namenodes=a:1,b:2").unwrap(),
+            ("client.namenodes".into(), "a:1,b:2".into())
+        );
+        assert_eq!(
+            parse_define(" k = v=w ").unwrap(),
+            ("k".into(), "v=w".into())
+        );
+        assert!(matches!(
+            parse_define("novalue"),
+            Err(HdfsError::Config { key, .. }) if key == "-D"
+        ));
+        assert!(parse_define("=v").is_err());
+    }
+
+    #[test]
+    fn env_var_names() {
+        assert_eq!(
+            env_var_name("default_block_size"),
+            "HDFS_DEFAULT_BLOCK_SIZE"
+        );
+        assert_eq!(
+            env_var_name("namenode.lease_soft_limit"),
+            "HDFS_NAMENODE_LEASE_SOFT_LIMIT"
+        );
+    }
+}
@@ -0,0 +1,505 @@
+use super::ClusterConfig;
+use super::layered::{ConfigLoader, EffectiveConfig, key_spec};
+use crate::error::{HdfsError, Result};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+/// A component that can pick up new values for some keys while running.
+///
+/// A key changes for all of its subscribers or for none: every subscriber
+/// first gets to [`check`](ReconfigSubscriber::check) the new value, and if
+/// one of them then fails to apply it, those that already did are handed the
+/// old value again.
+pub trait ReconfigSubscriber: Send + Sync {
+    /// Keys this subscriber wants to hear about.
+    fn keys(&self) -> &'static [&'static str];
+
+    /// Whether the new value of `key`, read from `new`, would be accepted.
+    /// Returning an error rejects the change before anything is applied.
+    fn check(&self, _key: &'static str, _new: &ClusterConfig) -> Result<()> {
+        Ok(())
+    }
+
+    /// Applies the value of `key` read from `new`, which is also how a
+    /// rolled back change is undone. Returning an error rejects the change
+    /// and keeps the old value in effect.
+    fn apply(&self, key: &'static str, new: &ClusterConfig) -> Result<()>;
+}
+
+/// A duration setting that can be swapped while others read it.
+#[derive(Debug)]
+pub struct AtomicDuration(AtomicU64);
+
+impl AtomicDuration {
+    pub fn new(d: Duration) -> Self {
+        Self(AtomicU64::new(nanos(d)))
+    }
+
+    pub fn load(&self) -> Duration {
+        Duration::from_nanos(self.0.load(Ordering::Acquire))
+    }
+
+    /// Saturates at `u64::MAX` nanoseconds, some 584 years.
+    pub fn store(&self, d: Duration) {
+        self.0.store(nanos(d), Ordering::Release);
+    }
+}
+
+fn nanos(d: Duration) -> u64 {
+    u64::try_from(d.as_nanos()).unwrap_or(u64::MAX)
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConfigChange {
+    pub key: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ReconfigReport {
+    pub applied: Vec<ConfigChange>,
+    /// Changes that were not applied, with the reason.
+    pub rejected: Vec<(ConfigChange, String)>,
+}
+
+impl ReconfigReport {
+    pub fn is_noop(&self) -> bool {
+        self.applied.is_empty() && self.rejected.is_empty()
+    }
+}
+
+/// Re-reads the layered config on demand (SIGHUP or an admin call), diffs it
+/// against what is running, and pushes the reconfigurable changes to the
+/// registered subscribers.
+pub struct Reconfigurer {
+    loader: ConfigLoader,
+    current: Mutex<EffectiveConfig>,
+    subscribers: RwLock<Vec<Arc<dyn ReconfigSubscriber>>>,
+    last: Mutex<Option<ReconfigReport>>,
+}
+
+impl Reconfigurer {
+    pub fn new(loader: ConfigLoader) -> Result<Self> {
+        let current = loader.load()?;
+        Ok(Self {
+            loader,
+            current: Mutex::new(current),
+            subscribers: RwLock::new(Vec::new()),
+            last: Mutex::new(None),
+        })
+    }
+
+    pub fn subscribe(&self, sub: Arc<dyn ReconfigSubscriber>) {
+        self.subscribers.write().unwrap().push(sub);
+    }
+
+    pub fn current(&self) -> ClusterConfig {
+        self.current.lock().unwrap().config.clone()
+    }
+
+    pub fn with_effective<R>(&self, f: impl FnOnce(&EffectiveConfig) -> R) -> R {
+        f(&self.current.lock().unwrap())
+    }
+
+    pub fn last_report(&self) -> Option<ReconfigReport> {
+        self.last.lock().unwrap().clone()
+    }
+
+    /// Reloads every layer and applies what can be applied live. Fails when
+    /// the new config cannot be loaded, or when the changes that passed
+    /// their checks don't validate together; nothing is applied then.
+    /// Other per-key problems end up in [`ReconfigReport::rejected`].
+    pub fn reconfigure(&self) -> Result<ReconfigReport> {
+        let new = self.loader.load()?;
+        let mut current = self.current.lock().unwrap();
+        let subscribers = self.subscribers.read().unwrap().clone();
+        let subscribed =
+            |key: &'static str| subscribers.iter().filter(move |s| s.keys().contains(&key));
+
+        let mut report = ReconfigReport::default();
+        let mut checked = Vec::new();
+
+        for key in current.changed_keys(&new) {
+            let change = ConfigChange {
+                key,
+                old: current.get(key).map(|(v, _)| v).unwrap_or_default(),
+                new: new.get(key).map(|(v, _)| v).unwrap_or_default(),
+            };
+
+            if !key_spec(key).is_some_and(|s| s.reconfigurable) {
+                report.rejected.push((change, "requires restart".into()));
+                continue;
+            }
+
+            match subscribed(key).find_map(|s| s.check(key, &new.config).err()) {
+                Some(e) => report.rejected.push((change, e.to_string())),
+                None => checked.push(change),
+            }
+        }
+
+        let keys: Vec<_> = checked.iter().map(|c| c.key).collect();
+        current.can_adopt(&new, &keys)?;
+
+        let mut accepted = Vec::new();
+        for change in checked {
+            let key = change.key;
+            let mut applied = Vec::new();
+            let failure = subscribed(key).find_map(|s| match s.apply(key, &new.config) {
+                Ok(()) => {
+                    applied.push(s);
+                    None
+                }
+                Err(e) => Some(e),
+            });
+            match failure {
+                Some(e) => {
+                    for s in applied {
+                        let _ = s.apply(key, &current.config);
+                    }
+                    report.rejected.push((change, e.to_string()));
+                }
+                None => {
+                    accepted.push(key);
+                    report.applied.push(change);
+                }
+            }
+        }
+
+        // a key that failed to apply may have been holding up another
+        if let Err(e) = current.can_adopt(&new, &accepted) {
+            for &key in &accepted {
+                for s in subscribed(key) {
+                    let _ = s.apply(key, &current.config);
+                }
+            }
+            return Err(e);
+        }
+        current.adopt(&new, &accepted)?;
+        *self.last.lock().unwrap() = Some(report.clone());
+        Ok(report)
+    }
+}
+
+/// Runs [`Reconfigurer::reconfigure`] on every SIGHUP from a background
+/// thread and hands each outcome to `on_report`.
+#[cfg(unix)]
+pub fn spawn_sighup_handler<F>(
+    reconf: Arc<Reconfigurer>,
+    on_report: F,
+) -> Result<std::thread::JoinHandle<()>>
+where
+    F: Fn(Result<ReconfigReport>) + Send + 'static,
+{
+    let mut signals = signal_hook::iterator::Signals::new([signal_hook::consts::SIGHUP])?;
+    std::thread::Builder::new()
+        .name("reconfig-sighup".into())
+        .spawn(move || {
+            for _ in signals.forever() {
+                on_report(reconf.reconfigure());
+            }
+        })
+        .map_err(HdfsError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+
+    struct Xceivers {
+        limit: AtomicU32,
+        calls: AtomicU32,
+    }
+
+    impl ReconfigSubscriber for Xceivers {
+        fn keys(&self) -> &'static [&'static str] {
+            &["datanode.max_xceivers"]
+        }
+
+        fn apply(&self, key: &'static str, new: &ClusterConfig) -> Result<()> {
+            assert_eq!(key, "datanode.max_xceivers");
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if new.datanode.max_xceivers > 10_000 {
+                return Err(HdfsError::Config {
+                    key: key.into(),
+                    msg: "too many".into(),
+                });
+            }
+            self.limit
+                .store(new.datanode.max_xceivers, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    /// Vetoes every change to `keys`, when checking or when applying.
+    struct Veto {
+        keys: &'static [&'static str],
+        at_check: bool,
+    }
+
+    impl Veto {
+        fn refuse(&self, key: &'static str) -> Result<()> {
+            Err(HdfsError::Config {
+                key: key.into(),
+                msg: "vetoed".into(),
+            })
+        }
+    }
+
+    impl ReconfigSubscriber for Veto {
+        fn keys(&self) -> &'static [&'static str] {
+            self.keys
+        }
+
+        fn check(&self, key: &'static str, _: &ClusterConfig) -> Result<()> {
+            if self.at_check {
+                self.refuse(key)
+            } else {
+                Ok(())
+            }
+        }
+
+        fn apply(&self, key: &'static str, _: &ClusterConfig) -> Result<()> {
+            self.refuse(key)
+        }
+    }
+
+    /// Accepts everything and records the keys it applied.
+    struct Recorder {
+        keys: &'static [&'static str],
+        applied: Mutex<Vec<&'static str>>,
+    }
+
+    impl ReconfigSubscriber for Recorder {
+        fn keys(&self) -> &'static [&'static str] {
+            self.keys
+        }
+
+        fn apply(&self, key: &'static str, _: &ClusterConfig) -> Result<()> {
+            self.applied.lock().unwrap().push(key);
+            Ok(())
+        }
+    }
+
+    struct Site(PathBuf);
+
+    impl Site {
+        fn new(name: &str, contents: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("hdfs-reconfig-{}-{name}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let site = Site(dir.join("hdfs-site.toml"));
+            site.write(contents);
+            site
+        }
+
+        fn write(&self, contents: &str) {
+            std::fs::write(&self.0, contents).unwrap();
+        }
+    }
+
+    impl Drop for Site {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(self.0.parent().unwrap());
+        }
+    }
+
+    fn setup(site: &Site) -> (Reconfigurer, Arc<Xceivers>) {
+        let r = Reconfigurer::new(ConfigLoader::new().site_file(&site.0)).unwrap();
+        let sub = Arc::new(Xceivers {
+            limit: AtomicU32::new(r.current().datanode.max_xceivers),
+            calls: AtomicU32::new(0),
+        });
+        r.subscribe(sub.clone());
+        (r, sub)
+    }
+
+    #[test]
+    fn no_changes_is_noop() {
+        let site = Site::new("noop", "");
+        let (r, sub) = setup(&site);
+        let report = r.reconfigure().unwrap();
+        assert!(report.is_noop());
+        assert_eq!(sub.calls.load(Ordering::SeqCst), 0);
+        assert_eq!(r.last_report(), Some(report));
+    }
+
+    #[test]
+    fn applies_live_keys_and_rejects_fixed_ones() {
+        let site = Site::new("mixed", "");
+        let (r, sub) = setup(&site);
+
+        site.write(
+            r#"
+            default_block_size = "64MiB"
+            [datanode]
+            max_xceivers = 128
+            heartbeat_interval = "10s"
+            "#,
+        );
+        let report = r.reconfigure().unwrap();
+
+        let applied: Vec<_> = report.applied.iter().map(|c| c.key).collect();
+        assert_eq!(
+            applied,
+            vec!["datanode.heartbeat_interval", "datanode.max_xceivers"]
+        );
+        assert_eq!(report.applied[1].old, "4096");
+        assert_eq!(report.applied[1].new, "128");
+
+        assert_eq!(report.rejected.len(), 1);
+        assert_eq!(report.rejected[0].0.key, "default_block_size");
+        assert_eq!(report.rejected[0].1, "requires restart");
+
+        assert_eq!(sub.limit.load(Ordering::SeqCst), 128);
+        let cur = r.current();
+        assert_eq!(cur.datanode.max_xceivers, 128);
+        assert_eq!(cur.datanode.heartbeat_interval, Duration::from_secs(10));
+        assert_eq!(cur.default_block_size.as_u64(), 128 * crate::consts::MIB);
+        r.with_effective(|eff| {
+            assert_eq!(eff.get("default_block_size").unwrap().0, "128MiB");
+            assert_eq!(
+                eff.origin("datanode.max_xceivers").unwrap().to_string(),
+                format!("site:{}", site.0.display())
+            );
+        });
+
+        // the rejected key keeps showing up until the file is fixed or we restart
+        let again = r.reconfigure().unwrap();
+        assert!(again.applied.is_empty());
+        assert_eq!(again.rejected.len(), 1);
+    }
+
+    #[test]
+    fn subscriber_can_veto() {
+        let site = Site::new("veto", "");
+        let (r, sub) = setup(&site);
+
+        site.write("[datanode]\nmax_xceivers = 20000\n");
+        let report = r.reconfigure().unwrap();
+        assert!(report.applied.is_empty());
+        assert_eq!(
+            report.rejected[0].1,
+            "config error (datanode.max_xceivers): too many"
+        );
+        assert_eq!(sub.limit.load(Ordering::SeqCst), 4096);
+        assert_eq!(r.current().datanode.max_xceivers, 4096);
+    }
+
+    #[test]
+    fn veto_by_a_later_subscriber_reaches_none() {
+        let site = Site::new("veto-second", "");
+        let (r, sub) = setup(&site);
+        r.subscribe(Arc::new(Veto {
+            keys: &["datanode.max_xceivers"],
+            at_check: true,
+        }));
+
+        site.write("[datanode]\nmax_xceivers = 128\n");
+        let report = r.reconfigure().unwrap();
+        assert!(report.applied.is_empty());
+        assert_eq!(
+            report.rejected[0].1,
+            "config error (datanode.max_xceivers): vetoed"
+        );
+        assert_eq!(sub.calls.load(Ordering::SeqCst), 0);
+        assert_eq!(sub.limit.load(Ordering::SeqCst), 4096);
+        assert_eq!(r.current().datanode.max_xceivers, 4096);
+    }
+
+    #[test]
+    fn failed_apply_is_rolled_back() {
+        let site = Site::new("rollback", "");
+        let (r, sub) = setup(&site);
+        r.subscribe(Arc::new(Veto {
+            keys: &["datanode.max_xceivers"],
+            at_check: false,
+        }));
+
+        site.write("[datanode]\nmax_xceivers = 128\n");
+        let report = r.reconfigure().unwrap();
+        assert!(report.applied.is_empty());
+        assert_eq!(report.rejected.len(), 1);
+        // applied, then handed the old value back
+        assert_eq!(sub.calls.load(Ordering::SeqCst), 2);
+        assert_eq!(sub.limit.load(Ordering::SeqCst), 4096);
+        assert_eq!(r.current().datanode.max_xceivers, 4096);
+    }
+
+    #[test]
+    fn changes_must_validate_together_before_any_is_applied() {
+        let site = Site::new("together", "");
+        let (r, _) = setup(&site);
+        let heartbeat = Arc::new(Recorder {
+            keys: &["datanode.heartbeat_interval"],
+            applied: Mutex::new(Vec::new()),
+        });
+        r.subscribe(heartbeat.clone());
+        r.subscribe(Arc::new(Veto {
+            keys: &["namenode.heartbeat_recheck_interval"],
+            at_check: true,
+        }));
+
+        // valid as a file, but not with the recheck interval left at 5m
+        site.write(
+            "[namenode]\nheartbeat_recheck_interval = \"15m\"\n\
+             [datanode]\nheartbeat_interval = \"10m\"\n",
+        );
+        assert!(matches!(
+            r.reconfigure(),
+            Err(HdfsError::Config { key, .. }) if key == "namenode.heartbeat_recheck_interval"
+        ));
+        assert!(heartbeat.applied.lock().unwrap().is_empty());
+        assert_eq!(r.current(), ClusterConfig::default());
+        assert_eq!(r.last_report(), None);
+    }
+
+    #[test]
+    fn atomic_duration() {
+        let d = AtomicDuration::new(Duration::from_secs(3));
+        assert_eq!(d.load(), Duration::from_secs(3));
+        d.store(Duration::from_millis(1500));
+        assert_eq!(d.load(), Duration::from_millis(1500));
+        d.store(Duration::MAX);
+        assert_eq!(d.load(), Duration::from_nanos(u64::MAX));
+    }
+
+    #[test]
+    fn invalid_file_fails_whole_reload() {
+        let site = Site::new("invalid", "");
+        let (r, _) = setup(&site);
+
+        site.write("[datanode]\nmax_xceivers = 0\n");
+        assert!(matches!(
+            r.reconfigure(),
+            Err(HdfsError::Config { key, .. }) if key == "datanode.max_xceivers"
+        ));
+        assert_eq!(r.current(), ClusterConfig::default());
+        assert_eq!(r.last_report(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sighup_triggers_reload() {
+        let site = Site::new("sighup", "");
+        let (r, sub) = setup(&site);
+        let r = Arc::new(r);
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        spawn_sighup_handler(r.clone(), move |res| {
+            let _ = tx.send(res.map(|rep| rep.applied.len()));
+        })
+        .unwrap();
+
+        site.write("[datanode]\nmax_xceivers = 64\n");
+        signal_hook::low_level::raise(signal_hook::consts::SIGHUP).unwrap();
+
+        let applied = rx.recv_timeout(Duration::from_secs(5)).unwrap().unwrap();
+        assert_eq!(applied, 1);
+        assert_eq!(sub.limit.load(Ordering::SeqCst), 64);
+    }
+}
@@ -0,0 +1,33 @@
+use std::time::Duration;
+
+pub const KIB: u64 = 1024;
+pub const MIB: u64 = 1024 * KIB;
+pub const GIB: u64 = 1024 * MIB;
+
+pub const DEFAULT_BLOCK_SIZE: u64 = 128 * MIB;
+pub const DEFAULT_CHECKSUM_CHUNK_SIZE: u64 = 512;
+pub const DEFAULT_REPLICATION: u16 = 3;
+pub const DEFAULT_MIN_REPLICATION: u16 = 1;
+pub const DEFAULT_MAX_REPLICATION: u16 = 512;
+pub const DEFAULT_WRITE_PACKET_SIZE: u64 = 64 * KIB;
+pub const DEFAULT_BALANCE_BANDWIDTH: u64 = 10 * MIB;
+
+pub const DEFAULT_NN_RPC_ADDR: &str = "0.0.0.0:8020";
+pub const DEFAULT_DN_DATA_ADDR: &str = "0.0.0.0:9866";
+pub const DEFAULT_DN_IPC_ADDR: &str = "0.0.0.0:9867";
+pub const DEFAULT_CLIENT_NAMENODE: &str = "localhost:8020";
+
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(3);
+pub const DEFAULT_HEARTBEAT_RECHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+pub const DEFAULT_BLOCK_REPORT_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+pub const DEFAULT_SAFEMODE_EXTENSION: Duration = Duration::from_secs(30);
+pub const DEFAULT_SAFEMODE_THRESHOLD_PCT: f64 = 0.999;
+pub const DEFAULT_LEASE_SOFT_LIMIT: Duration = Duration::from_secs(60);
+pub const DEFAULT_LEASE_HARD_LIMIT: Duration = Duration::from_secs(60 * 60);
+pub const DEFAULT_EDIT_LOG_ROLL_PERIOD: Duration = Duration::from_secs(2 * 60);
+pub const DEFAULT_REPLICATION_MAX_STREAMS: u32 = 2;
+pub const DEFAULT_MAX_XCEIVERS: u32 = 4096;
+pub const DEFAULT_SOCKET_TIMEOUT: Duration = Duration::from_secs(60);
+pub const DEFAULT_CLIENT_MAX_RETRIES: u32 = 10;
+pub const DEFAULT_CLIENT_RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+pub const DEFAULT_CLIENT_RETRY_MAX_DELAY: Duration = Duration::from_secs(15);
@@ -1,4 +1,7 @@
 use crate::ids::BlockId;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::io;
 use thiserror::Error;
 
 #[non_exhaustive]
@@ -8,10 +11,13 @@ pub enum HdfsError {
     Io(#[from] std::io::Error),
 
     #[error("config error ({key}): {msg}")]
-    Config { key: &'static str, msg: String },
+    Config { key: Cow<'static, str>, msg: String },
 
     #[error("invalid path '{path}': {reason}")]
-    InvalidPath { path: String, reason: &'static str },
+    InvalidPath {
+        path: String,
+        reason: Cow<'static, str>,
+    },
 
     #[error("already exists: {path}")]
     AlreadyExists { path: String },
@@ -20,10 +26,16 @@ pub enum HdfsError {
     NotFound { path: String },
 
     #[error("state error ({what}): {details}")]
-    State { what: &'static str, details: String },
+    State {
+        what: Cow<'static, str>,
+        details: String,
+    },
 
     #[error("protocol error ({op}): {details}")]
-    Protocol { op: &'static str, details: String },
+    Protocol {
+        op: Cow<'static, str>,
+        details: String,
+    },
 
     #[error(
         "checksum mismatch (blk_{block}, chunk {chunk_index}): expected 0x{expected:08X}, got 0x{got:08X}"
@@ -37,13 +49,277 @@ pub enum HdfsError {
 
     #[error("timeout during {op}: {during}")]
     Timeout {
-        op: &'static str,
-        during: &'static str,
+        op: Cow<'static, str>,
+        during: Cow<'static, str>,
     },
 }
 
 pub type Result<T> = std::result::Result<T, HdfsError>;
 
+impl HdfsError {
+    /// Stable numeric code; never reuse or renumber these.
+    pub fn code(&self) -> u16 {
+        match self {
+            HdfsError::Io(_) => 1,
+            HdfsError::Config { .. } => 2,
+            HdfsError::InvalidPath { .. } => 3,
+            HdfsError::AlreadyExists { .. } => 4,
+            HdfsError::NotFound { .. } => 5,
+            HdfsError::State { .. } => 6,
+            HdfsError::Protocol { .. } => 7,
+            HdfsError::ChecksumMismatch { .. } => 8,
+            HdfsError::Timeout { .. } => 9,
+        }
+    }
+
+    /// The Hadoop exception class this maps to, as sent in RPC responses.
+    pub fn class_name(&self) -> &'static str {
+        match self {
+            HdfsError::Io(_) => "java.io.IOException",
+            HdfsError::Config { .. } => "org.apache.hadoop.HadoopIllegalArgumentException",
+            HdfsError::InvalidPath { .. } => "org.apache.hadoop.fs.InvalidPathException",
+            HdfsError::AlreadyExists { .. } => "org.apache.hadoop.fs.FileAlreadyExistsException",
+            HdfsError::NotFound { .. } => "java.io.FileNotFoundException",
+            HdfsError::State { .. } => "java.lang.IllegalStateException",
+            HdfsError::Protocol { .. } => "org.apache.hadoop.ipc.RpcServerException",
+            HdfsError::ChecksumMismatch { .. } => "org.apache.hadoop.fs.ChecksumException",
+            HdfsError::Timeout { .. } => "java.net.SocketTimeoutException",
+        }
+    }
+
+    /// Transient failures where trying again (possibly elsewhere) may work.
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            HdfsError::Io(e) => matches!(
+                e.kind(),
+                io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::NotConnected
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::Interrupted
+                    | io::ErrorKind::WouldBlock
+                    | io::ErrorKind::UnexpectedEof
+            ),
+            HdfsError::Timeout { .. } | HdfsError::ChecksumMismatch { .. } => true,
+            e => e.is_standby(),
+        }
+    }
+
+    /// Retriable failures where the request certainly had no effect, so even
+    /// non-idempotent calls (create, append, rename) may be sent again. A
+    /// timeout or reset connection may have hit after the server applied it.
+    pub fn is_idempotent_safe(&self) -> bool {
+        match self {
+            HdfsError::Io(e) => matches!(
+                e.kind(),
+                io::ErrorKind::ConnectionRefused | io::ErrorKind::NotConnected
+            ),
+            HdfsError::ChecksumMismatch { .. } => true,
+            e => e.is_standby(),
+        }
+    }
+
+    /// Refused by an HA namenode that is not the active one; another
+    /// namenode may take the call.
+    pub fn is_standby(&self) -> bool {
+        matches!(self, HdfsError::State { what, .. } if what == "standby")
+    }
+
+    pub fn to_remote(&self) -> RemoteError {
+        let detail = match self {
+            HdfsError::Io(e) => RemoteDetail::Io {
+                kind: io_kind_name(e.kind()).to_string(),
+                msg: e.to_string(),
+            },
+            HdfsError::Config { key, msg } => RemoteDetail::Config {
+                key: key.to_string(),
+                msg: msg.clone(),
+            },
+            HdfsError::InvalidPath { path, reason } => RemoteDetail::InvalidPath {
+                path: path.clone(),
+                reason: reason.to_string(),
+            },
+            HdfsError::AlreadyExists { path } => RemoteDetail::AlreadyExists { path: path.clone() },
+            HdfsError::NotFound { path } => RemoteDetail::NotFound { path: path.clone() },
+            HdfsError::State { what, details } => RemoteDetail::State {
+                what: what.to_string(),
+                details: details.clone(),
+            },
+            HdfsError::Protocol { op, details } => RemoteDetail::Protocol {
+                op: op.to_string(),
+                details: details.clone(),
+            },
+            HdfsError::ChecksumMismatch {
+                block,
+                chunk_index,
+                expected,
+                got,
+            } => RemoteDetail::ChecksumMismatch {
+                block: *block,
+                chunk_index: *chunk_index,
+                expected: *expected,
+                got: *got,
+            },
+            HdfsError::Timeout { op, during } => RemoteDetail::Timeout {
+                op: op.to_string(),
+                during: during.to_string(),
+            },
+        };
+        RemoteError {
+            code: self.code(),
+            class_name: self.class_name().to_string(),
+            message: self.to_string(),
+            detail,
+        }
+    }
+}
+
+/// An `HdfsError` flattened for the wire. Servers send it in place of a
+/// result; clients turn it back into the typed error with `into_error`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RemoteError {
+    pub code: u16,
+    pub class_name: String,
+    /// Display output on the server, for logs.
+    pub message: String,
+    pub detail: RemoteDetail,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RemoteDetail {
+    Io {
+        kind: String,
+        msg: String,
+    },
+    Config {
+        key: String,
+        msg: String,
+    },
+    InvalidPath {
+        path: String,
+        reason: String,
+    },
+    AlreadyExists {
+        path: String,
+    },
+    NotFound {
+        path: String,
+    },
+    State {
+        what: String,
+        details: String,
+    },
+    Protocol {
+        op: String,
+        details: String,
+    },
+    ChecksumMismatch {
+        block: BlockId,
+        chunk_index: u64,
+        expected: u32,
+        got: u32,
+    },
+    Timeout {
+        op: String,
+        during: String,
+    },
+}
+
+impl RemoteError {
+    /// Rebuilds the typed error, with the names the peer sent kept as they
+    /// are.
+    pub fn into_error(self) -> HdfsError {
+        match self.detail {
+            RemoteDetail::Io { kind, msg } => {
+                HdfsError::Io(io::Error::new(io_kind_from_name(&kind), msg))
+            }
+            RemoteDetail::Config { key, msg } => HdfsError::Config {
+                key: key.into(),
+                msg,
+            },
+            RemoteDetail::InvalidPath { path, reason } => HdfsError::InvalidPath {
+                path,
+                reason: reason.into(),
+            },
+            RemoteDetail::AlreadyExists { path } => HdfsError::AlreadyExists { path },
+            RemoteDetail::NotFound { path } => HdfsError::NotFound { path },
+            RemoteDetail::State { what, details } => HdfsError::State {
+                what: what.into(),
+                details,
+            },
+            RemoteDetail::Protocol { op, details } => HdfsError::Protocol {
+                op: op.into(),
+                details,
+            },
+            RemoteDetail::ChecksumMismatch {
+                block,
+                chunk_index,
+                expected,
+                got,
+            } => HdfsError::ChecksumMismatch {
+                block,
+                chunk_index,
+                expected,
+                got,
+            },
+            RemoteDetail::Timeout { op, during } => HdfsError::Timeout {
+                op: op.into(),
+                during: during.into(),
+            },
+        }
+    }
+}
+
+impl From<&HdfsError> for RemoteError {
+    fn from(e: &HdfsError) -> Self {
+        e.to_remote()
+    }
+}
+
+impl From<RemoteError> for HdfsError {
+    fn from(e: RemoteError) -> Self {
+        e.into_error()
+    }
+}
+
+const IO_KINDS: &[(io::ErrorKind, &str)] = &[
+    (io::ErrorKind::NotFound, "not_found"),
+    (io::ErrorKind::PermissionDenied, "permission_denied"),
+    (io::ErrorKind::ConnectionRefused, "connection_refused"),
+    (io::ErrorKind::ConnectionReset, "connection_reset"),
+    (io::ErrorKind::ConnectionAborted, "connection_aborted"),
+    (io::ErrorKind::NotConnected, "not_connected"),
+    (io::ErrorKind::AddrInUse, "addr_in_use"),
+    (io::ErrorKind::BrokenPipe, "broken_pipe"),
+    (io::ErrorKind::AlreadyExists, "already_exists"),
+    (io::ErrorKind::WouldBlock, "would_block"),
+    (io::ErrorKind::InvalidInput, "invalid_input"),
+    (io::ErrorKind::InvalidData, "invalid_data"),
+    (io::ErrorKind::TimedOut, "timed_out"),
+    (io::ErrorKind::WriteZero, "write_zero"),
+    (io::ErrorKind::Interrupted, "interrupted"),
+    (io::ErrorKind::Unsupported, "unsupported"),
+    (io::ErrorKind::UnexpectedEof, "unexpected_eof"),
+    (io::ErrorKind::OutOfMemory, "out_of_memory"),
+];
+
+fn io_kind_name(kind: io::ErrorKind) -> &'static str {
+    IO_KINDS
+        .iter()
+        .find(|(k, _)| *k == kind)
+        .map_or("other", |(_, n)| n)
+}
+
+fn io_kind_from_name(name: &str) -> io::ErrorKind {
+    IO_KINDS
+        .iter()
+        .find(|(_, n)| *n == name)
+        .map_or(io::ErrorKind::Other, |(k, _)| *k)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -65,7 +341,7 @@ mod tests {
     #[test]
     fn config_display() {
         let e = HdfsError::Config {
-            key: "default_block_size",
+            key: "default_block_size".into(),
             msg: "must be > 0 and multiple of checksum_chunk_size (512B)".into(),
         };
 
@@ -79,7 +355,7 @@ mod tests {
     fn invalid_path_display() {
         let e = HdfsError::InvalidPath {
             path: "/a/\u{1}/b".into(),
-            reason: "control character not allowed",
+            reason: "control character not allowed".into(),
         };
 
         assert_eq!(
@@ -103,11 +379,11 @@ mod tests {
     #[test]
     fn state_and_protocol() {
         let st = HdfsError::State {
-            what: "complete",
+            what: "complete".into(),
             details: "file not under construction".into(),
         };
         let pr = HdfsError::Protocol {
-            op: "AddBlock",
+            op: "AddBlock".into(),
             details: "missing field 'path'".into(),
         };
         assert_eq!(
@@ -138,12 +414,187 @@ mod tests {
     #[test]
     fn timeout_display() {
         let e = HdfsError::Timeout {
-            op: "WriteChunk",
-            during: "client->DN transfer",
+            op: "WriteChunk".into(),
+            during: "client->DN transfer".into(),
         };
         assert_eq!(
             e.to_string(),
             "timeout during WriteChunk: client->DN transfer"
         );
     }
+
+    fn all_variants() -> Vec<HdfsError> {
+        vec![
+            HdfsError::from(io::Error::new(io::ErrorKind::ConnectionReset, "peer reset")),
+            HdfsError::Config {
+                key: "default_block_size".into(),
+                msg: "must be > 0".into(),
+            },
+            HdfsError::InvalidPath {
+                path: "a".into(),
+                reason: "must be absolut".into(),
+            },
+            HdfsError::AlreadyExists { path: "/a".into() },
+            HdfsError::NotFound { path: "/b".into() },
+            HdfsError::State {
+                what: "complete".into(),
+                details: "file not under construction".into(),
+            },
+            HdfsError::Protocol {
+                op: "AddBlock".into(),
+                details: "missing field 'path'".into(),
+            },
+            HdfsError::ChecksumMismatch {
+                block: BlockId(42),
+                chunk_index: 7,
+                expected: 0xDEADBEEF,
+                got: 0xFEEDBEEF,
+            },
+            HdfsError::Timeout {
+                op: "WriteChunk".into(),
+                during: "client->DN transfer".into(),
+            },
+        ]
+    }
+
+    #[test]
+    fn codes_are_stable_and_unique() {
+        let codes: Vec<u16> = all_variants().iter().map(HdfsError::code).collect();
+        assert_eq!(codes, (1..=9).collect::<Vec<_>>());
+
+        let nf = HdfsError::NotFound { path: "/x".into() };
+        assert_eq!(nf.class_name(), "java.io.FileNotFoundException");
+    }
+
+    #[test]
+    fn retriability() {
+        let io = |k| HdfsError::from(io::Error::new(k, "x"));
+
+        assert!(io(io::ErrorKind::ConnectionRefused).is_retriable());
+        assert!(io(io::ErrorKind::ConnectionRefused).is_idempotent_safe());
+        assert!(io(io::ErrorKind::ConnectionReset).is_retriable());
+        assert!(!io(io::ErrorKind::ConnectionReset).is_idempotent_safe());
+        assert!(!io(io::ErrorKind::PermissionDenied).is_retriable());
+
+        let timeout = HdfsError::Timeout {
+            op: "Create".into(),
+            during: "rpc".into(),
+        };
+        assert!(timeout.is_retriable());
+        assert!(!timeout.is_idempotent_safe());
+
+        let nf = HdfsError::NotFound { path: "/x".into() };
+        assert!(!nf.is_retriable());
+        assert!(!nf.is_idempotent_safe());
+
+        let standby = HdfsError::State {
+            what: "standby".into(),
+            details: "not active".into(),
+        };
+        assert!(standby.is_standby() && standby.is_idempotent_safe());
+        let other = HdfsError::State {
+            what: "lease".into(),
+            details: "expired".into(),
+        };
+        assert!(!other.is_standby() && !other.is_retriable());
+
+        // idempotent-safe implies retriable
+        for e in all_variants() {
+            assert!(!e.is_idempotent_safe() || e.is_retriable(), "{e}");
+        }
+    }
+
+    #[test]
+    fn remote_names_are_kept_verbatim() {
+        // method names, ops of the Hadoop layer and names no build of
+        // this crate uses come back exactly as sent
+        for name in [
+            "addBlock",
+            "hadoop_rpc",
+            "checksum_header",
+            "quota",
+            "noise-7",
+        ] {
+            let errors = [
+                HdfsError::Config {
+                    key: name.into(),
+                    msg: "bad".into(),
+                },
+                HdfsError::InvalidPath {
+                    path: "/a".into(),
+                    reason: name.into(),
+                },
+                HdfsError::State {
+                    what: name.into(),
+                    details: "expired".into(),
+                },
+                HdfsError::Protocol {
+                    op: name.into(),
+                    details: "truncated".into(),
+                },
+                HdfsError::Timeout {
+                    op: name.into(),
+                    during: name.into(),
+                },
+            ];
+            for e in errors {
+                let json = serde_json::to_string(&e.to_remote()).unwrap();
+                let back = serde_json::from_str::<RemoteError>(&json)
+                    .unwrap()
+                    .into_error();
+                assert_eq!(back.code(), e.code());
+                assert_eq!(back.to_string(), e.to_string());
+            }
+        }
+
+        let standby = HdfsError::State {
+            what: "standby".into(),
+            details: "not active".into(),
+        };
+        assert!(standby.to_remote().into_error().is_standby());
+    }
+
+    #[test]
+    fn remote_roundtrip_keeps_fields() {
+        for e in all_variants() {
+            let remote = e.to_remote();
+            assert_eq!(remote.code, e.code());
+            assert_eq!(remote.class_name, e.class_name());
+            assert_eq!(remote.message, e.to_string());
+
+            let json = serde_json::to_string(&remote).unwrap();
+            let back: RemoteError = serde_json::from_str(&json).unwrap();
+            assert_eq!(back, remote);
+
+            let rebuilt = back.into_error();
+            assert_eq!(rebuilt.code(), e.code());
+            assert_eq!(rebuilt.to_string(), e.to_string());
+        }
+
+        let remote = RemoteError::from(&HdfsError::ChecksumMismatch {
+            block: BlockId(42),
+            chunk_index: 7,
+            expected: 1,
+            got: 2,
+        });
+        assert!(matches!(
+            HdfsError::from(remote),
+            HdfsError::ChecksumMismatch {
+                block: BlockId(42),
+                chunk_index: 7,
+                expected: 1,
+                got: 2,
+            }
+        ));
+    }
+
+    #[test]
+    fn remote_io_keeps_kind() {
+        let e = HdfsError::from(io::Error::new(io::ErrorKind::TimedOut, "slow"));
+        let json = serde_json::to_string(&e.to_remote()).unwrap();
+        assert_eq!(
+            json,
+            r#"{"code":1,"class_name":"java.io.IOException","message":"io: slow","detail":{"type":"io","kind":"timed_out","msg":"slow"}}"#
+        );
+    }
 }
@@ -0,0 +1,21 @@
+//! Small filesystem helpers shared by the modules that keep state on disk.
+
+use std::path::Path;
+
+/// Replaces `path` with `bytes` so that readers see either the old or the
+/// new contents, and the new contents survive a crash once this returns.
+pub(crate) fn write_atomic(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let tmp = path.with_extension("tmp");
+    {
+        let mut f = std::fs::File::create(&tmp)?;
+        f.write_all(bytes)?;
+        f.sync_all()?;
+    }
+    std::fs::rename(&tmp, path)?;
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
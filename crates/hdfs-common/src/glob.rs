@@ -0,0 +1,580 @@
+use crate::error::{HdfsError, Result};
+use crate::path::{PathAbs, normalize};
+
+/// Upper bound on alternatives produced by `{..}` expansion in one segment.
+const MAX_ALTERNATIVES: usize = 1024;
+
+/// An absolute path pattern. Every segment is a glob supporting `*`, `?`,
+/// `[a-z]`/`[!a-z]`, `{a,b}` and `\` escapes; `/` is never matched by a
+/// wildcard.
+#[derive(Clone, Debug)]
+pub struct PathPattern {
+    raw: String,
+    segments: Vec<Segment>,
+}
+
+/// One child returned by the listing callback of [`PathPattern::expand`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GlobEntry {
+    pub name: String,
+    pub is_dir: bool,
+}
+
+impl GlobEntry {
+    pub fn dir(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            is_dir: true,
+        }
+    }
+
+    pub fn file(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            is_dir: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Literal(String),
+    Glob(Vec<Vec<Tok>>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Char(char),
+    Any,
+    Star,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+/// Parse tree before brace expansion.
+#[derive(Clone, Debug)]
+enum Node {
+    Tok(Tok),
+    Alt(Vec<Vec<Node>>),
+}
+
+impl PathPattern {
+    pub fn new(pattern: &str) -> Result<Self> {
+        let normalized = normalize(pattern)?;
+        let bad = |reason: &'static str| HdfsError::InvalidPath {
+            path: pattern.into(),
+            reason: reason.into(),
+        };
+
+        let mut segments = Vec::new();
+        if normalized != "/" {
+            for seg in normalized[1..].split('/') {
+                let chars: Vec<char> = seg.chars().collect();
+                let mut pos = 0;
+                let nodes = parse_seq(&chars, &mut pos, false).map_err(bad)?;
+                if pos != chars.len() {
+                    return Err(bad("unmatched '}'"));
+                }
+                let alts = expand_braces(&nodes).map_err(bad)?;
+                segments.push(match literal(&alts) {
+                    Some(name) => Segment::Literal(name),
+                    None => Segment::Glob(alts),
+                });
+            }
+        }
+
+        Ok(Self {
+            raw: normalized,
+            segments,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.raw
+    }
+
+    pub fn has_wildcards(&self) -> bool {
+        self.segments.iter().any(|s| matches!(s, Segment::Glob(_)))
+    }
+
+    pub fn matches(&self, path: &PathAbs) -> bool {
+        let comps: Vec<&str> = path.components().collect();
+        comps.len() == self.segments.len()
+            && self
+                .segments
+                .iter()
+                .zip(comps)
+                .all(|(seg, name)| seg.matches(name))
+    }
+
+    /// Expands the pattern against a namespace, one directory level at a
+    /// time. `list` returns the children of a directory; a `NotFound` error
+    /// counts as "no children". Only directories that can still lead to a
+    /// match are listed, and a leading run of literal segments is not
+    /// listed at all.
+    pub fn expand<F>(&self, mut list: F) -> Result<Vec<PathAbs>>
+    where
+        F: FnMut(&PathAbs) -> Result<Vec<GlobEntry>>,
+    {
+        let mut current = vec![PathAbs::root()];
+        let last = self.segments.len();
+
+        for (i, seg) in self.segments.iter().enumerate() {
+            let is_last = i + 1 == last;
+            let mut next = Vec::new();
+
+            for dir in &current {
+                match seg {
+                    // Descend without listing; the next level's listing
+                    // tells us whether it exists.
+                    Segment::Literal(name) if !is_last => next.push(dir.join(name)?),
+                    _ => {
+                        let entries = match list(dir) {
+                            Ok(e) => e,
+                            Err(HdfsError::NotFound { .. }) => continue,
+                            Err(e) => return Err(e),
+                        };
+                        for e in entries {
+                            if (is_last || e.is_dir) && seg.matches(&e.name) {
+                                next.push(dir.join(&e.name)?);
+                            }
+                        }
+                    }
+                }
+            }
+
+            if next.is_empty() {
+                return Ok(next);
+            }
+            current = next;
+        }
+
+        current.sort();
+        current.dedup();
+        Ok(current)
+    }
+}
+
+impl core::fmt::Display for PathPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+impl TryFrom<&str> for PathPattern {
+    type Error = HdfsError;
+    fn try_from(value: &str) -> Result<Self> {
+        PathPattern::new(value)
+    }
+}
+
+impl Segment {
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            Segment::Literal(l) => l == name,
+            Segment::Glob(alts) => {
+                let chars: Vec<char> = name.chars().collect();
+                alts.iter().any(|toks| match_toks(toks, &chars))
+            }
+        }
+    }
+}
+
+fn parse_seq(
+    chars: &[char],
+    pos: &mut usize,
+    in_brace: bool,
+) -> std::result::Result<Vec<Node>, &'static str> {
+    let mut out = Vec::new();
+    while *pos < chars.len() {
+        let c = chars[*pos];
+        match c {
+            ',' | '}' if in_brace => return Ok(out),
+            '}' => return Err("unmatched '}'"),
+            '\\' => {
+                *pos += 1;
+                let esc = *chars.get(*pos).ok_or("dangling escape")?;
+                out.push(Node::Tok(Tok::Char(esc)));
+                *pos += 1;
+            }
+            '*' => {
+                *pos += 1;
+                if out
+                    .last()
+                    .is_some_and(|n| matches!(n, Node::Tok(Tok::Star)))
+                {
+                    continue;
+                }
+                out.push(Node::Tok(Tok::Star));
+            }
+            '?' => {
+                *pos += 1;
+                out.push(Node::Tok(Tok::Any));
+            }
+            '[' => {
+                *pos += 1;
+                out.push(Node::Tok(parse_class(chars, pos)?));
+            }
+            '{' => {
+                *pos += 1;
+                let mut alts = Vec::new();
+                loop {
+                    alts.push(parse_seq(chars, pos, true)?);
+                    match chars.get(*pos) {
+                        Some(',') => *pos += 1,
+                        Some('}') => {
+                            *pos += 1;
+                            break;
+                        }
+                        _ => return Err("unterminated '{'"),
+                    }
+                }
+                out.push(Node::Alt(alts));
+            }
+            other => {
+                *pos += 1;
+                out.push(Node::Tok(Tok::Char(other)));
+            }
+        }
+    }
+    if in_brace {
+        return Err("unterminated '{'");
+    }
+    Ok(out)
+}
+
+fn parse_class(chars: &[char], pos: &mut usize) -> std::result::Result<Tok, &'static str> {
+    let negated = matches!(chars.get(*pos), Some('!' | '^'));
+    if negated {
+        *pos += 1;
+    }
+
+    let mut ranges = Vec::new();
+    loop {
+        let c = match chars.get(*pos) {
+            None => return Err("unterminated character class"),
+            Some(']') => {
+                *pos += 1;
+                break;
+            }
+            Some('\\') => {
+                *pos += 1;
+                *chars.get(*pos).ok_or("dangling escape")?
+            }
+            Some(c) => *c,
+        };
+        *pos += 1;
+
+        if chars.get(*pos) == Some(&'-') && chars.get(*pos + 1).is_some_and(|n| *n != ']') {
+            *pos += 1;
+            let hi = match chars[*pos] {
+                '\\' => {
+                    *pos += 1;
+                    *chars.get(*pos).ok_or("dangling escape")?
+                }
+                h => h,
+            };
+            *pos += 1;
+            if hi < c {
+                return Err("invalid character range");
+            }
+            ranges.push((c, hi));
+        } else {
+            ranges.push((c, c));
+        }
+    }
+
+    if ranges.is_empty() {
+        return Err("empty character class");
+    }
+    Ok(Tok::Class { negated, ranges })
+}
+
+/// Flattens `{..}` into plain token sequences, e.g. `a{b,c}` into `ab`, `ac`.
+fn expand_braces(nodes: &[Node]) -> std::result::Result<Vec<Vec<Tok>>, &'static str> {
+    let mut acc: Vec<Vec<Tok>> = vec![Vec::new()];
+    for node in nodes {
+        match node {
+            Node::Tok(t) => acc.iter_mut().for_each(|a| a.push(t.clone())),
+            Node::Alt(alts) => {
+                let mut options = Vec::new();
+                for alt in alts {
+                    options.extend(expand_braces(alt)?);
+                }
+                if acc.len() * options.len() > MAX_ALTERNATIVES {
+                    return Err("too many brace alternatives");
+                }
+                acc = acc
+                    .iter()
+                    .flat_map(|a| {
+                        options.iter().map(move |o| {
+                            let mut v = a.clone();
+                            v.extend(o.iter().cloned());
+                            v
+                        })
+                    })
+                    .collect();
+            }
+        }
+    }
+    Ok(acc)
+}
+
+fn literal(alts: &[Vec<Tok>]) -> Option<String> {
+    match alts {
+        [only] => only
+            .iter()
+            .map(|t| match t {
+                Tok::Char(c) => Some(*c),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+fn match_toks(toks: &[Tok], name: &[char]) -> bool {
+    let (mut t, mut n) = (0, 0);
+    // position to resume from after the last '*': (token index, name index)
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match toks.get(t) {
+            Some(Tok::Star) => {
+                backtrack = Some((t, n));
+                t += 1;
+                continue;
+            }
+            Some(tok) if tok_matches(tok, name[n]) => {
+                t += 1;
+                n += 1;
+                continue;
+            }
+            _ => {}
+        }
+        match backtrack {
+            Some((bt, bn)) => {
+                t = bt + 1;
+                n = bn + 1;
+                backtrack = Some((bt, bn + 1));
+            }
+            None => return false,
+        }
+    }
+    toks[t..].iter().all(|tok| *tok == Tok::Star)
+}
+
+fn tok_matches(tok: &Tok, c: char) -> bool {
+    match tok {
+        Tok::Char(x) => *x == c,
+        Tok::Any => true,
+        Tok::Star => false,
+        Tok::Class { negated, ranges } => {
+            ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&c)) != *negated
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn assert_err_reason(input: &str, expected_reason: &str) {
+        match PathPattern::new(input) {
+            Err(HdfsError::InvalidPath { path, reason }) => {
+                assert_eq!(path, input);
+                assert_eq!(reason, expected_reason);
+            }
+            other => panic!("expected InvalidPath error, got: {:?}", other),
+        }
+    }
+
+    fn m(pattern: &str, path: &str) -> bool {
+        PathPattern::new(pattern)
+            .unwrap()
+            .matches(&PathAbs::try_from(path).unwrap())
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(m("/logs/*", "/logs/a"));
+        assert!(!m("/logs/*", "/logs/a/b"));
+        assert!(m("/logs/2026-*", "/logs/2026-01-02"));
+        assert!(!m("/logs/2026-*", "/logs/2025-01-02"));
+        assert!(m("/a/?b", "/a/xb"));
+        assert!(!m("/a/?b", "/a/b"));
+        assert!(m("/a/*x*y", "/a/1x2x3y"));
+        assert!(!m("/a/*x*y", "/a/1x2x3"));
+        assert!(m("/a/**", "/a/anything"));
+    }
+
+    #[test]
+    fn classes_and_braces() {
+        assert!(m("/d/[a-c]1", "/d/b1"));
+        assert!(!m("/d/[a-c]1", "/d/d1"));
+        assert!(m("/d/[!a-c]1", "/d/d1"));
+        assert!(m("/d/[^a-c]1", "/d/z1"));
+        assert!(m("/d/[xyz]", "/d/y"));
+        assert!(m("/d/[a-]", "/d/-"));
+
+        assert!(m("/p/part-{0,1}*", "/p/part-0000"));
+        assert!(m("/p/part-{0,1}*", "/p/part-1234"));
+        assert!(!m("/p/part-{0,1}*", "/p/part-2000"));
+        assert!(m("/p/{a,b{c,d}}", "/p/bd"));
+        assert!(m("/p/x{,y}", "/p/x"));
+        assert!(m("/p/x{,y}", "/p/xy"));
+    }
+
+    #[test]
+    fn escapes_and_literals() {
+        assert!(m("/a/\\*", "/a/*"));
+        assert!(!m("/a/\\*", "/a/b"));
+        assert!(m("/a/[\\]]", "/a/]"));
+
+        let p = PathPattern::new("/a//b/./c").unwrap();
+        assert!(!p.has_wildcards());
+        assert_eq!(p.as_str(), "/a/b/c");
+        assert!(PathPattern::new("/a/*").unwrap().has_wildcards());
+        assert!(PathPattern::new("/a/{b,c}").unwrap().has_wildcards());
+        assert!(!PathPattern::new("/a/{b}").unwrap().has_wildcards());
+    }
+
+    #[test]
+    fn malformed_patterns() {
+        assert_err_reason("/a/[bc", "unterminated character class");
+        assert_err_reason("/a/[]", "empty character class");
+        assert_err_reason("/a/[z-a]", "invalid character range");
+        assert_err_reason("/a/{b,c", "unterminated '{'");
+        assert_err_reason("/a/{b/c}", "unterminated '{'");
+        assert_err_reason("/a/b}", "unmatched '}'");
+        assert_err_reason("/a/b\\", "dangling escape");
+        assert_err_reason(
+            "/a/{0,1,2,3}{0,1,2,3}{0,1,2,3}{0,1,2,3}{0,1,2,3}{0,1,2,3}",
+            "too many brace alternatives",
+        );
+        // normalize still applies
+        assert_err_reason("a/*", "must be absolut");
+        assert_err_reason("/a/\u{1}*", "control character not allowed");
+    }
+
+    /// A tiny namespace: directory path -> children.
+    struct Tree(BTreeMap<&'static str, Vec<GlobEntry>>);
+
+    impl Tree {
+        fn sample() -> Self {
+            Tree(BTreeMap::from([
+                ("/", vec![GlobEntry::dir("logs"), GlobEntry::dir("tmp")]),
+                (
+                    "/logs",
+                    vec![
+                        GlobEntry::dir("2026-01"),
+                        GlobEntry::dir("2026-02"),
+                        GlobEntry::dir("2025-12"),
+                        GlobEntry::file("2026-readme"),
+                    ],
+                ),
+                (
+                    "/logs/2026-01",
+                    vec![
+                        GlobEntry::file("part-0000"),
+                        GlobEntry::file("part-1000"),
+                        GlobEntry::file("part-2000"),
+                    ],
+                ),
+                ("/logs/2026-02", vec![GlobEntry::file("part-0001")]),
+                ("/logs/2025-12", vec![GlobEntry::file("part-0000")]),
+                ("/tmp", vec![]),
+            ]))
+        }
+
+        fn lister<'a>(
+            &'a self,
+            listed: &'a mut Vec<String>,
+        ) -> impl FnMut(&PathAbs) -> Result<Vec<GlobEntry>> + 'a {
+            move |dir: &PathAbs| {
+                listed.push(dir.to_string());
+                self.0
+                    .get(dir.as_str())
+                    .cloned()
+                    .ok_or_else(|| HdfsError::NotFound {
+                        path: dir.to_string(),
+                    })
+            }
+        }
+    }
+
+    fn paths(v: &[&str]) -> Vec<PathAbs> {
+        v.iter().map(|s| PathAbs::try_from(*s).unwrap()).collect()
+    }
+
+    #[test]
+    fn expand_walks_level_by_level() {
+        let tree = Tree::sample();
+        let mut listed = Vec::new();
+        let got = PathPattern::new("/logs/2026-*/part-{0,1}*")
+            .unwrap()
+            .expand(tree.lister(&mut listed))
+            .unwrap();
+
+        assert_eq!(
+            got,
+            paths(&[
+                "/logs/2026-01/part-0000",
+                "/logs/2026-01/part-1000",
+                "/logs/2026-02/part-0001",
+            ])
+        );
+        // "/" is never listed, and neither is 2025-12 or the file
+        assert_eq!(listed, vec!["/logs", "/logs/2026-01", "/logs/2026-02"]);
+    }
+
+    #[test]
+    fn expand_literals_and_misses() {
+        let tree = Tree::sample();
+
+        let mut listed = Vec::new();
+        let got = PathPattern::new("/logs/2026-01/part-0000")
+            .unwrap()
+            .expand(tree.lister(&mut listed))
+            .unwrap();
+        assert_eq!(got, paths(&["/logs/2026-01/part-0000"]));
+        assert_eq!(listed, vec!["/logs/2026-01"]);
+
+        let mut listed = Vec::new();
+        let got = PathPattern::new("/nope/*/x")
+            .unwrap()
+            .expand(tree.lister(&mut listed))
+            .unwrap();
+        assert!(got.is_empty());
+        assert_eq!(listed, vec!["/nope"]);
+
+        let mut listed = Vec::new();
+        let got = PathPattern::new("/logs/2026-*/missing")
+            .unwrap()
+            .expand(tree.lister(&mut listed))
+            .unwrap();
+        assert!(got.is_empty());
+
+        let mut listed = Vec::new();
+        let got = PathPattern::new("/")
+            .unwrap()
+            .expand(tree.lister(&mut listed))
+            .unwrap();
+        assert_eq!(got, paths(&["/"]));
+        assert!(listed.is_empty());
+    }
+
+    #[test]
+    fn expand_propagates_other_errors() {
+        let res = PathPattern::new("/a/*").unwrap().expand(|_| {
+            Err(HdfsError::State {
+                what: "list".into(),
+                details: "standby".into(),
+            })
+        });
+        assert!(matches!(res, Err(HdfsError::State { what, .. }) if what == "list"));
+    }
+}
@@ -1,4 +1,8 @@
+use crate::error::{HdfsError, Result};
+use crate::fs::write_atomic;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use uuid::Uuid;
 
 #[derive(
@@ -73,6 +77,42 @@ impl From<LeaseId> for u64 {
     }
 }
 
+#[derive(
+    Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(transparent)]
+pub struct GenerationStamp(pub u64);
+
+impl GenerationStamp {
+    /// Stamps up to here are reserved; the first real stamp is one above.
+    pub const LAST_RESERVED: GenerationStamp = GenerationStamp(1000);
+
+    pub const FIRST: GenerationStamp = GenerationStamp(Self::LAST_RESERVED.0 + 1);
+
+    /// `None` once the stamp space is used up.
+    pub fn next(self) -> Option<GenerationStamp> {
+        self.0.checked_add(1).map(GenerationStamp)
+    }
+}
+
+impl core::fmt::Display for GenerationStamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<u64> for GenerationStamp {
+    fn from(value: u64) -> Self {
+        GenerationStamp(value)
+    }
+}
+
+impl From<GenerationStamp> for u64 {
+    fn from(value: GenerationStamp) -> Self {
+        value.0
+    }
+}
+
 #[derive(
     Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, serde::Serialize, serde::Deserialize,
 )]
@@ -93,7 +133,7 @@ impl DatanodeId {
 
 impl std::str::FromStr for DatanodeId {
     type Err = uuid::Error;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
         let u = Uuid::parse_str(s.trim())?;
         Ok(DatanodeId(u))
     }
@@ -117,33 +157,433 @@ impl From<DatanodeId> for Uuid {
     }
 }
 
+/// Shared by every namenode and datanode of one cluster; set at format time.
+#[derive(
+    Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(transparent)]
+pub struct ClusterId(pub Uuid);
+
+impl ClusterId {
+    pub fn new_v4() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+impl std::str::FromStr for ClusterId {
+    type Err = uuid::Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let u = Uuid::parse_str(s.trim())?;
+        Ok(ClusterId(u))
+    }
+}
+
+impl core::fmt::Display for ClusterId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<Uuid> for ClusterId {
+    fn from(value: Uuid) -> Self {
+        ClusterId(value)
+    }
+}
+
+impl From<ClusterId> for Uuid {
+    fn from(value: ClusterId) -> Self {
+        value.0
+    }
+}
+
+/// One federated namespace, i.e. one namenode (or HA pair).
+#[derive(
+    Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(transparent)]
+pub struct NamespaceId(pub Uuid);
+
+impl NamespaceId {
+    pub fn new_v4() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+impl std::str::FromStr for NamespaceId {
+    type Err = uuid::Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let u = Uuid::parse_str(s.trim())?;
+        Ok(NamespaceId(u))
+    }
+}
+
+impl core::fmt::Display for NamespaceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<Uuid> for NamespaceId {
+    fn from(value: Uuid) -> Self {
+        NamespaceId(value)
+    }
+}
+
+impl From<NamespaceId> for Uuid {
+    fn from(value: NamespaceId) -> Self {
+        value.0
+    }
+}
+
+/// The blocks owned by one namespace. Datanodes keep one pool per namespace
+/// they serve.
+#[derive(
+    Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(transparent)]
+pub struct BlockPoolId(pub Uuid);
+
+impl BlockPoolId {
+    pub fn new_v4() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+impl std::str::FromStr for BlockPoolId {
+    type Err = uuid::Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let u = Uuid::parse_str(s.trim())?;
+        Ok(BlockPoolId(u))
+    }
+}
+
+impl core::fmt::Display for BlockPoolId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<Uuid> for BlockPoolId {
+    fn from(value: Uuid) -> Self {
+        BlockPoolId(value)
+    }
+}
+
+impl From<BlockPoolId> for Uuid {
+    fn from(value: BlockPoolId) -> Self {
+        value.0
+    }
+}
+
+/// Persisted high-water marks: every id below these may already have been
+/// handed out, so a restarted [`IdGen`] resumes here.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Watermarks {
+    pub inode: u64,
+    pub block: u64,
+    /// Missing from files written before stamps were persisted.
+    #[serde(default = "first_gen_stamp")]
+    pub gen_stamp: u64,
+}
+
+fn first_gen_stamp() -> u64 {
+    GenerationStamp::FIRST.0
+}
+
+/// Durable home for [`Watermarks`]. `persist` must not return until the
+/// values would survive a crash.
+pub trait IdStore: Send + Sync {
+    fn load(&self) -> Result<Option<Watermarks>>;
+    fn persist(&self, marks: &Watermarks) -> Result<()>;
+}
+
+/// Keeps the watermarks in a small JSON file, replaced atomically.
+#[derive(Clone, Debug)]
+pub struct FileIdStore {
+    path: PathBuf,
+}
+
+impl FileIdStore {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl IdStore for FileIdStore {
+    fn load(&self) -> Result<Option<Watermarks>> {
+        let text = match std::fs::read_to_string(&self.path) {
+            Ok(t) => t,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_str(&text)
+            .map(Some)
+            .map_err(|e| HdfsError::State {
+                what: "id_gen".into(),
+                details: format!("corrupt watermark file {}: {e}", self.path.display()),
+            })
+    }
+
+    fn persist(&self, marks: &Watermarks) -> Result<()> {
+        let json = serde_json::to_vec(marks).map_err(std::io::Error::from)?;
+        write_atomic(&self.path, &json)?;
+        Ok(())
+    }
+}
+
+/// In-memory store, for tests and tools that never restart.
+#[derive(Debug, Default)]
+pub struct MemIdStore(Mutex<Option<Watermarks>>);
+
+impl IdStore for MemIdStore {
+    fn load(&self) -> Result<Option<Watermarks>> {
+        Ok(*self.0.lock().unwrap())
+    }
+
+    fn persist(&self, marks: &Watermarks) -> Result<()> {
+        *self.0.lock().unwrap() = Some(*marks);
+        Ok(())
+    }
+}
+
+pub const DEFAULT_ID_RESERVATION: u64 = 1000;
+
+/// Allocates inode and block ids and generation stamps. With a store
+/// attached, ids are handed out from ranges whose upper bound is persisted
+/// before the first id of the range is used, so an id is never issued twice
+/// across restarts.
 pub struct IdGen {
-    inode: std::sync::atomic::AtomicU64,
-    block: std::sync::atomic::AtomicU64,
+    inode: AtomicU64,
+    block: AtomicU64,
+    gen_stamp: AtomicU64,
+    inode_limit: AtomicU64,
+    block_limit: AtomicU64,
+    gen_stamp_limit: AtomicU64,
+    durable: Option<Durable>,
+}
+
+struct Durable {
+    store: Arc<dyn IdStore>,
+    batch: u64,
+    lock: Mutex<()>,
+}
+
+#[derive(Copy, Clone)]
+enum Kind {
+    Inode,
+    Block,
+    GenStamp,
 }
 
 impl IdGen {
+    /// Purely in-memory generator; ids start over after a restart, and
+    /// generation stamps at [`GenerationStamp::FIRST`].
     pub fn new(start_inode: u64, start_block: u64) -> Self {
         Self {
             inode: AtomicU64::new(start_inode),
             block: AtomicU64::new(start_block),
+            gen_stamp: AtomicU64::new(first_gen_stamp()),
+            inode_limit: AtomicU64::new(u64::MAX),
+            block_limit: AtomicU64::new(u64::MAX),
+            gen_stamp_limit: AtomicU64::new(u64::MAX),
+            durable: None,
+        }
+    }
+
+    /// Resumes from the persisted watermarks, or from 1 and
+    /// [`GenerationStamp::FIRST`] on a fresh store.
+    pub fn open(store: Arc<dyn IdStore>, batch: u64) -> Result<Self> {
+        let marks = store.load()?.unwrap_or(Watermarks {
+            inode: 1,
+            block: 1,
+            gen_stamp: first_gen_stamp(),
+        });
+        Self::with_store(store, batch, marks)
+    }
+
+    /// Starts at explicit values, e.g. seeded from a checkpoint plus edits.
+    /// Starting below a persisted watermark could reissue ids, so that is
+    /// rejected. Generation stamps resume from the store as with
+    /// [`IdGen::open`]; stamps seen since are passed to
+    /// [`IdGen::observe_gen_stamp`].
+    pub fn open_at(
+        store: Arc<dyn IdStore>,
+        batch: u64,
+        start_inode: u64,
+        start_block: u64,
+    ) -> Result<Self> {
+        let persisted = store.load()?;
+        if let Some(marks) = persisted {
+            if start_inode < marks.inode {
+                return Err(HdfsError::State {
+                    what: "id_gen".into(),
+                    details: format!(
+                        "inode start {start_inode} is below persisted watermark {}",
+                        marks.inode
+                    ),
+                });
+            }
+            if start_block < marks.block {
+                return Err(HdfsError::State {
+                    what: "id_gen".into(),
+                    details: format!(
+                        "block start {start_block} is below persisted watermark {}",
+                        marks.block
+                    ),
+                });
+            }
         }
+        Self::with_store(
+            store,
+            batch,
+            Watermarks {
+                inode: start_inode,
+                block: start_block,
+                gen_stamp: persisted.map_or(first_gen_stamp(), |m| m.gen_stamp),
+            },
+        )
     }
+
+    fn with_store(store: Arc<dyn IdStore>, batch: u64, start: Watermarks) -> Result<Self> {
+        // Nothing is reserved yet; the first allocation of each kind persists.
+        Ok(Self {
+            inode: AtomicU64::new(start.inode),
+            block: AtomicU64::new(start.block),
+            gen_stamp: AtomicU64::new(start.gen_stamp),
+            inode_limit: AtomicU64::new(start.inode),
+            block_limit: AtomicU64::new(start.block),
+            gen_stamp_limit: AtomicU64::new(start.gen_stamp),
+            durable: Some(Durable {
+                store,
+                batch: batch.max(1),
+                lock: Mutex::new(()),
+            }),
+        })
+    }
+
+    /// # Panics
+    /// If a new range cannot be persisted; use [`IdGen::try_next_inode`] to
+    /// handle that instead.
     pub fn next_inode(&self) -> INodeId {
-        let id = self.inode.fetch_add(1, Ordering::Relaxed);
-        INodeId(id)
+        self.try_next_inode().expect("inode id reservation failed")
     }
+
+    /// # Panics
+    /// If a new range cannot be persisted; use [`IdGen::try_next_block`] to
+    /// handle that instead.
     pub fn next_block(&self) -> BlockId {
-        let id = self.block.fetch_add(1, Ordering::Relaxed);
-        BlockId(id)
+        self.try_next_block().expect("block id reservation failed")
+    }
+
+    pub fn try_next_inode(&self) -> Result<INodeId> {
+        self.next(Kind::Inode).map(INodeId)
+    }
+
+    pub fn try_next_block(&self) -> Result<BlockId> {
+        self.next(Kind::Block).map(BlockId)
+    }
+
+    /// A new stamp, taken for every block creation, append and pipeline
+    /// recovery.
+    ///
+    /// # Panics
+    /// If a new range cannot be persisted; use
+    /// [`IdGen::try_next_gen_stamp`] to handle that instead.
+    pub fn next_gen_stamp(&self) -> GenerationStamp {
+        self.try_next_gen_stamp()
+            .expect("generation stamp reservation failed")
+    }
+
+    pub fn try_next_gen_stamp(&self) -> Result<GenerationStamp> {
+        self.next(Kind::GenStamp).map(GenerationStamp)
     }
 
     pub fn peek_inode(&self) -> u64 {
-        self.inode.load(Ordering::Relaxed)
+        self.inode.load(Ordering::Acquire)
     }
 
     pub fn peek_block(&self) -> u64 {
-        self.block.load(Ordering::Relaxed)
+        self.block.load(Ordering::Acquire)
+    }
+
+    pub fn peek_gen_stamp(&self) -> GenerationStamp {
+        GenerationStamp(self.gen_stamp.load(Ordering::Acquire))
+    }
+
+    /// Makes sure future stamps are above one seen elsewhere, e.g. while
+    /// replaying edits or reading block reports.
+    pub fn observe_gen_stamp(&self, seen: GenerationStamp) {
+        self.gen_stamp
+            .fetch_max(seen.0.saturating_add(1), Ordering::AcqRel);
+    }
+
+    /// The persisted upper bounds, if a store is attached.
+    pub fn watermarks(&self) -> Option<Watermarks> {
+        self.durable.as_ref().map(|_| self.limits())
+    }
+
+    fn limits(&self) -> Watermarks {
+        Watermarks {
+            inode: self.inode_limit.load(Ordering::Acquire),
+            block: self.block_limit.load(Ordering::Acquire),
+            gen_stamp: self.gen_stamp_limit.load(Ordering::Acquire),
+        }
+    }
+
+    fn counters(&self, kind: Kind) -> (&AtomicU64, &AtomicU64) {
+        match kind {
+            Kind::Inode => (&self.inode, &self.inode_limit),
+            Kind::Block => (&self.block, &self.block_limit),
+            Kind::GenStamp => (&self.gen_stamp, &self.gen_stamp_limit),
+        }
+    }
+
+    fn next(&self, kind: Kind) -> Result<u64> {
+        let (next, limit) = self.counters(kind);
+        loop {
+            let id = next.load(Ordering::Acquire);
+            if id == u64::MAX {
+                return Err(HdfsError::State {
+                    what: "id_gen".into(),
+                    details: "id space exhausted".into(),
+                });
+            }
+            if id < limit.load(Ordering::Acquire) {
+                if next
+                    .compare_exchange(id, id + 1, Ordering::AcqRel, Ordering::Acquire)
+                    .is_ok()
+                {
+                    return Ok(id);
+                }
+                continue;
+            }
+            self.reserve(kind, id)?;
+        }
+    }
+
+    /// Persists a new range starting at `from` unless another thread already did.
+    fn reserve(&self, kind: Kind, from: u64) -> Result<()> {
+        let Some(d) = &self.durable else {
+            return Ok(());
+        };
+        let _guard = d.lock.lock().unwrap();
+        let (_, limit) = self.counters(kind);
+        if from < limit.load(Ordering::Acquire) {
+            return Ok(());
+        }
+
+        let new_limit = from.saturating_add(d.batch);
+        let mut marks = self.limits();
+        match kind {
+            Kind::Inode => marks.inode = new_limit,
+            Kind::Block => marks.block = new_limit,
+            Kind::GenStamp => marks.gen_stamp = new_limit,
+        }
+        d.store.persist(&marks)?;
+        limit.store(new_limit, Ordering::Release);
+        Ok(())
     }
 }
 
@@ -171,11 +611,6 @@ mod tests {
         id: LeaseId,
     }
 
-    #[derive(Serialize, Deserialize, Debug, PartialEq)]
-    struct HolderD {
-        id: DatanodeId,
-    }
-
     #[test]
     fn display_and_debug() {
         //block id
@@ -345,6 +780,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::clone_on_copy)]
     fn copy_and_clone_behave() {
         //block id
         let a = BlockId(5);
@@ -482,6 +918,35 @@ mod tests {
         assert_eq!(back, h);
     }
 
+    #[test]
+    fn federation_ids_parse_display_and_serde() {
+        let c = ClusterId::from_str(&format!(" {FIXED}\n")).unwrap();
+        let n = NamespaceId::from_str(FIXED).unwrap();
+        let b = BlockPoolId::from_str(FIXED).unwrap();
+        assert_eq!(c.to_string(), FIXED);
+        assert_eq!(Uuid::from(n), Uuid::from(b));
+        assert!(BlockPoolId::from_str("BP-1-10.0.0.1-1700000000000").is_err());
+        assert_ne!(ClusterId::new_v4(), ClusterId::new_v4());
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Holder {
+            cluster: ClusterId,
+            ns: NamespaceId,
+            pool: BlockPoolId,
+        }
+        let h = Holder {
+            cluster: c,
+            ns: n,
+            pool: b,
+        };
+        let json = serde_json::to_string(&h).unwrap();
+        assert_eq!(
+            json,
+            format!(r#"{{"cluster":"{FIXED}","ns":"{FIXED}","pool":"{FIXED}"}}"#)
+        );
+        assert_eq!(serde_json::from_str::<Holder>(&json).unwrap(), h);
+    }
+
     #[test]
     fn conversions_to_from_uuid() {
         let u = uuid::Uuid::new_v4();
@@ -489,4 +954,223 @@ mod tests {
         let u2: uuid::Uuid = d.into();
         assert_eq!(u, u2);
     }
+
+    struct FailingStore;
+
+    impl IdStore for FailingStore {
+        fn load(&self) -> Result<Option<Watermarks>> {
+            Ok(None)
+        }
+
+        fn persist(&self, _: &Watermarks) -> Result<()> {
+            Err(std::io::Error::other("disk full").into())
+        }
+    }
+
+    #[test]
+    fn id_gen_persists_before_handing_out() {
+        let store = Arc::new(MemIdStore::default());
+        let idgen = IdGen::open(store.clone(), 10).unwrap();
+        assert_eq!(store.load().unwrap(), None);
+
+        assert_eq!(idgen.next_inode(), INodeId(1));
+        assert_eq!(
+            store.load().unwrap(),
+            Some(Watermarks {
+                inode: 11,
+                block: 1,
+                gen_stamp: 1001,
+            })
+        );
+
+        for _ in 0..9 {
+            idgen.next_inode();
+        }
+        assert_eq!(store.load().unwrap().unwrap().inode, 11);
+        assert_eq!(idgen.next_inode(), INodeId(11));
+        assert_eq!(store.load().unwrap().unwrap().inode, 21);
+
+        assert_eq!(idgen.next_block(), BlockId(1));
+        assert_eq!(idgen.watermarks(), store.load().unwrap());
+    }
+
+    #[test]
+    fn id_gen_never_reissues_after_restart() {
+        let store = Arc::new(MemIdStore::default());
+        let mut seen = HashSet::new();
+        for _ in 0..3 {
+            // crash after a few allocations, without any clean shutdown
+            let idgen = IdGen::open(store.clone(), 4).unwrap();
+            for _ in 0..6 {
+                assert!(seen.insert(idgen.next_block()));
+            }
+        }
+        assert_eq!(seen.len(), 18);
+    }
+
+    #[test]
+    fn id_gen_open_at_rejects_start_below_watermark() {
+        let store = Arc::new(MemIdStore::default());
+        {
+            let idgen = IdGen::open_at(store.clone(), 100, 500, 7).unwrap();
+            assert_eq!(idgen.next_inode(), INodeId(500));
+            assert_eq!(idgen.next_block(), BlockId(7));
+        }
+
+        match IdGen::open_at(store.clone(), 100, 550, 1000) {
+            Err(HdfsError::State { what, details }) => {
+                assert_eq!(what, "id_gen");
+                assert_eq!(details, "inode start 550 is below persisted watermark 600");
+            }
+            Err(e) => panic!("expected State error, got: {e}"),
+            Ok(_) => panic!("expected State error"),
+        }
+        assert!(matches!(
+            IdGen::open_at(store.clone(), 100, 600, 50),
+            Err(HdfsError::State { what, .. }) if what == "id_gen"
+        ));
+
+        let idgen = IdGen::open_at(store, 100, 600, 107).unwrap();
+        assert_eq!(idgen.next_inode(), INodeId(600));
+    }
+
+    #[test]
+    fn id_gen_concurrent_allocations_are_unique() {
+        let store = Arc::new(MemIdStore::default());
+        let idgen = Arc::new(IdGen::open(store.clone(), 7).unwrap());
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let g = idgen.clone();
+                std::thread::spawn(move || (0..500).map(|_| g.next_inode()).collect::<Vec<_>>())
+            })
+            .collect();
+
+        let mut all = HashSet::new();
+        for h in handles {
+            for id in h.join().unwrap() {
+                assert!(all.insert(id));
+            }
+        }
+        assert_eq!(all.len(), 4000);
+        assert!(store.load().unwrap().unwrap().inode > 4000);
+    }
+
+    #[test]
+    fn id_gen_surfaces_store_failures() {
+        let idgen = IdGen::open(Arc::new(FailingStore), 10).unwrap();
+        assert!(matches!(idgen.try_next_inode(), Err(HdfsError::Io(_))));
+        assert_eq!(idgen.peek_inode(), 1);
+    }
+
+    #[test]
+    fn file_id_store_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("hdfs-ids-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let store = FileIdStore::new(dir.join("id_watermarks.json"));
+        assert_eq!(store.load().unwrap(), None);
+
+        let marks = Watermarks {
+            inode: 16386,
+            block: 1 << 30,
+            gen_stamp: 5000,
+        };
+        store.persist(&marks).unwrap();
+        assert_eq!(store.load().unwrap(), Some(marks));
+
+        // written before generation stamps were persisted
+        std::fs::write(
+            dir.join("id_watermarks.json"),
+            r#"{"inode":16386,"block":1073741824}"#,
+        )
+        .unwrap();
+        assert_eq!(store.load().unwrap().unwrap().gen_stamp, 1001);
+
+        std::fs::write(dir.join("id_watermarks.json"), "{garbage").unwrap();
+        assert!(matches!(
+            store.load(),
+            Err(HdfsError::State { what, .. }) if what == "id_gen"
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn generation_stamp_basics() {
+        let gs = GenerationStamp(1001);
+        assert_eq!(gs.to_string(), "1001");
+        assert_eq!(format!("{:?}", gs), "GenerationStamp(1001)");
+        assert_eq!(gs.next(), Some(GenerationStamp(1002)));
+        assert_eq!(
+            GenerationStamp::LAST_RESERVED.next(),
+            Some(GenerationStamp::FIRST)
+        );
+        assert_eq!(GenerationStamp(u64::MAX).next(), None);
+        assert!(GenerationStamp(7) < GenerationStamp(8));
+        assert_eq!(u64::from(GenerationStamp::from(9)), 9);
+        assert_eq!(serde_json::to_string(&gs).unwrap(), "1001");
+        assert_eq!(serde_json::from_str::<GenerationStamp>("1001").unwrap(), gs);
+    }
+
+    #[test]
+    fn gen_stamps_are_monotonic() {
+        let g = IdGen::new(1, 1);
+        assert_eq!(g.next_gen_stamp(), GenerationStamp(1001));
+        assert_eq!(g.next_gen_stamp(), GenerationStamp(1002));
+        assert_eq!(g.peek_gen_stamp(), GenerationStamp(1003));
+
+        g.observe_gen_stamp(GenerationStamp(2000));
+        assert_eq!(g.next_gen_stamp(), GenerationStamp(2001));
+        // observing an older stamp never moves it back
+        g.observe_gen_stamp(GenerationStamp(10));
+        assert_eq!(g.next_gen_stamp(), GenerationStamp(2002));
+
+        g.observe_gen_stamp(GenerationStamp(u64::MAX));
+        assert!(matches!(
+            g.try_next_gen_stamp(),
+            Err(HdfsError::State { what, .. }) if what == "id_gen"
+        ));
+    }
+
+    #[test]
+    fn gen_stamps_survive_restarts() {
+        let store = Arc::new(MemIdStore::default());
+        let mut seen = BTreeSet::new();
+        for _ in 0..3 {
+            // crash after a few stamps, without any clean shutdown
+            let idgen = IdGen::open(store.clone(), 4).unwrap();
+            for _ in 0..6 {
+                assert!(seen.insert(idgen.next_gen_stamp()));
+            }
+        }
+        assert_eq!(seen.len(), 18);
+        assert_eq!(seen.first(), Some(&GenerationStamp::FIRST));
+
+        // a stamp observed past the reserved range is reserved beyond too
+        let idgen = IdGen::open(store.clone(), 4).unwrap();
+        idgen.observe_gen_stamp(GenerationStamp(9000));
+        assert_eq!(idgen.next_gen_stamp(), GenerationStamp(9001));
+        assert_eq!(store.load().unwrap().unwrap().gen_stamp, 9005);
+        let idgen = IdGen::open_at(store, 4, 1, 1).unwrap();
+        assert_eq!(idgen.next_gen_stamp(), GenerationStamp(9005));
+    }
+
+    #[test]
+    fn gen_stamps_concurrent_unique() {
+        let g = Arc::new(IdGen::open(Arc::new(MemIdStore::default()), 16).unwrap());
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let g = g.clone();
+                std::thread::spawn(move || {
+                    (0..1000).map(|_| g.next_gen_stamp()).collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let mut all = HashSet::new();
+        for h in handles {
+            for gs in h.join().unwrap() {
+                assert!(all.insert(gs));
+            }
+        }
+        assert_eq!(g.peek_gen_stamp(), GenerationStamp(5001));
+    }
 }
@@ -1,7 +1,11 @@
+pub mod checksum;
 pub mod clock;
 pub mod config;
 pub mod consts;
 pub mod error;
+mod fs;
+pub mod glob;
 pub mod ids;
 pub mod path;
+pub mod storage;
 pub mod types;
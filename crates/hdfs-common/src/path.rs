@@ -1,10 +1,42 @@
 use crate::error::HdfsError;
 use crate::error::Result;
+use crate::ids::INodeId;
 use serde::{Deserialize, Serialize};
+use unicode_normalization::{UnicodeNormalization, is_nfc};
 
 const MAX_NAME_LEN: usize = 255;
 const MAX_PATH_LEN: usize = 4096;
 
+/// Top-level directory holding the virtual namespaces below.
+pub const RESERVED_DIR: &str = ".reserved";
+/// `/.reserved/.inodes/<id>` addresses a file by inode id.
+pub const INODES_DIR: &str = ".inodes";
+/// `/.reserved/raw/<path>` bypasses transparent encryption.
+pub const RAW_DIR: &str = "raw";
+/// `<dir>/.snapshot/<name>` is a read-only snapshot of a directory.
+pub const SNAPSHOT_DIR: &str = ".snapshot";
+
+/// A path with its reserved components interpreted. Remainders are kept as
+/// absolute paths relative to what they hang off; `/` means "nothing more".
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ResolvedPath {
+    Plain(PathAbs),
+    /// `/.reserved/.inodes/<id>/<rest>`
+    Inode {
+        id: INodeId,
+        rest: PathAbs,
+    },
+    /// `/.reserved/raw/<path>`
+    Raw(PathAbs),
+    /// `<dir>/.snapshot[/<name>/<rest>]`; without a name it lists the
+    /// snapshots of `dir`.
+    Snapshot {
+        dir: PathAbs,
+        name: Option<String>,
+        rest: PathAbs,
+    },
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
 #[serde(transparent)]
 pub struct PathAbs(String);
@@ -51,64 +83,350 @@ impl PathAbs {
             self.0.rsplit('/').next().unwrap()
         }
     }
+
+    /// Like `try_from`, but under `policy` instead of the default one.
+    pub fn parse_with(input: &str, policy: &NamePolicy) -> Result<PathAbs> {
+        Ok(PathAbs(policy.normalize(input)?))
+    }
+
+    pub fn root() -> PathAbs {
+        PathAbs("/".to_string())
+    }
+
+    /// `None` for the root.
+    pub fn parent(&self) -> Option<PathAbs> {
+        parent_str(&self.0).map(|p| PathAbs(p.to_string()))
+    }
+
+    /// Appends a relative path and normalizes the result, so `..` and the
+    /// length limits behave exactly as in [`normalize`].
+    pub fn join(&self, rel: &str) -> Result<PathAbs> {
+        if rel.starts_with('/') {
+            return Err(HdfsError::InvalidPath {
+                path: rel.into(),
+                reason: "join expects a relative path".into(),
+            });
+        }
+        let joined = if self.is_root() {
+            format!("/{rel}")
+        } else {
+            format!("{}/{rel}", self.0)
+        };
+        PathAbs::try_from(joined.as_str())
+    }
+
+    /// Path segments from the root down; empty for the root itself.
+    pub fn components(&self) -> Components<'_> {
+        Components {
+            inner: (!self.is_root()).then(|| self.0[1..].split('/')),
+        }
+    }
+
+    /// Proper ancestors from the parent up to the root.
+    pub fn ancestors(&self) -> Ancestors<'_> {
+        Ancestors {
+            next: parent_str(&self.0),
+        }
+    }
+
+    /// Number of components; 0 for the root.
+    pub fn depth(&self) -> usize {
+        if self.is_root() {
+            0
+        } else {
+            self.0.bytes().filter(|b| *b == b'/').count()
+        }
+    }
+
+    /// True if `self` is a proper ancestor of `other`.
+    pub fn is_ancestor_of(&self, other: &PathAbs) -> bool {
+        self != other && other.strip_prefix(self).is_some()
+    }
+
+    /// The part of `self` below `base`, without a leading slash; `""` when
+    /// both are equal and `None` when `base` is not an ancestor.
+    pub fn strip_prefix(&self, base: &PathAbs) -> Option<&str> {
+        if base.is_root() {
+            return Some(&self.0[1..]);
+        }
+        match self.0.strip_prefix(base.as_str())? {
+            "" => Some(""),
+            rest => rest.strip_prefix('/'),
+        }
+    }
+
+    /// A relative path leading from `base` to `self`, using `..` where
+    /// needed; `"."` when both are equal.
+    pub fn relative_to(&self, base: &PathAbs) -> String {
+        let common = self
+            .components()
+            .zip(base.components())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let ups = base.depth() - common;
+        let parts: Vec<&str> = std::iter::repeat_n("..", ups)
+            .chain(self.components().skip(common))
+            .collect();
+        if parts.is_empty() {
+            ".".to_string()
+        } else {
+            parts.join("/")
+        }
+    }
+}
+
+impl PathAbs {
+    pub fn is_reserved(&self) -> bool {
+        self.components().next() == Some(RESERVED_DIR)
+    }
+
+    /// Interprets `/.reserved/...` prefixes and `.snapshot` segments.
+    pub fn resolve(&self) -> Result<ResolvedPath> {
+        let bad = |reason: &'static str| HdfsError::InvalidPath {
+            path: self.0.clone(),
+            reason: reason.into(),
+        };
+        let comps: Vec<&str> = self.components().collect();
+
+        if comps.first() == Some(&RESERVED_DIR) {
+            return match comps.get(1) {
+                Some(&INODES_DIR) => {
+                    let id = comps
+                        .get(2)
+                        .and_then(|s| s.parse::<u64>().ok())
+                        .ok_or_else(|| bad("invalid inode id"))?;
+                    Ok(ResolvedPath::Inode {
+                        id: INodeId(id),
+                        rest: from_components(&comps[3..]),
+                    })
+                }
+                Some(&RAW_DIR) => Ok(ResolvedPath::Raw(from_components(&comps[2..]))),
+                _ => Err(bad("unknown reserved path")),
+            };
+        }
+
+        let mut snaps = comps
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| **c == SNAPSHOT_DIR)
+            .map(|(i, _)| i);
+        let Some(i) = snaps.next() else {
+            return Ok(ResolvedPath::Plain(self.clone()));
+        };
+        if snaps.next().is_some() {
+            return Err(bad("nested .snapshot"));
+        }
+        Ok(ResolvedPath::Snapshot {
+            dir: from_components(&comps[..i]),
+            name: comps.get(i + 1).map(|s| s.to_string()),
+            rest: from_components(comps.get(i + 2..).unwrap_or_default()),
+        })
+    }
+
+    /// Fails if creating an ordinary entry at `self` would shadow a reserved
+    /// name: `.reserved` directly under the root, or `.snapshot` anywhere.
+    pub fn check_creatable(&self) -> Result<()> {
+        if self.is_reserved() || self.components().any(|c| c == SNAPSHOT_DIR) {
+            return Err(HdfsError::InvalidPath {
+                path: self.0.clone(),
+                reason: "reserved name".into(),
+            });
+        }
+        Ok(())
+    }
+}
+
+fn from_components(comps: &[&str]) -> PathAbs {
+    // every component came from a normalized path
+    PathAbs(format!("/{}", comps.join("/")))
+}
+
+fn parent_str(p: &str) -> Option<&str> {
+    if p == "/" {
+        return None;
+    }
+    match p.rfind('/') {
+        Some(0) => Some("/"),
+        Some(i) => Some(&p[..i]),
+        None => None,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Components<'a> {
+    inner: Option<std::str::Split<'a, char>>,
+}
+
+impl<'a> Iterator for Components<'a> {
+    type Item = &'a str;
+    fn next(&mut self) -> Option<&'a str> {
+        self.inner.as_mut()?.next()
+    }
+}
+
+impl DoubleEndedIterator for Components<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.as_mut()?.next_back()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Ancestors<'a> {
+    next: Option<&'a str>,
+}
+
+impl Iterator for Ancestors<'_> {
+    type Item = PathAbs;
+    fn next(&mut self) -> Option<PathAbs> {
+        let cur = self.next?;
+        self.next = parent_str(cur);
+        // prefixes of a normalized path are normalized
+        Some(PathAbs(cur.to_string()))
+    }
 }
 
 fn has_forbidden(ch: char) -> bool {
     ch == '\0' || (ch.is_control() || ch == '\u{F7}')
 }
 
+/// Normalizes with [`NamePolicy::DEFAULT`].
 pub fn normalize(input: &str) -> Result<String> {
-    if !input.starts_with('/') {
-        return Err(HdfsError::InvalidPath {
-            path: input.into(),
-            reason: "must be absolut",
-        });
-    }
+    NamePolicy::DEFAULT.normalize(input)
+}
+
+/// Rules on names beyond what every path must satisfy. The default adds
+/// none: no NFC, no reserved characters and no depth limit.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NamePolicy {
+    /// Store names in Unicode NFC, so NFC and NFD spellings are one file.
+    pub nfc: bool,
+    /// Characters rejected in names, e.g. ":" for Windows clients.
+    pub reserved_chars: String,
+    /// Most components a path may have, or 0 for no limit.
+    pub max_depth: usize,
+    /// Refuse names that equal an existing sibling after case folding.
+    pub case_insensitive: bool,
+}
 
-    if input.chars().any(has_forbidden) {
-        return Err(HdfsError::InvalidPath {
-            path: input.into(),
-            reason: "control character not allowed",
-        });
+impl Default for NamePolicy {
+    fn default() -> Self {
+        Self::DEFAULT
     }
+}
 
-    let mut stack: Vec<&str> = Vec::new();
+impl NamePolicy {
+    pub const DEFAULT: NamePolicy = NamePolicy {
+        nfc: false,
+        reserved_chars: String::new(),
+        max_depth: 0,
+        case_insensitive: false,
+    };
 
-    for seg in input.split('/') {
-        match seg {
-            "" | "." => continue,
-            ".." => {
-                stack.pop();
-            }
-            other => {
-                if other.as_bytes().len() > MAX_NAME_LEN {
-                    return Err(HdfsError::InvalidPath {
-                        path: input.into(),
-                        reason: "segement too long",
-                    });
+    pub fn normalize(&self, input: &str) -> Result<String> {
+        if !input.starts_with('/') {
+            return Err(HdfsError::InvalidPath {
+                path: input.into(),
+                reason: "must be absolut".into(),
+            });
+        }
+
+        if input.chars().any(has_forbidden) {
+            return Err(HdfsError::InvalidPath {
+                path: input.into(),
+                reason: "control character not allowed".into(),
+            });
+        }
+
+        if input.chars().any(|c| self.reserved_chars.contains(c)) {
+            return Err(HdfsError::InvalidPath {
+                path: input.into(),
+                reason: "reserved character not allowed".into(),
+            });
+        }
+
+        let composed: String;
+        let text = if self.nfc && !is_nfc(input) {
+            composed = input.nfc().collect();
+            composed.as_str()
+        } else {
+            input
+        };
+
+        let mut stack: Vec<&str> = Vec::new();
+
+        for seg in text.split('/') {
+            match seg {
+                "" | "." => continue,
+                ".." => {
+                    stack.pop();
+                }
+                other => {
+                    if other.len() > MAX_NAME_LEN {
+                        return Err(HdfsError::InvalidPath {
+                            path: input.into(),
+                            reason: "segement too long".into(),
+                        });
+                    }
+                    stack.push(other);
                 }
-                stack.push(other);
             }
         }
-    }
 
-    let out = if stack.is_empty() {
-        "/".to_string()
-    } else {
-        let mut s = String::with_capacity(input.len().min(MAX_PATH_LEN));
-        s.push('/');
-        s.push_str(&stack.join("/"));
-        s
-    };
+        let out = if stack.is_empty() {
+            "/".to_string()
+        } else {
+            let mut s = String::with_capacity(text.len().min(MAX_PATH_LEN));
+            s.push('/');
+            s.push_str(&stack.join("/"));
+            s
+        };
+
+        if out.len() > MAX_PATH_LEN {
+            return Err(HdfsError::InvalidPath {
+                path: input.into(),
+                reason: "path too long".into(),
+            });
+        }
 
-    if out.as_bytes().len() > MAX_PATH_LEN {
-        return Err(HdfsError::InvalidPath {
-            path: input.into(),
-            reason: "path too long",
-        });
+        if self.max_depth != 0 && stack.len() > self.max_depth {
+            return Err(HdfsError::InvalidPath {
+                path: input.into(),
+                reason: "path too deep".into(),
+            });
+        }
+
+        Ok(out)
     }
 
-    Ok(out)
+    /// The form two names are compared in when `case_insensitive` is set.
+    pub fn fold_case(&self, name: &str) -> String {
+        name.to_lowercase().nfc().collect()
+    }
+
+    /// Checks a name about to be created against the existing entries of its
+    /// parent directory.
+    pub fn check_collision<'a>(
+        &self,
+        path: &PathAbs,
+        siblings: impl IntoIterator<Item = &'a str>,
+    ) -> Result<()> {
+        if !self.case_insensitive || path.is_root() {
+            return Ok(());
+        }
+        let name = path.name();
+        let key = self.fold_case(name);
+        if siblings
+            .into_iter()
+            .any(|s| s != name && self.fold_case(s) == key)
+        {
+            return Err(HdfsError::InvalidPath {
+                path: path.to_string(),
+                reason: "differs from an existing name only by case".into(),
+            });
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -160,10 +478,7 @@ mod tests {
         assert_err_reason(&input, "segement too long");
 
         let repeats = MAX_PATH_LEN / 2 + 16;
-        let input = format!(
-            "/{}",
-            std::iter::repeat("a/").take(repeats).collect::<String>()
-        );
+        let input = format!("/{}", "a/".repeat(repeats));
         assert_err_reason(&input, "path too long");
     }
 
@@ -204,4 +519,285 @@ mod tests {
         let n2 = normalize(&n1).unwrap();
         assert_eq!(n1, n2);
     }
+
+    fn pa(s: &str) -> PathAbs {
+        PathAbs::try_from(s).unwrap()
+    }
+
+    #[test]
+    fn parent_and_root() {
+        assert_eq!(PathAbs::root(), pa("/"));
+        assert_eq!(pa("/").parent(), None);
+        assert_eq!(pa("/a").parent(), Some(pa("/")));
+        assert_eq!(pa("/a/b/c").parent(), Some(pa("/a/b")));
+    }
+
+    #[test]
+    fn join_normalizes() {
+        assert_eq!(pa("/").join("a").unwrap(), pa("/a"));
+        assert_eq!(pa("/a").join("b/c").unwrap(), pa("/a/b/c"));
+        assert_eq!(pa("/a").join("./b//c/").unwrap(), pa("/a/b/c"));
+        assert_eq!(pa("/a/b").join("../c").unwrap(), pa("/a/c"));
+        assert_eq!(pa("/a").join("../../..").unwrap(), pa("/"));
+        assert_eq!(pa("/a").join("").unwrap(), pa("/a"));
+
+        match pa("/a").join("/b") {
+            Err(HdfsError::InvalidPath { path, reason }) => {
+                assert_eq!(path, "/b");
+                assert_eq!(reason, "join expects a relative path");
+            }
+            other => panic!("expected InvalidPath error, got: {:?}", other),
+        }
+        assert!(matches!(
+            pa("/a").join("b\u{1}"),
+            Err(HdfsError::InvalidPath { reason, .. }) if reason == "control character not allowed"
+        ));
+        assert!(matches!(
+            pa("/a").join(&"x".repeat(MAX_NAME_LEN + 1)),
+            Err(HdfsError::InvalidPath { reason, .. }) if reason == "segement too long"
+        ));
+    }
+
+    #[test]
+    fn join_respects_path_len_limit() {
+        let mut p = PathAbs::root();
+        let seg = "s".repeat(MAX_NAME_LEN);
+        let err = loop {
+            match p.join(&seg) {
+                Ok(next) => p = next,
+                Err(e) => break e,
+            }
+        };
+        assert!(p.len() <= MAX_PATH_LEN);
+        assert!(matches!(
+            err,
+            HdfsError::InvalidPath { reason, .. } if reason == "path too long"
+        ));
+    }
+
+    #[test]
+    fn components_and_depth() {
+        assert_eq!(pa("/").components().count(), 0);
+        assert_eq!(pa("/").depth(), 0);
+
+        let p = pa("/a/bb/ccc");
+        assert_eq!(p.components().collect::<Vec<_>>(), vec!["a", "bb", "ccc"]);
+        assert_eq!(
+            p.components().rev().collect::<Vec<_>>(),
+            vec!["ccc", "bb", "a"]
+        );
+        assert_eq!(p.depth(), 3);
+        assert_eq!(p.components().next_back(), Some(p.name()));
+    }
+
+    #[test]
+    fn ancestors_walk_to_root() {
+        let got: Vec<PathAbs> = pa("/a/b/c").ancestors().collect();
+        assert_eq!(got, vec![pa("/a/b"), pa("/a"), pa("/")]);
+        assert_eq!(pa("/").ancestors().count(), 0);
+        assert_eq!(pa("/a").ancestors().collect::<Vec<_>>(), vec![pa("/")]);
+    }
+
+    #[test]
+    fn ancestor_checks() {
+        assert!(pa("/").is_ancestor_of(&pa("/a")));
+        assert!(pa("/a").is_ancestor_of(&pa("/a/b/c")));
+        assert!(!pa("/a").is_ancestor_of(&pa("/a")));
+        assert!(!pa("/a").is_ancestor_of(&pa("/ab")));
+        assert!(!pa("/a/b").is_ancestor_of(&pa("/a")));
+
+        for p in pa("/x/y/z").ancestors() {
+            assert!(p.is_ancestor_of(&pa("/x/y/z")));
+        }
+    }
+
+    #[test]
+    fn strip_prefix_and_relative_to() {
+        assert_eq!(pa("/a/b/c").strip_prefix(&pa("/a")), Some("b/c"));
+        assert_eq!(pa("/a/b").strip_prefix(&pa("/")), Some("a/b"));
+        assert_eq!(pa("/a").strip_prefix(&pa("/a")), Some(""));
+        assert_eq!(pa("/").strip_prefix(&pa("/")), Some(""));
+        assert_eq!(pa("/ab").strip_prefix(&pa("/a")), None);
+        assert_eq!(pa("/a").strip_prefix(&pa("/a/b")), None);
+
+        assert_eq!(pa("/a/b/c").relative_to(&pa("/a")), "b/c");
+        assert_eq!(pa("/a").relative_to(&pa("/a/b/c")), "../..");
+        assert_eq!(pa("/a/x").relative_to(&pa("/a/b/c")), "../../x");
+        assert_eq!(pa("/a").relative_to(&pa("/a")), ".");
+        assert_eq!(pa("/").relative_to(&pa("/q")), "..");
+
+        // relative_to and join are inverses
+        let base = pa("/data/2026/01");
+        let target = pa("/data/logs/x");
+        assert_eq!(base.join(&target.relative_to(&base)).unwrap(), target);
+    }
+
+    #[test]
+    fn resolve_reserved_paths() {
+        assert_eq!(
+            pa("/a/b").resolve().unwrap(),
+            ResolvedPath::Plain(pa("/a/b"))
+        );
+        assert_eq!(
+            pa("/.reserved/.inodes/16386").resolve().unwrap(),
+            ResolvedPath::Inode {
+                id: INodeId(16386),
+                rest: pa("/"),
+            }
+        );
+        assert_eq!(
+            pa("/.reserved/.inodes/16386/x/y").resolve().unwrap(),
+            ResolvedPath::Inode {
+                id: INodeId(16386),
+                rest: pa("/x/y"),
+            }
+        );
+        assert_eq!(
+            pa("/.reserved/raw/enc/f").resolve().unwrap(),
+            ResolvedPath::Raw(pa("/enc/f"))
+        );
+        assert_eq!(
+            pa("/.reserved/raw").resolve().unwrap(),
+            ResolvedPath::Raw(pa("/"))
+        );
+        assert!(pa("/.reserved/raw").is_reserved());
+        assert!(!pa("/a/.reserved").is_reserved());
+
+        let err = |p: &str| match pa(p).resolve() {
+            Err(HdfsError::InvalidPath { reason, .. }) => reason,
+            other => panic!("expected InvalidPath error, got: {:?}", other),
+        };
+        assert_eq!(err("/.reserved"), "unknown reserved path");
+        assert_eq!(err("/.reserved/other"), "unknown reserved path");
+        assert_eq!(err("/.reserved/.inodes"), "invalid inode id");
+        assert_eq!(err("/.reserved/.inodes/x1"), "invalid inode id");
+    }
+
+    #[test]
+    fn resolve_snapshot_paths() {
+        assert_eq!(
+            pa("/data/.snapshot/s1/part-0").resolve().unwrap(),
+            ResolvedPath::Snapshot {
+                dir: pa("/data"),
+                name: Some("s1".into()),
+                rest: pa("/part-0"),
+            }
+        );
+        assert_eq!(
+            pa("/data/.snapshot").resolve().unwrap(),
+            ResolvedPath::Snapshot {
+                dir: pa("/data"),
+                name: None,
+                rest: pa("/"),
+            }
+        );
+        assert_eq!(
+            pa("/.snapshot/s1").resolve().unwrap(),
+            ResolvedPath::Snapshot {
+                dir: pa("/"),
+                name: Some("s1".into()),
+                rest: pa("/"),
+            }
+        );
+        // `..` is applied before reserved names are looked at
+        assert_eq!(
+            pa("/data/.snapshot/../x").resolve().unwrap(),
+            ResolvedPath::Plain(pa("/data/x"))
+        );
+        assert!(matches!(
+            pa("/a/.snapshot/s1/.snapshot/s2").resolve(),
+            Err(HdfsError::InvalidPath { reason, .. }) if reason == "nested .snapshot"
+        ));
+    }
+
+    #[test]
+    fn reserved_names_are_not_creatable() {
+        assert!(pa("/a/b").check_creatable().is_ok());
+        assert!(pa("/a/.reserved").check_creatable().is_ok());
+        assert!(pa("/a/.snapshots").check_creatable().is_ok());
+        for p in [
+            "/.reserved",
+            "/.reserved/x",
+            "/a/.snapshot",
+            "/a/.snapshot/s/f",
+        ] {
+            assert!(
+                matches!(
+                    pa(p).check_creatable(),
+                    Err(HdfsError::InvalidPath { reason, .. }) if reason == "reserved name"
+                ),
+                "{p}"
+            );
+        }
+    }
+
+    #[test]
+    fn nfc_merges_equivalent_spellings() {
+        let nfd = "/docs/cafe\u{301}";
+        let nfc = "/docs/caf\u{e9}";
+        let composed = NamePolicy {
+            nfc: true,
+            ..NamePolicy::default()
+        };
+        let parse = |p| PathAbs::parse_with(p, &composed).unwrap();
+        assert_eq!(parse(nfd), parse(nfc));
+        assert_eq!(parse(nfd).as_str(), nfc);
+
+        // off by default: names are kept as sent
+        assert_ne!(pa(nfd), pa(nfc));
+        assert_eq!(pa(nfd).as_str(), nfd);
+    }
+
+    #[test]
+    fn default_policy_adds_no_rules() {
+        assert_eq!(NamePolicy::default(), NamePolicy::DEFAULT);
+        let deep = "/d".repeat(2000);
+        assert_eq!(normalize(&deep).unwrap(), deep);
+        assert_eq!(normalize("/x:y/*").unwrap(), "/x:y/*");
+    }
+
+    #[test]
+    fn policy_reserved_chars_and_depth() {
+        let policy = NamePolicy {
+            reserved_chars: ":\\*".into(),
+            max_depth: 3,
+            ..NamePolicy::default()
+        };
+        assert_eq!(policy.normalize("/a/b/c").unwrap(), "/a/b/c");
+        assert_eq!(policy.normalize("/a/b/c/d/..").unwrap(), "/a/b/c");
+        assert!(pa("/a:b").name().contains(':'));
+
+        for (input, reason) in [
+            ("/a:b", "reserved character not allowed"),
+            ("/a/*", "reserved character not allowed"),
+            ("/a/b/c/d", "path too deep"),
+        ] {
+            match PathAbs::parse_with(input, &policy) {
+                Err(HdfsError::InvalidPath { path, reason: r }) => {
+                    assert_eq!(path, input);
+                    assert_eq!(r, reason);
+                }
+                other => panic!("expected InvalidPath error, got: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn case_collisions() {
+        let siblings = ["README.md", "data"];
+        let p = pa("/x/readme.MD");
+        assert!(NamePolicy::default().check_collision(&p, siblings).is_ok());
+
+        let ci = NamePolicy {
+            case_insensitive: true,
+            ..NamePolicy::default()
+        };
+        assert!(matches!(
+            ci.check_collision(&p, siblings),
+            Err(HdfsError::InvalidPath { reason, .. }) if reason == "differs from an existing name only by case"
+        ));
+        assert!(ci.check_collision(&pa("/x/data"), siblings).is_ok());
+        assert!(ci.check_collision(&pa("/x/other"), siblings).is_ok());
+        assert_eq!(ci.fold_case("CAF\u{c9}"), "caf\u{e9}");
+    }
 }
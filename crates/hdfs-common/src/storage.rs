@@ -0,0 +1,209 @@
+use crate::error::{HdfsError, Result};
+use crate::fs::write_atomic;
+use crate::ids::{BlockPoolId, ClusterId, DatanodeId, NamespaceId};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const VERSION_FILE: &str = "VERSION";
+
+/// Bumped whenever the on-disk layout changes incompatibly.
+pub const LAYOUT_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "node_type", rename_all = "snake_case")]
+pub enum NodeStorage {
+    NameNode {
+        namespace_id: NamespaceId,
+        block_pool_id: BlockPoolId,
+    },
+    DataNode {
+        datanode_id: DatanodeId,
+    },
+}
+
+/// Contents of the `VERSION` file at the top of every namenode and datanode
+/// storage directory. Written once at format time, checked on startup.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct StorageInfo {
+    pub layout_version: u32,
+    pub cluster_id: ClusterId,
+    /// Format time in millis since the epoch.
+    pub ctime: u64,
+    #[serde(flatten)]
+    pub node: NodeStorage,
+}
+
+/// What a namenode hands to datanodes when they register.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct NamespaceInfo {
+    pub layout_version: u32,
+    pub cluster_id: ClusterId,
+    pub namespace_id: NamespaceId,
+    pub block_pool_id: BlockPoolId,
+}
+
+impl StorageInfo {
+    /// A fresh namespace with its own block pool.
+    pub fn new_namenode(cluster_id: ClusterId, ctime: u64) -> Self {
+        Self {
+            layout_version: LAYOUT_VERSION,
+            cluster_id,
+            ctime,
+            node: NodeStorage::NameNode {
+                namespace_id: NamespaceId::new_v4(),
+                block_pool_id: BlockPoolId::new_v4(),
+            },
+        }
+    }
+
+    pub fn new_datanode(cluster_id: ClusterId, datanode_id: DatanodeId, ctime: u64) -> Self {
+        Self {
+            layout_version: LAYOUT_VERSION,
+            cluster_id,
+            ctime,
+            node: NodeStorage::DataNode { datanode_id },
+        }
+    }
+
+    /// `None` for datanode storage.
+    pub fn namespace_info(&self) -> Option<NamespaceInfo> {
+        match self.node {
+            NodeStorage::NameNode {
+                namespace_id,
+                block_pool_id,
+            } => Some(NamespaceInfo {
+                layout_version: self.layout_version,
+                cluster_id: self.cluster_id,
+                namespace_id,
+                block_pool_id,
+            }),
+            NodeStorage::DataNode { .. } => None,
+        }
+    }
+
+    /// Writes `dir/VERSION`, creating `dir` if needed. Refuses to overwrite
+    /// an already formatted directory.
+    pub fn format(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(VERSION_FILE);
+        if path.exists() {
+            return Err(HdfsError::AlreadyExists {
+                path: path.display().to_string(),
+            });
+        }
+        std::fs::create_dir_all(dir)?;
+        let text = toml::to_string(self).map_err(|e| HdfsError::State {
+            what: "storage".into(),
+            details: format!("cannot encode {}: {e}", path.display()),
+        })?;
+        write_atomic(&path, text.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn load(dir: &Path) -> Result<StorageInfo> {
+        let path = dir.join(VERSION_FILE);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(t) => t,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(HdfsError::State {
+                    what: "storage".into(),
+                    details: format!("{} is not formatted", dir.display()),
+                });
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let info: StorageInfo = toml::from_str(&text).map_err(|e| HdfsError::State {
+            what: "storage".into(),
+            details: format!("corrupt {}: {e}", path.display()),
+        })?;
+        if info.layout_version > LAYOUT_VERSION {
+            return Err(HdfsError::State {
+                what: "storage".into(),
+                details: format!(
+                    "{} has layout version {}, newer than supported {LAYOUT_VERSION}",
+                    dir.display(),
+                    info.layout_version
+                ),
+            });
+        }
+        Ok(info)
+    }
+
+    pub fn is_formatted(dir: &Path) -> bool {
+        dir.join(VERSION_FILE).exists()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn tmp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hdfs-storage-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn format_and_load_namenode() {
+        let dir = tmp_dir("nn");
+        assert!(!StorageInfo::is_formatted(&dir));
+
+        let info = StorageInfo::new_namenode(ClusterId::new_v4(), 1_700_000_000_000);
+        info.format(&dir).unwrap();
+        assert!(StorageInfo::is_formatted(&dir));
+        assert_eq!(StorageInfo::load(&dir).unwrap(), info);
+
+        let ns = info.namespace_info().unwrap();
+        assert_eq!(ns.cluster_id, info.cluster_id);
+        assert_eq!(ns.layout_version, LAYOUT_VERSION);
+
+        assert!(matches!(
+            info.format(&dir),
+            Err(HdfsError::AlreadyExists { .. })
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn version_file_is_readable_toml() {
+        let dir = tmp_dir("dn");
+        let cid: ClusterId = "0b6f3c1e-8d2a-4c55-9a77-1f2e3d4c5b6a".parse().unwrap();
+        let dn: DatanodeId = "5a4b3c2d-1e0f-4a9b-8c7d-6e5f4a3b2c1d".parse().unwrap();
+        let info = StorageInfo::new_datanode(cid, dn, 42);
+        info.format(&dir).unwrap();
+        assert!(info.namespace_info().is_none());
+
+        let text = std::fs::read_to_string(dir.join(VERSION_FILE)).unwrap();
+        assert_eq!(
+            text,
+            "layout_version = 1\n\
+             cluster_id = \"0b6f3c1e-8d2a-4c55-9a77-1f2e3d4c5b6a\"\n\
+             ctime = 42\n\
+             node_type = \"data_node\"\n\
+             datanode_id = \"5a4b3c2d-1e0f-4a9b-8c7d-6e5f4a3b2c1d\"\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn load_rejects_missing_corrupt_and_future() {
+        let dir = tmp_dir("bad");
+        let state = |r: Result<StorageInfo>| match r {
+            Err(HdfsError::State { what, details }) if what == "storage" => details,
+            other => panic!("expected storage error, got {other:?}"),
+        };
+        assert!(state(StorageInfo::load(&dir)).contains("is not formatted"));
+
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(VERSION_FILE), "layout_version = ").unwrap();
+        assert!(state(StorageInfo::load(&dir)).contains("corrupt"));
+
+        let mut info = StorageInfo::new_namenode(ClusterId::new_v4(), 0);
+        info.layout_version = LAYOUT_VERSION + 1;
+        std::fs::remove_file(dir.join(VERSION_FILE)).unwrap();
+        info.format(&dir).unwrap();
+        assert!(state(StorageInfo::load(&dir)).contains("newer than supported"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
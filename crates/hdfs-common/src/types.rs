@@ -0,0 +1,201 @@
+use crate::error::HdfsError;
+use crate::error::Result;
+use crate::ids::{BlockId, BlockPoolId, GenerationStamp};
+use serde::{Deserialize, Serialize};
+
+/// A block as seen across the cluster: which pool it belongs to, which
+/// version of it (generation stamp) and how long that version is.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct ExtendedBlock {
+    pub pool: BlockPoolId,
+    pub id: BlockId,
+    pub gen_stamp: GenerationStamp,
+    pub num_bytes: u64,
+}
+
+/// How a reported replica relates to the block the namenode expects.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ReplicaMatch {
+    Current,
+    /// Missed an append or a pipeline recovery; corrupt.
+    StaleGenStamp,
+    /// Newer than what the namenode knows, e.g. mid-recovery.
+    FutureGenStamp,
+    /// Same version but a different length than the finalized block.
+    LengthMismatch,
+    /// A different block or block pool altogether.
+    OtherBlock,
+}
+
+impl ExtendedBlock {
+    pub fn new(pool: BlockPoolId, id: BlockId, gen_stamp: GenerationStamp, num_bytes: u64) -> Self {
+        Self {
+            pool,
+            id,
+            gen_stamp,
+            num_bytes,
+        }
+    }
+
+    /// `blk_<id>`, the name of the block file on a datanode.
+    pub fn block_name(&self) -> String {
+        format!("blk_{}", self.id)
+    }
+
+    pub fn with_gen_stamp(&self, gen_stamp: GenerationStamp) -> Self {
+        Self {
+            gen_stamp,
+            ..self.clone()
+        }
+    }
+
+    pub fn same_block(&self, other: &ExtendedBlock) -> bool {
+        self.pool == other.pool && self.id == other.id
+    }
+
+    /// Compares a replica reported by a datanode against `self`, the
+    /// namenode's view of a finalized block.
+    pub fn check_replica(&self, reported: &ExtendedBlock) -> ReplicaMatch {
+        if !self.same_block(reported) {
+            return ReplicaMatch::OtherBlock;
+        }
+        match reported.gen_stamp.cmp(&self.gen_stamp) {
+            std::cmp::Ordering::Less => ReplicaMatch::StaleGenStamp,
+            std::cmp::Ordering::Greater => ReplicaMatch::FutureGenStamp,
+            std::cmp::Ordering::Equal if reported.num_bytes != self.num_bytes => {
+                ReplicaMatch::LengthMismatch
+            }
+            std::cmp::Ordering::Equal => ReplicaMatch::Current,
+        }
+    }
+}
+
+impl core::fmt::Display for ExtendedBlock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:blk_{}_{}", self.pool, self.id, self.gen_stamp)
+    }
+}
+
+/// Parses the `<pool>:blk_<id>_<genstamp>` form produced by `Display`. The
+/// length is not part of that form and comes back as 0.
+impl std::str::FromStr for ExtendedBlock {
+    type Err = HdfsError;
+    fn from_str(s: &str) -> Result<Self> {
+        let bad = || HdfsError::Protocol {
+            op: "parse_block".into(),
+            details: format!("expected <pool>:blk_<id>_<genstamp>, got '{s}'"),
+        };
+        let (pool, rest) = s.rsplit_once(':').ok_or_else(bad)?;
+        let rest = rest.strip_prefix("blk_").ok_or_else(bad)?;
+        let (id, gs) = rest.split_once('_').ok_or_else(bad)?;
+        Ok(ExtendedBlock {
+            pool: pool.parse().map_err(|_| bad())?,
+            id: BlockId(id.parse().map_err(|_| bad())?),
+            gen_stamp: GenerationStamp(gs.parse().map_err(|_| bad())?),
+            num_bytes: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POOL: &str = "6f1c2a3b-4d5e-4f60-8a7b-9c0d1e2f3a4b";
+
+    fn blk(id: u64, gs: u64, len: u64) -> ExtendedBlock {
+        ExtendedBlock::new(POOL.parse().unwrap(), BlockId(id), GenerationStamp(gs), len)
+    }
+
+    #[test]
+    fn display_and_names() {
+        let b = blk(42, 1001, 512);
+        assert_eq!(b.to_string(), format!("{POOL}:blk_42_1001"));
+        assert_eq!(b.block_name(), "blk_42");
+    }
+
+    #[test]
+    fn parse_roundtrip() {
+        let b = blk(42, 1001, 0);
+        let back: ExtendedBlock = b.to_string().parse().unwrap();
+        assert_eq!(back, b);
+
+        for bad in [
+            "",
+            "blk_1_2",
+            ":blk_1_2",
+            "p:blk_1",
+            "p:bk_1_2",
+            "p:blk_x_2",
+            "p:blk_1_y",
+            "BP-1:blk_1_2",
+        ] {
+            assert!(
+                matches!(
+                    bad.parse::<ExtendedBlock>(),
+                    Err(HdfsError::Protocol { op, .. }) if op == "parse_block"
+                ),
+                "{bad}"
+            );
+        }
+    }
+
+    #[test]
+    fn replica_checks() {
+        let expected = blk(7, 1005, 4096);
+        assert_eq!(
+            expected.check_replica(&blk(7, 1005, 4096)),
+            ReplicaMatch::Current
+        );
+        assert_eq!(
+            expected.check_replica(&blk(7, 1004, 4096)),
+            ReplicaMatch::StaleGenStamp
+        );
+        assert_eq!(
+            expected.check_replica(&blk(7, 1006, 8192)),
+            ReplicaMatch::FutureGenStamp
+        );
+        assert_eq!(
+            expected.check_replica(&blk(7, 1005, 100)),
+            ReplicaMatch::LengthMismatch
+        );
+        assert_eq!(
+            expected.check_replica(&blk(8, 1005, 4096)),
+            ReplicaMatch::OtherBlock
+        );
+
+        let other_pool = ExtendedBlock::new(
+            BlockPoolId::new_v4(),
+            BlockId(7),
+            GenerationStamp(1005),
+            4096,
+        );
+        assert_eq!(
+            expected.check_replica(&other_pool),
+            ReplicaMatch::OtherBlock
+        );
+    }
+
+    #[test]
+    fn bump_gen_stamp_keeps_identity() {
+        let b = blk(7, 1005, 4096);
+        let bumped = b.with_gen_stamp(b.gen_stamp.next().unwrap());
+        assert!(b.same_block(&bumped));
+        assert_eq!(bumped.gen_stamp, GenerationStamp(1006));
+        assert_eq!(bumped.check_replica(&b), ReplicaMatch::StaleGenStamp);
+    }
+
+    #[test]
+    fn serde_roundtrip() {
+        let b = blk(9, 1001, 1);
+        let json = serde_json::to_string(&b).unwrap();
+        assert_eq!(
+            json,
+            format!(r#"{{"pool":"{POOL}","id":9,"gen_stamp":1001,"num_bytes":1}}"#)
+        );
+        assert_eq!(serde_json::from_str::<ExtendedBlock>(&json).unwrap(), b);
+
+        let t = toml::to_string(&b).unwrap();
+        assert_eq!(toml::from_str::<ExtendedBlock>(&t).unwrap(), b);
+    }
+}
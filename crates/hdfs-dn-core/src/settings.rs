@@ -0,0 +1,126 @@
+use hdfs_common::config::ClusterConfig;
+use hdfs_common::config::reconfig::{AtomicDuration, ReconfigSubscriber};
+use hdfs_common::error::{HdfsError, Result};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Datanode settings that can change while the datanode runs. Readers always
+/// see the latest applied value.
+#[derive(Debug)]
+pub struct DataNodeSettings {
+    heartbeat_interval: AtomicDuration,
+    block_report_interval: AtomicDuration,
+    max_xceivers: AtomicU32,
+    balance_bandwidth: AtomicU64,
+}
+
+impl DataNodeSettings {
+    pub fn new(cfg: &ClusterConfig) -> Self {
+        let dn = &cfg.datanode;
+        Self {
+            heartbeat_interval: AtomicDuration::new(dn.heartbeat_interval),
+            block_report_interval: AtomicDuration::new(dn.block_report_interval),
+            max_xceivers: AtomicU32::new(dn.max_xceivers),
+            balance_bandwidth: AtomicU64::new(dn.balance_bandwidth.as_u64()),
+        }
+    }
+
+    pub fn heartbeat_interval(&self) -> Duration {
+        self.heartbeat_interval.load()
+    }
+
+    pub fn block_report_interval(&self) -> Duration {
+        self.block_report_interval.load()
+    }
+
+    pub fn max_xceivers(&self) -> u32 {
+        self.max_xceivers.load(Ordering::Acquire)
+    }
+
+    /// Bytes per second the balancer may use.
+    pub fn balance_bandwidth(&self) -> u64 {
+        self.balance_bandwidth.load(Ordering::Acquire)
+    }
+}
+
+impl ReconfigSubscriber for DataNodeSettings {
+    fn keys(&self) -> &'static [&'static str] {
+        &[
+            "datanode.heartbeat_interval",
+            "datanode.block_report_interval",
+            "datanode.max_xceivers",
+            "datanode.balance_bandwidth",
+        ]
+    }
+
+    fn apply(&self, key: &'static str, new: &ClusterConfig) -> Result<()> {
+        let dn = &new.datanode;
+        match key {
+            "datanode.heartbeat_interval" => self.heartbeat_interval.store(dn.heartbeat_interval),
+            "datanode.block_report_interval" => {
+                self.block_report_interval.store(dn.block_report_interval)
+            }
+            "datanode.max_xceivers" => self.max_xceivers.store(dn.max_xceivers, Ordering::Release),
+            "datanode.balance_bandwidth" => self
+                .balance_bandwidth
+                .store(dn.balance_bandwidth.as_u64(), Ordering::Release),
+            _ => {
+                return Err(HdfsError::Config {
+                    key: key.into(),
+                    msg: "not handled by datanode settings".into(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hdfs_common::config::ConfigLoader;
+    use hdfs_common::config::reconfig::Reconfigurer;
+    use std::sync::Arc;
+
+    #[test]
+    fn starts_from_config() {
+        let cfg = ClusterConfig::default();
+        let s = DataNodeSettings::new(&cfg);
+        assert_eq!(s.heartbeat_interval(), cfg.datanode.heartbeat_interval);
+        assert_eq!(
+            s.block_report_interval(),
+            cfg.datanode.block_report_interval
+        );
+        assert_eq!(s.max_xceivers(), cfg.datanode.max_xceivers);
+        assert_eq!(
+            s.balance_bandwidth(),
+            cfg.datanode.balance_bandwidth.as_u64()
+        );
+    }
+
+    #[test]
+    fn reconfigured_through_reconfigurer() {
+        let dir = std::env::temp_dir().join(format!("hdfs-dn-settings-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let site = dir.join("hdfs-site.toml");
+        std::fs::write(&site, "").unwrap();
+
+        let r = Reconfigurer::new(ConfigLoader::new().site_file(&site)).unwrap();
+        let settings = Arc::new(DataNodeSettings::new(&r.current()));
+        r.subscribe(settings.clone());
+
+        std::fs::write(
+            &site,
+            "[datanode]\nheartbeat_interval = \"1s\"\nbalance_bandwidth = \"100MiB\"\n",
+        )
+        .unwrap();
+        let report = r.reconfigure().unwrap();
+        assert_eq!(report.applied.len(), 2);
+        assert!(report.rejected.is_empty());
+
+        assert_eq!(settings.heartbeat_interval(), Duration::from_secs(1));
+        assert_eq!(settings.balance_bandwidth(), 100 * 1024 * 1024);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
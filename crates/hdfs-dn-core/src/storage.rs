@@ -0,0 +1,106 @@
+use hdfs_common::clock::Clock;
+use hdfs_common::error::{HdfsError, Result};
+use hdfs_common::ids::DatanodeId;
+use hdfs_common::storage::{NamespaceInfo, NodeStorage, StorageInfo};
+use std::path::PathBuf;
+
+/// Prepares the data directories for registering with a namenode. Formatted
+/// directories must belong to the namenode's cluster; fresh ones are
+/// formatted for it. All directories share one datanode id, which is
+/// returned.
+pub fn join_cluster(dirs: &[PathBuf], nn: &NamespaceInfo, clock: &dyn Clock) -> Result<DatanodeId> {
+    let mut datanode_id = None;
+    let mut fresh = Vec::new();
+
+    for dir in dirs {
+        if !StorageInfo::is_formatted(dir) {
+            fresh.push(dir);
+            continue;
+        }
+        let info = StorageInfo::load(dir)?;
+        if info.cluster_id != nn.cluster_id {
+            return Err(HdfsError::State {
+                what: "register_datanode".into(),
+                details: format!(
+                    "incompatible cluster ids in {}: namenode has {}, datanode has {}",
+                    dir.display(),
+                    nn.cluster_id,
+                    info.cluster_id
+                ),
+            });
+        }
+        let NodeStorage::DataNode { datanode_id: id } = info.node else {
+            return Err(HdfsError::State {
+                what: "register_datanode".into(),
+                details: format!("{} holds namenode storage", dir.display()),
+            });
+        };
+        match datanode_id {
+            Some(prev) if prev != id => {
+                return Err(HdfsError::State {
+                    what: "register_datanode".into(),
+                    details: format!(
+                        "{} belongs to datanode {id}, expected {prev}",
+                        dir.display()
+                    ),
+                });
+            }
+            _ => datanode_id = Some(id),
+        }
+    }
+
+    let datanode_id = datanode_id.unwrap_or_else(DatanodeId::new_v4);
+    let info = StorageInfo::new_datanode(nn.cluster_id, datanode_id, clock.wall_millis());
+    for dir in fresh {
+        info.format(dir)?;
+    }
+    Ok(datanode_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hdfs_common::clock::ManualClock;
+    use hdfs_common::ids::ClusterId;
+
+    fn tmp_dirs(name: &str, n: usize) -> Vec<PathBuf> {
+        let base = std::env::temp_dir().join(format!("hdfs-dn-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        (0..n).map(|i| base.join(format!("data{i}"))).collect()
+    }
+
+    fn namespace(cluster_id: ClusterId) -> NamespaceInfo {
+        StorageInfo::new_namenode(cluster_id, 0)
+            .namespace_info()
+            .unwrap()
+    }
+
+    #[test]
+    fn formats_fresh_dirs_and_keeps_id() {
+        let dirs = tmp_dirs("join", 2);
+        let nn = namespace(ClusterId::new_v4());
+        let clock = ManualClock::new();
+
+        let id = join_cluster(&dirs[..1], &nn, &clock).unwrap();
+        // a newly added disk gets the same datanode id
+        assert_eq!(join_cluster(&dirs, &nn, &clock).unwrap(), id);
+        assert_eq!(join_cluster(&dirs, &nn, &clock).unwrap(), id);
+        std::fs::remove_dir_all(dirs[0].parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn refuses_other_cluster() {
+        let dirs = tmp_dirs("other", 1);
+        let clock = ManualClock::new();
+        join_cluster(&dirs, &namespace(ClusterId::new_v4()), &clock).unwrap();
+
+        let err = join_cluster(&dirs, &namespace(ClusterId::new_v4()), &clock).unwrap_err();
+        match err {
+            HdfsError::State { what, details } if what == "register_datanode" => {
+                assert!(details.contains("incompatible cluster ids"))
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+        std::fs::remove_dir_all(dirs[0].parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+//! Block meta files. Every block file `blk_<id>` has a
+//! `blk_<id>_<genstamp>.meta` next to it: a header recording how the writer
+//! chose to checksum the block, then one checksum per chunk of the block,
+//! laid out as in Hadoop's `BlockMetadataHeader`.
+
+use hdfs_common::checksum::DataChecksum;
+use hdfs_common::error::{HdfsError, Result};
+use hdfs_common::ids::{BlockId, GenerationStamp};
+use std::io::{Read, Write};
+
+pub const META_VERSION: u16 = 1;
+
+/// version(2) checksum type(1) bytes_per_checksum(4)
+pub const META_HEADER_LEN: usize = 2 + DataChecksum::HEADER_LEN;
+
+pub fn meta_file_name(id: BlockId, gen_stamp: GenerationStamp) -> String {
+    format!("blk_{id}_{gen_stamp}.meta")
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct BlockMetaHeader {
+    pub version: u16,
+    pub checksum: DataChecksum,
+}
+
+impl BlockMetaHeader {
+    pub fn new(checksum: DataChecksum) -> Self {
+        Self {
+            version: META_VERSION,
+            checksum,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; META_HEADER_LEN] {
+        let mut out = [0; META_HEADER_LEN];
+        out[..2].copy_from_slice(&self.version.to_be_bytes());
+        out[2..].copy_from_slice(&self.checksum.to_header());
+        out
+    }
+
+    pub fn from_bytes(raw: &[u8; META_HEADER_LEN]) -> Result<Self> {
+        let corrupt = |details: String| HdfsError::State {
+            what: "block_meta".into(),
+            details,
+        };
+        let version = u16::from_be_bytes([raw[0], raw[1]]);
+        if version != META_VERSION {
+            return Err(corrupt(format!(
+                "meta file version {version}, expected {META_VERSION}"
+            )));
+        }
+        let checksum = DataChecksum::from_header(raw[2..].try_into().unwrap())
+            .map_err(|e| corrupt(format!("bad checksum header: {e}")))?;
+        Ok(Self { version, checksum })
+    }
+
+    pub fn read_from<R: Read>(r: &mut R) -> Result<Self> {
+        let mut raw = [0; META_HEADER_LEN];
+        r.read_exact(&mut raw)?;
+        Self::from_bytes(&raw)
+    }
+
+    pub fn write_to<W: Write>(&self, w: &mut W) -> Result<()> {
+        w.write_all(&self.to_bytes())?;
+        Ok(())
+    }
+
+    /// Where the checksum of `chunk` starts in the meta file.
+    pub fn checksum_offset(&self, chunk: u64) -> u64 {
+        META_HEADER_LEN as u64 + chunk * self.checksum.checksum_size() as u64
+    }
+
+    /// Size of the meta file of a block holding `block_len` bytes.
+    pub fn meta_len(&self, block_len: u64) -> u64 {
+        let bpc = u64::from(self.checksum.bytes_per_checksum());
+        match self.checksum.checksum_size() {
+            0 => META_HEADER_LEN as u64,
+            _ => self.checksum_offset(block_len.div_ceil(bpc)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hdfs_common::checksum::ChecksumType;
+    use std::io::{Seek, SeekFrom};
+
+    #[test]
+    fn header_layout() {
+        let h = BlockMetaHeader::new(DataChecksum::new(ChecksumType::Crc32c, 512));
+        assert_eq!(h.to_bytes(), [0, 1, 2, 0, 0, 2, 0]);
+        assert_eq!(BlockMetaHeader::from_bytes(&h.to_bytes()).unwrap(), h);
+        assert_eq!(h.checksum_offset(3), 7 + 12);
+        assert_eq!(h.meta_len(0), 7);
+        assert_eq!(h.meta_len(1025), 7 + 12);
+
+        let null = BlockMetaHeader::new(DataChecksum::new(ChecksumType::Null, 512));
+        assert_eq!(null.meta_len(1 << 20), 7);
+
+        for bad in [[0, 2, 2, 0, 0, 2, 0], [0, 1, 7, 0, 0, 2, 0]] {
+            assert!(matches!(
+                BlockMetaHeader::from_bytes(&bad),
+                Err(HdfsError::State { what, .. }) if what == "block_meta"
+            ));
+        }
+        assert_eq!(
+            meta_file_name(BlockId(1 << 30), GenerationStamp(1001)),
+            "blk_1073741824_1001.meta"
+        );
+    }
+
+    #[test]
+    fn meta_file_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("hdfs-dn-meta-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(meta_file_name(BlockId(1), GenerationStamp(1)));
+
+        let data: Vec<u8> = (0..3000u32).map(|i| (i * 7) as u8).collect();
+        let header = BlockMetaHeader::new(DataChecksum::new(ChecksumType::Crc32, 1024));
+        let mut f = std::fs::File::create(&path).unwrap();
+        header.write_to(&mut f).unwrap();
+        f.write_all(&header.checksum.compute_to_vec(&data)).unwrap();
+        drop(f);
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len(),
+            header.meta_len(data.len() as u64)
+        );
+
+        // a reader checks the last chunk alone
+        let mut f = std::fs::File::open(&path).unwrap();
+        let read = BlockMetaHeader::read_from(&mut f).unwrap();
+        assert_eq!(read, header);
+        f.seek(SeekFrom::Start(read.checksum_offset(2))).unwrap();
+        let mut sum = Vec::new();
+        f.read_to_end(&mut sum).unwrap();
+        read.checksum
+            .verify(&data[2048..], &sum, BlockId(1), 2048)
+            .unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
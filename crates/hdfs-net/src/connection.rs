@@ -0,0 +1,149 @@
+use hdfs_common::error::Result;
+use hdfs_wire::frame::{Frame, FrameCodec};
+use hdfs_wire::handshake::{Features, Hello, Negotiated, handshake_async};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+/// A framed stream whose protocol version and features have been agreed
+/// with the peer.
+#[derive(Debug)]
+pub struct Connection<S> {
+    stream: S,
+    codec: FrameCodec,
+    negotiated: Negotiated,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<S> {
+    /// Runs the handshake on a fresh stream. Both ends call this; `base`
+    /// supplies the limits, the negotiated features the rest.
+    pub async fn establish(mut stream: S, hello: &Hello, base: FrameCodec) -> Result<Self> {
+        let negotiated = handshake_async(&mut stream, hello).await?;
+        Ok(Self {
+            stream,
+            codec: negotiated.frame_codec(base),
+            negotiated,
+        })
+    }
+
+    pub fn negotiated(&self) -> &Negotiated {
+        &self.negotiated
+    }
+
+    pub fn features(&self) -> Features {
+        self.negotiated.features
+    }
+
+    pub fn codec(&self) -> &FrameCodec {
+        &self.codec
+    }
+
+    pub async fn send(&mut self, frame: &Frame) -> Result<()> {
+        self.codec
+            .write_frame_async(&mut self.stream, frame)
+            .await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    /// `None` once the peer has closed the connection.
+    pub async fn recv(&mut self) -> Result<Option<Frame>> {
+        self.codec.read_frame_async(&mut self.stream).await
+    }
+
+    pub async fn shutdown(&mut self) -> Result<()> {
+        self.stream.shutdown().await?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// The stream and what was agreed on it, for callers that split the
+    /// stream to read and write from separate tasks.
+    pub fn into_parts(self) -> (S, FrameCodec, Negotiated) {
+        (self.stream, self.codec, self.negotiated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hdfs_common::error::HdfsError;
+
+    #[tokio::test]
+    async fn frames_flow_after_handshake() {
+        let (a, b) = tokio::io::duplex(1024);
+        let client = Hello::new(Features::CRC32C | Features::VECTORED_READS);
+        let server = Hello::new(Features::CRC32C);
+        let (c, s) = tokio::join!(
+            Connection::establish(a, &client, FrameCodec::new()),
+            Connection::establish(b, &server, FrameCodec::new())
+        );
+        let (mut c, mut s) = (c.unwrap(), s.unwrap());
+        assert_eq!(c.features(), Features::CRC32C);
+        assert_eq!(c.negotiated(), s.negotiated());
+
+        let frame = Frame::new(1, 7, b"ping".to_vec());
+        c.send(&frame).await.unwrap();
+        assert_eq!(s.recv().await.unwrap(), Some(frame));
+        c.shutdown().await.unwrap();
+        assert_eq!(s.recv().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn checksums_off_when_not_agreed() {
+        let (a, b) = tokio::io::duplex(1024);
+        let (bare, default) = (Hello::new(Features::empty()), Hello::default());
+        let (c, s) = tokio::join!(
+            Connection::establish(a, &bare, FrameCodec::new()),
+            Connection::establish(b, &default, FrameCodec::new())
+        );
+        let (mut c, mut s) = (c.unwrap(), s.unwrap());
+        assert!(c.features().is_empty());
+
+        let frame = Frame::new(2, 1, b"pong".to_vec());
+        c.send(&frame).await.unwrap();
+        assert_eq!(s.recv().await.unwrap(), Some(frame));
+    }
+
+    #[tokio::test]
+    async fn large_frames_compressed_when_agreed() {
+        use hdfs_wire::compress::Compression;
+
+        let (a, b) = tokio::io::duplex(64 * 1024);
+        let client = Hello::new(Features::CRC32C | Features::COMPRESSION | Features::LZ4);
+        let server =
+            Hello::new(Features::CRC32C | Features::COMPRESSION | Features::LZ4 | Features::ZSTD);
+        let (c, s) = tokio::join!(
+            Connection::establish(a, &client, FrameCodec::new()),
+            Connection::establish(b, &server, FrameCodec::new())
+        );
+        let (mut c, mut s) = (c.unwrap(), s.unwrap());
+        assert_eq!(c.codec().compression(), Some(Compression::Lz4));
+        assert_eq!(s.codec().compression(), Some(Compression::Lz4));
+
+        let report = Frame::new(3, 1, b"blk_1073741825_1001 ".repeat(2000));
+        let ping = Frame::new(1, 2, b"ping".to_vec());
+        c.send(&report).await.unwrap();
+        c.send(&ping).await.unwrap();
+        assert_eq!(s.recv().await.unwrap(), Some(report));
+        assert_eq!(s.recv().await.unwrap(), Some(ping));
+    }
+
+    #[tokio::test]
+    async fn incompatible_peers_fail_on_both_ends() {
+        let (a, b) = tokio::io::duplex(1024);
+        let strict = Hello::default().require(Features::ENCRYPTION);
+        let plain = Hello::default();
+        let (c, s) = tokio::join!(
+            Connection::establish(a, &strict, FrameCodec::new()),
+            Connection::establish(b, &plain, FrameCodec::new())
+        );
+        for res in [c.map(|_| ()), s.map(|_| ())] {
+            assert!(matches!(
+                res,
+                Err(HdfsError::Protocol { op, .. }) if op == "handshake"
+            ));
+        }
+    }
+}
@@ -0,0 +1,374 @@
+use crate::retry::{RetryAction, RetryPolicy, is_connection_failure};
+use crate::rpc::RpcClient;
+use hdfs_common::config::ClientConfig;
+use hdfs_common::error::{HdfsError, Result};
+use hdfs_wire::frame::FrameCodec;
+use hdfs_wire::handshake::Hello;
+use hdfs_wire::message::Call;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+#[derive(Debug)]
+struct Active {
+    index: usize,
+    client: Option<RpcClient>,
+}
+
+/// Calls whichever of a set of HA namenodes is active. Starts with the
+/// first; a standby answer or a lost connection moves on to the next, and
+/// the retry policy decides whether and when the call is sent again.
+#[derive(Debug)]
+pub struct FailoverProxy {
+    addrs: Vec<String>,
+    hello: Hello,
+    codec: FrameCodec,
+    timeout: Duration,
+    policy: RetryPolicy,
+    active: Mutex<Active>,
+}
+
+impl FailoverProxy {
+    /// # Panics
+    ///
+    /// If `addrs` is empty.
+    pub fn new(addrs: Vec<String>, timeout: Duration, policy: RetryPolicy) -> Self {
+        assert!(!addrs.is_empty(), "no namenode addresses");
+        Self {
+            addrs,
+            hello: Hello::default(),
+            codec: FrameCodec::new(),
+            timeout,
+            policy,
+            active: Mutex::new(Active {
+                index: 0,
+                client: None,
+            }),
+        }
+    }
+
+    pub fn from_config(cfg: &ClientConfig) -> Result<Self> {
+        if cfg.namenodes.is_empty() {
+            return Err(HdfsError::Config {
+                key: "client.namenodes".into(),
+                msg: "no namenode addresses".into(),
+            });
+        }
+        Ok(Self::new(
+            cfg.namenodes.clone(),
+            cfg.socket_timeout,
+            RetryPolicy::from_config(cfg),
+        ))
+    }
+
+    pub fn with_hello(mut self, hello: Hello) -> Self {
+        self.hello = hello;
+        self
+    }
+
+    pub fn with_codec(mut self, codec: FrameCodec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    /// The namenode calls currently go to.
+    pub async fn current(&self) -> &str {
+        &self.addrs[self.active.lock().await.index]
+    }
+
+    /// Calls the active namenode, failing over and retrying as the policy
+    /// allows. The error returned is the last one seen.
+    pub async fn call<C: Call>(&self, req: &C) -> Result<C::Response> {
+        let started = Instant::now();
+        let mut retries = 0;
+        loop {
+            let (index, result) = match self.client().await {
+                Ok((index, client)) => (index, client.call(req).await),
+                // nothing was sent, so any call may go elsewhere
+                Err((index, e)) => (index, Err(e)),
+            };
+            let err = match result {
+                Ok(resp) => return Ok(resp),
+                Err(e) => e,
+            };
+            if is_connection_failure(&err) {
+                self.disconnect(index).await;
+            }
+            match self
+                .policy
+                .decide(&err, C::IDEMPOTENT, retries, started.elapsed())
+            {
+                RetryAction::Fail => return Err(err),
+                RetryAction::Retry { delay } => tokio::time::sleep(delay).await,
+                RetryAction::FailoverAndRetry { delay } => {
+                    self.failover(index).await;
+                    tokio::time::sleep(delay).await;
+                }
+            }
+            retries += 1;
+        }
+    }
+
+    /// The connection to the current namenode, made if need be. Connecting
+    /// happens unlocked, so a slow namenode holds up neither failovers nor
+    /// calls that find a connection; if another call connected or failed
+    /// over meanwhile, its outcome wins.
+    async fn client(&self) -> std::result::Result<(usize, RpcClient), (usize, HdfsError)> {
+        loop {
+            let index = {
+                let active = self.active.lock().await;
+                if let Some(client) = &active.client {
+                    return Ok((active.index, client.clone()));
+                }
+                active.index
+            };
+            let client =
+                RpcClient::connect(&self.addrs[index], &self.hello, self.codec, self.timeout)
+                    .await
+                    .map_err(|e| (index, not_sent(e)))?;
+            let mut active = self.active.lock().await;
+            if active.index != index {
+                continue;
+            }
+            let client = active.client.get_or_insert(client).clone();
+            return Ok((index, client));
+        }
+    }
+
+    /// Drops the connection to namenode `index`, so the next call makes a
+    /// new one.
+    async fn disconnect(&self, index: usize) {
+        let mut active = self.active.lock().await;
+        if active.index == index {
+            active.client = None;
+        }
+    }
+
+    /// Moves on from namenode `from`, unless a concurrent call already did.
+    async fn failover(&self, from: usize) {
+        let mut active = self.active.lock().await;
+        if active.index == from {
+            active.index = (from + 1) % self.addrs.len();
+            active.client = None;
+        }
+    }
+}
+
+/// A failure to connect means the call never went out; say so in a way
+/// [`RetryPolicy::decide`] sees, whatever the underlying error was.
+fn not_sent(e: HdfsError) -> HdfsError {
+    match e {
+        HdfsError::Io(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => HdfsError::Io(e),
+        e => HdfsError::Io(std::io::Error::new(
+            std::io::ErrorKind::NotConnected,
+            format!("could not connect: {e}"),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::{Handler, RpcServer, ShutdownHandle};
+    use hdfs_common::path::PathAbs;
+    use hdfs_wire::client::{
+        ClientRequest, ClientResponse, GetFileInfoRequest, GetFileInfoResponse, RenameRequest,
+        RenameResponse,
+    };
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::net::TcpListener;
+
+    /// A namenode in the given HA state that counts the calls it gets.
+    struct Namenode {
+        active: bool,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Handler<ClientRequest> for Namenode {
+        async fn handle(&self, req: ClientRequest) -> Result<ClientResponse> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if !self.active {
+                return Err(HdfsError::State {
+                    what: "standby".into(),
+                    details: "operation category READ is not supported in state standby".into(),
+                });
+            }
+            Ok(match req {
+                ClientRequest::Rename(_) => RenameResponse { renamed: true }.into(),
+                _ => GetFileInfoResponse { status: None }.into(),
+            })
+        }
+    }
+
+    async fn namenode(active: bool) -> (String, Arc<AtomicUsize>, ShutdownHandle) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let server = RpcServer::new(Hello::default()).register::<ClientRequest, _>(Namenode {
+            active,
+            calls: calls.clone(),
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let stop = server.shutdown_handle();
+        tokio::spawn(server.serve(listener));
+        (addr, calls, stop)
+    }
+
+    /// An address nothing listens on.
+    async fn dead_addr() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().to_string()
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::Fixed {
+            max_retries: 4,
+            delay: Duration::from_millis(5),
+        }
+    }
+
+    fn get_file_info() -> GetFileInfoRequest {
+        GetFileInfoRequest {
+            path: PathAbs::try_from("/a").unwrap(),
+        }
+    }
+
+    fn rename() -> RenameRequest {
+        RenameRequest {
+            src: PathAbs::try_from("/a").unwrap(),
+            dst: PathAbs::try_from("/b").unwrap(),
+            overwrite: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn fails_over_past_dead_and_standby_namenodes() {
+        let (standby, standby_calls, _s1) = namenode(false).await;
+        let (active, active_calls, _s2) = namenode(true).await;
+        let proxy = FailoverProxy::new(
+            vec![dead_addr().await, standby.clone(), active.clone()],
+            Duration::from_secs(5),
+            policy(),
+        );
+
+        // not idempotent, but neither failure let it run
+        assert_eq!(
+            proxy.call(&rename()).await.unwrap(),
+            RenameResponse { renamed: true }
+        );
+        assert_eq!(proxy.current().await, active);
+        assert_eq!(standby_calls.load(Ordering::SeqCst), 1);
+
+        // later calls stick with the active namenode
+        proxy.call(&get_file_info()).await.unwrap();
+        assert_eq!(active_calls.load(Ordering::SeqCst), 2);
+        assert_eq!(standby_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn follows_a_failover_between_calls() {
+        let (first, _, stop_first) = namenode(true).await;
+        let (second, second_calls, _s) = namenode(true).await;
+        let proxy = FailoverProxy::new(
+            vec![first.clone(), second.clone()],
+            Duration::from_secs(5),
+            policy(),
+        );
+        proxy.call(&get_file_info()).await.unwrap();
+        assert_eq!(proxy.current().await, first);
+
+        // the active one goes away; idempotent calls move on
+        stop_first.shutdown();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        proxy.call(&get_file_info()).await.unwrap();
+        assert_eq!(proxy.current().await, second);
+        assert_eq!(second_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn gives_up_when_the_policy_runs_out() {
+        let (standby, calls, _s) = namenode(false).await;
+        let proxy = FailoverProxy::new(vec![standby], Duration::from_secs(5), policy());
+        let err = proxy.call(&get_file_info()).await.unwrap_err();
+        assert!(err.is_standby(), "{err}");
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+
+        let proxy = FailoverProxy::new(
+            vec![dead_addr().await],
+            Duration::from_secs(5),
+            RetryPolicy::TryOnce,
+        );
+        assert!(matches!(
+            proxy.call(&get_file_info()).await,
+            Err(HdfsError::Io(_))
+        ));
+        assert!(matches!(
+            FailoverProxy::from_config(&ClientConfig {
+                namenodes: vec![],
+                ..ClientConfig::default()
+            }),
+            Err(HdfsError::Config { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn a_hanging_connect_holds_up_nothing_else() {
+        // takes the connection but never answers the handshake
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let hanging = listener.local_addr().unwrap().to_string();
+        let (active, _, _s) = namenode(true).await;
+        let proxy = Arc::new(FailoverProxy::new(
+            vec![hanging.clone(), active.clone()],
+            Duration::from_secs(30),
+            RetryPolicy::TryOnce,
+        ));
+        let stuck = tokio::spawn({
+            let proxy = proxy.clone();
+            async move { proxy.call(&get_file_info()).await }
+        });
+        let _conn = listener.accept().await.unwrap();
+
+        let current = tokio::time::timeout(Duration::from_secs(5), proxy.current());
+        assert_eq!(current.await.unwrap(), hanging);
+        proxy.failover(0).await;
+        let req = get_file_info();
+        let call = tokio::time::timeout(Duration::from_secs(5), proxy.call(&req));
+        assert!(call.await.unwrap().is_ok());
+        assert!(!stuck.is_finished());
+        stuck.abort();
+    }
+
+    #[tokio::test]
+    async fn non_idempotent_calls_are_not_resent_after_a_lost_connection() {
+        // reads one request, then hangs up without answering
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let mut conn = crate::connection::Connection::establish(
+                    stream,
+                    &Hello::default(),
+                    FrameCodec::new(),
+                )
+                .await
+                .unwrap();
+                if conn.recv().await.unwrap().is_some() {
+                    seen.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        });
+
+        let proxy = FailoverProxy::new(vec![addr], Duration::from_secs(5), policy());
+        let err = proxy.call(&rename()).await.unwrap_err();
+        assert_eq!(requests.load(Ordering::SeqCst), 1, "{err}");
+
+        // an idempotent call is resent until the policy runs out
+        assert!(proxy.call(&get_file_info()).await.is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 1 + 5);
+    }
+}
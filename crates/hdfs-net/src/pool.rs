@@ -0,0 +1,422 @@
+//! Idle connections kept for reuse, keyed by peer address and protocol.
+//! A connection is checked out with [`ConnectionPool::get`] and only goes
+//! back with [`ConnectionPool::put`], so one that failed mid-request is
+//! simply dropped. Before reuse an idle connection must be younger than the
+//! idle timeout and pass its health check; otherwise it is evicted.
+
+use crate::rpc::RpcClient;
+use hdfs_common::error::Result;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+
+/// As Hadoop's client socket cache.
+pub const DEFAULT_MAX_IDLE_PER_KEY: usize = 16;
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(3);
+
+pub trait Poolable: Send + 'static {
+    /// Whether the connection can carry another request. Must not block.
+    fn is_healthy(&mut self) -> bool;
+}
+
+impl Poolable for TcpStream {
+    /// An idle stream has nothing to read: anything there, or the end of
+    /// the stream, means the peer has moved on.
+    fn is_healthy(&mut self) -> bool {
+        matches!(self.try_read(&mut [0u8; 1]), Err(e) if e.kind() == io::ErrorKind::WouldBlock)
+    }
+}
+
+impl Poolable for RpcClient {
+    fn is_healthy(&mut self) -> bool {
+        self.is_open()
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct PoolKey {
+    pub addr: String,
+    /// E.g. a [`Requests::PROTOCOL`](hdfs_wire::message::Requests::PROTOCOL).
+    pub protocol: &'static str,
+}
+
+impl PoolKey {
+    pub fn new(addr: impl Into<String>, protocol: &'static str) -> Self {
+        Self {
+            addr: addr.into(),
+            protocol,
+        }
+    }
+}
+
+/// Counters since the pool was created.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct PoolStats {
+    /// Checkouts served by an idle connection.
+    pub hits: u64,
+    /// Checkouts that found none and needed a new connection.
+    pub misses: u64,
+    /// Idle connections dropped: timed out, unhealthy, or over the per-key
+    /// maximum.
+    pub evictions: u64,
+}
+
+#[derive(Debug)]
+struct Idle<C> {
+    conn: C,
+    since: Instant,
+}
+
+#[derive(Debug)]
+struct PoolInner<C> {
+    max_idle_per_key: usize,
+    idle_timeout: Duration,
+    /// Oldest first.
+    idle: Mutex<HashMap<PoolKey, VecDeque<Idle<C>>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl<C> PoolInner<C> {
+    fn evicted(&self, n: usize) {
+        self.evictions.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    fn evict_idle(&self) -> usize {
+        let mut idle = self.idle.lock().unwrap();
+        let mut evicted = 0;
+        idle.retain(|_, conns| {
+            let before = conns.len();
+            conns.retain(|c| c.since.elapsed() <= self.idle_timeout);
+            evicted += before - conns.len();
+            !conns.is_empty()
+        });
+        drop(idle);
+        self.evicted(evicted);
+        evicted
+    }
+}
+
+/// Clones share the pool.
+#[derive(Debug)]
+pub struct ConnectionPool<C> {
+    inner: Arc<PoolInner<C>>,
+}
+
+impl<C> Clone for ConnectionPool<C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<C: Poolable> ConnectionPool<C> {
+    /// Keeps up to `max_idle_per_key` idle connections per key, each for
+    /// up to `idle_timeout`.
+    pub fn new(max_idle_per_key: usize, idle_timeout: Duration) -> Self {
+        Self {
+            inner: Arc::new(PoolInner {
+                max_idle_per_key,
+                idle_timeout,
+                idle: Mutex::new(HashMap::new()),
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+                evictions: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    pub fn max_idle_per_key(&self) -> usize {
+        self.inner.max_idle_per_key
+    }
+
+    pub fn idle_timeout(&self) -> Duration {
+        self.inner.idle_timeout
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            hits: self.inner.hits.load(Ordering::Relaxed),
+            misses: self.inner.misses.load(Ordering::Relaxed),
+            evictions: self.inner.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Idle connections for `key`.
+    pub fn idle(&self, key: &PoolKey) -> usize {
+        self.inner
+            .idle
+            .lock()
+            .unwrap()
+            .get(key)
+            .map_or(0, VecDeque::len)
+    }
+
+    /// The most recently returned idle connection for `key` that is still
+    /// usable. Counts a hit or a miss.
+    pub fn take(&self, key: &PoolKey) -> Option<C> {
+        let mut idle = self.inner.idle.lock().unwrap();
+        let mut evicted = 0;
+        let mut found = None;
+        if let Some(conns) = idle.get_mut(key) {
+            while let Some(mut c) = conns.pop_back() {
+                if c.since.elapsed() > self.inner.idle_timeout {
+                    // the rest are older still
+                    evicted += 1 + conns.len();
+                    conns.clear();
+                    break;
+                }
+                if c.conn.is_healthy() {
+                    found = Some(c.conn);
+                    break;
+                }
+                evicted += 1;
+            }
+            if conns.is_empty() {
+                idle.remove(key);
+            }
+        }
+        drop(idle);
+        self.inner.evicted(evicted);
+        let counter = match found {
+            Some(_) => &self.inner.hits,
+            None => &self.inner.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
+    /// An idle connection for `key`, or a new one from `connect`.
+    pub async fn get<F, Fut>(&self, key: &PoolKey, connect: F) -> Result<C>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<C>>,
+    {
+        match self.take(key) {
+            Some(conn) => Ok(conn),
+            None => connect().await,
+        }
+    }
+
+    /// Keeps `conn` for reuse; only return a connection between requests.
+    /// The key's longest idle connection is evicted to make room.
+    pub fn put(&self, key: PoolKey, conn: C) {
+        if self.inner.max_idle_per_key == 0 {
+            self.inner.evicted(1);
+            return;
+        }
+        let mut idle = self.inner.idle.lock().unwrap();
+        let conns = idle.entry(key).or_default();
+        let evicted = if conns.len() >= self.inner.max_idle_per_key {
+            conns.pop_front()
+        } else {
+            None
+        };
+        conns.push_back(Idle {
+            conn,
+            since: Instant::now(),
+        });
+        drop(idle);
+        if evicted.is_some() {
+            self.inner.evicted(1);
+        }
+    }
+
+    /// Evicts every connection idle for longer than the idle timeout and
+    /// returns how many there were.
+    pub fn evict_idle(&self) -> usize {
+        self.inner.evict_idle()
+    }
+
+    /// Evicts timed-out connections in the background every idle timeout,
+    /// so idle sockets don't outlive it by much. Ends with the pool.
+    pub fn spawn_reaper(&self) -> JoinHandle<()> {
+        let pool: Weak<PoolInner<C>> = Arc::downgrade(&self.inner);
+        let period = self.inner.idle_timeout.max(Duration::from_millis(1));
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(period).await;
+                match pool.upgrade() {
+                    Some(pool) => pool.evict_idle(),
+                    None => break,
+                };
+            }
+        })
+    }
+}
+
+impl<C: Poolable> Default for ConnectionPool<C> {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_IDLE_PER_KEY, DEFAULT_IDLE_TIMEOUT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::RpcServer;
+    use hdfs_common::error::HdfsError;
+    use hdfs_wire::frame::FrameCodec;
+    use hdfs_wire::handshake::Hello;
+    use tokio::net::TcpListener;
+
+    #[derive(Debug, PartialEq)]
+    struct Conn {
+        id: u32,
+        healthy: bool,
+    }
+
+    impl Poolable for Conn {
+        fn is_healthy(&mut self) -> bool {
+            self.healthy
+        }
+    }
+
+    fn conn(id: u32) -> Conn {
+        Conn { id, healthy: true }
+    }
+
+    fn stats(hits: u64, misses: u64, evictions: u64) -> PoolStats {
+        PoolStats {
+            hits,
+            misses,
+            evictions,
+        }
+    }
+
+    #[test]
+    fn reuse_is_per_key_and_most_recent_first() {
+        let pool = ConnectionPool::new(2, Duration::from_secs(60));
+        let (dn1, dn2) = (PoolKey::new("dn1:9866", "a"), PoolKey::new("dn2:9866", "a"));
+        let dn1_other = PoolKey::new("dn1:9866", "b");
+
+        assert_eq!(pool.take(&dn1), None);
+        pool.put(dn1.clone(), conn(1));
+        pool.put(dn1.clone(), conn(2));
+        pool.put(dn2.clone(), conn(3));
+        assert_eq!(pool.take(&dn1_other), None);
+        assert_eq!(pool.take(&dn1).map(|c| c.id), Some(2));
+        assert_eq!(pool.take(&dn1).map(|c| c.id), Some(1));
+        assert_eq!(pool.take(&dn1), None);
+        assert_eq!(pool.idle(&dn2), 1);
+        assert_eq!(pool.stats(), stats(2, 3, 0));
+
+        // past the maximum the longest idle goes
+        for id in 4..=6 {
+            pool.put(dn1.clone(), conn(id));
+        }
+        assert_eq!(pool.idle(&dn1), 2);
+        assert_eq!(pool.stats().evictions, 1);
+        assert_eq!(pool.take(&dn1).map(|c| c.id), Some(6));
+        assert_eq!(pool.take(&dn1).map(|c| c.id), Some(5));
+
+        let none = ConnectionPool::new(0, Duration::from_secs(60));
+        none.put(dn1.clone(), conn(7));
+        assert_eq!(none.take(&dn1), None);
+        assert_eq!(none.stats(), stats(0, 1, 1));
+    }
+
+    #[test]
+    fn stale_and_broken_connections_are_evicted() {
+        let pool = ConnectionPool::new(4, Duration::from_millis(20));
+        let key = PoolKey::new("dn1:9866", "a");
+        pool.put(key.clone(), conn(1));
+        pool.put(key.clone(), conn(2));
+        std::thread::sleep(Duration::from_millis(30));
+        pool.put(key.clone(), conn(3));
+        pool.put(
+            key.clone(),
+            Conn {
+                id: 4,
+                healthy: false,
+            },
+        );
+        // 4 fails its check, 3 is fine
+        assert_eq!(pool.take(&key).map(|c| c.id), Some(3));
+        assert_eq!(pool.stats(), stats(1, 0, 1));
+        // 2 and 1 timed out
+        assert_eq!(pool.take(&key), None);
+        assert_eq!(pool.stats(), stats(1, 1, 3));
+
+        pool.put(key.clone(), conn(5));
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(pool.evict_idle(), 1);
+        assert_eq!(pool.idle(&key), 0);
+    }
+
+    #[tokio::test]
+    async fn reaper_evicts_in_the_background() {
+        let pool = ConnectionPool::new(4, Duration::from_millis(10));
+        let key = PoolKey::new("dn1:9866", "a");
+        pool.put(key.clone(), conn(1));
+        let reaper = pool.spawn_reaper();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(pool.idle(&key), 0);
+        assert_eq!(pool.stats().evictions, 1);
+
+        drop(pool);
+        tokio::time::timeout(Duration::from_secs(1), reaper)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn closed_sockets_fail_the_health_check() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let pool = ConnectionPool::default();
+        let key = PoolKey::new(&addr, "test");
+        let connect = || async { Ok(TcpStream::connect(&addr).await?) };
+
+        let stream = pool.get(&key, connect).await.unwrap();
+        let (peer, _) = listener.accept().await.unwrap();
+        pool.put(key.clone(), stream);
+        let mut stream = pool.get(&key, connect).await.unwrap();
+        assert_eq!(pool.stats(), stats(1, 1, 0));
+
+        assert!(stream.is_healthy());
+        pool.put(key.clone(), stream);
+        drop(peer);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(pool.take(&key).is_none());
+        assert_eq!(pool.stats(), stats(1, 2, 1));
+    }
+
+    #[tokio::test]
+    async fn rpc_clients_are_pooled_while_open() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = RpcServer::new(Hello::default());
+        let stop = server.shutdown_handle();
+        let serving = tokio::spawn(server.serve(listener));
+
+        let pool = ConnectionPool::<RpcClient>::default();
+        let key = PoolKey::new(&addr, "client");
+        let hello = Hello::default();
+        let connect =
+            || RpcClient::connect(&addr, &hello, FrameCodec::new(), Duration::from_secs(5));
+        let client = pool.get(&key, connect).await.unwrap();
+        pool.put(key.clone(), client);
+        let client = pool.get(&key, connect).await.unwrap();
+        assert_eq!(pool.stats(), stats(1, 1, 0));
+
+        stop.shutdown();
+        serving.await.unwrap().unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!client.is_open());
+        pool.put(key.clone(), client);
+        assert!(pool.take(&key).is_none());
+        assert_eq!(pool.stats().evictions, 1);
+
+        let err = pool.get(&key, connect).await.unwrap_err();
+        assert!(matches!(err, HdfsError::Io(_)), "{err}");
+    }
+}
@@ -0,0 +1,242 @@
+//! When to try a failed call again, and where. A call is only sent again if
+//! it is idempotent or the error shows it never ran, and only for errors
+//! that may go away: a lost connection or a standby namenode moves on to the
+//! next namenode, a timeout tries the same one again.
+
+use hdfs_common::config::ClientConfig;
+use hdfs_common::error::HdfsError;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::time::Duration;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RetryAction {
+    Fail,
+    /// Same target, after `delay`.
+    Retry {
+        delay: Duration,
+    },
+    /// Next target, after `delay`.
+    FailoverAndRetry {
+        delay: Duration,
+    },
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RetryPolicy {
+    TryOnce,
+    Fixed {
+        max_retries: u32,
+        delay: Duration,
+    },
+    /// `base * 2^n` before retry `n`, capped at `max_delay`; each delay is
+    /// then cut by a random amount of up to half, so clients that failed
+    /// together don't all come back together.
+    ExponentialBackoff {
+        max_retries: u32,
+        base: Duration,
+        max_delay: Duration,
+    },
+    /// Any number of retries, `delay` apart, as long as the next one starts
+    /// within `max_time` of the first attempt.
+    UpToMaximumTime {
+        max_time: Duration,
+        delay: Duration,
+    },
+}
+
+impl RetryPolicy {
+    /// Exponential backoff with the client's retry settings.
+    pub fn from_config(cfg: &ClientConfig) -> Self {
+        RetryPolicy::ExponentialBackoff {
+            max_retries: cfg.max_retries,
+            base: cfg.retry_base_delay,
+            max_delay: cfg.retry_max_delay,
+        }
+    }
+
+    /// The wait before retry number `retries` (from 0), `elapsed` after the
+    /// first attempt started; `None` once the policy is used up.
+    pub fn delay(&self, retries: u32, elapsed: Duration) -> Option<Duration> {
+        match *self {
+            RetryPolicy::TryOnce => None,
+            RetryPolicy::Fixed { max_retries, delay } => (retries < max_retries).then_some(delay),
+            RetryPolicy::ExponentialBackoff {
+                max_retries,
+                base,
+                max_delay,
+            } => {
+                if retries >= max_retries {
+                    return None;
+                }
+                let full = base.saturating_mul(1 << retries.min(31)).min(max_delay);
+                Some(full - full.mul_f64(jitter() / 2.0))
+            }
+            RetryPolicy::UpToMaximumTime { max_time, delay } => {
+                (elapsed + delay <= max_time).then_some(delay)
+            }
+        }
+    }
+
+    /// What to do about `err`, the outcome of a call that may only be sent
+    /// again if `idempotent`, after `retries` retries.
+    pub fn decide(
+        &self,
+        err: &HdfsError,
+        idempotent: bool,
+        retries: u32,
+        elapsed: Duration,
+    ) -> RetryAction {
+        if !err.is_retriable() || !(idempotent || err.is_idempotent_safe()) {
+            return RetryAction::Fail;
+        }
+        let Some(delay) = self.delay(retries, elapsed) else {
+            return RetryAction::Fail;
+        };
+        if err.is_standby() || is_connection_failure(err) {
+            RetryAction::FailoverAndRetry { delay }
+        } else {
+            RetryAction::Retry { delay }
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::from_config(&ClientConfig::default())
+    }
+}
+
+/// The target could not be reached or dropped the connection.
+pub fn is_connection_failure(err: &HdfsError) -> bool {
+    match err {
+        HdfsError::Io(e) => matches!(
+            e.kind(),
+            io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::NotConnected
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::UnexpectedEof
+        ),
+        _ => false,
+    }
+}
+
+/// Uniform in `[0, 1)`. The std hasher is keyed randomly per process and
+/// per instance, which is all the randomness backoff needs.
+fn jitter() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn io(kind: io::ErrorKind) -> HdfsError {
+        HdfsError::Io(io::Error::new(kind, "x"))
+    }
+
+    #[test]
+    fn delays() {
+        let ms = Duration::from_millis;
+        let fixed = RetryPolicy::Fixed {
+            max_retries: 2,
+            delay: ms(100),
+        };
+        assert_eq!(fixed.delay(0, ms(0)), Some(ms(100)));
+        assert_eq!(fixed.delay(1, ms(0)), Some(ms(100)));
+        assert_eq!(fixed.delay(2, ms(0)), None);
+        assert_eq!(RetryPolicy::TryOnce.delay(0, ms(0)), None);
+
+        let exp = RetryPolicy::ExponentialBackoff {
+            max_retries: 40,
+            base: ms(100),
+            max_delay: ms(1000),
+        };
+        for (n, full) in [(0, 100), (1, 200), (3, 800), (4, 1000), (39, 1000)] {
+            let d = exp.delay(n, ms(0)).unwrap();
+            assert!(ms(full / 2) <= d && d <= ms(full), "retry {n}: {d:?}");
+        }
+        assert_eq!(exp.delay(40, ms(0)), None);
+        // jittered, not all the same
+        let spread: std::collections::HashSet<_> =
+            (0..20).map(|_| exp.delay(4, ms(0)).unwrap()).collect();
+        assert!(spread.len() > 1);
+
+        let timed = RetryPolicy::UpToMaximumTime {
+            max_time: ms(1000),
+            delay: ms(300),
+        };
+        assert_eq!(timed.delay(50, ms(700)), Some(ms(300)));
+        assert_eq!(timed.delay(0, ms(701)), None);
+
+        assert!(matches!(
+            RetryPolicy::default(),
+            RetryPolicy::ExponentialBackoff {
+                max_retries: 10,
+                ..
+            }
+        ));
+        let cfg = ClientConfig {
+            max_retries: 3,
+            retry_base_delay: ms(20),
+            retry_max_delay: ms(50),
+            ..ClientConfig::default()
+        };
+        assert_eq!(
+            RetryPolicy::from_config(&cfg),
+            RetryPolicy::ExponentialBackoff {
+                max_retries: 3,
+                base: ms(20),
+                max_delay: ms(50),
+            }
+        );
+    }
+
+    #[test]
+    fn decisions_follow_the_error_class() {
+        let policy = RetryPolicy::Fixed {
+            max_retries: 3,
+            delay: Duration::from_millis(10),
+        };
+        let retry = RetryAction::Retry {
+            delay: Duration::from_millis(10),
+        };
+        let failover = RetryAction::FailoverAndRetry {
+            delay: Duration::from_millis(10),
+        };
+        let decide =
+            |err: &HdfsError, idempotent| policy.decide(err, idempotent, 0, Duration::ZERO);
+
+        let standby = HdfsError::State {
+            what: "standby".into(),
+            details: "not active".into(),
+        };
+        let refused = io(io::ErrorKind::ConnectionRefused);
+        let reset = io(io::ErrorKind::ConnectionReset);
+        let timeout = HdfsError::Timeout {
+            op: "Rename".into(),
+            during: "rpc".into(),
+        };
+        // refused before it ran: safe to send anywhere
+        for err in [&standby, &refused] {
+            assert_eq!(decide(err, true), failover, "{err}");
+            assert_eq!(decide(err, false), failover, "{err}");
+        }
+        // may have run: only idempotent calls go again
+        assert_eq!(decide(&reset, true), failover);
+        assert_eq!(decide(&reset, false), RetryAction::Fail);
+        assert_eq!(decide(&timeout, true), retry);
+        assert_eq!(decide(&timeout, false), RetryAction::Fail);
+
+        let nf = HdfsError::NotFound { path: "/a".into() };
+        assert_eq!(decide(&nf, true), RetryAction::Fail);
+        assert_eq!(
+            policy.decide(&standby, true, 3, Duration::ZERO),
+            RetryAction::Fail
+        );
+    }
+}
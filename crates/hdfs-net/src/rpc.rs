@@ -0,0 +1,514 @@
+//! RPC over framed connections. A client keeps many calls in flight on one
+//! connection and matches responses to them by call id; a server routes each
+//! request to the handler registered for its protocol and answers calls in
+//! whatever order they finish.
+//!
+//! Failed calls come back as the `HdfsError` the handler returned. Deadlines
+//! are enforced on both ends: the server stops a handler that overruns its
+//! call timeout, and the client stops waiting after its own.
+
+mod client;
+mod server;
+
+pub use client::RpcClient;
+pub use server::{DEFAULT_MAX_CALLS_IN_FLIGHT, Handler, RpcServer, ShutdownHandle};
+
+use hdfs_common::error::{HdfsError, Result};
+use std::io;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio::task::JoinError;
+
+/// Frames waiting for the writer task of one connection.
+const WRITE_QUEUE: usize = 256;
+
+/// Writes queued frames until every sender is gone, flushing whenever the
+/// queue runs dry, then closes the write side. Frames are queued already
+/// encoded, so one the codec refuses fails only its own call.
+async fn write_frames<W: AsyncWrite + Unpin>(
+    mut w: W,
+    mut rx: mpsc::Receiver<Vec<u8>>,
+) -> Result<()> {
+    while let Some(frame) = rx.recv().await {
+        w.write_all(&frame).await?;
+        if rx.is_empty() {
+            w.flush().await?;
+        }
+    }
+    w.flush().await?;
+    w.shutdown().await?;
+    Ok(())
+}
+
+/// The connection went away with calls outstanding; they may or may not
+/// have run.
+fn connection_lost(reason: &str) -> HdfsError {
+    HdfsError::Io(io::Error::new(
+        io::ErrorKind::ConnectionAborted,
+        format!("rpc connection lost: {reason}"),
+    ))
+}
+
+/// A call that was never sent.
+fn not_connected(reason: &str) -> HdfsError {
+    HdfsError::Io(io::Error::new(
+        io::ErrorKind::NotConnected,
+        format!("rpc connection closed: {reason}"),
+    ))
+}
+
+fn task_failed(e: JoinError) -> HdfsError {
+    HdfsError::State {
+        what: "rpc".into(),
+        details: format!("connection task failed: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::Connection;
+    use hdfs_common::ids::{DatanodeId, INodeId};
+    use hdfs_common::path::PathAbs;
+    use hdfs_wire::client::{
+        ClientRequest, ClientResponse, FileStatus, FileType, GetFileInfoRequest,
+        GetFileInfoResponse, ListStatusRequest, ListStatusResponse, MkdirsRequest, RenameRequest,
+    };
+    use hdfs_wire::datanode::{
+        DatanodeRequest, DatanodeResponse, ErrorCode, ErrorReportRequest, ErrorReportResponse,
+    };
+    use hdfs_wire::frame::FrameCodec;
+    use hdfs_wire::handshake::Hello;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::Semaphore;
+    use tokio::task::JoinHandle;
+
+    /// Answers `GetFileInfo` for `/sleep/<ms>` after that long, fails
+    /// `Mkdirs` with `NotFound`, panics on `/panic` and lists `limit`
+    /// copies of the directory asked for.
+    #[derive(Default)]
+    struct SlowNamenode {
+        calls: AtomicUsize,
+    }
+
+    impl Handler<ClientRequest> for SlowNamenode {
+        async fn handle(&self, req: ClientRequest) -> Result<ClientResponse> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            match req {
+                ClientRequest::GetFileInfo(GetFileInfoRequest { path }) => {
+                    let ms = path
+                        .as_str()
+                        .strip_prefix("/sleep/")
+                        .and_then(|ms| ms.parse().ok())
+                        .unwrap_or(0);
+                    assert_ne!(path.as_str(), "/panic", "handler asked to panic");
+                    tokio::time::sleep(Duration::from_millis(ms)).await;
+                    Ok(GetFileInfoResponse { status: None }.into())
+                }
+                ClientRequest::Mkdirs(MkdirsRequest { path, .. }) => Err(HdfsError::NotFound {
+                    path: path.to_string(),
+                }),
+                ClientRequest::ListStatus(ListStatusRequest { path, limit, .. }) => {
+                    let entry = FileStatus {
+                        inode: INodeId(16386),
+                        path,
+                        file_type: FileType::Dir,
+                        length: 0,
+                        replication: 0,
+                        block_size: 0,
+                        modification_time: 0,
+                        access_time: 0,
+                        permission: 0o755,
+                        owner: "hdfs".into(),
+                        group: "supergroup".into(),
+                        children: 0,
+                    };
+                    Ok(ListStatusResponse {
+                        entries: vec![entry; limit as usize],
+                        remaining: 0,
+                    }
+                    .into())
+                }
+                // anything else gets the wrong response, which the server catches
+                _ => Ok(GetFileInfoResponse { status: None }.into()),
+            }
+        }
+    }
+
+    async fn start(server: RpcServer) -> (String, ShutdownHandle, JoinHandle<Result<()>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let handle = server.shutdown_handle();
+        (addr, handle, tokio::spawn(server.serve(listener)))
+    }
+
+    async fn connect(addr: &str) -> RpcClient {
+        RpcClient::connect(
+            addr,
+            &Hello::default(),
+            FrameCodec::new(),
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap()
+    }
+
+    fn sleep(ms: u64) -> GetFileInfoRequest {
+        GetFileInfoRequest {
+            path: PathAbs::try_from(format!("/sleep/{ms}").as_str()).unwrap(),
+        }
+    }
+
+    /// Waits, without a deadline of its own, until `done` holds.
+    async fn until(done: impl Fn() -> bool) {
+        while !done() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+
+    fn mkdirs(path: &str) -> MkdirsRequest {
+        MkdirsRequest {
+            path: PathAbs::try_from(path).unwrap(),
+            permission: 0o755,
+            create_parent: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn calls_are_multiplexed_and_answered_out_of_order() {
+        let nn = Arc::new(SlowNamenode::default());
+        let server = RpcServer::new(Hello::default()).register::<ClientRequest, _>(nn.clone());
+        let (addr, stop, served) = start(server).await;
+        let client = connect(&addr).await;
+
+        let slow = tokio::spawn({
+            let client = client.clone();
+            async move { client.call(&sleep(1000)).await }
+        });
+        let (fast, missing) = (sleep(10), mkdirs("/missing/dir"));
+        let (fast, err) = tokio::join!(client.call(&fast), client.call(&missing));
+        // answered while the slow call is still running
+        assert!(!slow.is_finished());
+        assert_eq!(fast.unwrap(), GetFileInfoResponse { status: None });
+        assert_eq!(
+            slow.await.unwrap().unwrap(),
+            GetFileInfoResponse { status: None }
+        );
+        match err {
+            Err(HdfsError::NotFound { path }) => assert_eq!(path, "/missing/dir"),
+            other => panic!("expected NotFound, got {other:?}"),
+        }
+        assert_eq!(nn.calls.load(Ordering::SeqCst), 3);
+
+        stop.shutdown();
+        served.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn deadlines_on_both_ends() {
+        let server = RpcServer::new(Hello::default())
+            .with_call_timeout(Duration::from_millis(100))
+            .register::<ClientRequest, _>(Arc::new(SlowNamenode::default()));
+        let (addr, stop, served) = start(server).await;
+        let client = connect(&addr).await;
+
+        match client.call(&sleep(1000)).await {
+            Err(HdfsError::Timeout { op, during }) => {
+                assert_eq!((&*op, &*during), ("GetFileInfo", "handler"));
+            }
+            other => panic!("expected handler timeout, got {other:?}"),
+        }
+
+        // the client gives up first; the late answer is dropped and the
+        // connection stays usable
+        let impatient = client.clone().with_timeout(Duration::from_millis(20));
+        match impatient.call(&sleep(60)).await {
+            Err(HdfsError::Timeout { op, during }) => {
+                assert_eq!((&*op, &*during), ("GetFileInfo", "rpc"));
+            }
+            other => panic!("expected client timeout, got {other:?}"),
+        }
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        assert!(client.call(&sleep(0)).await.is_ok());
+
+        stop.shutdown();
+        served.await.unwrap().unwrap();
+    }
+
+    struct Datanodes;
+
+    impl Handler<DatanodeRequest> for Datanodes {
+        async fn handle(&self, req: DatanodeRequest) -> Result<DatanodeResponse> {
+            match req {
+                DatanodeRequest::ErrorReport(r) if r.message.is_empty() => Err(HdfsError::State {
+                    what: "errorReport".into(),
+                    details: "empty message".into(),
+                }),
+                _ => Ok(ErrorReportResponse {}.into()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn routes_by_protocol() {
+        let server = RpcServer::new(Hello::default())
+            .register::<ClientRequest, _>(SlowNamenode::default())
+            .register::<DatanodeRequest, _>(Datanodes);
+        let (addr, stop, served) = start(server).await;
+        let client = connect(&addr).await;
+
+        let report = |message: &str| ErrorReportRequest {
+            datanode: DatanodeId::new_v4(),
+            code: ErrorCode::Notify,
+            message: message.into(),
+        };
+        assert_eq!(
+            client.call(&report("disk slow")).await.unwrap(),
+            ErrorReportResponse {}
+        );
+        match client.call(&report("")).await {
+            Err(HdfsError::State { what, details }) => {
+                assert_eq!((&*what, details.as_str()), ("errorReport", "empty message"));
+            }
+            other => panic!("expected State error, got {other:?}"),
+        }
+        assert!(client.call(&sleep(0)).await.is_ok());
+
+        // a handler answering with the wrong op is an error for the caller
+        let rename = RenameRequest {
+            src: PathAbs::try_from("/a").unwrap(),
+            dst: PathAbs::try_from("/b").unwrap(),
+            overwrite: false,
+        };
+        match client.call(&rename).await {
+            Err(HdfsError::Protocol { op, details }) if op == "Rename" => {
+                assert!(details.contains("GetFileInfo response"), "{details}");
+            }
+            other => panic!("expected Protocol error, got {other:?}"),
+        }
+
+        stop.shutdown();
+        served.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn unregistered_protocols_are_refused() {
+        let server = RpcServer::new(Hello::default()).register::<DatanodeRequest, _>(Datanodes);
+        let (addr, stop, served) = start(server).await;
+        let client = connect(&addr).await;
+        match client.call(&sleep(0)).await {
+            Err(HdfsError::Protocol { op, details }) => {
+                assert_eq!(op, "dispatch");
+                assert_eq!(details, "no handler for message type 0x0001");
+            }
+            other => panic!("expected Protocol error, got {other:?}"),
+        }
+        stop.shutdown();
+        served.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn panicking_handlers_still_answer() {
+        let server =
+            RpcServer::new(Hello::default()).register::<ClientRequest, _>(SlowNamenode::default());
+        let (addr, stop, served) = start(server).await;
+        let client = connect(&addr).await;
+
+        let panic = GetFileInfoRequest {
+            path: PathAbs::try_from("/panic").unwrap(),
+        };
+        match client.call(&panic).await {
+            Err(HdfsError::State { what, details }) => {
+                assert_eq!(what, "rpc");
+                assert!(details.contains("panicked"), "{details}");
+            }
+            other => panic!("expected State error, got {other:?}"),
+        }
+        assert!(client.call(&sleep(0)).await.is_ok());
+
+        stop.shutdown();
+        served.await.unwrap().unwrap();
+    }
+
+    /// Holds every call until the test lets it through.
+    struct Gate {
+        started: AtomicUsize,
+        open: Semaphore,
+    }
+
+    impl Handler<ClientRequest> for Gate {
+        async fn handle(&self, _: ClientRequest) -> Result<ClientResponse> {
+            self.started.fetch_add(1, Ordering::SeqCst);
+            self.open.acquire().await.unwrap().forget();
+            Ok(GetFileInfoResponse { status: None }.into())
+        }
+    }
+
+    #[tokio::test]
+    async fn calls_in_flight_are_capped_per_connection() {
+        let gate = Arc::new(Gate {
+            started: AtomicUsize::new(0),
+            open: Semaphore::new(0),
+        });
+        let server = RpcServer::new(Hello::default())
+            .with_max_calls_in_flight(2)
+            .register::<ClientRequest, _>(gate.clone());
+        let (addr, stop, served) = start(server).await;
+        let client = connect(&addr).await;
+
+        let calls: Vec<_> = (0..5)
+            .map(|_| {
+                let client = client.clone();
+                tokio::spawn(async move { client.call(&sleep(0)).await })
+            })
+            .collect();
+        until(|| gate.started.load(Ordering::SeqCst) == 2).await;
+        // the rest are not even read while two are running
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(gate.started.load(Ordering::SeqCst), 2);
+
+        gate.open.add_permits(5);
+        for call in calls {
+            assert!(call.await.unwrap().is_ok());
+        }
+        assert_eq!(gate.started.load(Ordering::SeqCst), 5);
+
+        stop.shutdown();
+        served.await.unwrap().unwrap();
+    }
+
+    fn list(limit: u32) -> ListStatusRequest {
+        ListStatusRequest {
+            path: PathAbs::try_from("/").unwrap(),
+            start_after: None,
+            limit,
+        }
+    }
+
+    #[tokio::test]
+    async fn oversized_requests_fail_only_their_call() {
+        let nn = Arc::new(SlowNamenode::default());
+        let server = RpcServer::new(Hello::default()).register::<ClientRequest, _>(nn.clone());
+        let (addr, stop, served) = start(server).await;
+        let codec = FrameCodec::new().with_max_frame_size(256);
+        let client = RpcClient::connect(&addr, &Hello::default(), codec, Duration::from_secs(5))
+            .await
+            .unwrap();
+
+        let in_flight = tokio::spawn({
+            let client = client.clone();
+            async move { client.call(&sleep(100)).await }
+        });
+        until(|| nn.calls.load(Ordering::SeqCst) == 1).await;
+        let big = GetFileInfoRequest {
+            path: PathAbs::try_from(format!("/{}", vec!["a".repeat(100); 3].join("/")).as_str())
+                .unwrap(),
+        };
+        match client.call(&big).await {
+            Err(HdfsError::Protocol { op, details }) => {
+                assert_eq!(op, "encode_frame");
+                assert!(details.contains("exceeds max frame size 256"), "{details}");
+            }
+            other => panic!("expected Protocol error, got {other:?}"),
+        }
+        assert!(in_flight.await.unwrap().is_ok());
+        assert!(client.call(&sleep(0)).await.is_ok());
+        assert_eq!(nn.calls.load(Ordering::SeqCst), 2);
+
+        stop.shutdown();
+        served.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn oversized_responses_fail_only_their_call() {
+        let server = RpcServer::new(Hello::default())
+            .with_codec(FrameCodec::new().with_max_frame_size(1024))
+            .register::<ClientRequest, _>(SlowNamenode::default());
+        let (addr, stop, served) = start(server).await;
+        let client = connect(&addr).await;
+
+        assert_eq!(client.call(&list(2)).await.unwrap().entries.len(), 2);
+        match client.call(&list(100)).await {
+            Err(HdfsError::Protocol { op, details }) => {
+                assert_eq!(op, "encode_frame");
+                assert!(details.contains("exceeds max frame size 1024"), "{details}");
+            }
+            other => panic!("expected Protocol error, got {other:?}"),
+        }
+        assert!(client.call(&sleep(0)).await.is_ok());
+
+        stop.shutdown();
+        served.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn server_shutdown_answers_calls_in_flight() {
+        let nn = Arc::new(SlowNamenode::default());
+        let server = RpcServer::new(Hello::default()).register::<ClientRequest, _>(nn.clone());
+        let (addr, stop, served) = start(server).await;
+        let client = connect(&addr).await;
+
+        let in_flight = tokio::spawn({
+            let client = client.clone();
+            async move { client.call(&sleep(150)).await }
+        });
+        until(|| nn.calls.load(Ordering::SeqCst) == 1).await;
+        stop.shutdown();
+        assert!(in_flight.await.unwrap().is_ok());
+        served.await.unwrap().unwrap();
+
+        // the connection is gone; the call never went out
+        let err = client.call(&sleep(0)).await.unwrap_err();
+        assert!(err.is_retriable() && err.is_idempotent_safe(), "{err}");
+        assert!(TcpStream::connect(&addr).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn client_shutdown_waits_for_its_calls() {
+        let nn = Arc::new(SlowNamenode::default());
+        let server = RpcServer::new(Hello::default()).register::<ClientRequest, _>(nn.clone());
+        let (addr, stop, served) = start(server).await;
+        let client = connect(&addr).await;
+
+        let in_flight = tokio::spawn({
+            let client = client.clone();
+            async move { client.call(&sleep(100)).await }
+        });
+        until(|| nn.calls.load(Ordering::SeqCst) == 1).await;
+        client.shutdown().await.unwrap();
+        assert!(in_flight.await.unwrap().is_ok());
+        match client.call(&sleep(0)).await {
+            Err(HdfsError::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::NotConnected),
+            other => panic!("expected NotConnected, got {other:?}"),
+        }
+
+        stop.shutdown();
+        served.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn dead_server_fails_waiting_calls() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        // handshakes, reads one request and hangs up without answering
+        let peer = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut conn = Connection::establish(stream, &Hello::default(), FrameCodec::new())
+                .await
+                .unwrap();
+            conn.recv().await.unwrap().unwrap();
+        });
+        let client = connect(&addr).await;
+        let err = client.call(&sleep(0)).await.unwrap_err();
+        peer.await.unwrap();
+        match &err {
+            HdfsError::Io(e) => assert_eq!(e.kind(), std::io::ErrorKind::ConnectionAborted),
+            other => panic!("expected ConnectionAborted, got {other:?}"),
+        }
+        // it may have run, so only idempotent calls should be retried
+        assert!(err.is_retriable() && !err.is_idempotent_safe());
+    }
+}
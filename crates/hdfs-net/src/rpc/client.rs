@@ -0,0 +1,214 @@
+use super::{WRITE_QUEUE, connection_lost, not_connected, task_failed, write_frames};
+use crate::connection::Connection;
+use hdfs_common::error::{HdfsError, Result};
+use hdfs_wire::frame::{Frame, FrameCodec};
+use hdfs_wire::handshake::{Hello, Negotiated};
+use hdfs_wire::message::{Call, decode_response, request_frame};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+/// Calls waiting for their response, by call id.
+#[derive(Debug, Default)]
+struct Calls {
+    waiting: HashMap<u32, oneshot::Sender<Frame>>,
+    /// Why the connection went down, once it has.
+    lost: Option<String>,
+}
+
+impl Calls {
+    /// Fails every waiting call; their senders are dropped.
+    fn lose(&mut self, reason: String) {
+        self.lost.get_or_insert(reason);
+        self.waiting.clear();
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    /// `None` once shut down.
+    tx: Mutex<Option<mpsc::Sender<Vec<u8>>>>,
+    codec: FrameCodec,
+    calls: Arc<Mutex<Calls>>,
+    next_call_id: AtomicU32,
+    negotiated: Negotiated,
+    reader: Mutex<Option<JoinHandle<()>>>,
+}
+
+/// One connection to an RPC server. Clones share the connection and may
+/// call concurrently; it closes once all of them are dropped or on
+/// [`RpcClient::shutdown`].
+#[derive(Clone, Debug)]
+pub struct RpcClient {
+    inner: Arc<Inner>,
+    timeout: Duration,
+}
+
+impl RpcClient {
+    /// Connects and runs the handshake, both within `timeout`, which is
+    /// also the deadline of each call.
+    pub async fn connect(
+        addr: &str,
+        hello: &Hello,
+        codec: FrameCodec,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let setup = async {
+            let stream = TcpStream::connect(addr).await?;
+            stream.set_nodelay(true)?;
+            Connection::establish(stream, hello, codec).await
+        };
+        let conn =
+            tokio::time::timeout(timeout, setup)
+                .await
+                .map_err(|_| HdfsError::Timeout {
+                    op: "connect".into(),
+                    during: "rpc handshake".into(),
+                })??;
+        Ok(Self::new(conn, timeout))
+    }
+
+    /// Takes over an established connection. Must be called within a tokio
+    /// runtime, which runs the connection's reader and writer.
+    pub fn new<S>(conn: Connection<S>, timeout: Duration) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (stream, codec, negotiated) = conn.into_parts();
+        let (rd, wr) = tokio::io::split(stream);
+        let (tx, rx) = mpsc::channel(WRITE_QUEUE);
+        let calls = Arc::new(Mutex::new(Calls::default()));
+
+        let writer_calls = calls.clone();
+        tokio::spawn(async move {
+            if let Err(e) = write_frames(wr, rx).await {
+                writer_calls.lock().unwrap().lose(e.to_string());
+            }
+        });
+        let reader = tokio::spawn(read_responses(rd, codec, calls.clone()));
+
+        Self {
+            inner: Arc::new(Inner {
+                tx: Mutex::new(Some(tx)),
+                codec,
+                calls,
+                next_call_id: AtomicU32::new(1),
+                negotiated,
+                reader: Mutex::new(Some(reader)),
+            }),
+            timeout,
+        }
+    }
+
+    /// Deadline for calls made through this clone.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn negotiated(&self) -> &Negotiated {
+        &self.inner.negotiated
+    }
+
+    /// Neither shut down nor lost; calls may still fail if the connection
+    /// goes down in the meantime.
+    pub fn is_open(&self) -> bool {
+        self.inner.tx.lock().unwrap().is_some() && self.inner.calls.lock().unwrap().lost.is_none()
+    }
+
+    /// Sends `req` and waits for its response. An error the server sent
+    /// back is returned as the typed error; a missed deadline as
+    /// `Timeout { op: C::NAME, during: "rpc" }`. A request too big for
+    /// the connection fails without being sent.
+    pub async fn call<C: Call>(&self, req: &C) -> Result<C::Response> {
+        let call_id = self.inner.next_call_id.fetch_add(1, Ordering::Relaxed);
+        let frame = self
+            .inner
+            .codec
+            .encode_to_vec(&request_frame(call_id, req))?;
+        let (done, response) = oneshot::channel();
+        let tx = {
+            let Some(tx) = self.inner.tx.lock().unwrap().clone() else {
+                return Err(not_connected("client shut down"));
+            };
+            let mut calls = self.inner.calls.lock().unwrap();
+            if let Some(reason) = &calls.lost {
+                return Err(not_connected(reason));
+            }
+            calls.waiting.insert(call_id, done);
+            tx
+        };
+
+        let exchange = async {
+            let sent = tx.send(frame).await;
+            // the writer finishes once every in-flight sender is gone
+            drop(tx);
+            if sent.is_err() {
+                return Err(self.lost());
+            }
+            response.await.map_err(|_| self.lost())
+        };
+        match tokio::time::timeout(self.timeout, exchange).await {
+            Ok(frame) => decode_response::<C>(&frame?),
+            Err(_) => {
+                // a late response finds nobody waiting and is dropped
+                self.inner.calls.lock().unwrap().waiting.remove(&call_id);
+                Err(HdfsError::Timeout {
+                    op: C::NAME.into(),
+                    during: "rpc".into(),
+                })
+            }
+        }
+    }
+
+    /// Refuses new calls, waits for the ones in flight to be answered and
+    /// closes the connection. Applies to every clone.
+    pub async fn shutdown(&self) -> Result<()> {
+        drop(self.inner.tx.lock().unwrap().take());
+        let reader = self.inner.reader.lock().unwrap().take();
+        if let Some(reader) = reader {
+            tokio::time::timeout(self.timeout, reader)
+                .await
+                .map_err(|_| HdfsError::Timeout {
+                    op: "shutdown".into(),
+                    during: "rpc".into(),
+                })?
+                .map_err(task_failed)?;
+        }
+        Ok(())
+    }
+
+    fn lost(&self) -> HdfsError {
+        let calls = self.inner.calls.lock().unwrap();
+        connection_lost(calls.lost.as_deref().unwrap_or("closed"))
+    }
+}
+
+async fn read_responses<R: AsyncRead + Unpin>(
+    mut r: R,
+    codec: FrameCodec,
+    calls: Arc<Mutex<Calls>>,
+) {
+    let reason = loop {
+        match codec.read_frame_async(&mut r).await {
+            Ok(Some(frame)) => {
+                let waiting = calls.lock().unwrap().waiting.remove(&frame.call_id);
+                if let Some(done) = waiting {
+                    let _ = done.send(frame);
+                }
+            }
+            Ok(None) => break "closed by server".to_string(),
+            Err(e) => break e.to_string(),
+        }
+    };
+    calls.lock().unwrap().lose(reason);
+}
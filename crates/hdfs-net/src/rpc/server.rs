@@ -0,0 +1,299 @@
+use super::{WRITE_QUEUE, task_failed, write_frames};
+use crate::connection::Connection;
+use hdfs_common::consts::DEFAULT_SOCKET_TIMEOUT;
+use hdfs_common::error::{HdfsError, Result};
+use hdfs_wire::frame::{Frame, FrameCodec};
+use hdfs_wire::handshake::Hello;
+use hdfs_wire::message::{Requests, error_frame};
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio::sync::{Semaphore, mpsc, watch};
+use tokio::task::{JoinError, JoinSet};
+
+/// Calls one connection may have running before the server stops reading
+/// its requests.
+pub const DEFAULT_MAX_CALLS_IN_FLIGHT: usize = 256;
+
+/// Pause before accepting again when out of file descriptors.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Serves the requests of one protocol, e.g. `Handler<ClientRequest>` for
+/// the namenode's client protocol. Calls run concurrently.
+pub trait Handler<R: Requests>: Send + Sync + 'static {
+    fn handle(&self, req: R) -> impl Future<Output = Result<R::Response>> + Send;
+}
+
+impl<R: Requests, H: Handler<R>> Handler<R> for Arc<H> {
+    fn handle(&self, req: R) -> impl Future<Output = Result<R::Response>> + Send {
+        (**self).handle(req)
+    }
+}
+
+type Answer = Pin<Box<dyn Future<Output = Frame> + Send>>;
+
+/// A registered handler with its protocol erased.
+trait Route: Send + Sync {
+    fn protocol(&self) -> &'static str;
+
+    fn handles(&self, msg_type: u16) -> bool;
+
+    /// The response or error frame for `frame`.
+    fn dispatch(&self, frame: Frame, deadline: Duration) -> Answer;
+}
+
+struct Routed<R, H> {
+    handler: Arc<H>,
+    _requests: PhantomData<fn(R)>,
+}
+
+impl<R: Requests, H: Handler<R>> Route for Routed<R, H> {
+    fn protocol(&self) -> &'static str {
+        R::PROTOCOL
+    }
+
+    fn handles(&self, msg_type: u16) -> bool {
+        R::handles(msg_type)
+    }
+
+    fn dispatch(&self, frame: Frame, deadline: Duration) -> Answer {
+        let handler = self.handler.clone();
+        Box::pin(async move {
+            let call_id = frame.call_id;
+            let req = match R::decode(&frame) {
+                Ok(req) => req,
+                Err(e) => return error_frame(call_id, &e),
+            };
+            let name = req.name();
+            let answer = match tokio::time::timeout(deadline, handler.handle(req)).await {
+                Ok(resp) => resp.and_then(|resp| R::encode_response(name, call_id, &resp)),
+                Err(_) => Err(HdfsError::Timeout {
+                    op: name.into(),
+                    during: "handler".into(),
+                }),
+            };
+            answer.unwrap_or_else(|e| error_frame(call_id, &e))
+        })
+    }
+}
+
+/// Stops an [`RpcServer`] from another task.
+#[derive(Clone, Debug)]
+pub struct ShutdownHandle(Arc<watch::Sender<bool>>);
+
+impl ShutdownHandle {
+    /// Stops accepting connections and reading requests. Calls already
+    /// read are still answered before each connection closes.
+    pub fn shutdown(&self) {
+        self.0.send_replace(true);
+    }
+}
+
+pub struct RpcServer {
+    hello: Hello,
+    codec: FrameCodec,
+    call_timeout: Duration,
+    max_calls_in_flight: usize,
+    routes: Vec<Box<dyn Route>>,
+    shutdown: Arc<watch::Sender<bool>>,
+}
+
+impl fmt::Debug for RpcServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RpcServer")
+            .field("hello", &self.hello)
+            .field("codec", &self.codec)
+            .field("call_timeout", &self.call_timeout)
+            .field("max_calls_in_flight", &self.max_calls_in_flight)
+            .field(
+                "protocols",
+                &self.routes.iter().map(|r| r.protocol()).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl RpcServer {
+    pub fn new(hello: Hello) -> Self {
+        Self {
+            hello,
+            codec: FrameCodec::new(),
+            call_timeout: DEFAULT_SOCKET_TIMEOUT,
+            max_calls_in_flight: DEFAULT_MAX_CALLS_IN_FLIGHT,
+            routes: Vec::new(),
+            shutdown: Arc::new(watch::channel(false).0),
+        }
+    }
+
+    /// Limits and preferences for frames; features come from the handshake.
+    pub fn with_codec(mut self, codec: FrameCodec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// How long a handler may take before the caller gets a timeout
+    /// instead; also bounds the handshake.
+    pub fn with_call_timeout(mut self, timeout: Duration) -> Self {
+        self.call_timeout = timeout;
+        self
+    }
+
+    /// Per connection; at least 1.
+    pub fn with_max_calls_in_flight(mut self, calls: usize) -> Self {
+        self.max_calls_in_flight = calls.max(1);
+        self
+    }
+
+    /// Sends requests of `R`'s protocol to `handler`.
+    ///
+    /// # Panics
+    ///
+    /// If a handler for that protocol is already registered.
+    pub fn register<R: Requests, H: Handler<R>>(mut self, handler: H) -> Self {
+        assert!(
+            self.routes.iter().all(|r| r.protocol() != R::PROTOCOL),
+            "{} protocol registered twice",
+            R::PROTOCOL
+        );
+        self.routes.push(Box::new(Routed {
+            handler: Arc::new(handler),
+            _requests: PhantomData,
+        }));
+        self
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle(self.shutdown.clone())
+    }
+
+    /// Accepts connections until shut down, then waits for each one to
+    /// finish its calls and close. A failed accept is logged and the server
+    /// carries on, pausing first if it ran out of file descriptors.
+    pub async fn serve(self, listener: TcpListener) -> Result<()> {
+        let server = Arc::new(self);
+        let mut stop = server.shutdown.subscribe();
+        let mut conns = JoinSet::new();
+        loop {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = stop.wait_for(|stop| *stop) => break,
+            };
+            match accepted {
+                Ok((stream, peer)) => match stream.set_nodelay(true) {
+                    Ok(()) => {
+                        conns.spawn(server.clone().serve_connection(stream));
+                    }
+                    Err(e) => log::warn!("rpc connection from {peer} dropped: {e}"),
+                },
+                Err(e) => {
+                    log::warn!("rpc accept failed: {e}");
+                    if out_of_descriptors(&e) {
+                        tokio::time::sleep(ACCEPT_BACKOFF).await;
+                    }
+                }
+            }
+            while conns.try_join_next().is_some() {}
+        }
+        drop(listener);
+        while conns.join_next().await.is_some() {}
+        Ok(())
+    }
+
+    async fn serve_connection<S>(self: Arc<Self>, stream: S) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let mut stop = self.shutdown.subscribe();
+        let establish = Connection::establish(stream, &self.hello, self.codec);
+        let conn = tokio::time::timeout(self.call_timeout, establish)
+            .await
+            .map_err(|_| HdfsError::Timeout {
+                op: "accept".into(),
+                during: "rpc handshake".into(),
+            })??;
+        let (stream, codec, _) = conn.into_parts();
+        let (mut rd, wr) = tokio::io::split(stream);
+        let (tx, rx) = mpsc::channel(WRITE_QUEUE);
+        let writer = tokio::spawn(write_frames(wr, rx));
+        let in_flight = Arc::new(Semaphore::new(self.max_calls_in_flight));
+
+        let read = loop {
+            // at the limit, requests wait in the socket until a call finishes
+            let permit = tokio::select! {
+                permit = in_flight.clone().acquire_owned() => permit.expect("never closed"),
+                _ = stop.wait_for(|stop| *stop) => break Ok(()),
+                // the writer failed; nothing read from here on could be answered
+                _ = tx.closed() => break Ok(()),
+            };
+            let frame = tokio::select! {
+                frame = codec.read_frame_async(&mut rd) => frame,
+                _ = stop.wait_for(|stop| *stop) => break Ok(()),
+                _ = tx.closed() => break Ok(()),
+            };
+            let frame = match frame {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            };
+            let reply = tx.clone();
+            match self.routes.iter().find(|r| r.handles(frame.msg_type)) {
+                Some(route) => {
+                    let call_id = frame.call_id;
+                    let answer = tokio::spawn(route.dispatch(frame, self.call_timeout));
+                    tokio::spawn(async move {
+                        // a handler that panicked still gets its caller an answer
+                        let frame = answer
+                            .await
+                            .unwrap_or_else(|e| error_frame(call_id, &handler_failed(e)));
+                        if let Some(frame) = encode_answer(&codec, frame) {
+                            let _ = reply.send(frame).await;
+                        }
+                        drop(permit);
+                    });
+                }
+                None => {
+                    let err = HdfsError::Protocol {
+                        op: "dispatch".into(),
+                        details: format!("no handler for message type 0x{:04X}", frame.msg_type),
+                    };
+                    if let Some(frame) = encode_answer(&codec, error_frame(frame.call_id, &err)) {
+                        let _ = reply.send(frame).await;
+                    }
+                }
+            }
+        };
+        // the writer closes once the last answer in flight is written
+        drop(tx);
+        writer.await.map_err(task_failed)??;
+        read
+    }
+}
+
+/// Encodes an answer, replacing one the codec refuses with the error that
+/// says why. `None` only if even that does not fit, and the caller is left
+/// to its deadline.
+fn encode_answer(codec: &FrameCodec, frame: Frame) -> Option<Vec<u8>> {
+    codec
+        .encode_to_vec(&frame)
+        .or_else(|e| codec.encode_to_vec(&error_frame(frame.call_id, &e)))
+        .inspect_err(|e| log::warn!("rpc answer to call {} dropped: {e}", frame.call_id))
+        .ok()
+}
+
+fn handler_failed(e: JoinError) -> HdfsError {
+    HdfsError::State {
+        what: "rpc".into(),
+        details: format!("handler failed: {e}"),
+    }
+}
+
+/// EMFILE or ENFILE: accepting again right away would fail the same way.
+fn out_of_descriptors(e: &io::Error) -> bool {
+    matches!(e.raw_os_error(), Some(23 | 24))
+}
@@ -0,0 +1,232 @@
+//! Data transfer connections to datanodes: clients reading blocks and
+//! datanodes copying replicas to each other both open them here, from a
+//! shared [`ConnectionPool`]. Once an op has run to completion the stream is
+//! released back to the pool for the next op to the same datanode.
+
+use crate::pool::{ConnectionPool, PoolKey};
+use hdfs_common::consts::DEFAULT_SOCKET_TIMEOUT;
+use hdfs_common::error::{HdfsError, Result};
+use hdfs_wire::transfer::OpHeader;
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+/// Pool key protocol of data transfer streams.
+pub const DATA_TRANSFER_PROTOCOL: &str = "data-transfer";
+
+#[derive(Clone, Debug)]
+pub struct BlockConnections {
+    pool: ConnectionPool<TcpStream>,
+    timeout: Duration,
+}
+
+impl BlockConnections {
+    /// `timeout` bounds connecting and sending the op header.
+    pub fn new(pool: ConnectionPool<TcpStream>, timeout: Duration) -> Self {
+        Self { pool, timeout }
+    }
+
+    pub fn pool(&self) -> &ConnectionPool<TcpStream> {
+        &self.pool
+    }
+
+    /// Starts `header`'s op on the datanode at `addr`, on an idle stream if
+    /// the pool has one. A pooled stream that can't take the header is
+    /// replaced by a new one, once.
+    pub async fn open(&self, addr: &str, header: &OpHeader) -> Result<BlockStream> {
+        let key = PoolKey::new(addr, DATA_TRANSFER_PROTOCOL);
+        if let Some(mut stream) = self.pool.take(&key)
+            && self.send_header(&mut stream, header).await.is_ok()
+        {
+            return Ok(self.stream(key, stream));
+        }
+        let mut stream = tokio::time::timeout(self.timeout, TcpStream::connect(addr))
+            .await
+            .map_err(|_| HdfsError::Timeout {
+                op: "connect".into(),
+                during: "data transfer".into(),
+            })??;
+        stream.set_nodelay(true)?;
+        self.send_header(&mut stream, header).await?;
+        Ok(self.stream(key, stream))
+    }
+
+    async fn send_header(&self, stream: &mut TcpStream, header: &OpHeader) -> Result<()> {
+        tokio::time::timeout(self.timeout, header.write_to_async(stream))
+            .await
+            .map_err(|_| HdfsError::Timeout {
+                op: "send_op".into(),
+                during: "data transfer".into(),
+            })?
+    }
+
+    fn stream(&self, key: PoolKey, stream: TcpStream) -> BlockStream {
+        BlockStream {
+            key,
+            stream,
+            pool: self.pool.clone(),
+        }
+    }
+}
+
+impl Default for BlockConnections {
+    fn default() -> Self {
+        Self::new(ConnectionPool::default(), DEFAULT_SOCKET_TIMEOUT)
+    }
+}
+
+/// A stream with an op under way. Dropping it closes the connection.
+#[derive(Debug)]
+pub struct BlockStream {
+    key: PoolKey,
+    stream: TcpStream,
+    pool: ConnectionPool<TcpStream>,
+}
+
+impl BlockStream {
+    pub fn addr(&self) -> &str {
+        &self.key.addr
+    }
+
+    pub fn get_ref(&self) -> &TcpStream {
+        &self.stream
+    }
+
+    pub fn get_mut(&mut self) -> &mut TcpStream {
+        &mut self.stream
+    }
+
+    /// Hands the connection back for reuse. Only once the op is complete:
+    /// the last packet read or the last ack received.
+    pub fn release(self) {
+        self.pool.put(self.key, self.stream);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pool::PoolStats;
+    use hdfs_common::ids::{BlockId, GenerationStamp};
+    use hdfs_common::types::ExtendedBlock;
+    use hdfs_wire::transfer::{ReadBlockOp, TransferBlockOp};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    fn block() -> ExtendedBlock {
+        ExtendedBlock::new(
+            "6f1c3a52-0d1e-4f4b-9a7e-2b8f4c1d9e30".parse().unwrap(),
+            BlockId(1 << 30),
+            GenerationStamp(1001),
+            0,
+        )
+    }
+
+    fn read_block() -> OpHeader {
+        OpHeader::ReadBlock(ReadBlockOp {
+            block: block(),
+            client: "DFSClient_1".into(),
+            offset: 0,
+            length: 4,
+            send_checksums: true,
+        })
+    }
+
+    fn transfer_block() -> OpHeader {
+        OpHeader::TransferBlock(TransferBlockOp {
+            block: block(),
+            client: String::new(),
+            targets: Vec::new(),
+        })
+    }
+
+    /// Serves any number of ops per connection, answering each with `b"ok"`;
+    /// reports the ops it reads and counts connections.
+    async fn datanode() -> (String, Arc<AtomicUsize>, mpsc::UnboundedReceiver<OpHeader>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let (tx, rx) = mpsc::unbounded_channel();
+        let count = accepted.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                count.fetch_add(1, Ordering::SeqCst);
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    while let Ok(op) = OpHeader::read_from_async(&mut stream).await {
+                        tx.send(op).unwrap();
+                        stream.write_all(b"ok").await.unwrap();
+                    }
+                });
+            }
+        });
+        (addr, accepted, rx)
+    }
+
+    async fn finish(stream: &mut BlockStream) {
+        use tokio::io::AsyncReadExt;
+        let mut ok = [0u8; 2];
+        stream.get_mut().read_exact(&mut ok).await.unwrap();
+        assert_eq!(&ok, b"ok");
+    }
+
+    #[tokio::test]
+    async fn released_streams_carry_the_next_op() {
+        let (addr, accepted, mut ops) = datanode().await;
+        let conns = BlockConnections::default();
+
+        // a client read, then a replica transfer to the same node
+        let mut s = conns.open(&addr, &read_block()).await.unwrap();
+        assert_eq!(s.addr(), addr);
+        finish(&mut s).await;
+        s.release();
+        let mut s = conns.open(&addr, &transfer_block()).await.unwrap();
+        finish(&mut s).await;
+        assert_eq!(ops.recv().await.unwrap(), read_block());
+        assert_eq!(ops.recv().await.unwrap(), transfer_block());
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            conns.pool().stats(),
+            PoolStats {
+                hits: 1,
+                misses: 1,
+                evictions: 0
+            }
+        );
+
+        // not released: the next op needs a new connection
+        drop(s);
+        let mut s = conns.open(&addr, &read_block()).await.unwrap();
+        finish(&mut s).await;
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn dead_pooled_streams_are_replaced() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let conns = BlockConnections::default();
+        let s = conns.open(&addr, &read_block()).await.unwrap();
+        let (peer, _) = listener.accept().await.unwrap();
+        s.release();
+
+        // the datanode closes the idle connection
+        drop(peer);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        conns.open(&addr, &read_block()).await.unwrap();
+        let (mut peer, _) = listener.accept().await.unwrap();
+        assert_eq!(
+            OpHeader::read_from_async(&mut peer).await.unwrap(),
+            read_block()
+        );
+        assert_eq!(conns.pool().stats().evictions, 1);
+
+        drop(listener);
+        assert!(matches!(
+            conns.open(&addr, &read_block()).await,
+            Err(HdfsError::Io(_))
+        ));
+    }
+}
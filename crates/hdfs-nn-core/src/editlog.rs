@@ -0,0 +1,91 @@
+use crate::settings::NameNodeSettings;
+use hdfs_common::clock::{IntervalTimer, SharedClock};
+use std::sync::Arc;
+
+/// Decides when the active edit-log segment should be finalized and a new
+/// one started: after the roll period, or once it holds too many txns.
+#[derive(Debug)]
+pub struct RollPolicy {
+    timer: IntervalTimer,
+    settings: Arc<NameNodeSettings>,
+    max_txns: u64,
+    txns: u64,
+}
+
+impl RollPolicy {
+    pub fn new(clock: SharedClock, settings: Arc<NameNodeSettings>, max_txns: u64) -> Self {
+        Self {
+            timer: IntervalTimer::new(clock),
+            settings,
+            max_txns,
+            txns: 0,
+        }
+    }
+
+    pub fn record_txn(&mut self) {
+        self.txns += 1;
+    }
+
+    pub fn txns_in_segment(&self) -> u64 {
+        self.txns
+    }
+
+    pub fn should_roll(&self) -> bool {
+        if self.txns == 0 {
+            return false;
+        }
+        self.txns >= self.max_txns || self.timer.is_due(self.settings.edit_log_roll_period())
+    }
+
+    pub fn rolled(&mut self) {
+        self.txns = 0;
+        self.timer.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hdfs_common::clock::ManualClock;
+    use hdfs_common::config::ClusterConfig;
+    use std::time::Duration;
+
+    fn setup(max_txns: u64) -> (Arc<ManualClock>, RollPolicy) {
+        let clock = Arc::new(ManualClock::new());
+        let mut cfg = ClusterConfig::default();
+        cfg.namenode.edit_log_roll_period = Duration::from_secs(120);
+        let settings = Arc::new(NameNodeSettings::new(&cfg));
+        (clock.clone(), RollPolicy::new(clock, settings, max_txns))
+    }
+
+    #[test]
+    fn rolls_on_period() {
+        let (clock, mut rp) = setup(1_000);
+        rp.record_txn();
+        clock.advance(Duration::from_secs(119));
+        assert!(!rp.should_roll());
+        clock.advance(Duration::from_secs(1));
+        assert!(rp.should_roll());
+
+        rp.rolled();
+        assert!(!rp.should_roll());
+        assert_eq!(rp.txns_in_segment(), 0);
+    }
+
+    #[test]
+    fn rolls_on_txn_count() {
+        let (_, mut rp) = setup(3);
+        rp.record_txn();
+        rp.record_txn();
+        assert!(!rp.should_roll());
+        rp.record_txn();
+        assert!(rp.should_roll());
+    }
+
+    #[test]
+    fn empty_segment_never_rolls() {
+        let (clock, rp) = setup(3);
+        clock.advance(Duration::from_secs(3600));
+        assert!(!rp.should_roll());
+    }
+}
@@ -0,0 +1,152 @@
+use crate::settings::NameNodeSettings;
+use hdfs_common::clock::SharedClock;
+use hdfs_common::ids::DatanodeId;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Heartbeats a datanode may miss before it is considered stale.
+pub const STALE_AFTER_HEARTBEATS: u32 = 10;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Liveness {
+    Live,
+    /// Missed several heartbeats; avoid it for reads and new pipelines.
+    Stale,
+    /// Gone long enough that its replicas must be re-replicated.
+    Dead,
+}
+
+/// Tracks the last heartbeat of every registered datanode.
+#[derive(Debug)]
+pub struct HeartbeatMonitor {
+    clock: SharedClock,
+    settings: Arc<NameNodeSettings>,
+    last_seen: HashMap<DatanodeId, Instant>,
+}
+
+impl HeartbeatMonitor {
+    pub fn new(clock: SharedClock, settings: Arc<NameNodeSettings>) -> Self {
+        Self {
+            clock,
+            settings,
+            last_seen: HashMap::new(),
+        }
+    }
+
+    pub fn stale_interval(&self) -> Duration {
+        self.settings.heartbeat_interval() * STALE_AFTER_HEARTBEATS
+    }
+
+    /// `2 * recheck + 10 * heartbeat`, re-read on every call so a live
+    /// reconfiguration of either interval takes effect at once.
+    pub fn dead_interval(&self) -> Duration {
+        2 * self.settings.heartbeat_recheck_interval() + self.settings.heartbeat_interval() * 10
+    }
+
+    pub fn heartbeat(&mut self, dn: DatanodeId) {
+        self.last_seen.insert(dn, self.clock.now());
+    }
+
+    pub fn remove(&mut self, dn: &DatanodeId) -> bool {
+        self.last_seen.remove(dn).is_some()
+    }
+
+    pub fn liveness(&self, dn: &DatanodeId) -> Option<Liveness> {
+        let last = self.last_seen.get(dn)?;
+        let silent = self.clock.now().saturating_duration_since(*last);
+        Some(if silent > self.dead_interval() {
+            Liveness::Dead
+        } else if silent > self.stale_interval() {
+            Liveness::Stale
+        } else {
+            Liveness::Live
+        })
+    }
+
+    pub fn nodes_in(&self, state: Liveness) -> Vec<DatanodeId> {
+        let mut out: Vec<DatanodeId> = self
+            .last_seen
+            .keys()
+            .filter(|dn| self.liveness(dn) == Some(state))
+            .copied()
+            .collect();
+        out.sort();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hdfs_common::clock::ManualClock;
+    use hdfs_common::config::ClusterConfig;
+    use hdfs_common::config::reconfig::ReconfigSubscriber;
+
+    fn setup() -> (Arc<ManualClock>, Arc<NameNodeSettings>, HeartbeatMonitor) {
+        let clock = Arc::new(ManualClock::new());
+        let mut cfg = ClusterConfig::default();
+        cfg.namenode.heartbeat_recheck_interval = Duration::from_secs(300);
+        let settings = Arc::new(NameNodeSettings::new(&cfg));
+        let hm = HeartbeatMonitor::new(clock.clone(), settings.clone());
+        (clock, settings, hm)
+    }
+
+    #[test]
+    fn live_stale_dead() {
+        let (clock, _, mut hm) = setup();
+        let dn = DatanodeId::new_v4();
+        assert_eq!(hm.liveness(&dn), None);
+
+        hm.heartbeat(dn);
+        assert_eq!(hm.liveness(&dn), Some(Liveness::Live));
+
+        clock.advance(Duration::from_secs(31));
+        assert_eq!(hm.liveness(&dn), Some(Liveness::Stale));
+        assert_eq!(hm.nodes_in(Liveness::Stale), vec![dn]);
+
+        hm.heartbeat(dn);
+        assert_eq!(hm.liveness(&dn), Some(Liveness::Live));
+
+        // 2 * 300s + 10 * 3s
+        assert_eq!(hm.dead_interval(), Duration::from_secs(630));
+        clock.advance(Duration::from_secs(631));
+        assert_eq!(hm.liveness(&dn), Some(Liveness::Dead));
+        assert_eq!(hm.nodes_in(Liveness::Dead), vec![dn]);
+        assert!(hm.nodes_in(Liveness::Live).is_empty());
+
+        assert!(hm.remove(&dn));
+        assert_eq!(hm.liveness(&dn), None);
+    }
+
+    #[test]
+    fn recheck_reconfiguration_applies_immediately() {
+        let (clock, settings, mut hm) = setup();
+        let dn = DatanodeId::new_v4();
+        hm.heartbeat(dn);
+        clock.advance(Duration::from_secs(100));
+        assert_eq!(hm.liveness(&dn), Some(Liveness::Stale));
+
+        let mut cfg = ClusterConfig::default();
+        cfg.namenode.heartbeat_recheck_interval = Duration::from_secs(30);
+        settings
+            .apply("namenode.heartbeat_recheck_interval", &cfg)
+            .unwrap();
+        assert_eq!(hm.liveness(&dn), Some(Liveness::Dead));
+    }
+
+    #[test]
+    fn heartbeat_reconfiguration_applies_immediately() {
+        let (clock, settings, mut hm) = setup();
+        let dn = DatanodeId::new_v4();
+        hm.heartbeat(dn);
+        clock.advance(Duration::from_secs(40));
+        assert_eq!(hm.liveness(&dn), Some(Liveness::Stale));
+
+        let mut cfg = ClusterConfig::default();
+        cfg.datanode.heartbeat_interval = Duration::from_secs(5);
+        settings.apply("datanode.heartbeat_interval", &cfg).unwrap();
+        assert_eq!(hm.stale_interval(), Duration::from_secs(50));
+        assert_eq!(hm.liveness(&dn), Some(Liveness::Live));
+    }
+}
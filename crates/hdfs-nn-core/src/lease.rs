@@ -0,0 +1,245 @@
+use hdfs_common::clock::SharedClock;
+use hdfs_common::config::NameNodeConfig;
+use hdfs_common::error::{HdfsError, Result};
+use hdfs_common::ids::LeaseId;
+use hdfs_common::path::PathAbs;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+
+/// A client's write lease over the files it has open for writing.
+#[derive(Clone, Debug)]
+pub struct Lease {
+    pub id: LeaseId,
+    pub holder: String,
+    pub paths: BTreeSet<PathAbs>,
+    last_renewed: Instant,
+}
+
+impl Lease {
+    pub fn last_renewed(&self) -> Instant {
+        self.last_renewed
+    }
+}
+
+/// Tracks leases per holder. After the soft limit another client may take a
+/// file over; after the hard limit the namenode recovers it on its own.
+#[derive(Debug)]
+pub struct LeaseManager {
+    clock: SharedClock,
+    soft_limit: Duration,
+    hard_limit: Duration,
+    next_id: u64,
+    by_holder: HashMap<String, LeaseId>,
+    by_path: HashMap<PathAbs, LeaseId>,
+    leases: BTreeMap<LeaseId, Lease>,
+}
+
+impl LeaseManager {
+    pub fn new(clock: SharedClock, cfg: &NameNodeConfig) -> Self {
+        Self {
+            clock,
+            soft_limit: cfg.lease_soft_limit,
+            hard_limit: cfg.lease_hard_limit,
+            next_id: 1,
+            by_holder: HashMap::new(),
+            by_path: HashMap::new(),
+            leases: BTreeMap::new(),
+        }
+    }
+
+    /// Adds `path` to the holder's lease, creating the lease if needed.
+    pub fn add_lease(&mut self, holder: &str, path: PathAbs) -> Result<LeaseId> {
+        if let Some(other) = self.by_path.get(&path)
+            && self.leases[other].holder != holder
+        {
+            return Err(HdfsError::State {
+                what: "add_lease".into(),
+                details: format!("{path} is already leased by {}", self.leases[other].holder),
+            });
+        }
+
+        let now = self.clock.now();
+        let id = match self.by_holder.get(holder) {
+            Some(id) => *id,
+            None => {
+                let id = LeaseId(self.next_id);
+                self.next_id += 1;
+                self.by_holder.insert(holder.to_string(), id);
+                self.leases.insert(
+                    id,
+                    Lease {
+                        id,
+                        holder: holder.to_string(),
+                        paths: BTreeSet::new(),
+                        last_renewed: now,
+                    },
+                );
+                id
+            }
+        };
+
+        let lease = self.leases.get_mut(&id).unwrap();
+        lease.paths.insert(path.clone());
+        lease.last_renewed = now;
+        self.by_path.insert(path, id);
+        Ok(id)
+    }
+
+    pub fn renew(&mut self, holder: &str) -> Result<()> {
+        let id = self.by_holder.get(holder).ok_or_else(|| HdfsError::State {
+            what: "renew_lease".into(),
+            details: format!("no lease held by {holder}"),
+        })?;
+        self.leases.get_mut(id).unwrap().last_renewed = self.clock.now();
+        Ok(())
+    }
+
+    /// Releases `path`; the lease goes away with its last path.
+    pub fn remove_path(&mut self, path: &PathAbs) -> Option<LeaseId> {
+        let id = self.by_path.remove(path)?;
+        let lease = self.leases.get_mut(&id).unwrap();
+        lease.paths.remove(path);
+        if lease.paths.is_empty() {
+            let lease = self.leases.remove(&id).unwrap();
+            self.by_holder.remove(&lease.holder);
+        }
+        Some(id)
+    }
+
+    pub fn get(&self, id: LeaseId) -> Option<&Lease> {
+        self.leases.get(&id)
+    }
+
+    pub fn lease_for_path(&self, path: &PathAbs) -> Option<&Lease> {
+        self.by_path.get(path).and_then(|id| self.leases.get(id))
+    }
+
+    pub fn is_soft_expired(&self, id: LeaseId) -> bool {
+        self.age(id).is_some_and(|age| age > self.soft_limit)
+    }
+
+    pub fn is_hard_expired(&self, id: LeaseId) -> bool {
+        self.age(id).is_some_and(|age| age > self.hard_limit)
+    }
+
+    /// Leases past the hard limit, oldest first.
+    pub fn hard_expired(&self) -> Vec<LeaseId> {
+        let mut out: Vec<&Lease> = self
+            .leases
+            .values()
+            .filter(|l| self.is_hard_expired(l.id))
+            .collect();
+        out.sort_by_key(|l| l.last_renewed);
+        out.into_iter().map(|l| l.id).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.leases.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leases.is_empty()
+    }
+
+    fn age(&self, id: LeaseId) -> Option<Duration> {
+        let lease = self.leases.get(&id)?;
+        Some(
+            self.clock
+                .now()
+                .saturating_duration_since(lease.last_renewed),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hdfs_common::clock::ManualClock;
+    use std::sync::Arc;
+
+    fn setup() -> (Arc<ManualClock>, LeaseManager) {
+        let clock = Arc::new(ManualClock::new());
+        let cfg = NameNodeConfig {
+            lease_soft_limit: Duration::from_secs(60),
+            lease_hard_limit: Duration::from_secs(3600),
+            ..Default::default()
+        };
+        let lm = LeaseManager::new(clock.clone(), &cfg);
+        (clock, lm)
+    }
+
+    fn p(s: &str) -> PathAbs {
+        PathAbs::try_from(s).unwrap()
+    }
+
+    #[test]
+    fn one_lease_per_holder() {
+        let (_, mut lm) = setup();
+        let a = lm.add_lease("client-1", p("/a")).unwrap();
+        let b = lm.add_lease("client-1", p("/b")).unwrap();
+        let c = lm.add_lease("client-2", p("/c")).unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(lm.len(), 2);
+        assert_eq!(lm.get(a).unwrap().paths.len(), 2);
+        assert_eq!(lm.lease_for_path(&p("/c")).unwrap().holder, "client-2");
+
+        assert!(matches!(
+            lm.add_lease("client-2", p("/a")),
+            Err(HdfsError::State { what, .. }) if what == "add_lease"
+        ));
+    }
+
+    #[test]
+    fn soft_and_hard_expiry_follow_the_clock() {
+        let (clock, mut lm) = setup();
+        let id = lm.add_lease("client-1", p("/a")).unwrap();
+
+        clock.advance(Duration::from_secs(60));
+        assert!(!lm.is_soft_expired(id));
+        clock.advance(Duration::from_secs(1));
+        assert!(lm.is_soft_expired(id));
+        assert!(!lm.is_hard_expired(id));
+
+        lm.renew("client-1").unwrap();
+        assert!(!lm.is_soft_expired(id));
+
+        clock.advance(Duration::from_secs(3601));
+        assert!(lm.is_hard_expired(id));
+        assert_eq!(lm.hard_expired(), vec![id]);
+    }
+
+    #[test]
+    fn hard_expired_is_oldest_first() {
+        let (clock, mut lm) = setup();
+        let old = lm.add_lease("old", p("/old")).unwrap();
+        clock.advance(Duration::from_secs(10));
+        let young = lm.add_lease("young", p("/young")).unwrap();
+        clock.advance(Duration::from_secs(3601));
+        assert_eq!(lm.hard_expired(), vec![old, young]);
+    }
+
+    #[test]
+    fn renew_unknown_holder_fails() {
+        let (_, mut lm) = setup();
+        assert!(matches!(
+            lm.renew("ghost"),
+            Err(HdfsError::State { what, .. }) if what == "renew_lease"
+        ));
+    }
+
+    #[test]
+    fn removing_last_path_drops_lease() {
+        let (_, mut lm) = setup();
+        let id = lm.add_lease("client-1", p("/a")).unwrap();
+        lm.add_lease("client-1", p("/b")).unwrap();
+
+        assert_eq!(lm.remove_path(&p("/a")), Some(id));
+        assert!(lm.get(id).is_some());
+        assert_eq!(lm.remove_path(&p("/b")), Some(id));
+        assert!(lm.get(id).is_none());
+        assert!(lm.is_empty());
+        assert_eq!(lm.remove_path(&p("/b")), None);
+        assert!(lm.renew("client-1").is_err());
+    }
+}
@@ -0,0 +1,6 @@
+pub mod editlog;
+pub mod heartbeat;
+pub mod lease;
+pub mod safemode;
+pub mod settings;
+pub mod storage;
@@ -0,0 +1,180 @@
+use crate::settings::NameNodeSettings;
+use hdfs_common::clock::SharedClock;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SafeModeStatus {
+    /// Not enough blocks have been reported yet.
+    WaitingForBlocks {
+        safe: u64,
+        needed: u64,
+    },
+    /// Threshold reached; leaving once the extension has passed.
+    Extension {
+        remaining: Duration,
+    },
+    Off,
+}
+
+/// Startup safe mode: the namespace is read-only until enough blocks have a
+/// minimal replica reported, plus a grace period for stragglers.
+#[derive(Debug)]
+pub struct SafeMode {
+    clock: SharedClock,
+    settings: Arc<NameNodeSettings>,
+    threshold_pct: f64,
+    total_blocks: u64,
+    safe_blocks: u64,
+    reached_at: Option<Instant>,
+    left: bool,
+}
+
+impl SafeMode {
+    pub fn new(clock: SharedClock, settings: Arc<NameNodeSettings>, threshold_pct: f64) -> Self {
+        Self {
+            clock,
+            settings,
+            threshold_pct,
+            total_blocks: 0,
+            safe_blocks: 0,
+            reached_at: None,
+            left: false,
+        }
+    }
+
+    pub fn set_block_counts(&mut self, safe: u64, total: u64) {
+        self.safe_blocks = safe;
+        self.total_blocks = total;
+        if self.left {
+            return;
+        }
+        if safe >= self.needed() {
+            self.reached_at.get_or_insert_with(|| self.clock.now());
+        } else {
+            self.reached_at = None;
+        }
+    }
+
+    pub fn increment_safe(&mut self) {
+        self.set_block_counts(self.safe_blocks + 1, self.total_blocks);
+    }
+
+    fn needed(&self) -> u64 {
+        (self.total_blocks as f64 * self.threshold_pct).ceil() as u64
+    }
+
+    /// Current status; leaves safe mode for good once the extension is over.
+    pub fn status(&mut self) -> SafeModeStatus {
+        if self.left {
+            return SafeModeStatus::Off;
+        }
+        let Some(reached) = self.reached_at else {
+            return SafeModeStatus::WaitingForBlocks {
+                safe: self.safe_blocks,
+                needed: self.needed(),
+            };
+        };
+        let waited = self.clock.now().saturating_duration_since(reached);
+        let extension = self.settings.safemode_extension();
+        if waited >= extension {
+            self.left = true;
+            SafeModeStatus::Off
+        } else {
+            SafeModeStatus::Extension {
+                remaining: extension - waited,
+            }
+        }
+    }
+
+    pub fn is_on(&mut self) -> bool {
+        self.status() != SafeModeStatus::Off
+    }
+
+    /// Operator override.
+    pub fn leave(&mut self) {
+        self.left = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hdfs_common::clock::ManualClock;
+    use hdfs_common::config::ClusterConfig;
+
+    fn setup() -> (Arc<ManualClock>, SafeMode) {
+        let clock = Arc::new(ManualClock::new());
+        let mut cfg = ClusterConfig::default();
+        cfg.namenode.safemode_extension = Duration::from_secs(30);
+        let settings = Arc::new(NameNodeSettings::new(&cfg));
+        (clock.clone(), SafeMode::new(clock, settings, 0.9))
+    }
+
+    #[test]
+    fn waits_for_threshold_then_extension() {
+        let (clock, mut sm) = setup();
+        sm.set_block_counts(0, 100);
+        assert_eq!(
+            sm.status(),
+            SafeModeStatus::WaitingForBlocks {
+                safe: 0,
+                needed: 90
+            }
+        );
+
+        sm.set_block_counts(89, 100);
+        assert!(sm.is_on());
+        sm.increment_safe();
+        assert_eq!(
+            sm.status(),
+            SafeModeStatus::Extension {
+                remaining: Duration::from_secs(30)
+            }
+        );
+
+        clock.advance(Duration::from_secs(29));
+        assert!(sm.is_on());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(sm.status(), SafeModeStatus::Off);
+
+        // once off, losing blocks does not put it back
+        sm.set_block_counts(0, 100);
+        assert!(!sm.is_on());
+    }
+
+    #[test]
+    fn dropping_below_threshold_restarts_extension() {
+        let (clock, mut sm) = setup();
+        sm.set_block_counts(95, 100);
+        clock.advance(Duration::from_secs(20));
+        sm.set_block_counts(50, 100);
+        assert!(matches!(
+            sm.status(),
+            SafeModeStatus::WaitingForBlocks { .. }
+        ));
+
+        sm.set_block_counts(95, 100);
+        clock.advance(Duration::from_secs(20));
+        assert_eq!(
+            sm.status(),
+            SafeModeStatus::Extension {
+                remaining: Duration::from_secs(10)
+            }
+        );
+    }
+
+    #[test]
+    fn empty_namespace_and_manual_leave() {
+        let (clock, mut sm) = setup();
+        sm.set_block_counts(0, 0);
+        assert!(sm.is_on());
+        clock.advance(Duration::from_secs(30));
+        assert!(!sm.is_on());
+
+        let (_, mut sm) = setup();
+        sm.set_block_counts(0, 10);
+        sm.leave();
+        assert_eq!(sm.status(), SafeModeStatus::Off);
+    }
+}
@@ -0,0 +1,135 @@
+use hdfs_common::config::ClusterConfig;
+use hdfs_common::config::reconfig::{AtomicDuration, ReconfigSubscriber};
+use hdfs_common::error::{HdfsError, Result};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+/// Namenode settings that can change while the namenode runs. Readers always
+/// see the latest applied value.
+#[derive(Debug)]
+pub struct NameNodeSettings {
+    replication_max_streams: AtomicU32,
+    heartbeat_interval: AtomicDuration,
+    heartbeat_recheck_interval: AtomicDuration,
+    safemode_extension: AtomicDuration,
+    edit_log_roll_period: AtomicDuration,
+}
+
+impl NameNodeSettings {
+    pub fn new(cfg: &ClusterConfig) -> Self {
+        let nn = &cfg.namenode;
+        Self {
+            replication_max_streams: AtomicU32::new(nn.replication_max_streams),
+            heartbeat_interval: AtomicDuration::new(cfg.datanode.heartbeat_interval),
+            heartbeat_recheck_interval: AtomicDuration::new(nn.heartbeat_recheck_interval),
+            safemode_extension: AtomicDuration::new(nn.safemode_extension),
+            edit_log_roll_period: AtomicDuration::new(nn.edit_log_roll_period),
+        }
+    }
+
+    pub fn replication_max_streams(&self) -> u32 {
+        self.replication_max_streams.load(Ordering::Acquire)
+    }
+
+    /// How often datanodes are configured to heartbeat.
+    pub fn heartbeat_interval(&self) -> Duration {
+        self.heartbeat_interval.load()
+    }
+
+    pub fn heartbeat_recheck_interval(&self) -> Duration {
+        self.heartbeat_recheck_interval.load()
+    }
+
+    pub fn safemode_extension(&self) -> Duration {
+        self.safemode_extension.load()
+    }
+
+    pub fn edit_log_roll_period(&self) -> Duration {
+        self.edit_log_roll_period.load()
+    }
+}
+
+impl ReconfigSubscriber for NameNodeSettings {
+    fn keys(&self) -> &'static [&'static str] {
+        &[
+            "namenode.replication_max_streams",
+            "datanode.heartbeat_interval",
+            "namenode.heartbeat_recheck_interval",
+            "namenode.safemode_extension",
+            "namenode.edit_log_roll_period",
+        ]
+    }
+
+    fn apply(&self, key: &'static str, new: &ClusterConfig) -> Result<()> {
+        let nn = &new.namenode;
+        match key {
+            "namenode.replication_max_streams" => self
+                .replication_max_streams
+                .store(nn.replication_max_streams, Ordering::Release),
+            "datanode.heartbeat_interval" => self
+                .heartbeat_interval
+                .store(new.datanode.heartbeat_interval),
+            "namenode.heartbeat_recheck_interval" => self
+                .heartbeat_recheck_interval
+                .store(nn.heartbeat_recheck_interval),
+            "namenode.safemode_extension" => self.safemode_extension.store(nn.safemode_extension),
+            "namenode.edit_log_roll_period" => {
+                self.edit_log_roll_period.store(nn.edit_log_roll_period)
+            }
+            _ => {
+                return Err(HdfsError::Config {
+                    key: key.into(),
+                    msg: "not handled by namenode settings".into(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn starts_from_config() {
+        let cfg = ClusterConfig::default();
+        let s = NameNodeSettings::new(&cfg);
+        assert_eq!(
+            s.replication_max_streams(),
+            cfg.namenode.replication_max_streams
+        );
+        assert_eq!(
+            s.heartbeat_recheck_interval(),
+            cfg.namenode.heartbeat_recheck_interval
+        );
+        assert_eq!(s.heartbeat_interval(), cfg.datanode.heartbeat_interval);
+        assert_eq!(s.safemode_extension(), cfg.namenode.safemode_extension);
+        assert_eq!(s.edit_log_roll_period(), cfg.namenode.edit_log_roll_period);
+    }
+
+    #[test]
+    fn apply_updates_only_the_named_key() {
+        let s = NameNodeSettings::new(&ClusterConfig::default());
+        let mut cfg = ClusterConfig::default();
+        cfg.namenode.replication_max_streams = 8;
+        cfg.namenode.edit_log_roll_period = Duration::from_secs(10);
+
+        s.apply("namenode.replication_max_streams", &cfg).unwrap();
+        assert_eq!(s.replication_max_streams(), 8);
+        assert_eq!(
+            s.edit_log_roll_period(),
+            ClusterConfig::default().namenode.edit_log_roll_period
+        );
+
+        for key in s.keys() {
+            s.apply(key, &cfg).unwrap();
+        }
+        assert_eq!(s.edit_log_roll_period(), Duration::from_secs(10));
+
+        assert!(matches!(
+            s.apply("namenode.rpc_addr", &cfg),
+            Err(HdfsError::Config { key, .. }) if key == "namenode.rpc_addr"
+        ));
+    }
+}
@@ -0,0 +1,109 @@
+use hdfs_common::clock::Clock;
+use hdfs_common::error::{HdfsError, Result};
+use hdfs_common::ids::ClusterId;
+use hdfs_common::storage::{NamespaceInfo, StorageInfo};
+use std::path::PathBuf;
+
+/// Formats every name directory with one new namespace. Fails without
+/// touching anything if any of them is already formatted, or if there are
+/// none.
+pub fn format(dirs: &[PathBuf], cluster_id: ClusterId, clock: &dyn Clock) -> Result<NamespaceInfo> {
+    if dirs.is_empty() {
+        return Err(no_name_dirs());
+    }
+    if let Some(dir) = dirs.iter().find(|d| StorageInfo::is_formatted(d)) {
+        return Err(HdfsError::AlreadyExists {
+            path: dir.display().to_string(),
+        });
+    }
+    let info = StorageInfo::new_namenode(cluster_id, clock.wall_millis());
+    for dir in dirs {
+        info.format(dir)?;
+    }
+    Ok(info.namespace_info().unwrap())
+}
+
+/// Startup check: every name directory must hold namenode storage of the
+/// same namespace.
+pub fn load(dirs: &[PathBuf]) -> Result<NamespaceInfo> {
+    let mut found: Option<(&PathBuf, NamespaceInfo)> = None;
+    for dir in dirs {
+        let ns = StorageInfo::load(dir)?
+            .namespace_info()
+            .ok_or_else(|| HdfsError::State {
+                what: "name_dirs".into(),
+                details: format!("{} holds datanode storage", dir.display()),
+            })?;
+        match &found {
+            None => found = Some((dir, ns)),
+            Some((first, expected)) if *expected != ns => {
+                return Err(HdfsError::State {
+                    what: "name_dirs".into(),
+                    details: format!(
+                        "{} and {} belong to different namespaces",
+                        first.display(),
+                        dir.display()
+                    ),
+                });
+            }
+            Some(_) => {}
+        }
+    }
+    found.map(|(_, ns)| ns).ok_or_else(no_name_dirs)
+}
+
+fn no_name_dirs() -> HdfsError {
+    HdfsError::Config {
+        key: "namenode.name_dirs".into(),
+        msg: "must not be empty".into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hdfs_common::clock::ManualClock;
+
+    fn tmp_dirs(name: &str, n: usize) -> Vec<PathBuf> {
+        let base = std::env::temp_dir().join(format!("hdfs-nn-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        (0..n).map(|i| base.join(format!("name{i}"))).collect()
+    }
+
+    #[test]
+    fn format_then_load() {
+        let dirs = tmp_dirs("fmt", 2);
+        let cid = ClusterId::new_v4();
+        let ns = format(&dirs, cid, &ManualClock::new()).unwrap();
+        assert_eq!(ns.cluster_id, cid);
+        assert_eq!(load(&dirs).unwrap(), ns);
+
+        assert!(matches!(
+            format(&dirs, cid, &ManualClock::new()),
+            Err(HdfsError::AlreadyExists { .. })
+        ));
+        assert!(matches!(
+            format(&[], cid, &ManualClock::new()),
+            Err(HdfsError::Config { key, .. }) if key == "namenode.name_dirs"
+        ));
+        std::fs::remove_dir_all(dirs[0].parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn load_rejects_mismatched_dirs() {
+        let dirs = tmp_dirs("mix", 2);
+        let cid = ClusterId::new_v4();
+        format(&dirs[..1], cid, &ManualClock::new()).unwrap();
+        format(&dirs[1..], cid, &ManualClock::new()).unwrap();
+        assert!(matches!(
+            load(&dirs),
+            Err(HdfsError::State { what, .. }) if what == "name_dirs"
+        ));
+
+        assert!(matches!(
+            load(&[]),
+            Err(HdfsError::Config { key, .. }) if key == "namenode.name_dirs"
+        ));
+        std::fs::remove_dir_all(dirs[0].parent().unwrap()).unwrap();
+    }
+}
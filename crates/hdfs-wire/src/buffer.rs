@@ -0,0 +1,126 @@
+//! Receive buffers shared between block streams. Packets decoded from a
+//! buffer hold slices of it rather than copies, so an allocation can only be
+//! reused once every packet cut from it has been dropped.
+
+use bytes::{Bytes, BytesMut};
+use hdfs_common::consts::KIB;
+use std::sync::{Arc, Mutex};
+
+/// Room for a few default-sized write packets.
+pub const DEFAULT_BUFFER_SIZE: usize = 256 * KIB as usize;
+
+pub const DEFAULT_MAX_POOLED: usize = 64;
+
+/// A free list of equally sized buffers. Clones share the list.
+#[derive(Clone, Debug)]
+pub struct BufferPool {
+    inner: Arc<PoolInner>,
+}
+
+#[derive(Debug)]
+struct PoolInner {
+    buffer_size: usize,
+    max_pooled: usize,
+    free: Mutex<Vec<BytesMut>>,
+}
+
+impl BufferPool {
+    pub fn new(buffer_size: usize, max_pooled: usize) -> Self {
+        Self {
+            inner: Arc::new(PoolInner {
+                buffer_size,
+                max_pooled,
+                free: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    pub fn buffer_size(&self) -> usize {
+        self.inner.buffer_size
+    }
+
+    /// Buffers waiting to be reused.
+    pub fn pooled(&self) -> usize {
+        self.inner.free.lock().unwrap().len()
+    }
+
+    /// An empty buffer with at least `buffer_size` bytes of capacity.
+    pub fn get(&self) -> BytesMut {
+        match self.inner.free.lock().unwrap().pop() {
+            Some(buf) => buf,
+            None => BytesMut::with_capacity(self.inner.buffer_size),
+        }
+    }
+
+    /// Takes `buf` back if nothing else still points into its allocation.
+    pub fn put(&self, mut buf: BytesMut) {
+        buf.clear();
+        if !buf.try_reclaim(self.inner.buffer_size) {
+            return;
+        }
+        let mut free = self.inner.free.lock().unwrap();
+        if free.len() < self.inner.max_pooled {
+            free.push(buf);
+        }
+    }
+
+    /// Like [`BufferPool::put`], for a buffer that was frozen, e.g. the data
+    /// of a packet being written.
+    pub fn recycle(&self, buf: Bytes) {
+        if let Ok(buf) = buf.try_into_mut() {
+            self.put(buf);
+        }
+    }
+}
+
+impl Default for BufferPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUFFER_SIZE, DEFAULT_MAX_POOLED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffers_come_back_once_unshared() {
+        let pool = BufferPool::new(1024, 2);
+        let mut buf = pool.get();
+        assert!(buf.capacity() >= 1024);
+        buf.extend_from_slice(&[1; 100]);
+        let data = buf.split_to(60).freeze().slice(10..);
+
+        // `data` still points into the allocation
+        pool.put(buf);
+        assert_eq!(pool.pooled(), 0);
+        assert_eq!(data[0], 1);
+
+        let mut buf = pool.get();
+        buf.extend_from_slice(&[2; 100]);
+        let data = buf.split_to(60).freeze().slice(10..);
+        drop(data);
+        pool.put(buf);
+        assert_eq!(pool.pooled(), 1);
+        assert!(pool.get().capacity() >= 1024);
+
+        // frozen buffers are taken back too
+        let mut frozen = pool.get();
+        frozen.extend_from_slice(b"data");
+        pool.recycle(frozen.freeze());
+        assert_eq!(pool.pooled(), 1);
+    }
+
+    #[test]
+    fn pool_is_bounded() {
+        let pool = BufferPool::new(64, 2);
+        let bufs: Vec<_> = (0..3).map(|_| pool.get()).collect();
+        for buf in bufs {
+            pool.put(buf);
+        }
+        assert_eq!(pool.pooled(), 2);
+        let clone = pool.clone();
+        let _ = clone.get();
+        assert_eq!(pool.pooled(), 1);
+    }
+}
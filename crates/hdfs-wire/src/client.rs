@@ -0,0 +1,762 @@
+//! ClientProtocol: what clients send to the namenode.
+//!
+//! Each op has a message name (`AddBlock`), used as the `op` of
+//! `HdfsError::Protocol` when its messages fail to decode, and a method name
+//! (`addBlock`, as in Hadoop) that namenode handlers use for
+//! `HdfsError::State` errors.
+
+use crate::codec::{Decoder, Encoder, WireDecode, WireEncode, tagged_struct};
+use crate::message::protocol_ops;
+use hdfs_common::error::Result;
+use hdfs_common::ids::{DatanodeId, INodeId, LeaseId};
+use hdfs_common::path::PathAbs;
+use hdfs_common::types::ExtendedBlock;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum FileType {
+    File,
+    Dir,
+    Symlink,
+}
+
+impl WireEncode for FileType {
+    fn encode(&self, e: &mut Encoder) {
+        e.put_u8(match self {
+            FileType::File => 0,
+            FileType::Dir => 1,
+            FileType::Symlink => 2,
+        });
+    }
+}
+
+impl WireDecode for FileType {
+    fn decode(d: &mut Decoder<'_>) -> Result<Self> {
+        match d.get_u8()? {
+            0 => Ok(FileType::File),
+            1 => Ok(FileType::Dir),
+            2 => Ok(FileType::Symlink),
+            t => Err(d.error(format!("invalid file type {t}"))),
+        }
+    }
+}
+
+tagged_struct! {
+    pub struct FileStatus {
+        pub inode: INodeId,
+        pub path: PathAbs,
+        pub file_type: FileType,
+        pub length: u64,
+        pub replication: u16,
+        pub block_size: u64,
+        /// Millis since the epoch.
+        pub modification_time: u64,
+        pub access_time: u64,
+        pub permission: u16,
+        pub owner: String,
+        pub group: String,
+        pub children: u32,
+    }
+}
+
+tagged_struct! {
+    pub struct DatanodeInfo {
+        pub id: DatanodeId,
+        pub hostname: String,
+        /// `host:port` of the data transfer endpoint.
+        pub data_addr: String,
+    }
+}
+
+tagged_struct! {
+    pub struct LocatedBlock {
+        pub block: ExtendedBlock,
+        /// Offset of the block within the file.
+        pub offset: u64,
+        /// Replicas, closest to the client first.
+        pub locations: Vec<DatanodeInfo>,
+        pub corrupt: bool,
+    }
+}
+
+tagged_struct! {
+    pub struct LocatedBlocks {
+        pub file_length: u64,
+        pub under_construction: bool,
+        pub blocks: Vec<LocatedBlock>,
+    }
+}
+
+tagged_struct! {
+    pub struct ContentSummary {
+        pub length: u64,
+        pub file_count: u64,
+        pub directory_count: u64,
+        /// Length times replication.
+        pub space_consumed: u64,
+    }
+}
+
+tagged_struct! {
+    pub struct GetFileInfoRequest {
+        pub path: PathAbs,
+    }
+}
+
+tagged_struct! {
+    pub struct GetFileInfoResponse {
+        pub status: Option<FileStatus>,
+    }
+}
+
+tagged_struct! {
+    /// One page of a directory listing, in name order.
+    pub struct ListStatusRequest {
+        pub path: PathAbs,
+        /// Name of the last entry of the previous page.
+        pub start_after: Option<String>,
+        pub limit: u32,
+    }
+}
+
+tagged_struct! {
+    pub struct ListStatusResponse {
+        pub entries: Vec<FileStatus>,
+        /// Entries left after this page.
+        pub remaining: u32,
+    }
+}
+
+tagged_struct! {
+    pub struct MkdirsRequest {
+        pub path: PathAbs,
+        pub permission: u16,
+        pub create_parent: bool,
+    }
+}
+
+tagged_struct! {
+    pub struct MkdirsResponse {
+        pub created: bool,
+    }
+}
+
+tagged_struct! {
+    pub struct CreateRequest {
+        pub path: PathAbs,
+        /// Lease holder, normally the client name.
+        pub holder: String,
+        pub permission: u16,
+        pub overwrite: bool,
+        pub create_parent: bool,
+        pub replication: u16,
+        pub block_size: u64,
+    }
+}
+
+tagged_struct! {
+    pub struct CreateResponse {
+        pub status: FileStatus,
+        pub lease: LeaseId,
+    }
+}
+
+tagged_struct! {
+    pub struct AddBlockRequest {
+        pub path: PathAbs,
+        pub holder: String,
+        pub inode: INodeId,
+        /// The last block, now complete on the pipeline; `None` for the first.
+        pub previous: Option<ExtendedBlock>,
+        /// Datanodes the client failed to reach.
+        pub excluded: Vec<DatanodeId>,
+    }
+}
+
+tagged_struct! {
+    pub struct AddBlockResponse {
+        pub block: LocatedBlock,
+    }
+}
+
+tagged_struct! {
+    pub struct AbandonBlockRequest {
+        pub block: ExtendedBlock,
+        pub path: PathAbs,
+        pub holder: String,
+        pub inode: INodeId,
+    }
+}
+
+tagged_struct! {
+    pub struct AbandonBlockResponse {}
+}
+
+tagged_struct! {
+    pub struct CompleteRequest {
+        pub path: PathAbs,
+        pub holder: String,
+        pub inode: INodeId,
+        pub last: Option<ExtendedBlock>,
+    }
+}
+
+tagged_struct! {
+    /// `false` until the last block has its minimal replication; retry.
+    pub struct CompleteResponse {
+        pub completed: bool,
+    }
+}
+
+tagged_struct! {
+    pub struct GetBlockLocationsRequest {
+        pub path: PathAbs,
+        pub offset: u64,
+        pub length: u64,
+    }
+}
+
+tagged_struct! {
+    pub struct GetBlockLocationsResponse {
+        pub locations: LocatedBlocks,
+    }
+}
+
+tagged_struct! {
+    pub struct RenameRequest {
+        pub src: PathAbs,
+        pub dst: PathAbs,
+        pub overwrite: bool,
+    }
+}
+
+tagged_struct! {
+    pub struct RenameResponse {
+        pub renamed: bool,
+    }
+}
+
+tagged_struct! {
+    pub struct DeleteRequest {
+        pub path: PathAbs,
+        pub recursive: bool,
+    }
+}
+
+tagged_struct! {
+    pub struct DeleteResponse {
+        pub deleted: bool,
+    }
+}
+
+tagged_struct! {
+    pub struct SetReplicationRequest {
+        pub path: PathAbs,
+        pub replication: u16,
+    }
+}
+
+tagged_struct! {
+    /// `false` if `path` is not a file.
+    pub struct SetReplicationResponse {
+        pub applied: bool,
+    }
+}
+
+tagged_struct! {
+    pub struct SetPermissionRequest {
+        pub path: PathAbs,
+        pub permission: u16,
+    }
+}
+
+tagged_struct! {
+    pub struct SetPermissionResponse {}
+}
+
+tagged_struct! {
+    /// `None` leaves that part unchanged.
+    pub struct SetOwnerRequest {
+        pub path: PathAbs,
+        pub owner: Option<String>,
+        pub group: Option<String>,
+    }
+}
+
+tagged_struct! {
+    pub struct SetOwnerResponse {}
+}
+
+tagged_struct! {
+    pub struct RenewLeaseRequest {
+        pub holder: String,
+    }
+}
+
+tagged_struct! {
+    pub struct RenewLeaseResponse {}
+}
+
+tagged_struct! {
+    pub struct FsyncRequest {
+        pub path: PathAbs,
+        pub inode: INodeId,
+        pub holder: String,
+        /// Bytes of the last block known to be on all pipeline nodes.
+        pub last_block_length: u64,
+    }
+}
+
+tagged_struct! {
+    pub struct FsyncResponse {}
+}
+
+tagged_struct! {
+    pub struct GetContentSummaryRequest {
+        pub path: PathAbs,
+    }
+}
+
+tagged_struct! {
+    pub struct GetContentSummaryResponse {
+        pub summary: ContentSummary,
+    }
+}
+
+protocol_ops! {
+    "client", ClientOp, ClientRequest, ClientResponse;
+    GetFileInfo = 1, "getFileInfo", idempotent, GetFileInfoRequest, GetFileInfoResponse;
+    ListStatus = 2, "getListing", idempotent, ListStatusRequest, ListStatusResponse;
+    Mkdirs = 3, "mkdirs", idempotent, MkdirsRequest, MkdirsResponse;
+    Create = 4, "create", at_most_once, CreateRequest, CreateResponse;
+    AddBlock = 5, "addBlock", idempotent, AddBlockRequest, AddBlockResponse;
+    AbandonBlock = 6, "abandonBlock", idempotent, AbandonBlockRequest, AbandonBlockResponse;
+    Complete = 7, "complete", idempotent, CompleteRequest, CompleteResponse;
+    GetBlockLocations = 8, "getBlockLocations", idempotent, GetBlockLocationsRequest, GetBlockLocationsResponse;
+    Rename = 9, "rename", at_most_once, RenameRequest, RenameResponse;
+    Delete = 10, "delete", at_most_once, DeleteRequest, DeleteResponse;
+    SetReplication = 11, "setReplication", idempotent, SetReplicationRequest, SetReplicationResponse;
+    SetPermission = 12, "setPermission", idempotent, SetPermissionRequest, SetPermissionResponse;
+    SetOwner = 13, "setOwner", idempotent, SetOwnerRequest, SetOwnerResponse;
+    RenewLease = 14, "renewLease", idempotent, RenewLeaseRequest, RenewLeaseResponse;
+    Fsync = 15, "fsync", idempotent, FsyncRequest, FsyncResponse;
+    GetContentSummary = 16, "getContentSummary", idempotent, GetContentSummaryRequest, GetContentSummaryResponse;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::hex_lines;
+    use crate::frame::Frame;
+    use crate::message::{decode_response, error_frame, request_frame, response_frame};
+    use hdfs_common::error::HdfsError;
+    use hdfs_common::ids::{BlockId, BlockPoolId, GenerationStamp};
+    use uuid::Uuid;
+
+    fn p(s: &str) -> PathAbs {
+        PathAbs::try_from(s).unwrap()
+    }
+
+    fn block() -> ExtendedBlock {
+        ExtendedBlock::new(
+            BlockPoolId::new_v4(),
+            BlockId(1 << 30),
+            GenerationStamp(1001),
+            0,
+        )
+    }
+
+    fn status(path: &str) -> FileStatus {
+        FileStatus {
+            inode: INodeId(16386),
+            path: p(path),
+            file_type: FileType::File,
+            length: 1234,
+            replication: 3,
+            block_size: 128 << 20,
+            modification_time: 1_700_000_000_000,
+            access_time: 1_700_000_000_000,
+            permission: 0o644,
+            owner: "hdfs".into(),
+            group: "supergroup".into(),
+            children: 0,
+        }
+    }
+
+    #[test]
+    fn op_table() {
+        assert_eq!(ClientOp::ALL.len(), 16);
+        for (i, op) in ClientOp::ALL.iter().enumerate() {
+            assert_eq!(op.code() as usize, i + 1);
+            assert_eq!(ClientOp::from_code(op.code()), Some(*op));
+        }
+        assert_eq!(ClientOp::from_code(0), None);
+        assert_eq!(ClientOp::AddBlock.name(), "AddBlock");
+        assert_eq!(ClientOp::Complete.method(), "complete");
+        assert_eq!(ClientOp::ListStatus.method(), "getListing");
+
+        // as Hadoop annotates ClientProtocol
+        let at_most_once: Vec<_> = ClientOp::ALL
+            .iter()
+            .filter(|op| !op.is_idempotent())
+            .map(|op| op.name())
+            .collect();
+        assert_eq!(at_most_once, ["Create", "Rename", "Delete"]);
+        const {
+            assert!(!<CreateRequest as crate::message::Call>::IDEMPOTENT);
+            assert!(<AddBlockRequest as crate::message::Call>::IDEMPOTENT);
+        }
+    }
+
+    #[test]
+    fn requests_roundtrip_through_frames() {
+        let requests: Vec<ClientRequest> = vec![
+            GetFileInfoRequest { path: p("/a") }.into(),
+            ListStatusRequest {
+                path: p("/logs"),
+                start_after: Some("part-0099".into()),
+                limit: 1000,
+            }
+            .into(),
+            CreateRequest {
+                path: p("/a/f"),
+                holder: "DFSClient_1".into(),
+                permission: 0o644,
+                overwrite: false,
+                create_parent: true,
+                replication: 3,
+                block_size: 128 << 20,
+            }
+            .into(),
+            AddBlockRequest {
+                path: p("/a/f"),
+                holder: "DFSClient_1".into(),
+                inode: INodeId(16386),
+                previous: Some(block()),
+                excluded: vec![DatanodeId::new_v4()],
+            }
+            .into(),
+            CompleteRequest {
+                path: p("/a/f"),
+                holder: "DFSClient_1".into(),
+                inode: INodeId(16386),
+                last: None,
+            }
+            .into(),
+            SetOwnerRequest {
+                path: p("/a"),
+                owner: None,
+                group: Some("staff".into()),
+            }
+            .into(),
+            RenewLeaseRequest {
+                holder: "DFSClient_1".into(),
+            }
+            .into(),
+        ];
+        for req in requests {
+            let frame = req.to_frame(9);
+            assert_eq!(frame.call_id, 9);
+            assert_eq!(ClientRequest::from_frame(&frame).unwrap(), req);
+        }
+    }
+
+    #[test]
+    fn typed_calls() {
+        let req = GetFileInfoRequest { path: p("/a") };
+        let frame = request_frame(3, &req);
+        assert_eq!(frame.msg_type, ClientOp::GetFileInfo.code());
+        assert_eq!(
+            ClientRequest::from_frame(&frame).unwrap(),
+            ClientRequest::GetFileInfo(req)
+        );
+
+        let resp = GetFileInfoResponse {
+            status: Some(status("/a")),
+        };
+        let frame = response_frame::<GetFileInfoRequest>(3, &resp);
+        assert_eq!(frame, ClientResponse::GetFileInfo(resp.clone()).to_frame(3));
+        assert_eq!(decode_response::<GetFileInfoRequest>(&frame).unwrap(), resp);
+
+        // a response to a different call is rejected
+        assert!(matches!(
+            decode_response::<MkdirsRequest>(&frame),
+            Err(HdfsError::Protocol { op, .. }) if op == "Mkdirs"
+        ));
+    }
+
+    #[test]
+    fn requests_route_by_protocol() {
+        use crate::datanode::DatanodeRequest;
+        use crate::message::Requests;
+
+        assert_eq!(ClientRequest::PROTOCOL, "client");
+        assert!(ClientRequest::handles(ClientOp::Mkdirs.code()));
+        assert!(!ClientRequest::handles(0x101));
+        assert!(DatanodeRequest::handles(0x101));
+
+        let req: ClientRequest = GetFileInfoRequest { path: p("/a") }.into();
+        let frame = req.to_frame(5);
+        assert_eq!(<ClientRequest as Requests>::decode(&frame).unwrap(), req);
+        assert_eq!(req.name(), "GetFileInfo");
+
+        let resp = ClientResponse::GetFileInfo(GetFileInfoResponse { status: None });
+        assert_eq!(
+            ClientRequest::encode_response("GetFileInfo", 5, &resp).unwrap(),
+            resp.to_frame(5)
+        );
+        match ClientRequest::encode_response("Mkdirs", 5, &resp) {
+            Err(HdfsError::Protocol { op, details }) if op == "Mkdirs" => {
+                assert_eq!(details, "handler answered with a GetFileInfo response")
+            }
+            other => panic!("expected Protocol error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn errors_come_back_typed() {
+        let err = HdfsError::State {
+            what: ClientOp::Complete.method().into(),
+            details: "file not under construction".into(),
+        };
+        let frame = error_frame(4, &err);
+        match decode_response::<CompleteRequest>(&frame) {
+            Err(HdfsError::State { what, details }) => {
+                assert_eq!(what, "complete");
+                assert_eq!(details, "file not under construction");
+            }
+            other => panic!("expected State error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn decode_errors_use_message_name() {
+        let req = AddBlockRequest {
+            path: p("/a/f"),
+            holder: "c".into(),
+            inode: INodeId(1),
+            previous: None,
+            excluded: vec![],
+        };
+        let mut frame = request_frame(1, &req);
+        frame.payload.pop();
+        assert!(matches!(
+            ClientRequest::from_frame(&frame),
+            Err(HdfsError::Protocol { op, .. }) if op == "AddBlock"
+        ));
+
+        let unknown = Frame::new(0x7777, 1, Vec::new());
+        assert!(matches!(
+            ClientRequest::from_frame(&unknown),
+            Err(HdfsError::Protocol { op, .. }) if op == "decode_request"
+        ));
+    }
+
+    #[test]
+    fn responses_roundtrip() {
+        let located = LocatedBlocks {
+            file_length: 4096,
+            under_construction: false,
+            blocks: vec![LocatedBlock {
+                block: block(),
+                offset: 0,
+                locations: vec![DatanodeInfo {
+                    id: DatanodeId::new_v4(),
+                    hostname: "dn1".into(),
+                    data_addr: "10.0.0.1:9866".into(),
+                }],
+                corrupt: false,
+            }],
+        };
+        let resp = GetBlockLocationsResponse { locations: located };
+        let frame = response_frame::<GetBlockLocationsRequest>(1, &resp);
+        assert_eq!(
+            decode_response::<GetBlockLocationsRequest>(&frame).unwrap(),
+            resp
+        );
+
+        let listing = ListStatusResponse {
+            entries: vec![status("/d/a"), status("/d/b")],
+            remaining: 10,
+        };
+        let frame = response_frame::<ListStatusRequest>(2, &listing);
+        assert_eq!(
+            decode_response::<ListStatusRequest>(&frame).unwrap(),
+            listing
+        );
+
+        let empty = response_frame::<FsyncRequest>(3, &FsyncResponse {});
+        assert!(empty.payload.is_empty());
+        assert_eq!(
+            decode_response::<FsyncRequest>(&empty).unwrap(),
+            FsyncResponse {}
+        );
+    }
+
+    #[test]
+    fn golden_messages() {
+        let block = ExtendedBlock::new(
+            BlockPoolId(Uuid::from_u128(0xb1)),
+            BlockId(1 << 30),
+            GenerationStamp(1001),
+            4096,
+        );
+        let located = LocatedBlock {
+            block: block.clone(),
+            offset: 0,
+            locations: vec![DatanodeInfo {
+                id: DatanodeId(Uuid::from_u128(0xd1)),
+                hostname: "dn1".into(),
+                data_addr: "10.0.0.1:9866".into(),
+            }],
+            corrupt: false,
+        };
+        let holder = || "DFSClient_1".to_string();
+        let requests: Vec<ClientRequest> = vec![
+            GetFileInfoRequest { path: p("/a") }.into(),
+            ListStatusRequest {
+                path: p("/d"),
+                start_after: Some("b".into()),
+                limit: 1000,
+            }
+            .into(),
+            MkdirsRequest {
+                path: p("/d"),
+                permission: 0o755,
+                create_parent: true,
+            }
+            .into(),
+            CreateRequest {
+                path: p("/d/f"),
+                holder: holder(),
+                permission: 0o644,
+                overwrite: false,
+                create_parent: true,
+                replication: 3,
+                block_size: 128 << 20,
+            }
+            .into(),
+            AddBlockRequest {
+                path: p("/d/f"),
+                holder: holder(),
+                inode: INodeId(16386),
+                previous: None,
+                excluded: vec![DatanodeId(Uuid::from_u128(0xd2))],
+            }
+            .into(),
+            AbandonBlockRequest {
+                block: block.clone(),
+                path: p("/d/f"),
+                holder: holder(),
+                inode: INodeId(16386),
+            }
+            .into(),
+            CompleteRequest {
+                path: p("/d/f"),
+                holder: holder(),
+                inode: INodeId(16386),
+                last: Some(block.clone()),
+            }
+            .into(),
+            GetBlockLocationsRequest {
+                path: p("/d/f"),
+                offset: 0,
+                length: 4096,
+            }
+            .into(),
+            RenameRequest {
+                src: p("/d/f"),
+                dst: p("/d/g"),
+                overwrite: false,
+            }
+            .into(),
+            DeleteRequest {
+                path: p("/d"),
+                recursive: true,
+            }
+            .into(),
+            SetReplicationRequest {
+                path: p("/d/f"),
+                replication: 2,
+            }
+            .into(),
+            SetPermissionRequest {
+                path: p("/d"),
+                permission: 0o700,
+            }
+            .into(),
+            SetOwnerRequest {
+                path: p("/d"),
+                owner: None,
+                group: Some("staff".into()),
+            }
+            .into(),
+            RenewLeaseRequest { holder: holder() }.into(),
+            FsyncRequest {
+                path: p("/d/f"),
+                inode: INodeId(16386),
+                holder: holder(),
+                last_block_length: 512,
+            }
+            .into(),
+            GetContentSummaryRequest { path: p("/d") }.into(),
+        ];
+        let responses: Vec<ClientResponse> = vec![
+            GetFileInfoResponse {
+                status: Some(status("/a")),
+            }
+            .into(),
+            ListStatusResponse {
+                entries: vec![status("/d/f")],
+                remaining: 0,
+            }
+            .into(),
+            MkdirsResponse { created: true }.into(),
+            CreateResponse {
+                status: status("/d/f"),
+                lease: LeaseId(7),
+            }
+            .into(),
+            AddBlockResponse {
+                block: located.clone(),
+            }
+            .into(),
+            AbandonBlockResponse {}.into(),
+            CompleteResponse { completed: true }.into(),
+            GetBlockLocationsResponse {
+                locations: LocatedBlocks {
+                    file_length: 4096,
+                    under_construction: false,
+                    blocks: vec![located],
+                },
+            }
+            .into(),
+            RenameResponse { renamed: true }.into(),
+            DeleteResponse { deleted: true }.into(),
+            SetReplicationResponse { applied: true }.into(),
+            SetPermissionResponse {}.into(),
+            SetOwnerResponse {}.into(),
+            RenewLeaseResponse {}.into(),
+            FsyncResponse {}.into(),
+            GetContentSummaryResponse {
+                summary: ContentSummary {
+                    length: 4096,
+                    file_count: 1,
+                    directory_count: 1,
+                    space_consumed: 3 * 4096,
+                },
+            }
+            .into(),
+        ];
+        assert_eq!(requests.len(), ClientOp::ALL.len());
+        assert_eq!(responses.len(), ClientOp::ALL.len());
+
+        let mut dump = String::new();
+        for (req, resp) in requests.iter().zip(&responses) {
+            let (req, resp) = (req.to_frame(1), resp.to_frame(1));
+            let name = ClientOp::from_code(req.msg_type).unwrap().name();
+            dump += &format!("{name} request v1:\n{}", hex_lines(&req.payload));
+            dump += &format!("{name} response v1:\n{}", hex_lines(&resp.payload));
+        }
+        insta::assert_snapshot!(dump);
+    }
+}
@@ -0,0 +1,942 @@
+use hdfs_common::checksum::DataChecksum;
+use hdfs_common::error::{HdfsError, RemoteDetail, RemoteError, Result};
+use hdfs_common::ids::{
+    BlockId, BlockPoolId, ClusterId, DatanodeId, GenerationStamp, INodeId, LeaseId, NamespaceId,
+};
+use hdfs_common::path::PathAbs;
+use hdfs_common::types::ExtendedBlock;
+use uuid::Uuid;
+
+/// Builds a message body. Integers are big-endian, strings and sequences
+/// prefixed with a u32 length, options with a 0/1 byte. [`wire_struct!`]
+/// writes fields in declaration order with no tags; [`tagged_struct!`]
+/// wraps each one with [`Encoder::put_field`].
+#[derive(Debug, Default)]
+pub struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn put_u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    pub fn put_u16(&mut self, v: u16) {
+        self.buf.extend_from_slice(&v.to_be_bytes());
+    }
+
+    pub fn put_u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_be_bytes());
+    }
+
+    pub fn put_u64(&mut self, v: u64) {
+        self.buf.extend_from_slice(&v.to_be_bytes());
+    }
+
+    /// LEB128: seven bits per byte, low bits first.
+    pub fn put_varint(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.buf.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        self.buf.push(v as u8);
+    }
+
+    pub fn put_len(&mut self, len: usize) {
+        self.put_u32(u32::try_from(len).expect("wire sequences are limited to u32::MAX items"));
+    }
+
+    pub fn put_raw(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn put_bytes(&mut self, bytes: &[u8]) {
+        self.put_len(bytes.len());
+        self.put_raw(bytes);
+    }
+
+    pub fn put_str(&mut self, s: &str) {
+        self.put_bytes(s.as_bytes());
+    }
+
+    /// Writes `tag` and the value's length as varints, then the value.
+    /// Absent values (`None`, empty sequences) are left out.
+    pub fn put_field<T: WireEncode>(&mut self, tag: u64, v: &T) {
+        if v.is_absent() {
+            return;
+        }
+        let mut value = Encoder::new();
+        v.encode(&mut value);
+        self.put_varint(tag);
+        self.put_varint(value.len() as u64);
+        self.put_raw(&value.buf);
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Reads a message body written by [`Encoder`]. Every error is a
+/// `HdfsError::Protocol` carrying the name of the message being decoded.
+#[derive(Debug)]
+pub struct Decoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    op: &'static str,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(buf: &'a [u8], op: &'static str) -> Self {
+        Self { buf, pos: 0, op }
+    }
+
+    pub fn op(&self) -> &'static str {
+        self.op
+    }
+
+    pub fn error(&self, details: impl Into<String>) -> HdfsError {
+        HdfsError::Protocol {
+            op: self.op.into(),
+            details: details.into(),
+        }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    pub fn get_raw(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.remaining() < n {
+            return Err(self.error(format!(
+                "truncated at byte {}: need {n}, have {}",
+                self.pos,
+                self.remaining()
+            )));
+        }
+        let out = &self.buf[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(out)
+    }
+
+    fn get_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.get_raw(N)?.try_into().unwrap())
+    }
+
+    pub fn get_u8(&mut self) -> Result<u8> {
+        Ok(self.get_array::<1>()?[0])
+    }
+
+    pub fn get_u16(&mut self) -> Result<u16> {
+        self.get_array().map(u16::from_be_bytes)
+    }
+
+    pub fn get_u32(&mut self) -> Result<u32> {
+        self.get_array().map(u32::from_be_bytes)
+    }
+
+    pub fn get_u64(&mut self) -> Result<u64> {
+        self.get_array().map(u64::from_be_bytes)
+    }
+
+    pub fn get_varint(&mut self) -> Result<u64> {
+        let mut v = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.get_u8()?;
+            if shift == 63 && b > 1 {
+                return Err(self.error("varint overflows u64"));
+            }
+            v |= u64::from(b & 0x7F) << shift;
+            if b & 0x80 == 0 {
+                return Ok(v);
+            }
+        }
+        Err(self.error("varint longer than 10 bytes"))
+    }
+
+    pub fn get_len(&mut self) -> Result<usize> {
+        Ok(self.get_u32()? as usize)
+    }
+
+    pub fn get_bytes(&mut self) -> Result<&'a [u8]> {
+        let n = self.get_len()?;
+        self.get_raw(n)
+    }
+
+    pub fn get_str(&mut self) -> Result<&'a str> {
+        let bytes = self.get_bytes()?;
+        std::str::from_utf8(bytes).map_err(|e| self.error(format!("invalid utf-8: {e}")))
+    }
+
+    /// Fails if anything is left over after the message.
+    pub fn finish(&self) -> Result<()> {
+        match self.remaining() {
+            0 => Ok(()),
+            n => Err(self.error(format!("{n} trailing bytes"))),
+        }
+    }
+}
+
+pub trait WireEncode {
+    fn encode(&self, e: &mut Encoder);
+
+    /// Whether [`Encoder::put_field`] can leave this value out.
+    fn is_absent(&self) -> bool {
+        false
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut e = Encoder::new();
+        self.encode(&mut e);
+        e.into_bytes()
+    }
+}
+
+pub trait WireDecode: Sized {
+    fn decode(d: &mut Decoder<'_>) -> Result<Self>;
+
+    /// The value of a tagged field that was left out, or `None` if the
+    /// field is required.
+    fn absent() -> Option<Self> {
+        None
+    }
+
+    /// Decodes a whole buffer; `op` names the message in errors.
+    fn from_bytes(buf: &[u8], op: &'static str) -> Result<Self> {
+        let mut d = Decoder::new(buf, op);
+        let v = Self::decode(&mut d)?;
+        d.finish()?;
+        Ok(v)
+    }
+}
+
+/// Declares a struct whose fields are encoded positionally, in order.
+/// Append new fields at the end only.
+macro_rules! wire_struct {
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident {
+            $( $(#[$fmeta:meta])* pub $field:ident : $ty:ty ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Debug, PartialEq)]
+        pub struct $name {
+            $( $(#[$fmeta])* pub $field: $ty ),*
+        }
+
+        impl $crate::codec::WireEncode for $name {
+            #[allow(unused_variables)]
+            fn encode(&self, e: &mut $crate::codec::Encoder) {
+                $( $crate::codec::WireEncode::encode(&self.$field, e); )*
+            }
+        }
+
+        impl $crate::codec::WireDecode for $name {
+            #[allow(unused_variables)]
+            fn decode(
+                d: &mut $crate::codec::Decoder<'_>,
+            ) -> hdfs_common::error::Result<Self> {
+                Ok(Self {
+                    $( $field: $crate::codec::WireDecode::decode(d)?, )*
+                })
+            }
+        }
+    };
+}
+pub(crate) use wire_struct;
+
+/// Reads the fields of a [`tagged_struct!`] in tag order. Fields with tags
+/// the struct doesn't declare were added by a newer peer and are skipped.
+#[derive(Debug)]
+pub struct FieldReader<'a, 'd> {
+    d: &'d mut Decoder<'a>,
+    message: &'static str,
+    tag: u64,
+    next: Option<(u64, &'a [u8])>,
+}
+
+impl<'a, 'd> FieldReader<'a, 'd> {
+    /// Reads fields until `d` is exhausted.
+    pub fn new(d: &'d mut Decoder<'a>, message: &'static str) -> Result<Self> {
+        let mut r = Self {
+            d,
+            message,
+            tag: 0,
+            next: None,
+        };
+        r.advance(0)?;
+        Ok(r)
+    }
+
+    fn advance(&mut self, prev: u64) -> Result<()> {
+        self.next = None;
+        if self.d.remaining() == 0 {
+            return Ok(());
+        }
+        let tag = self.d.get_varint()?;
+        if tag <= prev {
+            return Err(self
+                .d
+                .error(format!("{}: field tag {tag} after {prev}", self.message)));
+        }
+        let len = usize::try_from(self.d.get_varint()?)
+            .map_err(|_| self.d.error("field length overflows usize"))?;
+        self.next = Some((tag, self.d.get_raw(len)?));
+        Ok(())
+    }
+
+    /// Decodes the field with the next tag, `field` naming it in errors.
+    pub fn field<T: WireDecode>(&mut self, field: &'static str) -> Result<T> {
+        self.tag += 1;
+        match self.next {
+            Some((tag, bytes)) if tag == self.tag => {
+                let mut value = Decoder::new(bytes, self.d.op());
+                let v = T::decode(&mut value)?;
+                if value.remaining() > 0 {
+                    return Err(self.d.error(format!(
+                        "{}.{field}: {} trailing bytes",
+                        self.message,
+                        value.remaining()
+                    )));
+                }
+                self.advance(tag)?;
+                Ok(v)
+            }
+            _ => T::absent().ok_or_else(|| {
+                self.d
+                    .error(format!("missing required field {}.{field}", self.message))
+            }),
+        }
+    }
+
+    /// Skips whatever fields are left: ones added after this struct's last.
+    pub fn finish(mut self) -> Result<()> {
+        while let Some((tag, _)) = self.next {
+            self.advance(tag)?;
+        }
+        Ok(())
+    }
+}
+
+/// Declares a struct whose fields are tagged, so peers running different
+/// versions can still talk: tags are assigned in declaration order from 1,
+/// fields a decoder doesn't know are skipped, and missing `Option` and
+/// `Vec` fields decode as `None` and empty. Anything else is required, so
+/// add only `Option` or `Vec` fields, at the end, and never remove one.
+///
+/// Nested inside other values the struct is prefixed with its varint
+/// length; `to_bytes` and `from_bytes` leave the prefix off.
+macro_rules! tagged_struct {
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident {
+            $( $(#[$fmeta:meta])* pub $field:ident : $ty:ty ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Debug, PartialEq)]
+        pub struct $name {
+            $( $(#[$fmeta])* pub $field: $ty ),*
+        }
+
+        impl $name {
+            #[allow(unused_mut, unused_variables)]
+            fn encode_fields(&self, e: &mut $crate::codec::Encoder) {
+                let mut tag = 0;
+                $(
+                    tag += 1;
+                    e.put_field(tag, &self.$field);
+                )*
+            }
+
+            fn decode_fields(
+                d: &mut $crate::codec::Decoder<'_>,
+            ) -> hdfs_common::error::Result<Self> {
+                #[allow(unused_mut)]
+                let mut r = $crate::codec::FieldReader::new(d, stringify!($name))?;
+                let v = Self {
+                    $( $field: r.field(stringify!($field))?, )*
+                };
+                r.finish()?;
+                Ok(v)
+            }
+        }
+
+        impl $crate::codec::WireEncode for $name {
+            fn encode(&self, e: &mut $crate::codec::Encoder) {
+                let mut body = $crate::codec::Encoder::new();
+                self.encode_fields(&mut body);
+                e.put_varint(body.len() as u64);
+                e.put_raw(&body.into_bytes());
+            }
+
+            fn to_bytes(&self) -> Vec<u8> {
+                let mut e = $crate::codec::Encoder::new();
+                self.encode_fields(&mut e);
+                e.into_bytes()
+            }
+        }
+
+        impl $crate::codec::WireDecode for $name {
+            fn decode(
+                d: &mut $crate::codec::Decoder<'_>,
+            ) -> hdfs_common::error::Result<Self> {
+                let len = usize::try_from(d.get_varint()?)
+                    .map_err(|_| d.error("length overflows usize"))?;
+                let mut body = $crate::codec::Decoder::new(d.get_raw(len)?, d.op());
+                Self::decode_fields(&mut body)
+            }
+
+            fn from_bytes(
+                buf: &[u8],
+                op: &'static str,
+            ) -> hdfs_common::error::Result<Self> {
+                Self::decode_fields(&mut $crate::codec::Decoder::new(buf, op))
+            }
+        }
+    };
+}
+pub(crate) use tagged_struct;
+
+macro_rules! wire_int {
+    ($($ty:ty => $put:ident, $get:ident;)*) => {$(
+        impl WireEncode for $ty {
+            fn encode(&self, e: &mut Encoder) {
+                e.$put(*self);
+            }
+        }
+
+        impl WireDecode for $ty {
+            fn decode(d: &mut Decoder<'_>) -> Result<Self> {
+                d.$get()
+            }
+        }
+    )*};
+}
+
+wire_int! {
+    u8 => put_u8, get_u8;
+    u16 => put_u16, get_u16;
+    u32 => put_u32, get_u32;
+    u64 => put_u64, get_u64;
+}
+
+impl WireEncode for bool {
+    fn encode(&self, e: &mut Encoder) {
+        e.put_u8(*self as u8);
+    }
+}
+
+impl WireDecode for bool {
+    fn decode(d: &mut Decoder<'_>) -> Result<Self> {
+        match d.get_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => Err(d.error(format!("invalid bool {b}"))),
+        }
+    }
+}
+
+impl WireEncode for String {
+    fn encode(&self, e: &mut Encoder) {
+        e.put_str(self);
+    }
+}
+
+impl WireDecode for String {
+    fn decode(d: &mut Decoder<'_>) -> Result<Self> {
+        d.get_str().map(str::to_string)
+    }
+}
+
+impl<T: WireEncode> WireEncode for Vec<T> {
+    fn encode(&self, e: &mut Encoder) {
+        e.put_len(self.len());
+        for item in self {
+            item.encode(e);
+        }
+    }
+
+    fn is_absent(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<T: WireDecode> WireDecode for Vec<T> {
+    fn decode(d: &mut Decoder<'_>) -> Result<Self> {
+        let n = d.get_len()?;
+        // every item takes at least a byte; don't trust `n` for the allocation
+        let mut out = Vec::with_capacity(n.min(d.remaining()));
+        for _ in 0..n {
+            out.push(T::decode(d)?);
+        }
+        Ok(out)
+    }
+
+    fn absent() -> Option<Self> {
+        Some(Vec::new())
+    }
+}
+
+impl<T: WireEncode> WireEncode for Option<T> {
+    fn encode(&self, e: &mut Encoder) {
+        match self {
+            None => e.put_u8(0),
+            Some(v) => {
+                e.put_u8(1);
+                v.encode(e);
+            }
+        }
+    }
+
+    fn is_absent(&self) -> bool {
+        self.is_none()
+    }
+}
+
+impl<T: WireDecode> WireDecode for Option<T> {
+    fn decode(d: &mut Decoder<'_>) -> Result<Self> {
+        match d.get_u8()? {
+            0 => Ok(None),
+            1 => T::decode(d).map(Some),
+            b => Err(d.error(format!("invalid option tag {b}"))),
+        }
+    }
+
+    fn absent() -> Option<Self> {
+        Some(None)
+    }
+}
+
+macro_rules! wire_u64_id {
+    ($($ty:ident),*) => {$(
+        impl WireEncode for $ty {
+            fn encode(&self, e: &mut Encoder) {
+                e.put_u64(self.0);
+            }
+        }
+
+        impl WireDecode for $ty {
+            fn decode(d: &mut Decoder<'_>) -> Result<Self> {
+                d.get_u64().map($ty)
+            }
+        }
+    )*};
+}
+
+wire_u64_id!(BlockId, INodeId, LeaseId, GenerationStamp);
+
+macro_rules! wire_uuid_id {
+    ($($ty:ident),*) => {$(
+        impl WireEncode for $ty {
+            fn encode(&self, e: &mut Encoder) {
+                e.put_raw(self.0.as_bytes());
+            }
+        }
+
+        impl WireDecode for $ty {
+            fn decode(d: &mut Decoder<'_>) -> Result<Self> {
+                let raw: [u8; 16] = d.get_array()?;
+                Ok($ty(Uuid::from_bytes(raw)))
+            }
+        }
+    )*};
+}
+
+wire_uuid_id!(DatanodeId, ClusterId, NamespaceId, BlockPoolId);
+
+impl WireEncode for PathAbs {
+    fn encode(&self, e: &mut Encoder) {
+        e.put_str(self.as_str());
+    }
+}
+
+impl WireDecode for PathAbs {
+    fn decode(d: &mut Decoder<'_>) -> Result<Self> {
+        let s = d.get_str()?;
+        PathAbs::try_from(s).map_err(|e| d.error(e.to_string()))
+    }
+}
+
+impl WireEncode for ExtendedBlock {
+    fn encode(&self, e: &mut Encoder) {
+        self.pool.encode(e);
+        self.id.encode(e);
+        self.gen_stamp.encode(e);
+        self.num_bytes.encode(e);
+    }
+}
+
+impl WireDecode for ExtendedBlock {
+    fn decode(d: &mut Decoder<'_>) -> Result<Self> {
+        Ok(ExtendedBlock {
+            pool: WireDecode::decode(d)?,
+            id: WireDecode::decode(d)?,
+            gen_stamp: WireDecode::decode(d)?,
+            num_bytes: WireDecode::decode(d)?,
+        })
+    }
+}
+
+impl WireEncode for DataChecksum {
+    fn encode(&self, e: &mut Encoder) {
+        e.put_raw(&self.to_header());
+    }
+}
+
+impl WireDecode for DataChecksum {
+    fn decode(d: &mut Decoder<'_>) -> Result<Self> {
+        DataChecksum::from_header(&d.get_array()?).map_err(|e| match e {
+            HdfsError::Protocol { details, .. } => d.error(details),
+            e => e,
+        })
+    }
+}
+
+impl WireEncode for RemoteError {
+    fn encode(&self, e: &mut Encoder) {
+        e.put_u16(self.code);
+        e.put_str(&self.class_name);
+        e.put_str(&self.message);
+        // the variant is implied by `code`
+        match &self.detail {
+            RemoteDetail::Io { kind, msg } => {
+                e.put_str(kind);
+                e.put_str(msg);
+            }
+            RemoteDetail::Config { key: a, msg: b }
+            | RemoteDetail::InvalidPath { path: a, reason: b }
+            | RemoteDetail::State {
+                what: a,
+                details: b,
+            }
+            | RemoteDetail::Protocol { op: a, details: b }
+            | RemoteDetail::Timeout { op: a, during: b } => {
+                e.put_str(a);
+                e.put_str(b);
+            }
+            RemoteDetail::AlreadyExists { path } | RemoteDetail::NotFound { path } => {
+                e.put_str(path);
+            }
+            RemoteDetail::ChecksumMismatch {
+                block,
+                chunk_index,
+                expected,
+                got,
+            } => {
+                block.encode(e);
+                e.put_u64(*chunk_index);
+                e.put_u32(*expected);
+                e.put_u32(*got);
+            }
+        }
+    }
+}
+
+impl WireDecode for RemoteError {
+    fn decode(d: &mut Decoder<'_>) -> Result<Self> {
+        let code = d.get_u16()?;
+        let class_name = String::decode(d)?;
+        let message = String::decode(d)?;
+        let mut s = || String::decode(d);
+        let detail = match code {
+            1 => RemoteDetail::Io {
+                kind: s()?,
+                msg: s()?,
+            },
+            2 => RemoteDetail::Config {
+                key: s()?,
+                msg: s()?,
+            },
+            3 => RemoteDetail::InvalidPath {
+                path: s()?,
+                reason: s()?,
+            },
+            4 => RemoteDetail::AlreadyExists { path: s()? },
+            5 => RemoteDetail::NotFound { path: s()? },
+            6 => RemoteDetail::State {
+                what: s()?,
+                details: s()?,
+            },
+            7 => RemoteDetail::Protocol {
+                op: s()?,
+                details: s()?,
+            },
+            8 => RemoteDetail::ChecksumMismatch {
+                block: BlockId::decode(d)?,
+                chunk_index: d.get_u64()?,
+                expected: d.get_u32()?,
+                got: d.get_u32()?,
+            },
+            9 => RemoteDetail::Timeout {
+                op: s()?,
+                during: s()?,
+            },
+            other => return Err(d.error(format!("unknown error code {other}"))),
+        };
+        Ok(RemoteError {
+            code,
+            class_name,
+            message,
+            detail,
+        })
+    }
+}
+
+/// Sixteen bytes per line, for golden fixtures.
+#[cfg(test)]
+pub(crate) fn hex_lines(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for row in bytes.chunks(16) {
+        let row: Vec<String> = row.iter().map(|b| format!("{b:02x}")).collect();
+        out.push_str("  ");
+        out.push_str(&row.join(" "));
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    wire_struct! {
+        pub struct Sample {
+            pub a: u16,
+            pub name: String,
+            pub items: Vec<u32>,
+            pub maybe: Option<bool>,
+        }
+    }
+
+    fn sample() -> Sample {
+        Sample {
+            a: 0x0102,
+            name: "ab".into(),
+            items: vec![7],
+            maybe: Some(true),
+        }
+    }
+
+    #[test]
+    fn positional_layout() {
+        assert_eq!(
+            sample().to_bytes(),
+            [1, 2, 0, 0, 0, 2, b'a', b'b', 0, 0, 0, 1, 0, 0, 0, 7, 1, 1]
+        );
+        let back = Sample::from_bytes(&sample().to_bytes(), "Sample").unwrap();
+        assert_eq!(back, sample());
+    }
+
+    #[test]
+    fn decode_errors_name_the_op() {
+        let bytes = sample().to_bytes();
+        let err = |buf: &[u8]| match Sample::from_bytes(buf, "Sample") {
+            Err(HdfsError::Protocol { op, details }) => {
+                assert_eq!(op, "Sample");
+                details
+            }
+            other => panic!("expected Protocol error, got {other:?}"),
+        };
+        assert!(err(&bytes[..bytes.len() - 1]).starts_with("truncated"));
+
+        let mut long = bytes.clone();
+        long.push(0);
+        assert_eq!(err(&long), "1 trailing bytes");
+
+        let mut bad = bytes.clone();
+        *bad.last_mut().unwrap() = 2;
+        assert_eq!(err(&bad), "invalid bool 2");
+
+        let mut bad = bytes.clone();
+        bad[6] = 0xFF;
+        assert!(err(&bad).starts_with("invalid utf-8"));
+
+        // a huge count fails on the missing items, not on allocation
+        let mut bad = bytes;
+        bad[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(err(&bad).starts_with("truncated"));
+    }
+
+    tagged_struct! {
+        pub struct PingV1 {
+            pub id: u16,
+            pub name: String,
+            pub tags: Vec<u8>,
+        }
+    }
+
+    tagged_struct! {
+        pub struct PingV2 {
+            pub id: u16,
+            pub name: String,
+            pub tags: Vec<u8>,
+            pub zone: Option<String>,
+            pub pings: Vec<PingV1>,
+        }
+    }
+
+    fn ping_v2() -> PingV2 {
+        PingV2 {
+            id: 0x0102,
+            name: "a".into(),
+            tags: vec![],
+            zone: Some("z".into()),
+            pings: vec![PingV1 {
+                id: 3,
+                name: String::new(),
+                tags: vec![4],
+            }],
+        }
+    }
+
+    #[test]
+    fn tagged_layout() {
+        let v1 = PingV1 {
+            id: 0x0102,
+            name: "a".into(),
+            tags: vec![],
+        };
+        // the empty `tags` is left out
+        assert_eq!(v1.to_bytes(), [1, 2, 1, 2, 2, 5, 0, 0, 0, 1, b'a']);
+        assert_eq!(PingV1::from_bytes(&v1.to_bytes(), "Ping").unwrap(), v1);
+
+        // nested, the struct carries its length
+        let mut e = Encoder::new();
+        v1.encode(&mut e);
+        assert_eq!(e.into_bytes()[0], 11);
+        assert!(PingV1::from_bytes(&[], "Ping").is_err());
+
+        let v2 = ping_v2();
+        assert_eq!(PingV2::from_bytes(&v2.to_bytes(), "Ping").unwrap(), v2);
+    }
+
+    #[test]
+    fn old_and_new_decoders_interoperate() {
+        // an old decoder skips `zone` and `pings`
+        let old = PingV1::from_bytes(&ping_v2().to_bytes(), "Ping").unwrap();
+        assert_eq!(old.id, 0x0102);
+        assert_eq!(old.name, "a");
+
+        // a new decoder fills them in as absent
+        let new = PingV2::from_bytes(&old.to_bytes(), "Ping").unwrap();
+        assert_eq!(new.zone, None);
+        assert!(new.pings.is_empty());
+
+        // skipped fields may hold anything, even nonsense for this version
+        let mut bytes = old.to_bytes();
+        bytes.extend_from_slice(&[9, 3, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(PingV1::from_bytes(&bytes, "Ping").unwrap(), old);
+    }
+
+    #[test]
+    fn tagged_decode_errors() {
+        let err = |buf: &[u8]| match PingV1::from_bytes(buf, "Ping") {
+            Err(HdfsError::Protocol { op, details }) => {
+                assert_eq!(op, "Ping");
+                details
+            }
+            other => panic!("expected Protocol error, got {other:?}"),
+        };
+        let bytes = ping_v2().to_bytes();
+
+        // required `name` missing
+        assert_eq!(err(&bytes[..4]), "missing required field PingV1.name");
+        assert_eq!(err(&[]), "missing required field PingV1.id");
+
+        // duplicated or reordered tags
+        let mut twice = bytes[..4].to_vec();
+        twice.extend_from_slice(&bytes[..4]);
+        assert_eq!(err(&twice), "PingV1: field tag 1 after 1");
+
+        // a known field must be used up exactly
+        assert_eq!(
+            err(&[1, 3, 0, 1, 0, 2, 5, 0, 0, 0, 1, b'a']),
+            "PingV1.id: 1 trailing bytes"
+        );
+
+        // a field longer than the message
+        assert!(err(&[1, 9, 0]).starts_with("truncated"));
+    }
+
+    #[test]
+    fn varints() {
+        for v in [0, 1, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+            let mut e = Encoder::new();
+            e.put_varint(v);
+            let bytes = e.into_bytes();
+            assert_eq!(
+                bytes.len(),
+                (64 - v.leading_zeros() as usize).div_ceil(7).max(1)
+            );
+            let mut d = Decoder::new(&bytes, "varint");
+            assert_eq!(d.get_varint().unwrap(), v);
+            d.finish().unwrap();
+        }
+        assert_eq!(
+            Decoder::new(&[0xAC, 0x02], "varint").get_varint().unwrap(),
+            300
+        );
+
+        let mut too_big = vec![0xFF; 9];
+        too_big.push(0x02);
+        assert!(Decoder::new(&too_big, "varint").get_varint().is_err());
+        assert!(Decoder::new(&[0x80], "varint").get_varint().is_err());
+    }
+
+    #[test]
+    fn common_types_roundtrip() {
+        let blk = ExtendedBlock::new(BlockPoolId::new_v4(), BlockId(5), GenerationStamp(1001), 77);
+        assert_eq!(
+            ExtendedBlock::from_bytes(&blk.to_bytes(), "blk").unwrap(),
+            blk
+        );
+
+        let p = PathAbs::try_from("/a/b").unwrap();
+        assert_eq!(PathAbs::from_bytes(&p.to_bytes(), "path").unwrap(), p);
+
+        let mut e = Encoder::new();
+        e.put_str("relative");
+        assert!(matches!(
+            PathAbs::from_bytes(&e.into_bytes(), "GetFileInfo"),
+            Err(HdfsError::Protocol { op, .. }) if op == "GetFileInfo"
+        ));
+    }
+
+    #[test]
+    fn remote_errors_roundtrip() {
+        let errors = [
+            HdfsError::NotFound { path: "/x".into() },
+            HdfsError::State {
+                what: "complete".into(),
+                details: "file not under construction".into(),
+            },
+            HdfsError::ChecksumMismatch {
+                block: BlockId(42),
+                chunk_index: 7,
+                expected: 1,
+                got: 2,
+            },
+            HdfsError::from(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "gone")),
+        ];
+        for err in errors {
+            let remote = err.to_remote();
+            let back = RemoteError::from_bytes(&remote.to_bytes(), "error").unwrap();
+            assert_eq!(back, remote);
+            assert_eq!(back.into_error().to_string(), err.to_string());
+        }
+    }
+}
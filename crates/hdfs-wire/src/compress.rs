@@ -0,0 +1,192 @@
+//! Payload compression, used by frames and packets when both sides agreed
+//! on it at handshake. A compressed payload always says how long it will be
+//! once decompressed, so a receiver can refuse it before doing the work.
+
+use crate::handshake::Features;
+use hdfs_common::consts::KIB;
+use hdfs_common::error::{HdfsError, Result};
+use std::fmt;
+
+/// Payloads smaller than this are sent as they are.
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 4 * KIB as usize;
+
+/// Most a payload may grow when decompressed. Senders stay raw rather than
+/// go past it, so only a hostile peer trips it.
+pub const MAX_COMPRESSION_RATIO: usize = 1024;
+
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Compression {
+    Lz4,
+    Zstd,
+}
+
+impl Compression {
+    /// In order of preference when both sides have more than one.
+    pub const ALL: [Compression; 2] = [Compression::Zstd, Compression::Lz4];
+
+    pub fn id(self) -> u8 {
+        match self {
+            Compression::Lz4 => 1,
+            Compression::Zstd => 2,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(Compression::Lz4),
+            2 => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// The handshake feature saying a side has this codec.
+    pub fn feature(self) -> Features {
+        match self {
+            Compression::Lz4 => Features::LZ4,
+            Compression::Zstd => Features::ZSTD,
+        }
+    }
+
+    /// `data` compressed, or `None` if that would not make it smaller or
+    /// would exceed [`MAX_COMPRESSION_RATIO`].
+    pub fn compress(self, data: &[u8]) -> Option<Vec<u8>> {
+        let out = match self {
+            Compression::Lz4 => lz4_flex::block::compress(data),
+            Compression::Zstd => zstd::bulk::compress(data, ZSTD_LEVEL).ok()?,
+        };
+        let worth_it =
+            out.len() < data.len() && data.len() <= out.len().saturating_mul(MAX_COMPRESSION_RATIO);
+        worth_it.then_some(out)
+    }
+
+    /// Decompresses `data`, which must come out at exactly `raw_len` bytes.
+    /// `raw_len` is checked against `limit` and the ratio cap before any
+    /// memory is set aside for it.
+    pub fn decompress(
+        self,
+        data: &[u8],
+        raw_len: usize,
+        limit: usize,
+        op: &'static str,
+    ) -> Result<Vec<u8>> {
+        let err = |details: String| HdfsError::Protocol {
+            op: op.into(),
+            details,
+        };
+        if raw_len > limit {
+            return Err(err(format!(
+                "{self} payload decompresses to {raw_len} bytes, over the limit of {limit}"
+            )));
+        }
+        if raw_len > data.len().saturating_mul(MAX_COMPRESSION_RATIO) {
+            return Err(err(format!(
+                "{self} payload of {} bytes claims {raw_len} decompressed, over the \
+                 {MAX_COMPRESSION_RATIO}x ratio limit",
+                data.len()
+            )));
+        }
+        let out = match self {
+            Compression::Lz4 => {
+                let mut out = vec![0; raw_len];
+                lz4_flex::block::decompress_into(data, &mut out)
+                    .map_err(|e| err(format!("bad lz4 payload: {e}")))
+                    .map(|n| {
+                        out.truncate(n);
+                        out
+                    })?
+            }
+            Compression::Zstd => zstd::bulk::decompress(data, raw_len)
+                .map_err(|e| err(format!("bad zstd payload: {e}")))?,
+        };
+        if out.len() != raw_len {
+            return Err(err(format!(
+                "{self} payload decompressed to {} bytes, expected {raw_len}",
+                out.len()
+            )));
+        }
+        Ok(out)
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Compression::Lz4 => "lz4",
+            Compression::Zstd => "zstd",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(len: usize) -> Vec<u8> {
+        b"/user/hdfs/warehouse/part-00000 "
+            .iter()
+            .copied()
+            .cycle()
+            .take(len)
+            .collect()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let data = text(10_000);
+        for c in Compression::ALL {
+            let packed = c.compress(&data).unwrap();
+            assert!(packed.len() < data.len() / 4, "{c}: {}", packed.len());
+            assert_eq!(
+                c.decompress(&packed, data.len(), data.len(), "t").unwrap(),
+                data
+            );
+            assert_eq!(Compression::from_id(c.id()), Some(c));
+        }
+        assert_eq!(Compression::from_id(0), None);
+    }
+
+    #[test]
+    fn incompressible_and_degenerate_data_stay_raw() {
+        let mut x = 0x2545_F491_4F6C_DD1Du64;
+        let noise: Vec<u8> = (0..4096)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x as u8
+            })
+            .collect();
+        let zeros = vec![0; 16 * hdfs_common::consts::MIB as usize];
+        for c in Compression::ALL {
+            assert_eq!(c.compress(&noise), None, "{c}");
+            assert_eq!(c.compress(&[]), None, "{c}");
+        }
+        // zstd squeezes 16 MiB of zeros far past the ratio cap
+        assert_eq!(Compression::Zstd.compress(&zeros), None);
+    }
+
+    #[test]
+    fn bombs_are_refused() {
+        let data = text(8192);
+        for c in Compression::ALL {
+            let packed = c.compress(&data).unwrap();
+            let details = |res: Result<Vec<u8>>| match res {
+                Err(HdfsError::Protocol { op, details }) if op == "t" => details,
+                other => panic!("expected protocol error, got {other:?}"),
+            };
+            assert!(details(c.decompress(&packed, 8192, 4096, "t")).contains("over the limit"));
+            assert!(
+                details(c.decompress(&packed[..1], 8192, usize::MAX, "t")).contains("ratio limit")
+            );
+            // lying about the size either way fails
+            assert!(c.decompress(&packed, 8191, usize::MAX, "t").is_err());
+            assert!(c.decompress(&packed, 8193, usize::MAX, "t").is_err());
+            assert!(
+                c.decompress(&packed[..packed.len() / 2], 8192, usize::MAX, "t")
+                    .is_err()
+            );
+        }
+    }
+}
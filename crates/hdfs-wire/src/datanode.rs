@@ -0,0 +1,800 @@
+//! DatanodeProtocol: what datanodes send to the namenode, and the commands
+//! they get back with heartbeat and block report responses.
+
+use crate::client::DatanodeInfo;
+use crate::codec::{Decoder, Encoder, WireDecode, WireEncode, tagged_struct};
+use crate::message::protocol_ops;
+use hdfs_common::error::Result;
+use hdfs_common::ids::{BlockId, BlockPoolId, ClusterId, DatanodeId, GenerationStamp};
+use hdfs_common::types::ExtendedBlock;
+
+tagged_struct! {
+    /// Who a datanode is and which namespace it believes it belongs to.
+    pub struct DatanodeRegistration {
+        pub datanode: DatanodeInfo,
+        pub cluster_id: ClusterId,
+        pub block_pool_id: BlockPoolId,
+        pub layout_version: u32,
+        pub software_version: String,
+    }
+}
+
+tagged_struct! {
+    /// Usage of one data directory, in bytes.
+    pub struct StorageReport {
+        pub storage_id: String,
+        pub failed: bool,
+        pub capacity: u64,
+        pub dfs_used: u64,
+        pub remaining: u64,
+        pub block_pool_used: u64,
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ReplicaState {
+    Finalized,
+    /// Open in a write pipeline.
+    BeingWritten,
+    /// Left over from a pipeline that died; waits for block recovery.
+    WaitingRecovery,
+}
+
+impl WireEncode for ReplicaState {
+    fn encode(&self, e: &mut Encoder) {
+        e.put_u8(match self {
+            ReplicaState::Finalized => 0,
+            ReplicaState::BeingWritten => 1,
+            ReplicaState::WaitingRecovery => 2,
+        });
+    }
+}
+
+impl WireDecode for ReplicaState {
+    fn decode(d: &mut Decoder<'_>) -> Result<Self> {
+        match d.get_u8()? {
+            0 => Ok(ReplicaState::Finalized),
+            1 => Ok(ReplicaState::BeingWritten),
+            2 => Ok(ReplicaState::WaitingRecovery),
+            s => Err(d.error(format!("invalid replica state {s}"))),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ReportedBlock {
+    pub id: BlockId,
+    pub gen_stamp: GenerationStamp,
+    pub num_bytes: u64,
+    pub state: ReplicaState,
+}
+
+/// Every replica of a storage, sorted by block id with no duplicates.
+///
+/// Full reports run to millions of entries, so ids are sent as varint deltas
+/// from the previous id and lengths and generation stamps as varints.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BlockList {
+    blocks: Vec<ReportedBlock>,
+}
+
+impl BlockList {
+    /// Sorts `blocks`; of several entries for one id the last one is kept.
+    pub fn new(mut blocks: Vec<ReportedBlock>) -> Self {
+        blocks.reverse();
+        blocks.sort_by_key(|b| b.id);
+        blocks.dedup_by_key(|b| b.id);
+        Self { blocks }
+    }
+
+    pub fn blocks(&self) -> &[ReportedBlock] {
+        &self.blocks
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn get(&self, id: BlockId) -> Option<&ReportedBlock> {
+        self.blocks
+            .binary_search_by_key(&id, |b| b.id)
+            .ok()
+            .map(|i| &self.blocks[i])
+    }
+}
+
+impl WireEncode for BlockList {
+    fn encode(&self, e: &mut Encoder) {
+        e.put_len(self.blocks.len());
+        let mut prev = 0;
+        for b in &self.blocks {
+            e.put_varint(b.id.0 - prev);
+            e.put_varint(b.num_bytes);
+            e.put_varint(b.gen_stamp.0);
+            b.state.encode(e);
+            prev = b.id.0;
+        }
+    }
+}
+
+impl WireDecode for BlockList {
+    fn decode(d: &mut Decoder<'_>) -> Result<Self> {
+        let n = d.get_len()?;
+        // every entry takes at least four bytes
+        let mut blocks = Vec::with_capacity(n.min(d.remaining() / 4));
+        let mut prev: Option<u64> = None;
+        for _ in 0..n {
+            let delta = d.get_varint()?;
+            let id = match prev {
+                None => delta,
+                Some(_) if delta == 0 => {
+                    return Err(d.error("block list is not strictly sorted"));
+                }
+                Some(p) => p
+                    .checked_add(delta)
+                    .ok_or_else(|| d.error("block id overflows u64"))?,
+            };
+            blocks.push(ReportedBlock {
+                id: BlockId(id),
+                num_bytes: d.get_varint()?,
+                gen_stamp: GenerationStamp(d.get_varint()?),
+                state: ReplicaState::decode(d)?,
+            });
+            prev = Some(id);
+        }
+        Ok(Self { blocks })
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ReceivedStatus {
+    /// A pipeline write to the replica has started.
+    Receiving,
+    /// The replica is finalized.
+    Received,
+    Deleted,
+}
+
+impl WireEncode for ReceivedStatus {
+    fn encode(&self, e: &mut Encoder) {
+        e.put_u8(match self {
+            ReceivedStatus::Receiving => 0,
+            ReceivedStatus::Received => 1,
+            ReceivedStatus::Deleted => 2,
+        });
+    }
+}
+
+impl WireDecode for ReceivedStatus {
+    fn decode(d: &mut Decoder<'_>) -> Result<Self> {
+        match d.get_u8()? {
+            0 => Ok(ReceivedStatus::Receiving),
+            1 => Ok(ReceivedStatus::Received),
+            2 => Ok(ReceivedStatus::Deleted),
+            s => Err(d.error(format!("invalid received status {s}"))),
+        }
+    }
+}
+
+tagged_struct! {
+    pub struct ReceivedDeletedBlock {
+        pub block: BlockId,
+        pub gen_stamp: GenerationStamp,
+        pub num_bytes: u64,
+        pub status: ReceivedStatus,
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ErrorCode {
+    /// Informational; the datanode keeps running.
+    Notify,
+    DiskError,
+    InvalidBlock,
+    /// The datanode is shutting down.
+    FatalDiskError,
+}
+
+impl WireEncode for ErrorCode {
+    fn encode(&self, e: &mut Encoder) {
+        e.put_u8(match self {
+            ErrorCode::Notify => 0,
+            ErrorCode::DiskError => 1,
+            ErrorCode::InvalidBlock => 2,
+            ErrorCode::FatalDiskError => 3,
+        });
+    }
+}
+
+impl WireDecode for ErrorCode {
+    fn decode(d: &mut Decoder<'_>) -> Result<Self> {
+        match d.get_u8()? {
+            0 => Ok(ErrorCode::Notify),
+            1 => Ok(ErrorCode::DiskError),
+            2 => Ok(ErrorCode::InvalidBlock),
+            3 => Ok(ErrorCode::FatalDiskError),
+            c => Err(d.error(format!("invalid error code {c}"))),
+        }
+    }
+}
+
+/// Work the namenode hands to a datanode. On the wire each command is its
+/// tag followed by its length-prefixed body, so a datanode can step over
+/// commands from a newer namenode and run the rest.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DatanodeCommand {
+    /// Copy `block` to `targets`, e.g. to restore its replication.
+    Transfer {
+        block: ExtendedBlock,
+        targets: Vec<DatanodeInfo>,
+    },
+    /// Delete these replicas.
+    Invalidate {
+        pool: BlockPoolId,
+        blocks: Vec<BlockId>,
+    },
+    /// Act as primary for recovering `block` across `replicas`, bumping it
+    /// to `new_gen_stamp`.
+    Recover {
+        block: ExtendedBlock,
+        new_gen_stamp: GenerationStamp,
+        replicas: Vec<DatanodeId>,
+    },
+    /// Pin (or with `uncache`, unpin) these replicas in memory.
+    Cache {
+        pool: BlockPoolId,
+        blocks: Vec<BlockId>,
+        uncache: bool,
+    },
+    /// An upgrade is committed; drop the pre-upgrade copy of the pool.
+    Finalize { pool: BlockPoolId },
+    /// The namenode does not know this datanode; register again.
+    Register,
+    /// A command this build does not know, by tag; its body is dropped.
+    Unknown(u8),
+}
+
+impl DatanodeCommand {
+    fn tag(&self) -> u8 {
+        match self {
+            DatanodeCommand::Transfer { .. } => 1,
+            DatanodeCommand::Invalidate { .. } => 2,
+            DatanodeCommand::Recover { .. } => 3,
+            DatanodeCommand::Cache { .. } => 4,
+            DatanodeCommand::Finalize { .. } => 5,
+            DatanodeCommand::Register => 6,
+            DatanodeCommand::Unknown(tag) => *tag,
+        }
+    }
+}
+
+impl WireEncode for DatanodeCommand {
+    fn encode(&self, e: &mut Encoder) {
+        let mut body = Encoder::new();
+        match self {
+            DatanodeCommand::Transfer { block, targets } => {
+                block.encode(&mut body);
+                targets.encode(&mut body);
+            }
+            DatanodeCommand::Invalidate { pool, blocks } => {
+                pool.encode(&mut body);
+                blocks.encode(&mut body);
+            }
+            DatanodeCommand::Recover {
+                block,
+                new_gen_stamp,
+                replicas,
+            } => {
+                block.encode(&mut body);
+                new_gen_stamp.encode(&mut body);
+                replicas.encode(&mut body);
+            }
+            DatanodeCommand::Cache {
+                pool,
+                blocks,
+                uncache,
+            } => {
+                pool.encode(&mut body);
+                blocks.encode(&mut body);
+                uncache.encode(&mut body);
+            }
+            DatanodeCommand::Finalize { pool } => pool.encode(&mut body),
+            DatanodeCommand::Register | DatanodeCommand::Unknown(_) => {}
+        }
+        e.put_u8(self.tag());
+        e.put_bytes(&body.into_bytes());
+    }
+}
+
+impl WireDecode for DatanodeCommand {
+    fn decode(d: &mut Decoder<'_>) -> Result<Self> {
+        let tag = d.get_u8()?;
+        let d = &mut Decoder::new(d.get_bytes()?, d.op());
+        // bytes left in a body are fields added after this build
+        Ok(match tag {
+            1 => DatanodeCommand::Transfer {
+                block: WireDecode::decode(d)?,
+                targets: WireDecode::decode(d)?,
+            },
+            2 => DatanodeCommand::Invalidate {
+                pool: WireDecode::decode(d)?,
+                blocks: WireDecode::decode(d)?,
+            },
+            3 => DatanodeCommand::Recover {
+                block: WireDecode::decode(d)?,
+                new_gen_stamp: WireDecode::decode(d)?,
+                replicas: WireDecode::decode(d)?,
+            },
+            4 => DatanodeCommand::Cache {
+                pool: WireDecode::decode(d)?,
+                blocks: WireDecode::decode(d)?,
+                uncache: WireDecode::decode(d)?,
+            },
+            5 => DatanodeCommand::Finalize {
+                pool: WireDecode::decode(d)?,
+            },
+            6 => DatanodeCommand::Register,
+            tag => DatanodeCommand::Unknown(tag),
+        })
+    }
+}
+
+tagged_struct! {
+    pub struct RegisterDatanodeRequest {
+        pub registration: DatanodeRegistration,
+        pub storages: Vec<StorageReport>,
+    }
+}
+
+tagged_struct! {
+    pub struct RegisterDatanodeResponse {
+        pub registration: DatanodeRegistration,
+    }
+}
+
+tagged_struct! {
+    /// Totals are across all storages, in bytes.
+    pub struct HeartbeatRequest {
+        pub datanode: DatanodeId,
+        pub capacity: u64,
+        pub dfs_used: u64,
+        pub remaining: u64,
+        /// Active data transfer threads.
+        pub xceiver_count: u32,
+        pub failed_volumes: u32,
+        pub storages: Vec<StorageReport>,
+    }
+}
+
+tagged_struct! {
+    pub struct HeartbeatResponse {
+        pub commands: Vec<DatanodeCommand>,
+    }
+}
+
+tagged_struct! {
+    pub struct BlockReportRequest {
+        pub datanode: DatanodeId,
+        pub pool: BlockPoolId,
+        pub storage_id: String,
+        pub blocks: BlockList,
+    }
+}
+
+tagged_struct! {
+    pub struct BlockReportResponse {
+        pub commands: Vec<DatanodeCommand>,
+    }
+}
+
+tagged_struct! {
+    /// Changes since the last report, sent as they happen rather than
+    /// waiting for the next full report.
+    pub struct IncrementalBlockReportRequest {
+        pub datanode: DatanodeId,
+        pub pool: BlockPoolId,
+        pub storage_id: String,
+        pub blocks: Vec<ReceivedDeletedBlock>,
+    }
+}
+
+tagged_struct! {
+    pub struct IncrementalBlockReportResponse {}
+}
+
+tagged_struct! {
+    pub struct ErrorReportRequest {
+        pub datanode: DatanodeId,
+        pub code: ErrorCode,
+        pub message: String,
+    }
+}
+
+tagged_struct! {
+    pub struct ErrorReportResponse {}
+}
+
+protocol_ops! {
+    "datanode", DatanodeOp, DatanodeRequest, DatanodeResponse;
+    RegisterDatanode = 0x101, "registerDatanode", idempotent, RegisterDatanodeRequest, RegisterDatanodeResponse;
+    Heartbeat = 0x102, "sendHeartbeat", idempotent, HeartbeatRequest, HeartbeatResponse;
+    BlockReport = 0x103, "blockReport", idempotent, BlockReportRequest, BlockReportResponse;
+    IncrementalBlockReport = 0x104, "blockReceivedAndDeleted", idempotent, IncrementalBlockReportRequest, IncrementalBlockReportResponse;
+    ErrorReport = 0x105, "errorReport", idempotent, ErrorReportRequest, ErrorReportResponse;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::ClientOp;
+    use crate::codec::hex_lines;
+    use crate::message::{decode_response, response_frame};
+    use hdfs_common::error::HdfsError;
+    use uuid::Uuid;
+
+    fn dn() -> DatanodeInfo {
+        DatanodeInfo {
+            id: DatanodeId::new_v4(),
+            hostname: "dn1".into(),
+            data_addr: "10.0.0.1:9866".into(),
+        }
+    }
+
+    fn reported(id: u64, state: ReplicaState) -> ReportedBlock {
+        ReportedBlock {
+            id: BlockId(id),
+            gen_stamp: GenerationStamp(1001),
+            num_bytes: 128 << 20,
+            state,
+        }
+    }
+
+    #[test]
+    fn op_codes_do_not_collide_with_client_ops() {
+        assert_eq!(DatanodeOp::ALL.len(), 5);
+        for op in DatanodeOp::ALL {
+            assert_eq!(DatanodeOp::from_code(op.code()), Some(*op));
+            assert_eq!(ClientOp::from_code(op.code()), None);
+        }
+        assert_eq!(DatanodeOp::Heartbeat.method(), "sendHeartbeat");
+    }
+
+    #[test]
+    fn block_lists_are_sorted_and_compact() {
+        let list = BlockList::new(vec![
+            reported(1 << 30 | 7, ReplicaState::Finalized),
+            reported(1 << 30 | 2, ReplicaState::BeingWritten),
+            reported(1 << 30 | 7, ReplicaState::WaitingRecovery),
+        ]);
+        assert_eq!(list.len(), 2);
+        assert_eq!(list.blocks()[0].id, BlockId(1 << 30 | 2));
+        assert_eq!(
+            list.get(BlockId(1 << 30 | 7)).unwrap().state,
+            ReplicaState::WaitingRecovery
+        );
+        assert_eq!(list.get(BlockId(3)), None);
+
+        let bytes = list.to_bytes();
+        assert_eq!(BlockList::from_bytes(&bytes, "BlockReport").unwrap(), list);
+
+        // a dense run of ids costs one byte per delta
+        let dense = BlockList::new(
+            (0..1000)
+                .map(|i| reported((1 << 30) + i, ReplicaState::Finalized))
+                .collect(),
+        );
+        assert!(dense.to_bytes().len() < 1000 * 10);
+    }
+
+    #[test]
+    fn unsorted_block_lists_are_rejected() {
+        let mut e = Encoder::new();
+        e.put_len(2);
+        for _ in 0..2 {
+            e.put_varint(5);
+            e.put_varint(0);
+            e.put_varint(1);
+            e.put_u8(0);
+        }
+        let mut bytes = e.into_bytes();
+        assert!(BlockList::from_bytes(&bytes, "BlockReport").is_ok());
+        // second delta of zero repeats the first id
+        bytes[8] = 0;
+        assert!(matches!(
+            BlockList::from_bytes(&bytes, "BlockReport"),
+            Err(HdfsError::Protocol { op, .. }) if op == "BlockReport"
+        ));
+    }
+
+    #[test]
+    fn requests_roundtrip_through_frames() {
+        let pool = BlockPoolId::new_v4();
+        let storage = StorageReport {
+            storage_id: "DS-1".into(),
+            failed: false,
+            capacity: 1 << 40,
+            dfs_used: 1 << 30,
+            remaining: (1 << 40) - (1 << 30),
+            block_pool_used: 1 << 30,
+        };
+        let requests: Vec<DatanodeRequest> = vec![
+            RegisterDatanodeRequest {
+                registration: DatanodeRegistration {
+                    datanode: dn(),
+                    cluster_id: ClusterId::new_v4(),
+                    block_pool_id: pool,
+                    layout_version: 1,
+                    software_version: "0.1.0".into(),
+                },
+                storages: vec![storage.clone()],
+            }
+            .into(),
+            HeartbeatRequest {
+                datanode: DatanodeId::new_v4(),
+                capacity: storage.capacity,
+                dfs_used: storage.dfs_used,
+                remaining: storage.remaining,
+                xceiver_count: 4,
+                failed_volumes: 0,
+                storages: vec![storage],
+            }
+            .into(),
+            BlockReportRequest {
+                datanode: DatanodeId::new_v4(),
+                pool,
+                storage_id: "DS-1".into(),
+                blocks: BlockList::new(vec![reported(9, ReplicaState::Finalized)]),
+            }
+            .into(),
+            IncrementalBlockReportRequest {
+                datanode: DatanodeId::new_v4(),
+                pool,
+                storage_id: "DS-1".into(),
+                blocks: vec![ReceivedDeletedBlock {
+                    block: BlockId(9),
+                    gen_stamp: GenerationStamp(1002),
+                    num_bytes: 0,
+                    status: ReceivedStatus::Deleted,
+                }],
+            }
+            .into(),
+            ErrorReportRequest {
+                datanode: DatanodeId::new_v4(),
+                code: ErrorCode::DiskError,
+                message: "/data/3 is read-only".into(),
+            }
+            .into(),
+        ];
+        for req in requests {
+            let frame = req.to_frame(5);
+            assert_eq!(DatanodeRequest::from_frame(&frame).unwrap(), req);
+        }
+    }
+
+    #[test]
+    fn heartbeat_commands_roundtrip() {
+        let pool = BlockPoolId::new_v4();
+        let block = ExtendedBlock::new(pool, BlockId(1 << 30), GenerationStamp(1001), 42);
+        let resp = HeartbeatResponse {
+            commands: vec![
+                DatanodeCommand::Transfer {
+                    block: block.clone(),
+                    targets: vec![dn(), dn()],
+                },
+                DatanodeCommand::Invalidate {
+                    pool,
+                    blocks: vec![BlockId(1), BlockId(2)],
+                },
+                DatanodeCommand::Recover {
+                    block,
+                    new_gen_stamp: GenerationStamp(1002),
+                    replicas: vec![DatanodeId::new_v4()],
+                },
+                DatanodeCommand::Cache {
+                    pool,
+                    blocks: vec![BlockId(3)],
+                    uncache: true,
+                },
+                DatanodeCommand::Finalize { pool },
+                DatanodeCommand::Register,
+            ],
+        };
+        let frame = response_frame::<HeartbeatRequest>(1, &resp);
+        assert_eq!(decode_response::<HeartbeatRequest>(&frame).unwrap(), resp);
+
+        assert!(matches!(
+            DatanodeCommand::from_bytes(&[1, 0], "Heartbeat"),
+            Err(HdfsError::Protocol { op, .. }) if op == "Heartbeat"
+        ));
+    }
+
+    #[test]
+    fn unknown_commands_are_stepped_over() {
+        let pool = BlockPoolId::new_v4();
+        let mut e = Encoder::new();
+        e.put_len(4);
+        DatanodeCommand::Register.encode(&mut e);
+        // a command from a newer namenode, with a body this build can't read
+        e.put_u8(42);
+        e.put_bytes(b"\xffnew");
+        // a known command with a field added after its last one
+        let mut body = Encoder::new();
+        pool.encode(&mut body);
+        body.put_u64(7);
+        e.put_u8(5);
+        e.put_bytes(&body.into_bytes());
+        DatanodeCommand::Finalize { pool }.encode(&mut e);
+
+        let commands = Vec::<DatanodeCommand>::from_bytes(&e.into_bytes(), "Heartbeat").unwrap();
+        assert_eq!(
+            commands,
+            vec![
+                DatanodeCommand::Register,
+                DatanodeCommand::Unknown(42),
+                DatanodeCommand::Finalize { pool },
+                DatanodeCommand::Finalize { pool },
+            ]
+        );
+        // and it survives being passed on
+        let frame = response_frame::<HeartbeatRequest>(1, &HeartbeatResponse { commands });
+        assert_eq!(
+            decode_response::<HeartbeatRequest>(&frame)
+                .unwrap()
+                .commands[1],
+            DatanodeCommand::Unknown(42)
+        );
+    }
+
+    tagged_struct! {
+        /// `HeartbeatRequest` as a newer datanode might send it.
+        pub struct HeartbeatRequestV2 {
+            pub datanode: DatanodeId,
+            pub capacity: u64,
+            pub dfs_used: u64,
+            pub remaining: u64,
+            pub xceiver_count: u32,
+            pub failed_volumes: u32,
+            pub storages: Vec<StorageReport>,
+            pub cache_capacity: Option<u64>,
+            pub slow_disks: Vec<String>,
+        }
+    }
+
+    #[test]
+    fn golden_messages() {
+        let datanode = DatanodeId(Uuid::from_u128(0xd1));
+        let pool = BlockPoolId(Uuid::from_u128(0xb1));
+        let storage = StorageReport {
+            storage_id: "DS-1".into(),
+            failed: false,
+            capacity: 1 << 40,
+            dfs_used: 1 << 30,
+            remaining: (1 << 40) - (1 << 30),
+            block_pool_used: 1 << 30,
+        };
+        let heartbeat = HeartbeatRequest {
+            datanode,
+            capacity: storage.capacity,
+            dfs_used: storage.dfs_used,
+            remaining: storage.remaining,
+            xceiver_count: 4,
+            failed_volumes: 0,
+            storages: vec![storage.clone()],
+        };
+        let heartbeat_v2 = HeartbeatRequestV2 {
+            datanode,
+            capacity: storage.capacity,
+            dfs_used: storage.dfs_used,
+            remaining: storage.remaining,
+            xceiver_count: 4,
+            failed_volumes: 0,
+            storages: vec![storage.clone()],
+            cache_capacity: Some(1 << 30),
+            slow_disks: vec!["/data/2".into()],
+        };
+        let messages: Vec<(&str, Vec<u8>)> = vec![
+            (
+                "RegisterDatanodeRequest v1",
+                RegisterDatanodeRequest {
+                    registration: DatanodeRegistration {
+                        datanode: DatanodeInfo {
+                            id: datanode,
+                            hostname: "dn1".into(),
+                            data_addr: "10.0.0.1:9866".into(),
+                        },
+                        cluster_id: ClusterId(Uuid::from_u128(0xc1)),
+                        block_pool_id: pool,
+                        layout_version: 1,
+                        software_version: "0.1.0".into(),
+                    },
+                    storages: vec![storage],
+                }
+                .to_bytes(),
+            ),
+            ("HeartbeatRequest v1", heartbeat.to_bytes()),
+            ("HeartbeatRequest v2", heartbeat_v2.to_bytes()),
+            (
+                "HeartbeatResponse v1",
+                HeartbeatResponse {
+                    commands: vec![
+                        DatanodeCommand::Invalidate {
+                            pool,
+                            blocks: vec![BlockId(1)],
+                        },
+                        DatanodeCommand::Register,
+                    ],
+                }
+                .to_bytes(),
+            ),
+            (
+                "BlockReportRequest v1",
+                BlockReportRequest {
+                    datanode,
+                    pool,
+                    storage_id: "DS-1".into(),
+                    blocks: BlockList::new(vec![
+                        reported(1 << 30, ReplicaState::Finalized),
+                        reported(1 << 30 | 1, ReplicaState::BeingWritten),
+                    ]),
+                }
+                .to_bytes(),
+            ),
+            (
+                "IncrementalBlockReportRequest v1",
+                IncrementalBlockReportRequest {
+                    datanode,
+                    pool,
+                    storage_id: "DS-1".into(),
+                    blocks: vec![ReceivedDeletedBlock {
+                        block: BlockId(9),
+                        gen_stamp: GenerationStamp(1002),
+                        num_bytes: 0,
+                        status: ReceivedStatus::Received,
+                    }],
+                }
+                .to_bytes(),
+            ),
+            (
+                "ErrorReportRequest v1",
+                ErrorReportRequest {
+                    datanode,
+                    code: ErrorCode::DiskError,
+                    message: "/data/3 is read-only".into(),
+                }
+                .to_bytes(),
+            ),
+        ];
+        let dump: String = messages
+            .iter()
+            .map(|(name, bytes)| format!("{name}:\n{}", hex_lines(bytes)))
+            .collect();
+        insta::assert_snapshot!(dump);
+
+        // each version reads the other's heartbeat
+        let old = HeartbeatRequest::from_bytes(&heartbeat_v2.to_bytes(), "Heartbeat").unwrap();
+        assert_eq!(old, heartbeat);
+        let new = HeartbeatRequestV2::from_bytes(&heartbeat.to_bytes(), "Heartbeat").unwrap();
+        assert_eq!(new.cache_capacity, None);
+        assert!(new.slow_disks.is_empty());
+
+        // cutting off the storages leaves a valid v1 heartbeat; cutting off
+        // `failed_volumes` doesn't
+        let bytes = heartbeat.to_bytes();
+        let storages_at = bytes.len() - heartbeat.storages.to_bytes().len() - 2;
+        assert!(HeartbeatRequest::from_bytes(&bytes[..storages_at], "Heartbeat").is_ok());
+        match HeartbeatRequest::from_bytes(&bytes[..storages_at - 6], "Heartbeat") {
+            Err(HdfsError::Protocol { op, details }) => {
+                assert_eq!(op, "Heartbeat");
+                assert_eq!(
+                    details,
+                    "missing required field HeartbeatRequest.failed_volumes"
+                );
+            }
+            other => panic!("expected Protocol error, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,701 @@
+use crate::compress::{Compression, DEFAULT_COMPRESSION_THRESHOLD};
+use hdfs_common::consts::MIB;
+use hdfs_common::error::{HdfsError, Result};
+use std::io::{self, Read, Write};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// "HDFS" in ASCII.
+pub const MAGIC: u32 = 0x4844_4653;
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// magic(4) version(1) flags(1) msg_type(2) call_id(4) payload_len(4)
+pub const HEADER_LEN: usize = 16;
+pub const TRAILER_LEN: usize = 4;
+/// codec(1) raw_len(4), at the front of a compressed payload.
+pub const COMPRESSION_HEADER_LEN: usize = 5;
+
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 64 * MIB as usize;
+
+const FLAG_CRC32C: u8 = 0x01;
+const FLAG_COMPRESSED: u8 = 0x02;
+const KNOWN_FLAGS: u8 = FLAG_CRC32C | FLAG_COMPRESSED;
+
+/// One message on the wire. All integers are big-endian; with the CRC32C
+/// flag set, a trailer covering header and payload follows the payload.
+/// With the compressed flag set, the payload on the wire is a compression
+/// header followed by the compressed bytes, and the trailer covers those.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Frame {
+    pub msg_type: u16,
+    pub call_id: u32,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(msg_type: u16, call_id: u32, payload: impl Into<Vec<u8>>) -> Self {
+        Self {
+            msg_type,
+            call_id,
+            payload: payload.into(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct Header {
+    flags: u8,
+    msg_type: u16,
+    call_id: u32,
+    payload_len: usize,
+}
+
+impl Header {
+    fn body_len(&self) -> usize {
+        self.payload_len
+            + if self.flags & FLAG_CRC32C != 0 {
+                TRAILER_LEN
+            } else {
+                0
+            }
+    }
+}
+
+fn decode_err(details: impl Into<String>) -> HdfsError {
+    HdfsError::Protocol {
+        op: "decode_frame".into(),
+        details: details.into(),
+    }
+}
+
+fn truncated(e: io::Error) -> HdfsError {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        decode_err("truncated frame")
+    } else {
+        e.into()
+    }
+}
+
+/// Frame encoding settings. Decoding always verifies a CRC32C trailer when
+/// the sender added one and decompresses what the sender compressed;
+/// `checksum` and `compression` only control what we send.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct FrameCodec {
+    max_frame_size: usize,
+    checksum: bool,
+    compression: Option<Compression>,
+    compression_threshold: usize,
+    version: u8,
+}
+
+impl Default for FrameCodec {
+    fn default() -> Self {
+        Self {
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            checksum: true,
+            compression: None,
+            compression_threshold: DEFAULT_COMPRESSION_THRESHOLD,
+            version: PROTOCOL_VERSION,
+        }
+    }
+}
+
+impl FrameCodec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Largest payload accepted in either direction; at most `u32::MAX`,
+    /// the most the length field can say.
+    pub fn with_max_frame_size(mut self, max: usize) -> Self {
+        self.max_frame_size = max.min(u32::MAX as usize);
+        self
+    }
+
+    pub fn with_checksum(mut self, on: bool) -> Self {
+        self.checksum = on;
+        self
+    }
+
+    /// Compress payloads of at least the threshold with `compression`, when
+    /// that makes them smaller. Smaller payloads go out raw, so the two kinds
+    /// interleave on one connection.
+    pub fn with_compression(mut self, compression: Option<Compression>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn with_compression_threshold(mut self, bytes: usize) -> Self {
+        self.compression_threshold = bytes;
+        self
+    }
+
+    /// The protocol version frames are sent with and must arrive with; the
+    /// one the handshake agreed on.
+    pub fn with_version(mut self, version: u8) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    pub fn compression(&self) -> Option<Compression> {
+        self.compression
+    }
+
+    /// Limits apply to the payload before compression, so a peer can
+    /// always take in what it decompresses. A payload is only sent
+    /// compressed if that is smaller counting the compression header, so
+    /// compressing never takes a frame over the limit.
+    pub fn encode(&self, frame: &Frame, out: &mut Vec<u8>) -> Result<()> {
+        let len = frame.payload.len();
+        if len > self.max_frame_size {
+            return Err(HdfsError::Protocol {
+                op: "encode_frame".into(),
+                details: format!(
+                    "payload of {len} bytes exceeds max frame size {}",
+                    self.max_frame_size
+                ),
+            });
+        }
+        let compressed = self
+            .compression
+            .filter(|_| len >= self.compression_threshold)
+            .and_then(|c| Some((c, c.compress(&frame.payload)?)))
+            .filter(|(_, packed)| COMPRESSION_HEADER_LEN + packed.len() < len);
+        let mut flags = if self.checksum { FLAG_CRC32C } else { 0 };
+        let wire_len = match &compressed {
+            Some((_, packed)) => {
+                flags |= FLAG_COMPRESSED;
+                COMPRESSION_HEADER_LEN + packed.len()
+            }
+            None => len,
+        };
+        let start = out.len();
+        out.reserve(HEADER_LEN + wire_len + TRAILER_LEN);
+        out.extend_from_slice(&MAGIC.to_be_bytes());
+        out.push(self.version);
+        out.push(flags);
+        out.extend_from_slice(&frame.msg_type.to_be_bytes());
+        out.extend_from_slice(&frame.call_id.to_be_bytes());
+        out.extend_from_slice(&(wire_len as u32).to_be_bytes());
+        match &compressed {
+            Some((c, packed)) => {
+                out.push(c.id());
+                out.extend_from_slice(&(len as u32).to_be_bytes());
+                out.extend_from_slice(packed);
+            }
+            None => out.extend_from_slice(&frame.payload),
+        }
+        if self.checksum {
+            let crc = crc32c::crc32c(&out[start..]);
+            out.extend_from_slice(&crc.to_be_bytes());
+        }
+        Ok(())
+    }
+
+    pub fn encode_to_vec(&self, frame: &Frame) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        self.encode(frame, &mut out)?;
+        Ok(out)
+    }
+
+    /// Decodes one frame from the front of `buf`. Returns the frame and the
+    /// number of bytes it took, or `None` if `buf` does not yet hold a
+    /// whole frame. Bad headers are reported as soon as the header is in.
+    pub fn decode(&self, buf: &[u8]) -> Result<Option<(Frame, usize)>> {
+        let Some(head) = buf.first_chunk::<HEADER_LEN>() else {
+            return Ok(None);
+        };
+        let header = self.parse_header(head)?;
+        let total = HEADER_LEN + header.body_len();
+        if buf.len() < total {
+            return Ok(None);
+        }
+        let frame = self.finish(&header, head, &buf[HEADER_LEN..total])?;
+        Ok(Some((frame, total)))
+    }
+
+    /// Reads one frame. `None` on a clean end of stream between frames.
+    pub fn read_frame<R: Read>(&self, r: &mut R) -> Result<Option<Frame>> {
+        let mut head = [0u8; HEADER_LEN];
+        let mut filled = 0;
+        while filled < HEADER_LEN {
+            match r.read(&mut head[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(decode_err("truncated frame")),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let header = self.parse_header(&head)?;
+        let mut body = vec![0u8; header.body_len()];
+        r.read_exact(&mut body).map_err(truncated)?;
+        self.finish(&header, &head, &body).map(Some)
+    }
+
+    pub async fn read_frame_async<R: AsyncRead + Unpin>(&self, r: &mut R) -> Result<Option<Frame>> {
+        let mut head = [0u8; HEADER_LEN];
+        let mut filled = 0;
+        while filled < HEADER_LEN {
+            match r.read(&mut head[filled..]).await? {
+                0 if filled == 0 => return Ok(None),
+                0 => return Err(decode_err("truncated frame")),
+                n => filled += n,
+            }
+        }
+        let header = self.parse_header(&head)?;
+        let mut body = vec![0u8; header.body_len()];
+        r.read_exact(&mut body).await.map_err(truncated)?;
+        self.finish(&header, &head, &body).map(Some)
+    }
+
+    pub fn write_frame<W: Write>(&self, w: &mut W, frame: &Frame) -> Result<()> {
+        w.write_all(&self.encode_to_vec(frame)?)?;
+        Ok(())
+    }
+
+    pub async fn write_frame_async<W: AsyncWrite + Unpin>(
+        &self,
+        w: &mut W,
+        frame: &Frame,
+    ) -> Result<()> {
+        w.write_all(&self.encode_to_vec(frame)?).await?;
+        Ok(())
+    }
+
+    fn parse_header(&self, head: &[u8; HEADER_LEN]) -> Result<Header> {
+        let magic = u32::from_be_bytes(head[0..4].try_into().unwrap());
+        if magic != MAGIC {
+            return Err(decode_err(format!("bad magic 0x{magic:08X}")));
+        }
+        let version = head[4];
+        if version != self.version {
+            return Err(decode_err(format!(
+                "unsupported protocol version {version} (negotiated: {})",
+                self.version
+            )));
+        }
+        let flags = head[5];
+        if flags & !KNOWN_FLAGS != 0 {
+            return Err(decode_err(format!("unknown flags 0x{flags:02X}")));
+        }
+        let payload_len = u32::from_be_bytes(head[12..16].try_into().unwrap()) as usize;
+        if payload_len > self.max_frame_size {
+            return Err(decode_err(format!(
+                "payload of {payload_len} bytes exceeds max frame size {}",
+                self.max_frame_size
+            )));
+        }
+        Ok(Header {
+            flags,
+            msg_type: u16::from_be_bytes(head[6..8].try_into().unwrap()),
+            call_id: u32::from_be_bytes(head[8..12].try_into().unwrap()),
+            payload_len,
+        })
+    }
+
+    /// `body` is the payload plus the trailer, if any.
+    fn finish(&self, header: &Header, head: &[u8], body: &[u8]) -> Result<Frame> {
+        let (payload, trailer) = body.split_at(header.payload_len);
+        if header.flags & FLAG_CRC32C != 0 {
+            let expected = u32::from_be_bytes(trailer.try_into().unwrap());
+            let got = crc32c::crc32c_append(crc32c::crc32c(head), payload);
+            if got != expected {
+                return Err(decode_err(format!(
+                    "crc32c mismatch on call {}: expected 0x{expected:08X}, got 0x{got:08X}",
+                    header.call_id
+                )));
+            }
+        }
+        let payload = if header.flags & FLAG_COMPRESSED != 0 {
+            let Some((head, packed)) = payload.split_first_chunk::<COMPRESSION_HEADER_LEN>() else {
+                return Err(decode_err(format!(
+                    "compressed payload of {} bytes on call {} has no compression header",
+                    payload.len(),
+                    header.call_id
+                )));
+            };
+            let codec = Compression::from_id(head[0])
+                .ok_or_else(|| decode_err(format!("unknown compression codec {}", head[0])))?;
+            let raw_len = u32::from_be_bytes(head[1..].try_into().unwrap()) as usize;
+            codec.decompress(packed, raw_len, self.max_frame_size, "decode_frame")?
+        } else {
+            payload.to_vec()
+        };
+        Ok(Frame {
+            msg_type: header.msg_type,
+            call_id: header.call_id,
+            payload,
+        })
+    }
+}
+
+/// Buffers bytes as they arrive and hands out complete frames.
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    codec: FrameCodec,
+    buf: Vec<u8>,
+}
+
+impl FrameDecoder {
+    pub fn new(codec: FrameCodec) -> Self {
+        Self {
+            codec,
+            buf: Vec::new(),
+        }
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn next_frame(&mut self) -> Result<Option<Frame>> {
+        match self.codec.decode(&self.buf)? {
+            Some((frame, used)) => {
+                self.buf.drain(..used);
+                Ok(Some(frame))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Bytes received but not yet part of a returned frame.
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_decode_err(res: Result<impl std::fmt::Debug>, needle: &str) {
+        match res {
+            Err(HdfsError::Protocol { op, details }) if op == "decode_frame" => {
+                assert!(details.contains(needle), "{details}")
+            }
+            other => panic!("expected decode_frame error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn header_layout() {
+        let codec = FrameCodec::new().with_checksum(false);
+        let bytes = codec
+            .encode_to_vec(&Frame::new(0x0102, 0x0A0B0C0D, b"hi".to_vec()))
+            .unwrap();
+        assert_eq!(
+            bytes,
+            [
+                b'H', b'D', b'F', b'S', 1, 0, 0x01, 0x02, 0x0A, 0x0B, 0x0C, 0x0D, 0, 0, 0, 2, b'h',
+                b'i'
+            ]
+        );
+
+        let with_crc = FrameCodec::new()
+            .encode_to_vec(&Frame::new(0x0102, 0x0A0B0C0D, b"hi".to_vec()))
+            .unwrap();
+        assert_eq!(with_crc.len(), HEADER_LEN + 2 + TRAILER_LEN);
+        assert_eq!(with_crc[5], FLAG_CRC32C);
+    }
+
+    #[test]
+    fn roundtrip_with_and_without_crc() {
+        for checksum in [true, false] {
+            let codec = FrameCodec::new().with_checksum(checksum);
+            let frame = Frame::new(7, 42, vec![1, 2, 3, 4, 5]);
+            let bytes = codec.encode_to_vec(&frame).unwrap();
+            let (back, used) = codec.decode(&bytes).unwrap().unwrap();
+            assert_eq!(back, frame);
+            assert_eq!(used, bytes.len());
+        }
+        let empty = Frame::new(1, 0, Vec::new());
+        let bytes = FrameCodec::new().encode_to_vec(&empty).unwrap();
+        assert_eq!(FrameCodec::new().decode(&bytes).unwrap().unwrap().0, empty);
+    }
+
+    #[test]
+    fn decodes_byte_by_byte() {
+        let codec = FrameCodec::new();
+        let frames = [
+            Frame::new(1, 1, b"first".to_vec()),
+            Frame::new(2, 2, Vec::new()),
+            Frame::new(3, 3, vec![0xAB; 300]),
+        ];
+        let mut wire = Vec::new();
+        for f in &frames {
+            codec.encode(f, &mut wire).unwrap();
+        }
+
+        let mut dec = FrameDecoder::new(codec);
+        let mut got = Vec::new();
+        for b in &wire {
+            dec.feed(std::slice::from_ref(b));
+            while let Some(f) = dec.next_frame().unwrap() {
+                got.push(f);
+            }
+        }
+        assert_eq!(got, frames);
+        assert_eq!(dec.buffered(), 0);
+    }
+
+    #[test]
+    fn malformed_frames() {
+        let codec = FrameCodec::new();
+        let good = codec
+            .encode_to_vec(&Frame::new(1, 9, b"data".to_vec()))
+            .unwrap();
+
+        let mut bad = good.clone();
+        bad[0] = b'X';
+        assert_decode_err(codec.decode(&bad), "bad magic");
+
+        let mut bad = good.clone();
+        bad[4] = 2;
+        assert_decode_err(codec.decode(&bad), "unsupported protocol version 2");
+
+        let mut bad = good.clone();
+        bad[5] |= 0x80;
+        assert_decode_err(codec.decode(&bad), "unknown flags");
+
+        let mut bad = good.clone();
+        bad[HEADER_LEN] ^= 0xFF;
+        assert_decode_err(codec.decode(&bad), "crc32c mismatch on call 9");
+
+        // a corrupted header is caught by the trailer too
+        let mut bad = good.clone();
+        bad[7] ^= 0x01;
+        assert_decode_err(codec.decode(&bad), "crc32c mismatch");
+
+        // partial input is not an error
+        assert!(codec.decode(&good[..HEADER_LEN - 1]).unwrap().is_none());
+        assert!(codec.decode(&good[..good.len() - 1]).unwrap().is_none());
+    }
+
+    #[test]
+    fn frames_carry_the_codec_version() {
+        let v2 = FrameCodec::new().with_version(2);
+        let frame = Frame::new(1, 9, b"data".to_vec());
+        let bytes = v2.encode_to_vec(&frame).unwrap();
+        assert_eq!(bytes[4], 2);
+        assert_eq!(v2.decode(&bytes).unwrap().unwrap().0, frame);
+        assert_decode_err(
+            FrameCodec::new().decode(&bytes),
+            "unsupported protocol version 2 (negotiated: 1)",
+        );
+    }
+
+    #[test]
+    fn max_frame_size_both_ways() {
+        let small = FrameCodec::new().with_max_frame_size(4);
+        assert!(matches!(
+            small.encode_to_vec(&Frame::new(1, 1, vec![0; 5])),
+            Err(HdfsError::Protocol { op, .. }) if op == "encode_frame"
+        ));
+
+        let bytes = FrameCodec::new()
+            .encode_to_vec(&Frame::new(1, 1, vec![0; 5]))
+            .unwrap();
+        // rejected from the header alone, before the payload arrives
+        assert_decode_err(
+            small.decode(&bytes[..HEADER_LEN]),
+            "exceeds max frame size 4",
+        );
+    }
+
+    #[test]
+    fn compressed_and_raw_frames_interleave() {
+        let codec = FrameCodec::new()
+            .with_compression(Some(Compression::Lz4))
+            .with_compression_threshold(64);
+        let listing: Vec<u8> = b"/data/logs/2026/10/17/part-0000.gz "
+            .iter()
+            .copied()
+            .cycle()
+            .take(20_000)
+            .collect();
+        let frames = [
+            Frame::new(1, 1, b"small".to_vec()),
+            Frame::new(2, 2, listing.clone()),
+            Frame::new(3, 3, Vec::new()),
+            Frame::new(4, 4, (0..=255).collect::<Vec<u8>>()),
+        ];
+        let mut wire = Vec::new();
+        let mut flags = Vec::new();
+        for f in &frames {
+            let start = wire.len();
+            codec.encode(f, &mut wire).unwrap();
+            flags.push(wire[start + 5]);
+        }
+        // incompressible payloads go out raw even above the threshold
+        assert_eq!(
+            flags,
+            [
+                FLAG_CRC32C,
+                FLAG_CRC32C | FLAG_COMPRESSED,
+                FLAG_CRC32C,
+                FLAG_CRC32C
+            ]
+        );
+        assert!(wire.len() < listing.len() / 4);
+
+        // decoding does not depend on our own compression setting
+        let mut dec = FrameDecoder::new(FrameCodec::new());
+        dec.feed(&wire);
+        for f in &frames {
+            assert_eq!(dec.next_frame().unwrap().as_ref(), Some(f));
+        }
+        assert_eq!(dec.buffered(), 0);
+
+        let zstd = codec.with_compression(Some(Compression::Zstd));
+        let bytes = zstd.encode_to_vec(&frames[1]).unwrap();
+        assert_eq!(bytes[HEADER_LEN], Compression::Zstd.id());
+        assert_eq!(codec.decode(&bytes).unwrap().unwrap().0, frames[1]);
+
+        // the trailer covers the compressed bytes
+        let mut bad = codec.encode_to_vec(&frames[1]).unwrap();
+        bad[HEADER_LEN + COMPRESSION_HEADER_LEN + 3] ^= 0x40;
+        assert_decode_err(codec.decode(&bad), "crc32c mismatch on call 2");
+    }
+
+    #[test]
+    fn compression_header_counts_against_the_limit() {
+        const MAX: usize = 1024;
+        let codec = FrameCodec::new()
+            .with_max_frame_size(MAX)
+            .with_compression(Some(Compression::Lz4))
+            .with_compression_threshold(0);
+        // noise after a run of zeros that lz4 shortens by a byte or two
+        let mut x = 0x2545_F491_u32;
+        let payload = |run: usize| -> Vec<u8> {
+            let noise = std::iter::repeat_with(|| {
+                x ^= x << 13;
+                x ^= x >> 17;
+                x ^= x << 5;
+                x as u8
+            });
+            std::iter::repeat_n(0, run).chain(noise).take(MAX).collect()
+        };
+        let payload = (0..MAX)
+            .map(payload)
+            .find(|p| {
+                Compression::Lz4
+                    .compress(p)
+                    .is_some_and(|packed| COMPRESSION_HEADER_LEN + packed.len() >= MAX)
+            })
+            .expect("a payload that compresses by less than the header");
+
+        let frame = Frame::new(1, 1, payload);
+        let bytes = codec.encode_to_vec(&frame).unwrap();
+        assert_eq!(bytes[5], FLAG_CRC32C);
+        assert_eq!(bytes.len(), HEADER_LEN + MAX + TRAILER_LEN);
+        assert_eq!(codec.decode(&bytes).unwrap().unwrap().0, frame);
+    }
+
+    #[test]
+    fn decompression_limits() {
+        let listing = vec![b'a'; 4096];
+        let compressed = |codec: Compression, raw_len: u32| {
+            let packed = codec.compress(&listing).unwrap();
+            let mut payload = vec![codec.id()];
+            payload.extend_from_slice(&raw_len.to_be_bytes());
+            payload.extend_from_slice(&packed);
+            let mut out = FrameCodec::new()
+                .with_checksum(false)
+                .encode_to_vec(&Frame::new(1, 5, payload))
+                .unwrap();
+            out[5] = FLAG_COMPRESSED;
+            out
+        };
+        let small = FrameCodec::new().with_max_frame_size(1024);
+        for c in Compression::ALL {
+            let bytes = compressed(c, 4096);
+            assert_eq!(
+                FrameCodec::new().decode(&bytes).unwrap().unwrap().0.payload,
+                listing
+            );
+            // fine on the wire, too big once decompressed
+            assert_decode_err(small.decode(&bytes), "over the limit of 1024");
+            assert_decode_err(
+                FrameCodec::new().decode(&compressed(c, 60 << 20)),
+                "ratio limit",
+            );
+            assert_decode_err(FrameCodec::new().decode(&compressed(c, 4000)), "payload");
+        }
+
+        let mut unknown = compressed(Compression::Lz4, 4096);
+        unknown[HEADER_LEN] = 9;
+        assert_decode_err(
+            FrameCodec::new().decode(&unknown),
+            "unknown compression codec 9",
+        );
+
+        let mut headless = FrameCodec::new()
+            .with_checksum(false)
+            .encode_to_vec(&Frame::new(1, 6, vec![1, 0, 0]))
+            .unwrap();
+        headless[5] = FLAG_COMPRESSED;
+        assert_decode_err(
+            FrameCodec::new().decode(&headless),
+            "has no compression header",
+        );
+    }
+
+    #[test]
+    fn sync_reader() {
+        let codec = FrameCodec::new();
+        let mut wire = Vec::new();
+        codec
+            .write_frame(&mut wire, &Frame::new(1, 1, b"a".to_vec()))
+            .unwrap();
+        codec
+            .write_frame(&mut wire, &Frame::new(2, 2, b"bc".to_vec()))
+            .unwrap();
+
+        let mut r = wire.as_slice();
+        assert_eq!(codec.read_frame(&mut r).unwrap().unwrap().payload, b"a");
+        assert_eq!(codec.read_frame(&mut r).unwrap().unwrap().payload, b"bc");
+        assert_eq!(codec.read_frame(&mut r).unwrap(), None);
+
+        let mut cut = &wire[..wire.len() - 2];
+        codec.read_frame(&mut cut).unwrap();
+        assert_decode_err(codec.read_frame(&mut cut), "truncated frame");
+        let mut cut = &wire[..3];
+        assert_decode_err(codec.read_frame(&mut cut), "truncated frame");
+    }
+
+    #[tokio::test]
+    async fn async_reader_over_split_writes() {
+        let codec = FrameCodec::new();
+        let frame = Frame::new(5, 77, vec![9; 1000]);
+        let bytes = codec.encode_to_vec(&frame).unwrap();
+
+        let (mut tx, mut rx) = tokio::io::duplex(64);
+        let writer = async move {
+            for chunk in bytes.chunks(7) {
+                tx.write_all(chunk).await.unwrap();
+            }
+            codec
+                .write_frame_async(&mut tx, &Frame::new(6, 78, Vec::new()))
+                .await
+                .unwrap();
+        };
+        let reader = async {
+            let a = codec.read_frame_async(&mut rx).await.unwrap().unwrap();
+            let b = codec.read_frame_async(&mut rx).await.unwrap().unwrap();
+            let end = codec.read_frame_async(&mut rx).await.unwrap();
+            (a, b, end)
+        };
+        let ((), (a, b, end)) = tokio::join!(writer, reader);
+        assert_eq!(a, frame);
+        assert_eq!(b.call_id, 78);
+        assert_eq!(end, None);
+    }
+}
@@ -0,0 +1,364 @@
+//! Hadoop IPC framing with protobuf messages for `ClientProtocol`, and the
+//! protobuf flavour of the DataTransferProtocol, with SIMPLE auth only.
+//! Written from Hadoop 3's `.proto` definitions and not yet checked against
+//! a Hadoop client, namenode or datanode, so it is not known to interoperate
+//! with one; see the note on the fixtures in the tests.
+//!
+//! Servers can accept both dialects on one port by looking at the first
+//! four bytes of a connection, see [`sniff`].
+
+pub mod namenode;
+pub mod proto;
+pub mod rpc;
+pub mod transfer;
+
+use crate::frame::MAGIC;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Dialect {
+    /// This crate's own framing, starting with a handshake hello.
+    Native,
+    /// Hadoop IPC, starting with the `hrpc` connection header.
+    Hadoop,
+}
+
+/// Which protocol a peer speaks, from the first bytes it sent.
+pub fn sniff(first: &[u8; 4]) -> Option<Dialect> {
+    if *first == MAGIC.to_be_bytes() {
+        Some(Dialect::Native)
+    } else if first == rpc::HRPC_MAGIC {
+        Some(Dialect::Hadoop)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! Source of the fixtures: none of them is a capture of a real Hadoop
+    //! process. They were hand-assembled from Hadoop 3's `.proto`
+    //! definitions (RpcHeader, IpcConnectionContext, ProtobufRpcEngine,
+    //! hdfs, ClientNamenodeProtocol, datatransfer) by a separate encoder, in
+    //! the shape a Hadoop 3 client and datanode send them, including fields
+    //! this crate does not model. They pin the encoding down, but they can
+    //! share a misreading of the protocol with the code under test; only
+    //! bytes captured from a Hadoop client, namenode or datanode can rule
+    //! that out. Replace a constant with such a capture when one is at
+    //! hand, and note the Hadoop version and command it came from.
+
+    use super::namenode::*;
+    use super::proto::ProtoMessage;
+    use super::rpc::*;
+    use super::transfer::*;
+    use super::*;
+    use crate::client::{
+        ClientRequest, ClientResponse, FileStatus, FileType, GetFileInfoResponse, ListStatusRequest,
+    };
+    use crate::codec::Decoder;
+    use crate::transfer::TransferOp;
+    use hdfs_common::checksum::{ChecksumType, DataChecksum};
+    use hdfs_common::error::HdfsError;
+    use hdfs_common::ids::{BlockId, INodeId};
+    use hdfs_common::path::PathAbs;
+
+    const CLIENT_ID: [u8; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+
+    /// Connection context for user `hdfs`, call id -3.
+    const CONTEXT: &str = "000000541a0802100018052210000102030405060708090a0b0c0d0e0f28013812060a04686466731a2e6f72672e6170616368652e6861646f6f702e686466732e70726f746f636f6c2e436c69656e7450726f746f636f6c";
+    /// `getFileInfo("/")`, call id 0.
+    const GET_FILE_INFO: &str = "0000005f1a0802100018002210000102030405060708090a0b0c0d0e0f28013f0a0b67657446696c65496e666f122e6f72672e6170616368652e6861646f6f702e686466732e70726f746f636f6c2e436c69656e7450726f746f636f6c1801030a012f";
+    /// `getListing("/", startAfter = "", needLocation = false)`, call id 1.
+    const GET_LISTING: &str = "000000621a0802100018022210000102030405060708090a0b0c0d0e0f28013e0a0a6765744c697374696e67122e6f72672e6170616368652e6861646f6f702e686466732e70726f746f636f6c2e436c69656e7450726f746f636f6c1801070a012f12001800";
+    /// A listing of `/` holding the directory `tmp`, with a storage policy
+    /// field (17) this crate does not model.
+    const LISTING_RESPONSE: &str = "0000005a1a0801100018093a10000102030405060708090a0b0c0d0e0f40013e0a3c0a3808011203746d701800220308ff072a0468646673320a737570657267726f75703880d095ffbc314000500058006882800170008a010208001000";
+    /// `getFileInfo("/")` answered by this crate's server.
+    const FILE_INFO_RESPONSE: &str = "0000004a1a0800100018093a10000102030405060708090a0b0c0d0e0f40012e0a2c080112001800220308ed032a0468646673320a737570657267726f75703880d095ffbc314000688180017001";
+    /// FileNotFoundException for call id 2.
+    const ERROR_RESPONSE: &str = "0000005857080210011809221d6a6176612e696f2e46696c654e6f74466f756e64457863657074696f6e2a1a46696c6520646f6573206e6f742065786973743a202f6e6f706530013a10000102030405060708090a0b0c0d0e0f4001";
+    /// READ_BLOCK of the first KiB of blk_1073741825, with an empty block
+    /// token and a caching strategy (field 5) this crate does not model.
+    const READ_BLOCK: &str = "001c51520a450a290a1d0a1042502d312d3132372e302e302e312d3110818080800418e907200012080a0012001a0022001218444653436c69656e745f4e4f4e4d41505245445543455f31100018800820012a020800";
+    /// The datanode's answer: CRC32C every 512 bytes, from offset 0.
+    const READ_RESPONSE: &str = "0d080022090a0508021080041000";
+    /// One packet holding "hello, hadoop".
+    const PACKET: &str = "00000015001b0900000000000000001100000000000000001800250d0000002800707ed35b68656c6c6f2c206861646f6f70";
+
+    fn unhex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// The body of a length-prefixed RPC packet.
+    fn body(packet: &[u8]) -> &[u8] {
+        let len = u32::from_be_bytes(packet[..4].try_into().unwrap()) as usize;
+        assert_eq!(packet.len(), 4 + len);
+        &packet[4..]
+    }
+
+    fn p(s: &str) -> PathAbs {
+        PathAbs::try_from(s).unwrap()
+    }
+
+    #[test]
+    fn dialects_are_told_apart() {
+        assert_eq!(sniff(b"hrpc"), Some(Dialect::Hadoop));
+        assert_eq!(sniff(b"HDFS"), Some(Dialect::Native));
+        assert_eq!(sniff(b"GET "), None);
+    }
+
+    #[test]
+    fn connection_header() {
+        let header = ConnectionHeader::new();
+        assert_eq!(header.to_bytes(), *b"hrpc\x09\x00\x00");
+        assert_eq!(
+            ConnectionHeader::from_bytes(b"hrpc\x09\x00\x00").unwrap(),
+            header
+        );
+
+        for (bytes, msg) in [
+            (b"hrpc\x09\x00\xdf", "only SIMPLE auth"),
+            (b"hrpc\x07\x00\x00", "unsupported IPC version 7"),
+        ] {
+            match ConnectionHeader::from_bytes(bytes) {
+                Err(HdfsError::Protocol { op, details }) if op == "hrpc_header" => {
+                    assert!(details.contains(msg), "{details}")
+                }
+                other => panic!("expected hrpc_header error, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn connection_context_fixture() {
+        let req = RpcRequest::connection_context(CLIENT_ID, "hdfs", CLIENT_PROTOCOL);
+        let fixture = unhex(CONTEXT);
+        assert_eq!(req.to_packet(), fixture);
+        assert_eq!(RpcRequest::decode(body(&fixture)).unwrap(), req);
+        assert_eq!(req.header().call_id, CONNECTION_CONTEXT_CALL_ID);
+    }
+
+    #[test]
+    fn client_calls_decode_to_native_requests() {
+        let fixture = unhex(GET_FILE_INFO);
+        let req = RpcRequest::call(
+            0,
+            CLIENT_ID,
+            CLIENT_PROTOCOL,
+            "getFileInfo",
+            &GetFileInfoRequestProto { src: "/".into() },
+        );
+        assert_eq!(req.to_packet(), fixture);
+
+        let decode = |fixture: &str| match RpcRequest::decode(body(&unhex(fixture))).unwrap() {
+            RpcRequest::Call { method, param, .. } => {
+                assert_eq!(method.declaring_class_protocol_name, CLIENT_PROTOCOL);
+                decode_client_request(&method.method_name, &param).unwrap()
+            }
+            other => panic!("expected a call, got {other:?}"),
+        };
+        assert_eq!(
+            decode(GET_FILE_INFO),
+            ClientRequest::GetFileInfo(crate::client::GetFileInfoRequest { path: p("/") })
+        );
+        assert_eq!(
+            decode(GET_LISTING),
+            ClientRequest::ListStatus(ListStatusRequest {
+                path: p("/"),
+                start_after: None,
+                limit: LISTING_LIMIT,
+            })
+        );
+
+        assert!(matches!(
+            decode_client_request("setQuota", &[]),
+            Err(HdfsError::Protocol { op, .. }) if op == "hadoop_rpc"
+        ));
+    }
+
+    #[test]
+    fn listing_fixture_decodes() {
+        let resp = RpcResponse::decode(body(&unhex(LISTING_RESPONSE))).unwrap();
+        assert_eq!(resp.header.call_id, 1);
+        let listing = resp.into_result::<GetListingResponseProto>().unwrap();
+        let dir = listing.dir_list.unwrap();
+        assert_eq!(dir.remaining_entries, 0);
+        let tmp = dir.partial_listing[0]
+            .to_file_status(&PathAbs::root())
+            .unwrap();
+        assert_eq!(tmp.path, p("/tmp"));
+        assert_eq!(tmp.file_type, FileType::Dir);
+        assert_eq!(tmp.permission, 0o1777);
+        assert_eq!(tmp.inode, INodeId(16386));
+        assert_eq!(tmp.owner, "hdfs");
+    }
+
+    #[test]
+    fn server_responses_match_fixture() {
+        let root = FileStatus {
+            inode: INodeId(16385),
+            path: PathAbs::root(),
+            file_type: FileType::Dir,
+            length: 0,
+            replication: 0,
+            block_size: 0,
+            modification_time: 1_700_000_000_000,
+            access_time: 0,
+            permission: 0o755,
+            owner: "hdfs".into(),
+            group: "supergroup".into(),
+            children: 1,
+        };
+        let native = ClientResponse::GetFileInfo(GetFileInfoResponse {
+            status: Some(root.clone()),
+        });
+        let call = RpcRequest::decode(body(&unhex(GET_FILE_INFO))).unwrap();
+        let resp = RpcResponse::success(call.header(), encode_client_response(&native).unwrap());
+        assert_eq!(resp.to_packet(), unhex(FILE_INFO_RESPONSE));
+
+        let back = resp.into_result::<GetFileInfoResponseProto>().unwrap();
+        assert_eq!(
+            back.fs.unwrap().to_file_status(&PathAbs::root()).unwrap(),
+            root
+        );
+    }
+
+    #[test]
+    fn remote_exceptions_become_typed_errors() {
+        let resp = RpcResponse::decode(body(&unhex(ERROR_RESPONSE))).unwrap();
+        match resp.into_result::<GetFileInfoResponseProto>() {
+            Err(HdfsError::NotFound { path }) => assert_eq!(path, "/nope"),
+            other => panic!("expected NotFound, got {other:?}"),
+        }
+
+        // and back out through our own server
+        let call = RpcRequest::decode(body(&unhex(GET_FILE_INFO))).unwrap();
+        let err = HdfsError::AlreadyExists { path: "/a".into() };
+        let resp = RpcResponse::error(call.header(), &err);
+        let resp = RpcResponse::decode(body(&resp.to_packet())).unwrap();
+        assert!(resp.payload.is_empty());
+        assert!(matches!(
+            resp.into_result::<MkdirsResponseProto>(),
+            Err(HdfsError::AlreadyExists { path }) if path == "/a"
+        ));
+
+        assert!(matches!(
+            remote_exception(RPC_ERROR, STANDBY_EXCEPTION, "not active".into()),
+            HdfsError::State { what, .. } if what == "standby"
+        ));
+        assert!(matches!(
+            remote_exception(
+                RPC_ERROR,
+                "org.apache.hadoop.security.AccessControlException",
+                "no".into()
+            ),
+            HdfsError::Io(_)
+        ));
+    }
+
+    #[test]
+    fn read_block_fixture() {
+        let fixture = unhex(READ_BLOCK);
+        let mut d = Decoder::new(&fixture, "read_op");
+        let op = HadoopOp::decode(&mut d).unwrap();
+        d.finish().unwrap();
+        assert_eq!(op.op(), TransferOp::ReadBlock);
+        let HadoopOp::ReadBlock(read) = op else {
+            unreachable!()
+        };
+        let block = &read.header.base_header.block;
+        assert_eq!(block.pool_id, "BP-1-127.0.0.1-1");
+        assert_eq!(block.block_id, 1_073_741_825);
+        assert_eq!(block.generation_stamp, 1001);
+        assert_eq!(read.header.client_name, "DFSClient_NONMAPREDUCE_1");
+        assert_eq!((read.offset, read.len), (0, 1024));
+        assert_eq!(read.send_checksums, Some(true));
+
+        let mut bad = fixture.clone();
+        bad[1] = 27;
+        assert!(HadoopOp::decode(&mut Decoder::new(&bad, "read_op")).is_err());
+    }
+
+    #[test]
+    fn packets_are_verified() {
+        let resp = unhex(READ_RESPONSE);
+        let resp =
+            BlockOpResponseProto::read_delimited(&mut Decoder::new(&resp, "read_op")).unwrap();
+        assert_eq!(resp.status, SUCCESS);
+        let checksum = resp.read_op_checksum_info.unwrap().checksum;
+        assert_eq!(checksum.checksum_type, CHECKSUM_CRC32C);
+        assert_eq!(checksum.bytes_per_checksum, 512);
+
+        let fixture = unhex(PACKET);
+        let block = BlockId(1_073_741_825);
+        assert!(
+            HadoopPacket::decode(&fixture[..10], &checksum, block)
+                .unwrap()
+                .is_none()
+        );
+        let (packet, used) = HadoopPacket::decode(&fixture, &checksum, block)
+            .unwrap()
+            .unwrap();
+        assert_eq!(used, fixture.len());
+        assert_eq!(packet.data, b"hello, hadoop");
+        assert!(!packet.header.last_packet_in_block);
+        let mut again = Vec::new();
+        packet.encode(&mut again);
+        assert_eq!(again, fixture);
+
+        let mut corrupt = fixture;
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            HadoopPacket::decode(&corrupt, &checksum, block),
+            Err(HdfsError::ChecksumMismatch {
+                block: BlockId(1_073_741_825),
+                chunk_index: 0,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn crc32_packets() {
+        let crc32 = DataChecksum::new(ChecksumType::Crc32, 4);
+        let proto = ChecksumProto::from(crc32);
+        assert_eq!(proto.checksum_type, CHECKSUM_CRC32);
+        assert_eq!(proto.to_data_checksum().unwrap(), crc32);
+
+        let data = b"hello, hadoop".to_vec();
+        let packet = HadoopPacket {
+            header: PacketHeaderProto {
+                offset_in_block: 8,
+                seqno: 1,
+                last_packet_in_block: true,
+                data_len: data.len() as i32,
+                sync_block: None,
+            },
+            checksums: crc32
+                .compute_to_vec(&data)
+                .chunks(4)
+                .map(|c| u32::from_be_bytes(c.try_into().unwrap()))
+                .collect(),
+            data,
+        };
+        let mut wire = Vec::new();
+        packet.encode(&mut wire);
+        let block = BlockId(1_073_741_825);
+        let (back, _) = HadoopPacket::decode(&wire, &proto, block).unwrap().unwrap();
+        assert_eq!(back, packet);
+
+        *wire.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            HadoopPacket::decode(&wire, &proto, block),
+            Err(HdfsError::ChecksumMismatch { chunk_index: 5, .. })
+        ));
+
+        let unknown = ChecksumProto {
+            checksum_type: 9,
+            bytes_per_checksum: 512,
+        };
+        assert!(unknown.to_data_checksum().is_err());
+    }
+}
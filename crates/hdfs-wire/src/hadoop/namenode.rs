@@ -0,0 +1,423 @@
+//! The part of `ClientNamenodeProtocol` needed for namespace browsing and
+//! block reads: `getFileInfo`, `getListing`, `mkdirs`, `delete`, `rename`
+//! and `getBlockLocations`.
+
+use super::proto::{
+    Bool, Bytes, Enum, Int32, Message, ProtoMessage, Str, Uint32, Uint64, proto_message,
+};
+use crate::client::{
+    ClientRequest, ClientResponse, DatanodeInfo, DeleteRequest, FileStatus, FileType,
+    GetBlockLocationsRequest, GetFileInfoRequest, ListStatusRequest, LocatedBlock, LocatedBlocks,
+    MkdirsRequest, RenameRequest,
+};
+use hdfs_common::error::{HdfsError, Result};
+use hdfs_common::ids::INodeId;
+use hdfs_common::path::PathAbs;
+use hdfs_common::types::ExtendedBlock;
+
+pub const CLIENT_PROTOCOL: &str = "org.apache.hadoop.hdfs.protocol.ClientProtocol";
+
+/// Entries per `getListing` page, Hadoop's `dfs.ls.limit` default.
+pub const LISTING_LIMIT: u32 = 1000;
+
+/// `HdfsFileStatusProto.FileType`
+pub const IS_DIR: i32 = 1;
+pub const IS_FILE: i32 = 2;
+pub const IS_SYMLINK: i32 = 3;
+
+proto_message! {
+    pub struct FsPermissionProto {
+        1 => required perm: Uint32,
+    }
+}
+
+proto_message! {
+    pub struct TokenProto {
+        1 => required identifier: Bytes,
+        2 => required password: Bytes,
+        3 => required kind: Str,
+        4 => required service: Str,
+    }
+}
+
+proto_message! {
+    pub struct ExtendedBlockProto {
+        1 => required pool_id: Str,
+        2 => required block_id: Uint64,
+        3 => required generation_stamp: Uint64,
+        4 => optional num_bytes: Uint64,
+    }
+}
+
+proto_message! {
+    pub struct DatanodeIdProto {
+        1 => required ip_addr: Str,
+        2 => required host_name: Str,
+        3 => required datanode_uuid: Str,
+        4 => required xfer_port: Uint32,
+        5 => required info_port: Uint32,
+        6 => required ipc_port: Uint32,
+    }
+}
+
+proto_message! {
+    pub struct DatanodeInfoProto {
+        1 => required id: Message<DatanodeIdProto>,
+        2 => optional capacity: Uint64,
+        3 => optional dfs_used: Uint64,
+        4 => optional remaining: Uint64,
+        5 => optional block_pool_used: Uint64,
+        6 => optional last_update: Uint64,
+        7 => optional xceiver_count: Uint32,
+        8 => optional location: Str,
+    }
+}
+
+proto_message! {
+    pub struct LocatedBlockProto {
+        1 => required b: Message<ExtendedBlockProto>,
+        2 => required offset: Uint64,
+        3 => repeated locs: Message<DatanodeInfoProto>,
+        4 => required corrupt: Bool,
+        5 => required block_token: Message<TokenProto>,
+    }
+}
+
+proto_message! {
+    pub struct LocatedBlocksProto {
+        1 => required file_length: Uint64,
+        2 => repeated blocks: Message<LocatedBlockProto>,
+        3 => required under_construction: Bool,
+        4 => optional last_block: Message<LocatedBlockProto>,
+        5 => required is_last_block_complete: Bool,
+    }
+}
+
+proto_message! {
+    pub struct HdfsFileStatusProto {
+        1 => required file_type: Enum,
+        /// The local name in listings, empty from `getFileInfo`.
+        2 => required path: Bytes,
+        3 => required length: Uint64,
+        4 => required permission: Message<FsPermissionProto>,
+        5 => required owner: Str,
+        6 => required group: Str,
+        7 => required modification_time: Uint64,
+        8 => required access_time: Uint64,
+        9 => optional symlink: Bytes,
+        10 => optional block_replication: Uint32,
+        11 => optional blocksize: Uint64,
+        12 => optional locations: Message<LocatedBlocksProto>,
+        13 => optional file_id: Uint64,
+        14 => optional children_num: Int32,
+    }
+}
+
+proto_message! {
+    pub struct DirectoryListingProto {
+        1 => repeated partial_listing: Message<HdfsFileStatusProto>,
+        2 => required remaining_entries: Uint32,
+    }
+}
+
+proto_message! {
+    pub struct GetFileInfoRequestProto {
+        1 => required src: Str,
+    }
+}
+
+proto_message! {
+    pub struct GetFileInfoResponseProto {
+        1 => optional fs: Message<HdfsFileStatusProto>,
+    }
+}
+
+proto_message! {
+    pub struct GetListingRequestProto {
+        1 => required src: Str,
+        2 => required start_after: Bytes,
+        3 => required need_location: Bool,
+    }
+}
+
+proto_message! {
+    pub struct GetListingResponseProto {
+        1 => optional dir_list: Message<DirectoryListingProto>,
+    }
+}
+
+proto_message! {
+    pub struct MkdirsRequestProto {
+        1 => required src: Str,
+        2 => required masked: Message<FsPermissionProto>,
+        3 => required create_parent: Bool,
+    }
+}
+
+proto_message! {
+    pub struct MkdirsResponseProto {
+        1 => required result: Bool,
+    }
+}
+
+proto_message! {
+    pub struct DeleteRequestProto {
+        1 => required src: Str,
+        2 => required recursive: Bool,
+    }
+}
+
+proto_message! {
+    pub struct DeleteResponseProto {
+        1 => required result: Bool,
+    }
+}
+
+proto_message! {
+    pub struct RenameRequestProto {
+        1 => required src: Str,
+        2 => required dst: Str,
+    }
+}
+
+proto_message! {
+    pub struct RenameResponseProto {
+        1 => required result: Bool,
+    }
+}
+
+proto_message! {
+    pub struct GetBlockLocationsRequestProto {
+        1 => required src: Str,
+        2 => required offset: Uint64,
+        3 => required length: Uint64,
+    }
+}
+
+proto_message! {
+    pub struct GetBlockLocationsResponseProto {
+        1 => optional locations: Message<LocatedBlocksProto>,
+    }
+}
+
+fn path(src: &str) -> Result<PathAbs> {
+    PathAbs::try_from(src)
+}
+
+/// Server side: a Hadoop call as the native request it stands for.
+pub fn decode_client_request(method: &str, param: &[u8]) -> Result<ClientRequest> {
+    Ok(match method {
+        "getFileInfo" => {
+            let m = GetFileInfoRequestProto::from_proto(param, "getFileInfo")?;
+            GetFileInfoRequest {
+                path: path(&m.src)?,
+            }
+            .into()
+        }
+        "getListing" => {
+            let m = GetListingRequestProto::from_proto(param, "getListing")?;
+            let start_after = match m.start_after.as_slice() {
+                [] => None,
+                name => {
+                    Some(
+                        String::from_utf8(name.to_vec()).map_err(|_| HdfsError::Protocol {
+                            op: "getListing".into(),
+                            details: "startAfter is not utf-8".into(),
+                        })?,
+                    )
+                }
+            };
+            ListStatusRequest {
+                path: path(&m.src)?,
+                start_after,
+                limit: LISTING_LIMIT,
+            }
+            .into()
+        }
+        "mkdirs" => {
+            let m = MkdirsRequestProto::from_proto(param, "mkdirs")?;
+            MkdirsRequest {
+                path: path(&m.src)?,
+                permission: (m.masked.perm & 0o7777) as u16,
+                create_parent: m.create_parent,
+            }
+            .into()
+        }
+        "delete" => {
+            let m = DeleteRequestProto::from_proto(param, "delete")?;
+            DeleteRequest {
+                path: path(&m.src)?,
+                recursive: m.recursive,
+            }
+            .into()
+        }
+        "rename" => {
+            let m = RenameRequestProto::from_proto(param, "rename")?;
+            RenameRequest {
+                src: path(&m.src)?,
+                dst: path(&m.dst)?,
+                overwrite: false,
+            }
+            .into()
+        }
+        "getBlockLocations" => {
+            let m = GetBlockLocationsRequestProto::from_proto(param, "getBlockLocations")?;
+            GetBlockLocationsRequest {
+                path: path(&m.src)?,
+                offset: m.offset,
+                length: m.length,
+            }
+            .into()
+        }
+        other => {
+            return Err(HdfsError::Protocol {
+                op: "hadoop_rpc".into(),
+                details: format!("unsupported {CLIENT_PROTOCOL} method {other}"),
+            });
+        }
+    })
+}
+
+/// Server side: the Hadoop result message for a native response.
+pub fn encode_client_response(resp: &ClientResponse) -> Result<Vec<u8>> {
+    Ok(match resp {
+        ClientResponse::GetFileInfo(r) => GetFileInfoResponseProto {
+            fs: r.status.as_ref().map(|s| file_status_proto(s, b"")),
+        }
+        .to_proto(),
+        ClientResponse::ListStatus(r) => GetListingResponseProto {
+            dir_list: Some(DirectoryListingProto {
+                partial_listing: r
+                    .entries
+                    .iter()
+                    .map(|s| file_status_proto(s, s.path.name().as_bytes()))
+                    .collect(),
+                remaining_entries: r.remaining,
+            }),
+        }
+        .to_proto(),
+        ClientResponse::Mkdirs(r) => MkdirsResponseProto { result: r.created }.to_proto(),
+        ClientResponse::Delete(r) => DeleteResponseProto { result: r.deleted }.to_proto(),
+        ClientResponse::Rename(r) => RenameResponseProto { result: r.renamed }.to_proto(),
+        ClientResponse::GetBlockLocations(r) => GetBlockLocationsResponseProto {
+            locations: Some(located_blocks_proto(&r.locations)),
+        }
+        .to_proto(),
+        other => {
+            return Err(HdfsError::Protocol {
+                op: "hadoop_rpc".into(),
+                details: format!("no {CLIENT_PROTOCOL} mapping for {}", other.op().name()),
+            });
+        }
+    })
+}
+
+pub fn file_status_proto(s: &FileStatus, name: &[u8]) -> HdfsFileStatusProto {
+    let is_file = s.file_type == FileType::File;
+    HdfsFileStatusProto {
+        file_type: match s.file_type {
+            FileType::Dir => IS_DIR,
+            FileType::File => IS_FILE,
+            FileType::Symlink => IS_SYMLINK,
+        },
+        path: name.to_vec(),
+        length: s.length,
+        permission: FsPermissionProto {
+            perm: s.permission.into(),
+        },
+        owner: s.owner.clone(),
+        group: s.group.clone(),
+        modification_time: s.modification_time,
+        access_time: s.access_time,
+        symlink: None,
+        block_replication: is_file.then_some(s.replication.into()),
+        blocksize: is_file.then_some(s.block_size),
+        locations: None,
+        file_id: Some(s.inode.0),
+        children_num: Some(if is_file { -1 } else { s.children as i32 }),
+    }
+}
+
+impl HdfsFileStatusProto {
+    /// Client side. Statuses carry only their local name, so the caller
+    /// passes where it asked: the path itself for `getFileInfo`, the
+    /// listed directory for `getListing`.
+    pub fn to_file_status(&self, base: &PathAbs) -> Result<FileStatus> {
+        let path = match self.path.as_slice() {
+            [] => base.clone(),
+            name => base.join(std::str::from_utf8(name).map_err(|_| HdfsError::Protocol {
+                op: "HdfsFileStatusProto".into(),
+                details: "path is not utf-8".into(),
+            })?)?,
+        };
+        Ok(FileStatus {
+            inode: INodeId(self.file_id.unwrap_or(0)),
+            path,
+            file_type: match self.file_type {
+                IS_DIR => FileType::Dir,
+                IS_SYMLINK => FileType::Symlink,
+                _ => FileType::File,
+            },
+            length: self.length,
+            replication: self.block_replication.unwrap_or(0) as u16,
+            block_size: self.blocksize.unwrap_or(0),
+            modification_time: self.modification_time,
+            access_time: self.access_time,
+            permission: (self.permission.perm & 0o7777) as u16,
+            owner: self.owner.clone(),
+            group: self.group.clone(),
+            children: self.children_num.unwrap_or(0).max(0) as u32,
+        })
+    }
+}
+
+impl From<&ExtendedBlock> for ExtendedBlockProto {
+    fn from(b: &ExtendedBlock) -> Self {
+        Self {
+            pool_id: b.pool.to_string(),
+            block_id: b.id.0,
+            generation_stamp: b.gen_stamp.0,
+            num_bytes: Some(b.num_bytes),
+        }
+    }
+}
+
+fn datanode_info_proto(dn: &DatanodeInfo) -> DatanodeInfoProto {
+    let (ip, port) = dn
+        .data_addr
+        .rsplit_once(':')
+        .map(|(h, p)| (h, p.parse().unwrap_or(0)))
+        .unwrap_or((dn.data_addr.as_str(), 0));
+    DatanodeInfoProto {
+        id: DatanodeIdProto {
+            ip_addr: ip.into(),
+            host_name: dn.hostname.clone(),
+            datanode_uuid: dn.id.to_string(),
+            xfer_port: port,
+            info_port: 0,
+            ipc_port: 0,
+        },
+        ..Default::default()
+    }
+}
+
+fn located_block_proto(b: &LocatedBlock) -> LocatedBlockProto {
+    LocatedBlockProto {
+        b: (&b.block).into(),
+        offset: b.offset,
+        locs: b.locations.iter().map(datanode_info_proto).collect(),
+        corrupt: b.corrupt,
+        // no block access tokens: SIMPLE auth only
+        block_token: TokenProto::default(),
+    }
+}
+
+pub fn located_blocks_proto(l: &LocatedBlocks) -> LocatedBlocksProto {
+    LocatedBlocksProto {
+        file_length: l.file_length,
+        blocks: l.blocks.iter().map(located_block_proto).collect(),
+        under_construction: l.under_construction,
+        last_block: l.blocks.last().map(located_block_proto),
+        is_last_block_complete: !l.under_construction,
+    }
+}
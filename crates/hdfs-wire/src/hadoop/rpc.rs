@@ -0,0 +1,443 @@
+//! Hadoop IPC framing (RPC version 9, protobuf engine, SIMPLE auth).
+//!
+//! A connection opens with the 7-byte `hrpc` header, then carries packets:
+//! a 4-byte big-endian length followed by varint-delimited protos. Requests
+//! are `RpcRequestHeaderProto`, `RequestHeaderProto` and the method's
+//! parameter; responses are `RpcResponseHeaderProto` and, on success, the
+//! method's result. The first packet after the header is the connection
+//! context, under call id -3.
+
+use super::proto::{
+    Bytes, Enum, Message, ProtoMessage, Sint32, Str, Uint32, Uint64, proto_message,
+};
+use crate::codec::{Decoder, Encoder};
+use hdfs_common::consts::MIB;
+use hdfs_common::error::{HdfsError, Result};
+use std::io::{self, Read};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+pub const HRPC_MAGIC: &[u8; 4] = b"hrpc";
+pub const IPC_VERSION: u8 = 9;
+pub const CONNECTION_HEADER_LEN: usize = 7;
+
+/// `AuthProtocol.NONE`; SASL (-33) is not supported.
+pub const AUTH_NONE: u8 = 0;
+pub const AUTH_SASL: u8 = -33i8 as u8;
+
+pub const CONNECTION_CONTEXT_CALL_ID: i32 = -3;
+pub const PING_CALL_ID: i32 = -4;
+
+/// Hadoop's `ipc.maximum.data.length` default.
+pub const DEFAULT_MAX_RPC_LEN: usize = 64 * MIB as usize;
+
+/// `RpcKindProto.RPC_PROTOCOL_BUFFER`
+pub const RPC_PROTOCOL_BUFFER: i32 = 2;
+/// `OperationProto.RPC_FINAL_PACKET`
+pub const RPC_FINAL_PACKET: i32 = 0;
+
+/// `RpcStatusProto`
+pub const RPC_SUCCESS: i32 = 0;
+pub const RPC_ERROR: i32 = 1;
+pub const RPC_FATAL: i32 = 2;
+
+/// `RpcErrorCodeProto`
+pub const ERROR_APPLICATION: i32 = 1;
+pub const ERROR_NO_SUCH_METHOD: i32 = 2;
+pub const ERROR_NO_SUCH_PROTOCOL: i32 = 3;
+pub const FATAL_DESERIALIZING_REQUEST: i32 = 13;
+pub const FATAL_UNAUTHORIZED: i32 = 15;
+
+pub const STANDBY_EXCEPTION: &str = "org.apache.hadoop.ipc.StandbyException";
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ConnectionHeader {
+    pub service_class: u8,
+    pub auth_protocol: u8,
+}
+
+impl ConnectionHeader {
+    pub fn new() -> Self {
+        Self {
+            service_class: 0,
+            auth_protocol: AUTH_NONE,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; CONNECTION_HEADER_LEN] {
+        let mut out = [0u8; CONNECTION_HEADER_LEN];
+        out[..4].copy_from_slice(HRPC_MAGIC);
+        out[4] = IPC_VERSION;
+        out[5] = self.service_class;
+        out[6] = self.auth_protocol;
+        out
+    }
+
+    pub fn from_bytes(buf: &[u8; CONNECTION_HEADER_LEN]) -> Result<Self> {
+        let err = |details: String| HdfsError::Protocol {
+            op: "hrpc_header".into(),
+            details,
+        };
+        if &buf[..4] != HRPC_MAGIC {
+            return Err(err(format!("bad magic {:02X?}", &buf[..4])));
+        }
+        if buf[4] != IPC_VERSION {
+            return Err(err(format!(
+                "unsupported IPC version {} (supported: {IPC_VERSION})",
+                buf[4]
+            )));
+        }
+        if buf[6] != AUTH_NONE {
+            return Err(err(format!(
+                "unsupported auth protocol {} (only SIMPLE auth is supported)",
+                buf[6] as i8
+            )));
+        }
+        Ok(Self {
+            service_class: buf[5],
+            auth_protocol: buf[6],
+        })
+    }
+}
+
+impl Default for ConnectionHeader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+proto_message! {
+    pub struct UserInformationProto {
+        1 => optional effective_user: Str,
+        2 => optional real_user: Str,
+    }
+}
+
+proto_message! {
+    pub struct IpcConnectionContextProto {
+        2 => optional user_info: Message<UserInformationProto>,
+        3 => optional protocol: Str,
+    }
+}
+
+proto_message! {
+    pub struct RpcRequestHeaderProto {
+        1 => optional rpc_kind: Enum,
+        2 => optional rpc_op: Enum,
+        3 => required call_id: Sint32,
+        /// 16 bytes, a UUID per client.
+        4 => required client_id: Bytes,
+        /// -1 when absent.
+        5 => optional retry_count: Sint32,
+    }
+}
+
+proto_message! {
+    pub struct RequestHeaderProto {
+        1 => required method_name: Str,
+        2 => required declaring_class_protocol_name: Str,
+        3 => required client_protocol_version: Uint64,
+    }
+}
+
+proto_message! {
+    pub struct RpcResponseHeaderProto {
+        1 => required call_id: Uint32,
+        2 => required status: Enum,
+        3 => optional server_ipc_version_num: Uint32,
+        4 => optional exception_class_name: Str,
+        5 => optional error_msg: Str,
+        6 => optional error_detail: Enum,
+        7 => optional client_id: Bytes,
+        8 => optional retry_count: Sint32,
+    }
+}
+
+impl RpcRequestHeaderProto {
+    fn new(call_id: i32, client_id: [u8; 16]) -> Self {
+        Self {
+            rpc_kind: Some(RPC_PROTOCOL_BUFFER),
+            rpc_op: Some(RPC_FINAL_PACKET),
+            call_id,
+            client_id: client_id.to_vec(),
+            retry_count: Some(-1),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RpcRequest {
+    /// Sent once, right after the connection header.
+    ConnectionContext {
+        header: RpcRequestHeaderProto,
+        context: IpcConnectionContextProto,
+    },
+    Call {
+        header: RpcRequestHeaderProto,
+        method: RequestHeaderProto,
+        /// The encoded parameter message.
+        param: Vec<u8>,
+    },
+}
+
+impl RpcRequest {
+    pub fn connection_context(client_id: [u8; 16], user: &str, protocol: &str) -> Self {
+        RpcRequest::ConnectionContext {
+            header: RpcRequestHeaderProto::new(CONNECTION_CONTEXT_CALL_ID, client_id),
+            context: IpcConnectionContextProto {
+                user_info: Some(UserInformationProto {
+                    effective_user: Some(user.into()),
+                    real_user: None,
+                }),
+                protocol: Some(protocol.into()),
+            },
+        }
+    }
+
+    pub fn call<M: ProtoMessage>(
+        call_id: i32,
+        client_id: [u8; 16],
+        protocol: &str,
+        method: &str,
+        param: &M,
+    ) -> Self {
+        RpcRequest::Call {
+            header: RpcRequestHeaderProto::new(call_id, client_id),
+            method: RequestHeaderProto {
+                method_name: method.into(),
+                declaring_class_protocol_name: protocol.into(),
+                client_protocol_version: 1,
+            },
+            param: param.to_proto(),
+        }
+    }
+
+    pub fn header(&self) -> &RpcRequestHeaderProto {
+        match self {
+            RpcRequest::ConnectionContext { header, .. } | RpcRequest::Call { header, .. } => {
+                header
+            }
+        }
+    }
+
+    /// The whole packet, length prefix included.
+    pub fn to_packet(&self) -> Vec<u8> {
+        let mut e = Encoder::new();
+        match self {
+            RpcRequest::ConnectionContext { header, context } => {
+                header.write_delimited(&mut e);
+                context.write_delimited(&mut e);
+            }
+            RpcRequest::Call {
+                header,
+                method,
+                param,
+            } => {
+                header.write_delimited(&mut e);
+                method.write_delimited(&mut e);
+                put_delimited(&mut e, param);
+            }
+        }
+        packet(e)
+    }
+
+    /// Decodes a packet body, without its length prefix.
+    pub fn decode(body: &[u8]) -> Result<Self> {
+        let mut d = Decoder::new(body, "hadoop_rpc_request");
+        let header = RpcRequestHeaderProto::read_delimited(&mut d)?;
+        if header.rpc_kind.is_some_and(|k| k != RPC_PROTOCOL_BUFFER) {
+            return Err(d.error(format!(
+                "unsupported rpc kind {} (only protocol buffers)",
+                header.rpc_kind.unwrap()
+            )));
+        }
+        let req = if header.call_id == CONNECTION_CONTEXT_CALL_ID {
+            let context = IpcConnectionContextProto::read_delimited(&mut d)?;
+            RpcRequest::ConnectionContext { header, context }
+        } else {
+            let method = RequestHeaderProto::read_delimited(&mut d)?;
+            let param = get_delimited(&mut d)?.to_vec();
+            RpcRequest::Call {
+                header,
+                method,
+                param,
+            }
+        };
+        d.finish()?;
+        Ok(req)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpcResponse {
+    pub header: RpcResponseHeaderProto,
+    /// The encoded result; empty unless the call succeeded.
+    pub payload: Vec<u8>,
+}
+
+impl RpcResponse {
+    /// `payload` is the encoded result message.
+    pub fn success(request: &RpcRequestHeaderProto, payload: Vec<u8>) -> Self {
+        Self {
+            header: Self::header(request, RPC_SUCCESS),
+            payload,
+        }
+    }
+
+    /// `err` as an exception class and message, the form `RpcHeader.proto`
+    /// gives for errors.
+    pub fn error(request: &RpcRequestHeaderProto, err: &HdfsError) -> Self {
+        let mut header = Self::header(request, RPC_ERROR);
+        header.exception_class_name = Some(err.class_name().into());
+        header.error_msg = Some(err.to_string());
+        header.error_detail = Some(ERROR_APPLICATION);
+        Self {
+            header,
+            payload: Vec::new(),
+        }
+    }
+
+    fn header(request: &RpcRequestHeaderProto, status: i32) -> RpcResponseHeaderProto {
+        RpcResponseHeaderProto {
+            call_id: request.call_id as u32,
+            status,
+            server_ipc_version_num: Some(IPC_VERSION.into()),
+            client_id: Some(request.client_id.clone()),
+            retry_count: request.retry_count,
+            ..Default::default()
+        }
+    }
+
+    pub fn to_packet(&self) -> Vec<u8> {
+        let mut e = Encoder::new();
+        self.header.write_delimited(&mut e);
+        if self.header.status == RPC_SUCCESS {
+            put_delimited(&mut e, &self.payload);
+        }
+        packet(e)
+    }
+
+    pub fn decode(body: &[u8]) -> Result<Self> {
+        let mut d = Decoder::new(body, "hadoop_rpc_response");
+        let header = RpcResponseHeaderProto::read_delimited(&mut d)?;
+        let payload = if header.status == RPC_SUCCESS {
+            get_delimited(&mut d)?.to_vec()
+        } else {
+            Vec::new()
+        };
+        d.finish()?;
+        Ok(Self { header, payload })
+    }
+
+    /// The call's result, or the remote exception as an `HdfsError`.
+    pub fn into_result<M: ProtoMessage>(self) -> Result<M> {
+        match self.header.status {
+            RPC_SUCCESS => M::from_proto(&self.payload, M::NAME),
+            status => Err(remote_exception(
+                status,
+                self.header.exception_class_name.as_deref().unwrap_or(""),
+                self.header.error_msg.unwrap_or_default(),
+            )),
+        }
+    }
+}
+
+/// Maps a Java exception to the closest `HdfsError`. Anything without a
+/// counterpart becomes an `Io` error carrying the class name, which is what
+/// `java.io.IOException` subclasses are.
+pub fn remote_exception(status: i32, class: &str, msg: String) -> HdfsError {
+    if status == RPC_FATAL {
+        return HdfsError::Protocol {
+            op: "hadoop_rpc".into(),
+            details: format!("{class}: {msg}"),
+        };
+    }
+    let path = |prefixes: &[&str]| {
+        let mut p = msg.as_str();
+        for prefix in prefixes {
+            p = p.strip_prefix(prefix).unwrap_or(p);
+        }
+        p.to_string()
+    };
+    match class {
+        "java.io.FileNotFoundException" => HdfsError::NotFound {
+            path: path(&["File does not exist: ", "not found: "]),
+        },
+        "org.apache.hadoop.fs.FileAlreadyExistsException" => HdfsError::AlreadyExists {
+            path: path(&["already exists: "]),
+        },
+        STANDBY_EXCEPTION => HdfsError::State {
+            what: "standby".into(),
+            details: msg,
+        },
+        _ => HdfsError::Io(io::Error::other(format!("{class}: {msg}"))),
+    }
+}
+
+fn put_delimited(e: &mut Encoder, bytes: &[u8]) {
+    e.put_varint(bytes.len() as u64);
+    e.put_raw(bytes);
+}
+
+fn get_delimited<'a>(d: &mut Decoder<'a>) -> Result<&'a [u8]> {
+    let n = d.get_varint()?;
+    let n = usize::try_from(n).map_err(|_| d.error("length overflows usize"))?;
+    d.get_raw(n)
+}
+
+fn packet(e: Encoder) -> Vec<u8> {
+    let body = e.into_bytes();
+    let mut out = Vec::with_capacity(4 + body.len());
+    out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    out.extend_from_slice(&body);
+    out
+}
+
+fn packet_len(raw: [u8; 4], max: usize) -> Result<usize> {
+    let len = u32::from_be_bytes(raw) as usize;
+    if len > max {
+        return Err(HdfsError::Protocol {
+            op: "hadoop_rpc".into(),
+            details: format!("packet of {len} bytes exceeds max {max}"),
+        });
+    }
+    Ok(len)
+}
+
+fn truncated(e: io::Error) -> HdfsError {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        HdfsError::Protocol {
+            op: "hadoop_rpc".into(),
+            details: "truncated packet".into(),
+        }
+    } else {
+        e.into()
+    }
+}
+
+/// Reads one packet body. `None` on a clean end of stream between packets.
+pub fn read_packet<R: Read>(r: &mut R, max: usize) -> Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match r.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let mut body = vec![0u8; packet_len(len, max)?];
+    r.read_exact(&mut body).map_err(truncated)?;
+    Ok(Some(body))
+}
+
+pub async fn read_packet_async<R: AsyncRead + Unpin>(
+    r: &mut R,
+    max: usize,
+) -> Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match r.read_exact(&mut len).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let mut body = vec![0u8; packet_len(len, max)?];
+    r.read_exact(&mut body).await.map_err(truncated)?;
+    Ok(Some(body))
+}
@@ -0,0 +1,287 @@
+//! Hadoop's DataTransferProtocol (version 28): a 2-byte version and 1-byte
+//! op code, then the varint-delimited op proto. Packets are
+//! `plen(4) hlen(2) PacketHeaderProto checksums data`, where `plen` counts
+//! itself, the checksums and the data, but not the header.
+
+use super::namenode::{DatanodeInfoProto, ExtendedBlockProto, TokenProto};
+use super::proto::{
+    Bool, Enum, Message, ProtoMessage, Sfixed32, Sfixed64, Sint64, Str, Uint32, Uint64,
+    proto_message,
+};
+use crate::codec::{Decoder, Encoder};
+use crate::transfer::TransferOp;
+use hdfs_common::checksum::{ChecksumType, DataChecksum};
+use hdfs_common::error::{HdfsError, Result};
+use hdfs_common::ids::BlockId;
+
+pub const DATA_TRANSFER_VERSION: u16 = 28;
+
+/// `Status`
+pub const SUCCESS: i32 = 0;
+pub const ERROR: i32 = 1;
+pub const ERROR_CHECKSUM: i32 = 2;
+pub const CHECKSUM_OK: i32 = 6;
+
+/// `ChecksumTypeProto`
+pub const CHECKSUM_NULL: i32 = 0;
+pub const CHECKSUM_CRC32: i32 = 1;
+pub const CHECKSUM_CRC32C: i32 = 2;
+
+proto_message! {
+    pub struct BaseHeaderProto {
+        1 => required block: Message<ExtendedBlockProto>,
+        2 => optional token: Message<TokenProto>,
+    }
+}
+
+proto_message! {
+    pub struct ClientOperationHeaderProto {
+        1 => required base_header: Message<BaseHeaderProto>,
+        2 => required client_name: Str,
+    }
+}
+
+proto_message! {
+    pub struct ChecksumProto {
+        1 => required checksum_type: Enum,
+        2 => required bytes_per_checksum: Uint32,
+    }
+}
+
+impl ChecksumProto {
+    pub fn to_data_checksum(&self) -> Result<DataChecksum> {
+        let kind = u8::try_from(self.checksum_type)
+            .ok()
+            .and_then(ChecksumType::from_id);
+        match kind {
+            Some(kind) if self.bytes_per_checksum > 0 => {
+                Ok(DataChecksum::new(kind, self.bytes_per_checksum))
+            }
+            _ => Err(HdfsError::Protocol {
+                op: "hadoop_checksum".into(),
+                details: format!(
+                    "unsupported checksum type {} / {} bytes",
+                    self.checksum_type, self.bytes_per_checksum
+                ),
+            }),
+        }
+    }
+}
+
+impl From<DataChecksum> for ChecksumProto {
+    fn from(c: DataChecksum) -> Self {
+        ChecksumProto {
+            checksum_type: i32::from(c.kind().id()),
+            bytes_per_checksum: c.bytes_per_checksum(),
+        }
+    }
+}
+
+proto_message! {
+    pub struct OpReadBlockProto {
+        1 => required header: Message<ClientOperationHeaderProto>,
+        2 => required offset: Uint64,
+        3 => required len: Uint64,
+        4 => optional send_checksums: Bool,
+    }
+}
+
+proto_message! {
+    pub struct OpWriteBlockProto {
+        1 => required header: Message<ClientOperationHeaderProto>,
+        2 => repeated targets: Message<DatanodeInfoProto>,
+        3 => optional source: Message<DatanodeInfoProto>,
+        4 => required stage: Enum,
+        5 => required pipeline_size: Uint32,
+        6 => required min_bytes_rcvd: Uint64,
+        7 => required max_bytes_rcvd: Uint64,
+        8 => required latest_generation_stamp: Uint64,
+        9 => required requested_checksum: Message<ChecksumProto>,
+    }
+}
+
+proto_message! {
+    pub struct OpTransferBlockProto {
+        1 => required header: Message<ClientOperationHeaderProto>,
+        2 => repeated targets: Message<DatanodeInfoProto>,
+    }
+}
+
+proto_message! {
+    pub struct OpBlockChecksumProto {
+        1 => required header: Message<BaseHeaderProto>,
+    }
+}
+
+proto_message! {
+    pub struct ReadOpChecksumInfoProto {
+        1 => required checksum: Message<ChecksumProto>,
+        /// Where the first packet starts; reads begin on a chunk boundary.
+        2 => required chunk_offset: Uint64,
+    }
+}
+
+proto_message! {
+    pub struct BlockOpResponseProto {
+        1 => required status: Enum,
+        2 => optional first_bad_link: Str,
+        4 => optional read_op_checksum_info: Message<ReadOpChecksumInfoProto>,
+        5 => optional message: Str,
+    }
+}
+
+proto_message! {
+    /// Sent by a reader after the last packet.
+    pub struct ClientReadStatusProto {
+        1 => required status: Enum,
+    }
+}
+
+proto_message! {
+    pub struct PacketHeaderProto {
+        1 => required offset_in_block: Sfixed64,
+        2 => required seqno: Sfixed64,
+        3 => required last_packet_in_block: Bool,
+        4 => required data_len: Sfixed32,
+        5 => optional sync_block: Bool,
+    }
+}
+
+proto_message! {
+    pub struct PipelineAckProto {
+        1 => required seqno: Sint64,
+        2 => repeated reply: Enum,
+        3 => optional downstream_ack_time_nanos: Uint64,
+        4 => repeated flag: Uint32,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum HadoopOp {
+    ReadBlock(OpReadBlockProto),
+    WriteBlock(Box<OpWriteBlockProto>),
+    TransferBlock(OpTransferBlockProto),
+    BlockChecksum(OpBlockChecksumProto),
+}
+
+impl HadoopOp {
+    pub fn op(&self) -> TransferOp {
+        match self {
+            HadoopOp::ReadBlock(_) => TransferOp::ReadBlock,
+            HadoopOp::WriteBlock(_) => TransferOp::WriteBlock,
+            HadoopOp::TransferBlock(_) => TransferOp::TransferBlock,
+            HadoopOp::BlockChecksum(_) => TransferOp::BlockChecksum,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut e = Encoder::new();
+        e.put_u16(DATA_TRANSFER_VERSION);
+        e.put_u8(self.op() as u8);
+        match self {
+            HadoopOp::ReadBlock(m) => m.write_delimited(&mut e),
+            HadoopOp::WriteBlock(m) => m.write_delimited(&mut e),
+            HadoopOp::TransferBlock(m) => m.write_delimited(&mut e),
+            HadoopOp::BlockChecksum(m) => m.write_delimited(&mut e),
+        }
+        e.into_bytes()
+    }
+
+    pub fn decode(d: &mut Decoder<'_>) -> Result<Self> {
+        let version = d.get_u16()?;
+        if version != DATA_TRANSFER_VERSION {
+            return Err(d.error(format!(
+                "unsupported data transfer version {version} (supported: {DATA_TRANSFER_VERSION})"
+            )));
+        }
+        Ok(match d.get_u8()? {
+            81 => HadoopOp::ReadBlock(ProtoMessage::read_delimited(d)?),
+            80 => HadoopOp::WriteBlock(Box::new(ProtoMessage::read_delimited(d)?)),
+            86 => HadoopOp::TransferBlock(ProtoMessage::read_delimited(d)?),
+            85 => HadoopOp::BlockChecksum(ProtoMessage::read_delimited(d)?),
+            op => return Err(d.error(format!("unsupported transfer op {op}"))),
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HadoopPacket {
+    pub header: PacketHeaderProto,
+    pub checksums: Vec<u32>,
+    pub data: Vec<u8>,
+}
+
+impl HadoopPacket {
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        let header = self.header.to_proto();
+        let plen = 4 + 4 * self.checksums.len() + self.data.len();
+        out.extend_from_slice(&(plen as u32).to_be_bytes());
+        out.extend_from_slice(&(header.len() as u16).to_be_bytes());
+        out.extend_from_slice(&header);
+        for crc in &self.checksums {
+            out.extend_from_slice(&crc.to_be_bytes());
+        }
+        out.extend_from_slice(&self.data);
+    }
+
+    /// Decodes one packet from the front of `buf` and verifies it against
+    /// the checksum the datanode announced; `None` until `buf` holds the
+    /// whole packet.
+    pub fn decode(
+        buf: &[u8],
+        checksum: &ChecksumProto,
+        block: BlockId,
+    ) -> Result<Option<(HadoopPacket, usize)>> {
+        let err = |details: String| HdfsError::Protocol {
+            op: "hadoop_packet".into(),
+            details,
+        };
+        if buf.len() < 6 {
+            return Ok(None);
+        }
+        let plen = u32::from_be_bytes(buf[..4].try_into().unwrap()) as usize;
+        let hlen = u16::from_be_bytes(buf[4..6].try_into().unwrap()) as usize;
+        if plen < 4 {
+            return Err(err(format!("packet length {plen} is below 4")));
+        }
+        let total = 6 + hlen + plen - 4;
+        if buf.len() < total {
+            return Ok(None);
+        }
+        let header = PacketHeaderProto::from_proto(&buf[6..6 + hlen], "hadoop_packet")?;
+        let data_len = usize::try_from(header.data_len)
+            .map_err(|_| err(format!("negative data length {}", header.data_len)))?;
+        let sums_len = (plen - 4).checked_sub(data_len).ok_or_else(|| {
+            err(format!(
+                "data length {data_len} exceeds packet length {plen}"
+            ))
+        })?;
+
+        let checksum = checksum.to_data_checksum()?;
+        if sums_len != checksum.checksums_len(data_len) {
+            return Err(err(format!(
+                "{sums_len} checksum bytes for {data_len} data bytes"
+            )));
+        }
+        let body = &buf[6 + hlen..total];
+        let checksums: Vec<u32> = body[..sums_len]
+            .chunks_exact(4)
+            .map(|c| u32::from_be_bytes(c.try_into().unwrap()))
+            .collect();
+        let data = body[sums_len..].to_vec();
+        checksum.verify(
+            &data,
+            &body[..sums_len],
+            block,
+            header.offset_in_block.max(0) as u64,
+        )?;
+        Ok(Some((
+            HadoopPacket {
+                header,
+                checksums,
+                data,
+            },
+            total,
+        )))
+    }
+}
@@ -0,0 +1,571 @@
+//! Connection preamble, exchanged by both sides before the first frame.
+//!
+//! Each side sends a [`Hello`] with the protocol versions and optional
+//! features it supports, then reads the peer's. Both compute the same
+//! [`Negotiated`] outcome from the pair, so there is no separate accept or
+//! reject message: an incompatible pair fails on both ends and the
+//! connection is closed.
+//!
+//! The hello layout is fixed for all protocol versions:
+//! magic(4) min_version(1) max_version(1) supported(8) required(8).
+
+use crate::compress::Compression;
+use crate::frame::{FrameCodec, MAGIC, PROTOCOL_VERSION};
+use crate::transfer::PacketCodec;
+use hdfs_common::error::{HdfsError, Result};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::ops::{BitAnd, BitOr};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+pub const MIN_PROTOCOL_VERSION: u8 = 1;
+
+pub const HELLO_LEN: usize = 22;
+
+/// A set of optional protocol features. Bits this build does not know are
+/// kept, so they can be named in errors.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Features(u64);
+
+impl Features {
+    /// Compressed frames and packets, with a codec from the bits below.
+    pub const COMPRESSION: Features = Features(1 << 0);
+    /// CRC32C trailers on frames.
+    pub const CRC32C: Features = Features(1 << 1);
+    pub const ENCRYPTION: Features = Features(1 << 2);
+    pub const VECTORED_READS: Features = Features(1 << 3);
+    pub const LZ4: Features = Features(1 << 4);
+    pub const ZSTD: Features = Features(1 << 5);
+
+    /// What this build can actually do. Other bits are neither advertised
+    /// nor agreed to, and requiring one fails the handshake.
+    pub const IMPLEMENTED: Features =
+        Features(Features::COMPRESSION.0 | Features::CRC32C.0 | Features::LZ4.0 | Features::ZSTD.0);
+
+    const NAMES: &[(Features, &'static str)] = &[
+        (Features::COMPRESSION, "compression"),
+        (Features::CRC32C, "crc32c"),
+        (Features::ENCRYPTION, "encryption"),
+        (Features::VECTORED_READS, "vectored_reads"),
+        (Features::LZ4, "lz4"),
+        (Features::ZSTD, "zstd"),
+    ];
+
+    pub const fn empty() -> Self {
+        Features(0)
+    }
+
+    pub const fn from_bits(bits: u64) -> Self {
+        Features(bits)
+    }
+
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn contains(self, other: Features) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn union(self, other: Features) -> Self {
+        Features(self.0 | other.0)
+    }
+
+    pub const fn intersection(self, other: Features) -> Self {
+        Features(self.0 & other.0)
+    }
+
+    pub const fn difference(self, other: Features) -> Self {
+        Features(self.0 & !other.0)
+    }
+
+    pub fn insert(&mut self, other: Features) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Features) {
+        self.0 &= !other.0;
+    }
+}
+
+impl BitOr for Features {
+    type Output = Features;
+
+    fn bitor(self, rhs: Features) -> Features {
+        self.union(rhs)
+    }
+}
+
+impl BitAnd for Features {
+    type Output = Features;
+
+    fn bitand(self, rhs: Features) -> Features {
+        self.intersection(rhs)
+    }
+}
+
+/// Comma separated names, e.g. `crc32c,encryption`; unknown bits as `bit12`.
+impl fmt::Display for Features {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("none");
+        }
+        let mut first = true;
+        for bit in (0..64)
+            .map(|i| Features(1 << i))
+            .filter(|b| self.contains(*b))
+        {
+            if !first {
+                f.write_str(",")?;
+            }
+            first = false;
+            match Features::NAMES.iter().find(|(b, _)| *b == bit) {
+                Some((_, name)) => f.write_str(name)?,
+                None => write!(f, "bit{}", bit.0.trailing_zeros())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What one side offers.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Hello {
+    pub min_version: u8,
+    pub max_version: u8,
+    pub supported: Features,
+    /// Features the connection must not go without; always also supported.
+    pub required: Features,
+}
+
+impl Hello {
+    /// Every version this build speaks, nothing required.
+    pub fn new(supported: Features) -> Self {
+        Self {
+            min_version: MIN_PROTOCOL_VERSION,
+            max_version: PROTOCOL_VERSION,
+            supported,
+            required: Features::empty(),
+        }
+    }
+
+    pub fn require(mut self, features: Features) -> Self {
+        self.supported.insert(features);
+        self.required.insert(features);
+        self
+    }
+
+    /// Advertises only implemented features, plus whatever is required so
+    /// that the peer, too, sees a requirement it can't meet.
+    pub fn to_bytes(&self) -> [u8; HELLO_LEN] {
+        let supported = (self.supported & Features::IMPLEMENTED) | self.required;
+        let mut out = [0u8; HELLO_LEN];
+        out[0..4].copy_from_slice(&MAGIC.to_be_bytes());
+        out[4] = self.min_version;
+        out[5] = self.max_version;
+        out[6..14].copy_from_slice(&supported.bits().to_be_bytes());
+        out[14..22].copy_from_slice(&self.required.bits().to_be_bytes());
+        out
+    }
+
+    pub fn from_bytes(buf: &[u8; HELLO_LEN]) -> Result<Self> {
+        let magic = u32::from_be_bytes(buf[0..4].try_into().unwrap());
+        if magic != MAGIC {
+            return Err(handshake_err(format!(
+                "bad magic 0x{magic:08X}, peer does not speak this protocol"
+            )));
+        }
+        let hello = Self {
+            min_version: buf[4],
+            max_version: buf[5],
+            supported: Features::from_bits(u64::from_be_bytes(buf[6..14].try_into().unwrap())),
+            required: Features::from_bits(u64::from_be_bytes(buf[14..22].try_into().unwrap())),
+        };
+        if hello.min_version > hello.max_version {
+            return Err(handshake_err(format!(
+                "peer sent empty version range {}..={}",
+                hello.min_version, hello.max_version
+            )));
+        }
+        Ok(hello)
+    }
+
+    /// The highest common version and the implemented features both sides
+    /// support.
+    pub fn negotiate(&self, peer: &Hello) -> Result<Negotiated> {
+        let version = self.max_version.min(peer.max_version);
+        if version < self.min_version.max(peer.min_version) {
+            return Err(handshake_err(format!(
+                "no common protocol version: local supports {}..={}, peer supports {}..={}",
+                self.min_version, self.max_version, peer.min_version, peer.max_version
+            )));
+        }
+        let missing = self.required.difference(Features::IMPLEMENTED);
+        if !missing.is_empty() {
+            return Err(handshake_err(format!(
+                "required features not implemented: {missing}"
+            )));
+        }
+        let features = self.supported & peer.supported & Features::IMPLEMENTED;
+        let missing = self.required.difference(features);
+        if !missing.is_empty() {
+            return Err(handshake_err(format!(
+                "peer does not support required features: {missing}"
+            )));
+        }
+        let missing = peer.required.difference(features);
+        if !missing.is_empty() {
+            return Err(handshake_err(format!(
+                "peer requires unsupported features: {missing}"
+            )));
+        }
+        Ok(Negotiated { version, features })
+    }
+}
+
+impl Default for Hello {
+    fn default() -> Self {
+        Self::new(Features::CRC32C)
+    }
+}
+
+/// The agreed protocol version and feature set of one connection.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Negotiated {
+    pub version: u8,
+    pub features: Features,
+}
+
+impl Negotiated {
+    pub fn has(&self, feature: Features) -> bool {
+        self.features.contains(feature)
+    }
+
+    /// The codec both sides send large payloads with, if compression was
+    /// agreed: the first of [`Compression::ALL`] they both have.
+    pub fn compression(&self) -> Option<Compression> {
+        if !self.has(Features::COMPRESSION) {
+            return None;
+        }
+        Compression::ALL.into_iter().find(|c| self.has(c.feature()))
+    }
+
+    /// `base` adjusted to what was agreed.
+    pub fn frame_codec(&self, base: FrameCodec) -> FrameCodec {
+        base.with_version(self.version)
+            .with_checksum(self.has(Features::CRC32C))
+            .with_compression(self.compression())
+    }
+
+    /// `base` adjusted to what was agreed, for block data sent over this
+    /// connection.
+    pub fn packet_codec(&self, base: PacketCodec) -> PacketCodec {
+        base.with_compression(self.compression())
+    }
+}
+
+/// Sends `local`, reads the peer's hello and negotiates.
+pub fn handshake<S: Read + Write>(stream: &mut S, local: &Hello) -> Result<Negotiated> {
+    stream.write_all(&local.to_bytes())?;
+    stream.flush()?;
+    let mut buf = [0u8; HELLO_LEN];
+    stream.read_exact(&mut buf).map_err(closed)?;
+    local.negotiate(&Hello::from_bytes(&buf)?)
+}
+
+pub async fn handshake_async<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    local: &Hello,
+) -> Result<Negotiated> {
+    stream.write_all(&local.to_bytes()).await?;
+    stream.flush().await?;
+    let mut buf = [0u8; HELLO_LEN];
+    stream.read_exact(&mut buf).await.map_err(closed)?;
+    local.negotiate(&Hello::from_bytes(&buf)?)
+}
+
+fn handshake_err(details: impl Into<String>) -> HdfsError {
+    HdfsError::Protocol {
+        op: "handshake".into(),
+        details: details.into(),
+    }
+}
+
+fn closed(e: io::Error) -> HdfsError {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        handshake_err("peer closed the connection during handshake")
+    } else {
+        e.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn details(res: Result<Negotiated>) -> String {
+        match res {
+            Err(HdfsError::Protocol { op, details }) if op == "handshake" => details,
+            other => panic!("expected handshake error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn features_display_and_ops() {
+        let f = Features::CRC32C | Features::ENCRYPTION;
+        assert_eq!(f.to_string(), "crc32c,encryption");
+        assert!(f.contains(Features::CRC32C));
+        assert!(!f.contains(Features::CRC32C | Features::COMPRESSION));
+        assert_eq!(f & Features::CRC32C, Features::CRC32C);
+        assert_eq!(Features::empty().to_string(), "none");
+        assert_eq!(
+            Features::from_bits(1 << 12 | 1).to_string(),
+            "compression,bit12"
+        );
+
+        let mut g = f;
+        g.remove(Features::ENCRYPTION);
+        assert_eq!(g, Features::CRC32C);
+    }
+
+    #[test]
+    fn hello_roundtrip() {
+        let h = Hello::new(Features::CRC32C).require(Features::ZSTD);
+        assert!(h.supported.contains(Features::ZSTD));
+        let bytes = h.to_bytes();
+        assert_eq!(&bytes[..4], b"HDFS");
+        assert_eq!(Hello::from_bytes(&bytes).unwrap(), h);
+
+        // unimplemented features are only sent when required
+        let h = Hello::new(Features::CRC32C | Features::VECTORED_READS);
+        assert_eq!(
+            Hello::from_bytes(&h.to_bytes()).unwrap().supported,
+            Features::CRC32C
+        );
+        let h = Hello::default().require(Features::ENCRYPTION);
+        assert_eq!(Hello::from_bytes(&h.to_bytes()).unwrap(), h);
+
+        let mut hrpc = bytes;
+        hrpc[..4].copy_from_slice(b"hrpc");
+        assert!(matches!(
+            Hello::from_bytes(&hrpc),
+            Err(HdfsError::Protocol { op, .. }) if op == "handshake"
+        ));
+    }
+
+    #[test]
+    fn negotiation_intersects() {
+        let a = Hello::new(Features::CRC32C | Features::COMPRESSION);
+        let b = Hello::new(Features::CRC32C | Features::LZ4);
+        let n = a.negotiate(&b).unwrap();
+        assert_eq!(n, b.negotiate(&a).unwrap());
+        assert_eq!(n.version, PROTOCOL_VERSION);
+        assert_eq!(n.features, Features::CRC32C);
+        assert!(n.has(Features::CRC32C));
+        assert!(!n.has(Features::COMPRESSION));
+    }
+
+    #[test]
+    fn picks_highest_common_version() {
+        let old = Hello {
+            min_version: 1,
+            max_version: 2,
+            ..Hello::default()
+        };
+        let new = Hello {
+            min_version: 2,
+            max_version: 4,
+            ..Hello::default()
+        };
+        assert_eq!(old.negotiate(&new).unwrap().version, 2);
+
+        let newer = Hello {
+            min_version: 3,
+            max_version: 4,
+            ..Hello::default()
+        };
+        let msg = details(old.negotiate(&newer));
+        assert!(msg.contains("no common protocol version"), "{msg}");
+        assert!(msg.contains("1..=2") && msg.contains("3..=4"), "{msg}");
+    }
+
+    #[test]
+    fn required_features_must_be_shared() {
+        let strict = Hello::default().require(Features::ZSTD);
+        let plain = Hello::default();
+        assert_eq!(
+            details(strict.negotiate(&plain)),
+            "peer does not support required features: zstd"
+        );
+        assert_eq!(
+            details(plain.negotiate(&strict)),
+            "peer requires unsupported features: zstd"
+        );
+
+        // a required feature from a newer build is named by bit
+        let future = Hello {
+            required: Features::from_bits(1 << 20),
+            supported: Features::from_bits(1 << 20),
+            ..Hello::default()
+        };
+        assert!(details(plain.negotiate(&future)).ends_with("bit20"));
+    }
+
+    #[test]
+    fn unimplemented_features_are_never_agreed() {
+        let all = Hello::new(Features::from_bits(u64::MAX));
+        let n = all.negotiate(&all).unwrap();
+        assert_eq!(n.features, Features::IMPLEMENTED);
+        assert!(!n.has(Features::ENCRYPTION) && !n.has(Features::VECTORED_READS));
+
+        // requiring one fails on both ends, even against a peer claiming it
+        let strict = Hello::default().require(Features::ENCRYPTION);
+        assert_eq!(
+            details(strict.negotiate(&all)),
+            "required features not implemented: encryption"
+        );
+        assert_eq!(
+            details(all.negotiate(&strict)),
+            "peer requires unsupported features: encryption"
+        );
+        assert_eq!(
+            details(strict.negotiate(&strict)),
+            "required features not implemented: encryption"
+        );
+    }
+
+    #[test]
+    fn handshake_over_a_stream() {
+        struct Duplex<'a> {
+            rx: &'a [u8],
+            tx: Vec<u8>,
+        }
+        impl Read for Duplex<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                Read::read(&mut self.rx, buf)
+            }
+        }
+        impl Write for Duplex<'_> {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                Write::write(&mut self.tx, buf)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let peer = Hello::new(Features::CRC32C | Features::ENCRYPTION);
+        let local = Hello::default();
+        let incoming = peer.to_bytes();
+        let mut d = Duplex {
+            rx: &incoming,
+            tx: Vec::new(),
+        };
+        let n = handshake(&mut d, &local).unwrap();
+        assert_eq!(n.features, Features::CRC32C);
+        assert_eq!(d.tx, local.to_bytes());
+
+        let mut silent = Duplex {
+            rx: &incoming[..10],
+            tx: Vec::new(),
+        };
+        assert!(details(handshake(&mut silent, &local)).contains("closed"));
+    }
+
+    #[test]
+    fn negotiated_checksum_drives_the_frame_codec() {
+        use crate::frame::Frame;
+
+        let frame = Frame::new(1, 1, b"x".to_vec());
+        let with = Negotiated {
+            version: 1,
+            features: Features::CRC32C,
+        };
+        let without = Negotiated {
+            version: 1,
+            features: Features::empty(),
+        };
+        let a = with
+            .frame_codec(FrameCodec::new())
+            .encode_to_vec(&frame)
+            .unwrap();
+        let b = without
+            .frame_codec(FrameCodec::new())
+            .encode_to_vec(&frame)
+            .unwrap();
+        assert_eq!(a.len(), b.len() + crate::frame::TRAILER_LEN);
+    }
+
+    #[test]
+    fn negotiated_version_drives_the_frame_codec() {
+        use crate::frame::Frame;
+
+        let old = Negotiated {
+            version: MIN_PROTOCOL_VERSION,
+            features: Features::empty(),
+        };
+        let codec = old.frame_codec(FrameCodec::new().with_version(PROTOCOL_VERSION + 1));
+        assert_eq!(codec.version(), MIN_PROTOCOL_VERSION);
+        let bytes = codec
+            .encode_to_vec(&Frame::new(1, 1, b"x".to_vec()))
+            .unwrap();
+        assert_eq!(bytes[4], MIN_PROTOCOL_VERSION);
+        assert!(
+            FrameCodec::new()
+                .with_version(PROTOCOL_VERSION + 1)
+                .decode(&bytes)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn compression_codec_is_agreed() {
+        let all = Features::COMPRESSION | Features::LZ4 | Features::ZSTD;
+        let agree = |a: Features, b: Features| {
+            let n = Hello::new(a).negotiate(&Hello::new(b)).unwrap();
+            assert_eq!(n, Hello::new(b).negotiate(&Hello::new(a)).unwrap());
+            n.compression()
+        };
+        assert_eq!(agree(all, all), Some(Compression::Zstd));
+        assert_eq!(
+            agree(all, Features::COMPRESSION | Features::LZ4),
+            Some(Compression::Lz4)
+        );
+        // codecs alone, or the switch without a shared codec, turn nothing on
+        assert_eq!(agree(all, Features::LZ4 | Features::ZSTD), None);
+        assert_eq!(
+            agree(
+                Features::COMPRESSION | Features::LZ4,
+                Features::COMPRESSION | Features::ZSTD
+            ),
+            None
+        );
+        assert_eq!(all.to_string(), "compression,lz4,zstd");
+
+        let n = Hello::new(all).negotiate(&Hello::new(all)).unwrap();
+        assert_eq!(
+            n.frame_codec(FrameCodec::new()).compression(),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(
+            n.packet_codec(PacketCodec::default()).compression(),
+            Some(Compression::Zstd)
+        );
+    }
+
+    #[tokio::test]
+    async fn async_handshake_both_sides() {
+        let (mut a, mut b) = tokio::io::duplex(64);
+        let client = Hello::new(Features::CRC32C | Features::COMPRESSION);
+        let server = Hello::new(Features::COMPRESSION);
+        let (x, y) = tokio::join!(
+            handshake_async(&mut a, &client),
+            handshake_async(&mut b, &server)
+        );
+        assert_eq!(x.unwrap(), y.unwrap());
+    }
+}
@@ -1,14 +1,10 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
-}
+pub mod buffer;
+pub mod client;
+pub mod codec;
+pub mod compress;
+pub mod datanode;
+pub mod frame;
+pub mod hadoop;
+pub mod handshake;
+pub mod message;
+pub mod transfer;
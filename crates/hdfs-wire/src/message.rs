@@ -0,0 +1,245 @@
+use crate::codec::{WireDecode, WireEncode};
+use crate::frame::Frame;
+use hdfs_common::error::{HdfsError, RemoteError, Result};
+
+/// Set on the message type of every successful response.
+pub const RESPONSE_FLAG: u16 = 0x8000;
+/// Message type of a response carrying a [`RemoteError`] instead of a result.
+pub const ERROR_MSG_TYPE: u16 = 0xFFFF;
+
+/// A request message and the response it gets back.
+pub trait Call: WireEncode + WireDecode {
+    type Response: WireEncode + WireDecode;
+    /// Frame message type of the request; the response uses
+    /// `MSG_TYPE | RESPONSE_FLAG`.
+    const MSG_TYPE: u16;
+    /// Message name, also used as the `op` of decode errors.
+    const NAME: &'static str;
+    /// Whether running the call twice has the same effect as once, so a
+    /// client may resend it when unsure whether it ran.
+    const IDEMPOTENT: bool;
+}
+
+pub fn request_frame<C: Call>(call_id: u32, req: &C) -> Frame {
+    Frame::new(C::MSG_TYPE, call_id, req.to_bytes())
+}
+
+pub fn response_frame<C: Call>(call_id: u32, resp: &C::Response) -> Frame {
+    Frame::new(C::MSG_TYPE | RESPONSE_FLAG, call_id, resp.to_bytes())
+}
+
+pub fn error_frame(call_id: u32, err: &HdfsError) -> Frame {
+    Frame::new(ERROR_MSG_TYPE, call_id, err.to_remote().to_bytes())
+}
+
+/// Decodes the answer to a `C` request; an error frame comes back as the
+/// typed error the server sent.
+pub fn decode_response<C: Call>(frame: &Frame) -> Result<C::Response> {
+    if frame.msg_type == ERROR_MSG_TYPE {
+        let remote = RemoteError::from_bytes(&frame.payload, C::NAME)?;
+        return Err(remote.into_error());
+    }
+    if frame.msg_type != C::MSG_TYPE | RESPONSE_FLAG {
+        return Err(HdfsError::Protocol {
+            op: C::NAME.into(),
+            details: format!(
+                "unexpected message type 0x{:04X} in response",
+                frame.msg_type
+            ),
+        });
+    }
+    C::Response::from_bytes(&frame.payload, C::NAME)
+}
+
+/// The request enum of a protocol, so a server can route frames to it
+/// without knowing which protocol it is.
+pub trait Requests: Sized + Send + 'static {
+    type Response: Send + 'static;
+    /// e.g. `"client"`
+    const PROTOCOL: &'static str;
+
+    fn handles(msg_type: u16) -> bool;
+
+    fn decode(frame: &Frame) -> Result<Self>;
+
+    /// Name of the op, as in [`Call::NAME`].
+    fn name(&self) -> &'static str;
+
+    /// The frame answering an op named `name`; an error if `resp` answers
+    /// a different op.
+    fn encode_response(name: &'static str, call_id: u32, resp: &Self::Response) -> Result<Frame>;
+}
+
+/// `idempotent` or `at_most_once`, as Hadoop annotates its protocols.
+macro_rules! is_idempotent {
+    (idempotent) => {
+        true
+    };
+    (at_most_once) => {
+        false
+    };
+}
+
+/// Declares a protocol's op table: an `$Op` enum of message types, the
+/// `Call` impls, and `$Req`/`$Resp` enums for servers that dispatch on
+/// whichever request arrives.
+macro_rules! protocol_ops {
+    (
+        $proto:literal, $Op:ident, $Req:ident, $Resp:ident;
+        $( $op:ident = $code:literal, $method:literal, $sem:ident, $req:ident, $resp:ident; )*
+    ) => {
+        #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+        #[repr(u16)]
+        pub enum $Op {
+            $( $op = $code, )*
+        }
+
+        impl $Op {
+            pub const ALL: &[$Op] = &[$( $Op::$op, )*];
+
+            pub fn code(self) -> u16 {
+                self as u16
+            }
+
+            pub fn from_code(code: u16) -> Option<$Op> {
+                match code {
+                    $( $code => Some($Op::$op), )*
+                    _ => None,
+                }
+            }
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $( $Op::$op => stringify!($op), )*
+                }
+            }
+
+            pub fn method(self) -> &'static str {
+                match self {
+                    $( $Op::$op => $method, )*
+                }
+            }
+
+            pub fn is_idempotent(self) -> bool {
+                match self {
+                    $( $Op::$op => $crate::message::is_idempotent!($sem), )*
+                }
+            }
+        }
+
+        $(
+            impl $crate::message::Call for $req {
+                type Response = $resp;
+                const MSG_TYPE: u16 = $code;
+                const NAME: &'static str = stringify!($op);
+                const IDEMPOTENT: bool = $crate::message::is_idempotent!($sem);
+            }
+        )*
+
+        #[derive(Clone, Debug, PartialEq)]
+        pub enum $Req {
+            $( $op($req), )*
+        }
+
+        #[derive(Clone, Debug, PartialEq)]
+        pub enum $Resp {
+            $( $op($resp), )*
+        }
+
+        impl $Req {
+            pub fn op(&self) -> $Op {
+                match self {
+                    $( $Req::$op(_) => $Op::$op, )*
+                }
+            }
+
+            pub fn to_frame(&self, call_id: u32) -> $crate::frame::Frame {
+                let payload = match self {
+                    $( $Req::$op(m) => $crate::codec::WireEncode::to_bytes(m), )*
+                };
+                $crate::frame::Frame::new(self.op().code(), call_id, payload)
+            }
+
+            /// Server side: decodes whichever request `frame` carries.
+            pub fn from_frame(frame: &$crate::frame::Frame) -> ::hdfs_common::error::Result<$Req> {
+                let op = $Op::from_code(frame.msg_type).ok_or_else(|| {
+                    ::hdfs_common::error::HdfsError::Protocol {
+                        op: "decode_request".into(),
+                        details: format!(concat!("unknown ", $proto, " op 0x{:04X}"), frame.msg_type),
+                    }
+                })?;
+                Ok(match op {
+                    $( $Op::$op => $Req::$op(
+                        <$req as $crate::codec::WireDecode>::from_bytes(&frame.payload, stringify!($op))?,
+                    ), )*
+                })
+            }
+        }
+
+        impl $crate::message::Requests for $Req {
+            type Response = $Resp;
+            const PROTOCOL: &'static str = $proto;
+
+            fn handles(msg_type: u16) -> bool {
+                $Op::from_code(msg_type).is_some()
+            }
+
+            fn decode(frame: &$crate::frame::Frame) -> ::hdfs_common::error::Result<$Req> {
+                $Req::from_frame(frame)
+            }
+
+            fn name(&self) -> &'static str {
+                self.op().name()
+            }
+
+            fn encode_response(
+                name: &'static str,
+                call_id: u32,
+                resp: &$Resp,
+            ) -> ::hdfs_common::error::Result<$crate::frame::Frame> {
+                if resp.op().name() != name {
+                    return Err(::hdfs_common::error::HdfsError::Protocol {
+                        op: name.into(),
+                        details: format!("handler answered with a {} response", resp.op().name()),
+                    });
+                }
+                Ok(resp.to_frame(call_id))
+            }
+        }
+
+        impl $Resp {
+            pub fn op(&self) -> $Op {
+                match self {
+                    $( $Resp::$op(_) => $Op::$op, )*
+                }
+            }
+
+            pub fn to_frame(&self, call_id: u32) -> $crate::frame::Frame {
+                let payload = match self {
+                    $( $Resp::$op(m) => $crate::codec::WireEncode::to_bytes(m), )*
+                };
+                $crate::frame::Frame::new(
+                    self.op().code() | $crate::message::RESPONSE_FLAG,
+                    call_id,
+                    payload,
+                )
+            }
+        }
+
+        $(
+            impl From<$req> for $Req {
+                fn from(m: $req) -> Self {
+                    $Req::$op(m)
+                }
+            }
+
+            impl From<$resp> for $Resp {
+                fn from(m: $resp) -> Self {
+                    $Resp::$op(m)
+                }
+            }
+        )*
+    };
+}
+
+pub(crate) use {is_idempotent, protocol_ops};
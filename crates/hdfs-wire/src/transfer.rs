@@ -0,0 +1,1221 @@
+//! DataTransferProtocol: block data streamed between clients and datanodes,
+//! outside of RPC framing.
+//!
+//! A connection starts with one length-prefixed op header, then carries
+//! packets of block data, each prefixed with its own length and holding one
+//! checksum per chunk. Writes flow down a pipeline of datanodes and
+//! every packet is acknowledged back up with a status per node.
+//!
+//! Decoded packets don't copy their payload: checksums and data are slices
+//! of the buffer the packet was received into. The exception is data that
+//! was compressed on the wire, which is decompressed into its own buffer;
+//! checksums always cover the data as written, never the compressed form.
+
+use crate::buffer::{BufferPool, DEFAULT_BUFFER_SIZE};
+use crate::client::DatanodeInfo;
+use crate::codec::{Decoder, Encoder, WireDecode, WireEncode, wire_struct};
+use crate::compress::{Compression, DEFAULT_COMPRESSION_THRESHOLD};
+use bytes::{Buf, Bytes, BytesMut};
+use hdfs_common::checksum::DataChecksum;
+use hdfs_common::consts::{KIB, MIB};
+use hdfs_common::error::{HdfsError, Result};
+use hdfs_common::ids::{BlockId, GenerationStamp};
+use hdfs_common::types::ExtendedBlock;
+use std::io::{self, Read, Write};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+pub const DATA_TRANSFER_VERSION: u16 = 2;
+
+/// Upper bound on an encoded op header.
+pub const MAX_OP_HEADER_LEN: usize = 64 * KIB as usize;
+
+pub const DEFAULT_MAX_PACKET_DATA: u32 = 16 * MIB as u32;
+
+/// seqno(8) offset(8) flags(1) data_len(4) checksum_count(4), then the
+/// checksums and, with the compressed flag, a codec(1) byte before the data.
+/// `data_len` is always the length before compression.
+const PACKET_HEADER_LEN: usize = 25;
+
+/// Smallest read [`PacketReader`] makes room for.
+const MIN_READ: usize = 64 * KIB as usize;
+
+const FLAG_LAST: u8 = 0x01;
+const FLAG_SYNC: u8 = 0x02;
+const FLAG_COMPRESSED: u8 = 0x04;
+const KNOWN_FLAGS: u8 = FLAG_LAST | FLAG_SYNC | FLAG_COMPRESSED;
+
+/// Op codes as in Hadoop.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[repr(u8)]
+pub enum TransferOp {
+    WriteBlock = 80,
+    ReadBlock = 81,
+    BlockChecksum = 85,
+    TransferBlock = 86,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum WriteStage {
+    /// A new block.
+    Create,
+    /// Reopening a finalized block to append to it.
+    Append,
+    /// Rebuilding a pipeline after a node failed; the block gets a new
+    /// generation stamp.
+    Recovery,
+}
+
+impl WireEncode for WriteStage {
+    fn encode(&self, e: &mut Encoder) {
+        e.put_u8(match self {
+            WriteStage::Create => 0,
+            WriteStage::Append => 1,
+            WriteStage::Recovery => 2,
+        });
+    }
+}
+
+impl WireDecode for WriteStage {
+    fn decode(d: &mut Decoder<'_>) -> Result<Self> {
+        match d.get_u8()? {
+            0 => Ok(WriteStage::Create),
+            1 => Ok(WriteStage::Append),
+            2 => Ok(WriteStage::Recovery),
+            s => Err(d.error(format!("invalid write stage {s}"))),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Status {
+    Success,
+    Error,
+    ErrorChecksum,
+    ErrorInvalid,
+    ErrorExists,
+}
+
+impl WireEncode for Status {
+    fn encode(&self, e: &mut Encoder) {
+        e.put_u8(match self {
+            Status::Success => 0,
+            Status::Error => 1,
+            Status::ErrorChecksum => 2,
+            Status::ErrorInvalid => 3,
+            Status::ErrorExists => 4,
+        });
+    }
+}
+
+impl WireDecode for Status {
+    fn decode(d: &mut Decoder<'_>) -> Result<Self> {
+        match d.get_u8()? {
+            0 => Ok(Status::Success),
+            1 => Ok(Status::Error),
+            2 => Ok(Status::ErrorChecksum),
+            3 => Ok(Status::ErrorInvalid),
+            4 => Ok(Status::ErrorExists),
+            s => Err(d.error(format!("invalid status {s}"))),
+        }
+    }
+}
+
+wire_struct! {
+    pub struct ReadBlockOp {
+        pub block: ExtendedBlock,
+        pub client: String,
+        pub offset: u64,
+        pub length: u64,
+        pub send_checksums: bool,
+    }
+}
+
+wire_struct! {
+    pub struct WriteBlockOp {
+        pub block: ExtendedBlock,
+        pub client: String,
+        /// The rest of the pipeline, after the receiving node.
+        pub targets: Vec<DatanodeInfo>,
+        pub stage: WriteStage,
+        pub latest_gen_stamp: GenerationStamp,
+        /// Chosen by the writer for the whole file.
+        pub checksum: DataChecksum,
+    }
+}
+
+wire_struct! {
+    /// Copies a finalized replica to `targets`, e.g. for re-replication.
+    pub struct TransferBlockOp {
+        pub block: ExtendedBlock,
+        pub client: String,
+        pub targets: Vec<DatanodeInfo>,
+    }
+}
+
+wire_struct! {
+    pub struct BlockChecksumOp {
+        pub block: ExtendedBlock,
+    }
+}
+
+wire_struct! {
+    /// The datanode's answer to an op header, before any packets.
+    pub struct BlockOpResponse {
+        pub status: Status,
+        pub message: String,
+        /// Set for reads: how the packets that follow are checksummed, as
+        /// recorded with the block.
+        pub checksum: Option<DataChecksum>,
+    }
+}
+
+/// The first message on a data transfer connection.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OpHeader {
+    ReadBlock(ReadBlockOp),
+    WriteBlock(WriteBlockOp),
+    TransferBlock(TransferBlockOp),
+    BlockChecksum(BlockChecksumOp),
+}
+
+impl OpHeader {
+    pub fn op(&self) -> TransferOp {
+        match self {
+            OpHeader::ReadBlock(_) => TransferOp::ReadBlock,
+            OpHeader::WriteBlock(_) => TransferOp::WriteBlock,
+            OpHeader::TransferBlock(_) => TransferOp::TransferBlock,
+            OpHeader::BlockChecksum(_) => TransferOp::BlockChecksum,
+        }
+    }
+
+    pub fn block(&self) -> &ExtendedBlock {
+        match self {
+            OpHeader::ReadBlock(o) => &o.block,
+            OpHeader::WriteBlock(o) => &o.block,
+            OpHeader::TransferBlock(o) => &o.block,
+            OpHeader::BlockChecksum(o) => &o.block,
+        }
+    }
+}
+
+impl WireEncode for OpHeader {
+    fn encode(&self, e: &mut Encoder) {
+        e.put_u16(DATA_TRANSFER_VERSION);
+        e.put_u8(self.op() as u8);
+        match self {
+            OpHeader::ReadBlock(o) => o.encode(e),
+            OpHeader::WriteBlock(o) => o.encode(e),
+            OpHeader::TransferBlock(o) => o.encode(e),
+            OpHeader::BlockChecksum(o) => o.encode(e),
+        }
+    }
+}
+
+impl WireDecode for OpHeader {
+    fn decode(d: &mut Decoder<'_>) -> Result<Self> {
+        let version = d.get_u16()?;
+        if version != DATA_TRANSFER_VERSION {
+            return Err(d.error(format!(
+                "unsupported data transfer version {version} (supported: {DATA_TRANSFER_VERSION})"
+            )));
+        }
+        Ok(match d.get_u8()? {
+            81 => OpHeader::ReadBlock(WireDecode::decode(d)?),
+            80 => OpHeader::WriteBlock(WireDecode::decode(d)?),
+            86 => OpHeader::TransferBlock(WireDecode::decode(d)?),
+            85 => OpHeader::BlockChecksum(WireDecode::decode(d)?),
+            op => return Err(d.error(format!("unknown transfer op {op}"))),
+        })
+    }
+}
+
+impl OpHeader {
+    pub fn write_to<W: Write>(&self, w: &mut W) -> Result<()> {
+        w.write_all(&length_prefixed(&self.to_bytes()))?;
+        Ok(())
+    }
+
+    pub async fn write_to_async<W: AsyncWrite + Unpin>(&self, w: &mut W) -> Result<()> {
+        w.write_all(&length_prefixed(&self.to_bytes())).await?;
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(r: &mut R) -> Result<OpHeader> {
+        let mut len = [0u8; 4];
+        r.read_exact(&mut len)
+            .map_err(|e| truncated("read_op", e))?;
+        let len = op_header_len(len)?;
+        let mut body = vec![0u8; len];
+        r.read_exact(&mut body)
+            .map_err(|e| truncated("read_op", e))?;
+        OpHeader::from_bytes(&body, "read_op")
+    }
+
+    pub async fn read_from_async<R: AsyncRead + Unpin>(r: &mut R) -> Result<OpHeader> {
+        let mut len = [0u8; 4];
+        r.read_exact(&mut len)
+            .await
+            .map_err(|e| truncated("read_op", e))?;
+        let len = op_header_len(len)?;
+        let mut body = vec![0u8; len];
+        r.read_exact(&mut body)
+            .await
+            .map_err(|e| truncated("read_op", e))?;
+        OpHeader::from_bytes(&body, "read_op")
+    }
+}
+
+fn op_header_len(raw: [u8; 4]) -> Result<usize> {
+    let len = u32::from_be_bytes(raw) as usize;
+    if len > MAX_OP_HEADER_LEN {
+        return Err(HdfsError::Protocol {
+            op: "read_op".into(),
+            details: format!("op header of {len} bytes exceeds {MAX_OP_HEADER_LEN}"),
+        });
+    }
+    Ok(len)
+}
+
+fn length_prefixed(body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(4 + body.len());
+    out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    out.extend_from_slice(body);
+    out
+}
+
+fn truncated(op: &'static str, e: io::Error) -> HdfsError {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        HdfsError::Protocol {
+            op: op.into(),
+            details: "truncated stream".into(),
+        }
+    } else {
+        e.into()
+    }
+}
+
+wire_struct! {
+    /// Sent upstream for every packet. `replies[0]` is the sending node's own
+    /// status, followed by one per node further down the pipeline.
+    pub struct PipelineAck {
+        pub seqno: u64,
+        pub replies: Vec<Status>,
+    }
+}
+
+impl PipelineAck {
+    pub fn is_success(&self) -> bool {
+        self.replies.iter().all(|s| *s == Status::Success)
+    }
+
+    /// Index in the pipeline of the first node that reported a failure.
+    pub fn first_failed(&self) -> Option<usize> {
+        self.replies.iter().position(|s| *s != Status::Success)
+    }
+}
+
+/// Big-endian checksums, one per chunk (none for `ChecksumType::Null`), as
+/// laid out in a packet.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Checksums(Bytes);
+
+impl Checksums {
+    pub fn compute(data: &[u8], checksum: &DataChecksum) -> Self {
+        Checksums(checksum.compute_to_vec(data).into())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len() / 4
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, i: usize) -> Option<u32> {
+        let raw = self.0.get(4 * i..4 * i + 4)?;
+        Some(u32::from_be_bytes(raw.try_into().unwrap()))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        self.0
+            .chunks_exact(4)
+            .map(|c| u32::from_be_bytes(c.try_into().unwrap()))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl FromIterator<u32> for Checksums {
+    fn from_iter<I: IntoIterator<Item = u32>>(iter: I) -> Self {
+        let raw: Vec<u8> = iter.into_iter().flat_map(u32::to_be_bytes).collect();
+        Checksums(raw.into())
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Packet {
+    pub seqno: u64,
+    /// Where `data` starts in the block; always chunk aligned.
+    pub offset_in_block: u64,
+    pub last: bool,
+    /// Ask datanodes to sync the data to disk before acking (hflush/hsync).
+    pub sync: bool,
+    /// One per chunk of `data`; the last chunk may be short.
+    pub checksums: Checksums,
+    pub data: Bytes,
+}
+
+/// Builds, encodes and decodes packets for one block stream.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PacketCodec {
+    block: BlockId,
+    checksum: DataChecksum,
+    max_data_len: u32,
+    compression: Option<Compression>,
+}
+
+impl PacketCodec {
+    pub fn new(block: BlockId, checksum: DataChecksum) -> Self {
+        Self {
+            block,
+            checksum,
+            max_data_len: DEFAULT_MAX_PACKET_DATA,
+            compression: None,
+        }
+    }
+
+    pub fn with_max_data_len(mut self, max: u32) -> Self {
+        self.max_data_len = max;
+        self
+    }
+
+    /// Compress the data of packets we send, when it is large enough and
+    /// shrinks. Received packets are decompressed either way.
+    pub fn with_compression(mut self, compression: Option<Compression>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn compression(&self) -> Option<Compression> {
+        self.compression
+    }
+
+    pub fn checksum(&self) -> &DataChecksum {
+        &self.checksum
+    }
+
+    pub fn bytes_per_checksum(&self) -> u32 {
+        self.checksum.bytes_per_checksum()
+    }
+
+    /// A packet over `data` with its checksums filled in.
+    pub fn packet(
+        &self,
+        seqno: u64,
+        offset_in_block: u64,
+        data: impl Into<Bytes>,
+        last: bool,
+    ) -> Packet {
+        let data = data.into();
+        Packet {
+            seqno,
+            offset_in_block,
+            last,
+            sync: false,
+            checksums: Checksums::compute(&data, &self.checksum),
+            data,
+        }
+    }
+
+    pub fn encode(&self, p: &Packet, out: &mut Vec<u8>) {
+        let packed = self.compress(p);
+        let data = packed.as_ref().map_or(&p.data[..], |(_, d)| d);
+        out.reserve(4 + PACKET_HEADER_LEN + p.checksums.as_bytes().len() + 1 + data.len());
+        self.encode_header(p, packed.as_ref(), out);
+        out.extend_from_slice(data);
+    }
+
+    /// The data of `p` compressed, if we compress and it pays off.
+    fn compress(&self, p: &Packet) -> Option<(Compression, Vec<u8>)> {
+        let c = self
+            .compression
+            .filter(|_| p.data.len() >= DEFAULT_COMPRESSION_THRESHOLD)?;
+        Some((c, c.compress(&p.data)?))
+    }
+
+    /// Everything up to the data: length, header and checksums, and the
+    /// codec if `packed` is what follows.
+    fn encode_header(
+        &self,
+        p: &Packet,
+        packed: Option<&(Compression, Vec<u8>)>,
+        out: &mut Vec<u8>,
+    ) {
+        let wire_data_len = packed.map_or(p.data.len(), |(_, d)| 1 + d.len());
+        let body_len = PACKET_HEADER_LEN + p.checksums.as_bytes().len() + wire_data_len;
+        out.extend_from_slice(&(body_len as u32).to_be_bytes());
+        out.extend_from_slice(&p.seqno.to_be_bytes());
+        out.extend_from_slice(&p.offset_in_block.to_be_bytes());
+        let mut flags = 0;
+        if p.last {
+            flags |= FLAG_LAST;
+        }
+        if p.sync {
+            flags |= FLAG_SYNC;
+        }
+        if packed.is_some() {
+            flags |= FLAG_COMPRESSED;
+        }
+        out.push(flags);
+        out.extend_from_slice(&(p.data.len() as u32).to_be_bytes());
+        out.extend_from_slice(&(p.checksums.len() as u32).to_be_bytes());
+        out.extend_from_slice(p.checksums.as_bytes());
+        if let Some((c, _)) = packed {
+            out.push(c.id());
+        }
+    }
+
+    pub fn encode_to_vec(&self, p: &Packet) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode(p, &mut out);
+        out
+    }
+
+    /// Decodes and verifies one packet from the front of `buf`; `None` if
+    /// `buf` does not hold a whole packet yet. The packet gets a copy of its
+    /// part of `buf`; see [`PacketCodec::decode_buf`] to avoid it.
+    pub fn decode(&self, buf: &[u8]) -> Result<Option<(Packet, usize)>> {
+        let Some(len) = buf.first_chunk::<4>() else {
+            return Ok(None);
+        };
+        let body_len = self.body_len(*len)?;
+        let Some(body) = buf.get(4..4 + body_len) else {
+            return Ok(None);
+        };
+        let packet = self.decode_body(Bytes::copy_from_slice(body))?;
+        Ok(Some((packet, 4 + body_len)))
+    }
+
+    /// Splits one packet off the front of `buf` and verifies it, without
+    /// copying: the packet's checksums and data point into `buf`'s
+    /// allocation. `None` if `buf` does not hold a whole packet yet.
+    pub fn decode_buf(&self, buf: &mut BytesMut) -> Result<Option<Packet>> {
+        let Some(len) = buf.first_chunk::<4>() else {
+            return Ok(None);
+        };
+        let body_len = self.body_len(*len)?;
+        if buf.len() < 4 + body_len {
+            return Ok(None);
+        }
+        let mut body = buf.split_to(4 + body_len).freeze();
+        body.advance(4);
+        self.decode_body(body).map(Some)
+    }
+
+    /// Reads one packet. `None` on a clean end of stream between packets.
+    pub fn read_packet<R: Read>(&self, r: &mut R) -> Result<Option<Packet>> {
+        let mut len = [0u8; 4];
+        match r.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let mut body = BytesMut::zeroed(self.body_len(len)?);
+        r.read_exact(&mut body)
+            .map_err(|e| truncated("read_packet", e))?;
+        self.decode_body(body.freeze()).map(Some)
+    }
+
+    pub async fn read_packet_async<R: AsyncRead + Unpin>(
+        &self,
+        r: &mut R,
+    ) -> Result<Option<Packet>> {
+        let mut len = [0u8; 4];
+        match r.read_exact(&mut len).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let mut body = BytesMut::zeroed(self.body_len(len)?);
+        r.read_exact(&mut body)
+            .await
+            .map_err(|e| truncated("read_packet", e))?;
+        self.decode_body(body.freeze()).map(Some)
+    }
+
+    /// Writes the data straight from the packet rather than encoding it
+    /// into a new buffer first, unless it gets compressed.
+    pub fn write_packet<W: Write>(&self, w: &mut W, p: &Packet) -> Result<()> {
+        let packed = self.compress(p);
+        let mut header = Vec::new();
+        self.encode_header(p, packed.as_ref(), &mut header);
+        w.write_all(&header)?;
+        w.write_all(packed.as_ref().map_or(&p.data[..], |(_, d)| d))?;
+        Ok(())
+    }
+
+    pub async fn write_packet_async<W: AsyncWrite + Unpin>(
+        &self,
+        w: &mut W,
+        p: &Packet,
+    ) -> Result<()> {
+        let packed = self.compress(p);
+        let mut header = Vec::new();
+        self.encode_header(p, packed.as_ref(), &mut header);
+        w.write_all(&header).await?;
+        w.write_all(packed.as_ref().map_or(&p.data[..], |(_, d)| d))
+            .await?;
+        Ok(())
+    }
+
+    fn body_len(&self, raw: [u8; 4]) -> Result<usize> {
+        let len = u32::from_be_bytes(raw) as usize;
+        let max_data = self.max_data_len as usize;
+        let max = PACKET_HEADER_LEN + self.checksum.checksums_len(max_data) + max_data;
+        if len > max {
+            return Err(HdfsError::Protocol {
+                op: "read_packet".into(),
+                details: format!("packet of {len} bytes exceeds max {max}"),
+            });
+        }
+        Ok(len)
+    }
+
+    fn decode_body(&self, body: Bytes) -> Result<Packet> {
+        let mut d = Decoder::new(&body, "read_packet");
+        let seqno = d.get_u64()?;
+        let offset_in_block = d.get_u64()?;
+        let flags = d.get_u8()?;
+        if flags & !KNOWN_FLAGS != 0 {
+            return Err(d.error(format!("unknown packet flags 0x{flags:02X}")));
+        }
+        let data_len = d.get_u32()? as usize;
+        let count = d.get_u32()? as usize;
+        let checksum = &self.checksum;
+        if count != checksum.num_checksums(data_len) {
+            return Err(d.error(format!(
+                "{count} checksums for {data_len} bytes with {checksum}"
+            )));
+        }
+        let bpc = checksum.bytes_per_checksum();
+        if offset_in_block % u64::from(bpc) != 0 {
+            return Err(d.error(format!(
+                "offset {offset_in_block} is not aligned to {bpc} bytes per checksum"
+            )));
+        }
+        let sums_len = count * checksum.checksum_size();
+        d.get_raw(sums_len)?;
+        let checksums = Checksums(body.slice(PACKET_HEADER_LEN..PACKET_HEADER_LEN + sums_len));
+        let data = if flags & FLAG_COMPRESSED != 0 {
+            let id = d.get_u8()?;
+            let codec = Compression::from_id(id)
+                .ok_or_else(|| d.error(format!("unknown compression codec {id}")))?;
+            let packed = d.get_raw(d.remaining())?;
+            let limit = self.max_data_len as usize;
+            Bytes::from(codec.decompress(packed, data_len, limit, "read_packet")?)
+        } else {
+            d.get_raw(data_len)?;
+            d.finish()?;
+            body.slice(PACKET_HEADER_LEN + sums_len..)
+        };
+        checksum.verify(&data, checksums.as_bytes(), self.block, offset_in_block)?;
+
+        Ok(Packet {
+            seqno,
+            offset_in_block,
+            last: flags & FLAG_LAST != 0,
+            sync: flags & FLAG_SYNC != 0,
+            checksums,
+            data,
+        })
+    }
+}
+
+impl Default for PacketCodec {
+    fn default() -> Self {
+        Self::new(BlockId(0), DataChecksum::default())
+    }
+}
+
+/// Reads packets from a stream through one receive buffer, taking in as
+/// much as the stream has ready on each read. Packets are slices of that
+/// buffer, which is reused in place once they have all been dropped.
+#[derive(Debug)]
+pub struct PacketReader<R> {
+    inner: R,
+    codec: PacketCodec,
+    buf: BytesMut,
+    /// Bytes at the end of `buf`'s allocation that were never written. All
+    /// before them were, so sync reads only zero this tail, once.
+    unwritten: usize,
+    pool: Option<BufferPool>,
+}
+
+impl<R> PacketReader<R> {
+    pub fn new(inner: R, codec: PacketCodec) -> Self {
+        Self::with_buf(
+            inner,
+            codec,
+            BytesMut::with_capacity(DEFAULT_BUFFER_SIZE),
+            None,
+        )
+    }
+
+    /// Takes receive buffers from `pool`, and gives the last one back on drop.
+    pub fn with_pool(inner: R, codec: PacketCodec, pool: &BufferPool) -> Self {
+        Self::with_buf(inner, codec, pool.get(), Some(pool.clone()))
+    }
+
+    fn with_buf(inner: R, codec: PacketCodec, buf: BytesMut, pool: Option<BufferPool>) -> Self {
+        Self {
+            inner,
+            codec,
+            unwritten: buf.capacity() - buf.len(),
+            buf,
+            pool,
+        }
+    }
+
+    pub fn codec(&self) -> &PacketCodec {
+        &self.codec
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Bytes received but not yet returned as packets.
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Makes room for the next read and keeps `unwritten` up to date: a
+    /// buffer that moved to a new allocation starts out with all of its
+    /// spare capacity unwritten.
+    fn reserve(&mut self) -> Result<()> {
+        let end = alloc_end(&self.buf);
+        self.grow()?;
+        if alloc_end(&self.buf) != end {
+            self.unwritten = self.buf.capacity() - self.buf.len();
+        }
+        Ok(())
+    }
+
+    /// Makes room for the rest of the next packet, or at least
+    /// [`MIN_READ`] bytes. Packets still alive keep their part of the old
+    /// buffer, so this moves to a new one when that is all of it.
+    fn grow(&mut self) -> Result<()> {
+        let needed = match self.buf.first_chunk::<4>() {
+            Some(len) => 4 + self.codec.body_len(*len)?,
+            None => 4,
+        };
+        let want = needed.saturating_sub(self.buf.len()).max(MIN_READ);
+        if self.buf.try_reclaim(want) {
+            return Ok(());
+        }
+        match &self.pool {
+            Some(pool) if self.buf.len() + want <= pool.buffer_size() => {
+                let mut fresh = pool.get();
+                fresh.extend_from_slice(&self.buf);
+                self.buf = fresh;
+            }
+            _ => self.buf.reserve(want),
+        }
+        Ok(())
+    }
+
+    fn end_of_stream(&self) -> Result<Option<Packet>> {
+        if self.buf.is_empty() {
+            return Ok(None);
+        }
+        Err(HdfsError::Protocol {
+            op: "read_packet".into(),
+            details: "truncated stream".into(),
+        })
+    }
+}
+
+impl<R: Read> PacketReader<R> {
+    /// The next packet, or `None` on a clean end of stream between packets.
+    pub fn read_packet(&mut self) -> Result<Option<Packet>> {
+        loop {
+            if let Some(p) = self.codec.decode_buf(&mut self.buf)? {
+                return Ok(Some(p));
+            }
+            self.reserve()?;
+            let start = self.buf.len();
+            let cap = self.buf.capacity();
+            let written = (cap - self.unwritten).max(start);
+            // SAFETY: `written <= cap`. Any byte of the allocation that was
+            // never written lies in its last `unwritten` bytes: `reserve`
+            // sets that to all the spare capacity of a new allocation, and
+            // it drops to 0 only after the `resize` below has written every
+            // byte. `start..written` ends before that tail, or is empty, so
+            // it is initialized.
+            unsafe { self.buf.set_len(written) };
+            self.buf.resize(cap, 0);
+            self.unwritten = 0;
+            let read = Read::read(&mut self.inner, &mut self.buf[start..]);
+            self.buf.truncate(start + *read.as_ref().unwrap_or(&0));
+            match read {
+                Ok(0) => return self.end_of_stream(),
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> PacketReader<R> {
+    pub async fn read_packet_async(&mut self) -> Result<Option<Packet>> {
+        loop {
+            if let Some(p) = self.codec.decode_buf(&mut self.buf)? {
+                return Ok(Some(p));
+            }
+            self.reserve()?;
+            if self.inner.read_buf(&mut self.buf).await? == 0 {
+                return self.end_of_stream();
+            }
+        }
+    }
+}
+
+/// Where `buf`'s allocation ends; fixed until it moves to another one.
+fn alloc_end(buf: &BytesMut) -> usize {
+    buf.as_ptr() as usize + buf.capacity()
+}
+
+impl<R> Drop for PacketReader<R> {
+    fn drop(&mut self) {
+        if let Some(pool) = &self.pool {
+            pool.put(std::mem::take(&mut self.buf));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hdfs_common::checksum::ChecksumType;
+    use hdfs_common::ids::{BlockPoolId, DatanodeId};
+
+    fn block() -> ExtendedBlock {
+        ExtendedBlock::new(
+            BlockPoolId::new_v4(),
+            BlockId(1 << 30),
+            GenerationStamp(1001),
+            0,
+        )
+    }
+
+    fn codec() -> PacketCodec {
+        PacketCodec::new(
+            BlockId(1 << 30),
+            DataChecksum::new(ChecksumType::Crc32c, 512),
+        )
+    }
+
+    #[test]
+    fn op_headers_roundtrip() {
+        let ops = [
+            OpHeader::ReadBlock(ReadBlockOp {
+                block: block(),
+                client: "DFSClient_1".into(),
+                offset: 1024,
+                length: 4096,
+                send_checksums: true,
+            }),
+            OpHeader::WriteBlock(WriteBlockOp {
+                block: block(),
+                client: "DFSClient_1".into(),
+                targets: vec![DatanodeInfo {
+                    id: DatanodeId::new_v4(),
+                    hostname: "dn2".into(),
+                    data_addr: "10.0.0.2:9866".into(),
+                }],
+                stage: WriteStage::Create,
+                latest_gen_stamp: GenerationStamp(1001),
+                checksum: DataChecksum::new(ChecksumType::Crc32, 512),
+            }),
+            OpHeader::TransferBlock(TransferBlockOp {
+                block: block(),
+                client: "NameNode".into(),
+                targets: vec![],
+            }),
+            OpHeader::BlockChecksum(BlockChecksumOp { block: block() }),
+        ];
+        for op in ops {
+            let mut wire = Vec::new();
+            op.write_to(&mut wire).unwrap();
+            assert_eq!(wire[4..6], DATA_TRANSFER_VERSION.to_be_bytes());
+            assert_eq!(wire[6], op.op() as u8);
+            assert_eq!(OpHeader::read_from(&mut wire.as_slice()).unwrap(), op);
+        }
+
+        let mut wire = Vec::new();
+        OpHeader::BlockChecksum(BlockChecksumOp { block: block() })
+            .write_to(&mut wire)
+            .unwrap();
+        wire[5] = 9;
+        assert!(matches!(
+            OpHeader::read_from(&mut wire.as_slice()),
+            Err(HdfsError::Protocol { op, .. }) if op == "read_op"
+        ));
+    }
+
+    #[test]
+    fn packets_roundtrip() {
+        let c = codec();
+        let data: Vec<u8> = (0..1300u32).map(|i| i as u8).collect();
+        let mut p = c.packet(3, 2048, data, false);
+        p.sync = true;
+        assert_eq!(p.checksums.len(), 3);
+
+        let bytes = c.encode_to_vec(&p);
+        let (back, used) = c.decode(&bytes).unwrap().unwrap();
+        assert_eq!(back, p);
+        assert_eq!(used, bytes.len());
+        assert!(c.decode(&bytes[..bytes.len() - 1]).unwrap().is_none());
+
+        let last = c.packet(4, 2048 + 1536, Vec::new(), true);
+        assert!(last.checksums.is_empty());
+        let mut wire = Vec::new();
+        c.write_packet(&mut wire, &p).unwrap();
+        c.write_packet(&mut wire, &last).unwrap();
+        let mut r = wire.as_slice();
+        assert_eq!(c.read_packet(&mut r).unwrap().unwrap(), p);
+        assert!(c.read_packet(&mut r).unwrap().unwrap().last);
+        assert_eq!(c.read_packet(&mut r).unwrap(), None);
+    }
+
+    #[test]
+    fn packets_use_the_block_checksum() {
+        let data = vec![3u8; 1300];
+        let crc32 = PacketCodec::new(BlockId(1), DataChecksum::new(ChecksumType::Crc32, 1024));
+        let p = crc32.packet(0, 0, data.clone(), false);
+        assert_eq!(p.checksums.len(), 2);
+        let bytes = crc32.encode_to_vec(&p);
+        assert_eq!(crc32.decode(&bytes).unwrap().unwrap().0, p);
+        // the same packet checked as CRC32C fails
+        let crc32c = PacketCodec::new(BlockId(1), DataChecksum::new(ChecksumType::Crc32c, 1024));
+        assert!(matches!(
+            crc32c.decode(&bytes),
+            Err(HdfsError::ChecksumMismatch { chunk_index: 0, .. })
+        ));
+
+        let null = PacketCodec::new(BlockId(1), DataChecksum::new(ChecksumType::Null, 512));
+        let p = null.packet(0, 512, data, true);
+        assert!(p.checksums.is_empty());
+        let bytes = null.encode_to_vec(&p);
+        assert_eq!(bytes.len(), 4 + PACKET_HEADER_LEN + 1300);
+        assert_eq!(null.decode(&bytes).unwrap().unwrap().0, p);
+
+        let resp = BlockOpResponse {
+            status: Status::Success,
+            message: String::new(),
+            checksum: Some(*crc32.checksum()),
+        };
+        assert_eq!(
+            BlockOpResponse::from_bytes(&resp.to_bytes(), "read_op").unwrap(),
+            resp
+        );
+    }
+
+    #[test]
+    fn compressed_packets() {
+        let plain = codec();
+        let lz4 = plain.with_compression(Some(Compression::Lz4));
+        let data: Vec<u8> = b"2026-10-17 INFO DataNode: Receiving block "
+            .iter()
+            .copied()
+            .cycle()
+            .take(64 * 1024)
+            .collect();
+        let p = lz4.packet(3, 4096, data.clone(), true);
+        let bytes = lz4.encode_to_vec(&p);
+        assert!(bytes.len() < plain.encode_to_vec(&p).len() / 4);
+        assert_eq!(bytes[4 + 16], FLAG_LAST | FLAG_COMPRESSED);
+        // checksums are of the data as written, and decoding needs no setting
+        assert_eq!(
+            plain.decode(&bytes).unwrap().unwrap(),
+            (p.clone(), bytes.len())
+        );
+
+        // small packets stay raw, and both kinds share a stream
+        let small = lz4.packet(4, 4096 + 65536, vec![1; 100], false);
+        let mut wire = Vec::new();
+        lz4.write_packet(&mut wire, &p).unwrap();
+        lz4.write_packet(&mut wire, &small).unwrap();
+        let mut r = PacketReader::new(wire.as_slice(), plain);
+        assert_eq!(r.read_packet().unwrap(), Some(p.clone()));
+        assert_eq!(r.read_packet().unwrap(), Some(small));
+
+        // a packet can't decompress past the max data length
+        let tight = plain.with_max_data_len(32 * 1024);
+        match tight.decode(&bytes) {
+            Err(HdfsError::Protocol { op, details }) if op == "read_packet" => {
+                assert!(details.contains("over the limit"), "{details}")
+            }
+            other => panic!("expected read_packet error, got {other:?}"),
+        }
+
+        let mut unknown = bytes.clone();
+        unknown[4 + PACKET_HEADER_LEN + p.checksums.as_bytes().len()] = 9;
+        assert!(plain.decode(&unknown).is_err());
+    }
+
+    #[test]
+    fn corrupt_chunk_is_named() {
+        let c = codec();
+        let p = c.packet(0, 4 * 512, vec![7u8; 3 * 512], false);
+        let mut bytes = c.encode_to_vec(&p);
+        // flip a byte in the second chunk of the packet
+        let data_start = bytes.len() - p.data.len();
+        bytes[data_start + 512 + 10] ^= 0xFF;
+
+        match c.decode(&bytes) {
+            Err(HdfsError::ChecksumMismatch {
+                block,
+                chunk_index,
+                expected,
+                got,
+            }) => {
+                assert_eq!(block, BlockId(1 << 30));
+                assert_eq!(chunk_index, 5);
+                assert_eq!(expected, p.checksums.get(1).unwrap());
+                assert_ne!(got, expected);
+            }
+            other => panic!("expected ChecksumMismatch, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn malformed_packets() {
+        let c = codec();
+        let p = c.packet(0, 0, vec![1u8; 600], false);
+        let bytes = c.encode_to_vec(&p);
+
+        let protocol = |res: Result<Option<(Packet, usize)>>| match res {
+            Err(HdfsError::Protocol { op, details }) if op == "read_packet" => details,
+            other => panic!("expected read_packet error, got {other:?}"),
+        };
+
+        // checksum count disagrees with the data length
+        let mut bad = bytes.clone();
+        bad[4 + 21..4 + 25].copy_from_slice(&1u32.to_be_bytes());
+        assert!(protocol(c.decode(&bad)).contains("1 checksums for 600 bytes"));
+
+        let unaligned = c.encode_to_vec(&Packet {
+            offset_in_block: 100,
+            ..p.clone()
+        });
+        assert!(protocol(c.decode(&unaligned)).contains("not aligned"));
+
+        let small = c.with_max_data_len(512);
+        assert!(protocol(small.decode(&bytes)).contains("exceeds max"));
+
+        let mut cut = &bytes[..bytes.len() - 1];
+        assert!(matches!(
+            c.read_packet(&mut cut),
+            Err(HdfsError::Protocol { op, .. }) if op == "read_packet"
+        ));
+    }
+
+    /// Hands out at most `step` bytes per read.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        step: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.step.min(buf.len()).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn packets_are_slices_of_the_receive_buffer() {
+        let c = codec();
+        let packets: Vec<Packet> = (0..3)
+            .map(|i| c.packet(i, i * 1024, vec![i as u8; 1000], i == 2))
+            .collect();
+        let mut wire = Vec::new();
+        for p in &packets {
+            c.write_packet(&mut wire, p).unwrap();
+        }
+        assert_eq!(
+            wire,
+            packets
+                .iter()
+                .flat_map(|p| c.encode_to_vec(p))
+                .collect::<Vec<_>>()
+        );
+
+        let mut r = PacketReader::new(wire.as_slice(), c);
+        let first = r.read_packet().unwrap().unwrap();
+        assert_eq!(first, packets[0]);
+        // the whole stream came in with one read
+        assert_eq!(r.buffered(), wire.len() - c.encode_to_vec(&first).len());
+        let second = r.read_packet().unwrap().unwrap();
+        assert_eq!(second, packets[1]);
+        let next_data = first.data.as_ptr() as usize
+            + first.data.len()
+            + 4
+            + PACKET_HEADER_LEN
+            + 4 * second.checksums.len();
+        assert_eq!(second.data.as_ptr() as usize, next_data);
+        assert_eq!(
+            second.checksums.as_bytes().as_ptr() as usize + 4 * second.checksums.len(),
+            next_data
+        );
+        assert!(r.read_packet().unwrap().unwrap().last);
+        assert_eq!(r.read_packet().unwrap(), None);
+
+        let mut r = PacketReader::new(
+            Trickle {
+                data: &wire,
+                step: 7,
+            },
+            c,
+        );
+        for p in &packets {
+            assert_eq!(r.read_packet().unwrap().as_ref(), Some(p));
+        }
+        assert_eq!(r.read_packet().unwrap(), None);
+
+        let mut r = PacketReader::new(&wire[..wire.len() - 1], c);
+        r.read_packet().unwrap();
+        r.read_packet().unwrap();
+        assert!(matches!(
+            r.read_packet(),
+            Err(HdfsError::Protocol { op, .. }) if op == "read_packet"
+        ));
+    }
+
+    #[test]
+    fn sync_reads_zero_the_buffer_once() {
+        /// Hands out one byte per read, marking the rest of what it is
+        /// given, and notes whether a later read finds the marks wiped.
+        struct Marker<'a> {
+            data: &'a [u8],
+            reads: usize,
+            wiped: bool,
+        }
+        impl Read for Marker<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.reads > 0 && buf[1..].iter().any(|b| *b != 0xAA) {
+                    self.wiped = true;
+                }
+                self.reads += 1;
+                buf.fill(0xAA);
+                Read::read(&mut self.data, &mut buf[..1])
+            }
+        }
+
+        let c = codec();
+        let packets = [
+            c.packet(0, 0, vec![1u8; 512], false),
+            c.packet(1, 512, vec![2u8; 300], true),
+        ];
+        let wire: Vec<u8> = packets.iter().flat_map(|p| c.encode_to_vec(p)).collect();
+        let mut r = PacketReader::new(
+            Marker {
+                data: &wire,
+                reads: 0,
+                wiped: false,
+            },
+            c,
+        );
+        for p in &packets {
+            assert_eq!(r.read_packet().unwrap().as_ref(), Some(p));
+        }
+        assert_eq!(r.read_packet().unwrap(), None);
+        assert_eq!(r.inner.reads, wire.len() + 1);
+        assert!(!r.inner.wiped);
+    }
+
+    #[test]
+    fn packet_readers_return_buffers_to_the_pool() {
+        let c = codec();
+        let wire = c.encode_to_vec(&c.packet(0, 0, vec![1u8; 1000], true));
+        let pool = BufferPool::default();
+
+        let mut r = PacketReader::with_pool(wire.as_slice(), c, &pool);
+        let p = r.read_packet().unwrap().unwrap();
+        drop(r);
+        // the packet still holds the buffer
+        assert_eq!(pool.pooled(), 0);
+
+        drop(p);
+        let mut r = PacketReader::with_pool(wire.as_slice(), c, &pool);
+        drop(r.read_packet().unwrap().unwrap());
+        drop(r);
+        assert_eq!(pool.pooled(), 1);
+    }
+
+    #[test]
+    fn acks() {
+        let ok = PipelineAck {
+            seqno: 9,
+            replies: vec![Status::Success; 3],
+        };
+        assert!(ok.is_success());
+        assert_eq!(ok.first_failed(), None);
+        assert_eq!(PipelineAck::from_bytes(&ok.to_bytes(), "ack").unwrap(), ok);
+
+        let bad = PipelineAck {
+            seqno: 10,
+            replies: vec![Status::Success, Status::ErrorChecksum, Status::Success],
+        };
+        assert!(!bad.is_success());
+        assert_eq!(bad.first_failed(), Some(1));
+        assert_eq!(
+            PipelineAck::from_bytes(&bad.to_bytes(), "ack").unwrap(),
+            bad
+        );
+    }
+
+    #[tokio::test]
+    async fn async_stream() {
+        let c = codec();
+        let op = OpHeader::BlockChecksum(BlockChecksumOp { block: block() });
+        let p = c.packet(0, 0, vec![5u8; 1000], true);
+
+        let mut wire = Vec::new();
+        op.write_to_async(&mut wire).await.unwrap();
+        c.write_packet_async(&mut wire, &p).await.unwrap();
+
+        let mut r = wire.as_slice();
+        assert_eq!(OpHeader::read_from_async(&mut r).await.unwrap(), op);
+        assert_eq!(c.read_packet_async(&mut r).await.unwrap().unwrap(), p);
+        assert_eq!(c.read_packet_async(&mut r).await.unwrap(), None);
+
+        let (mut tx, rx) = tokio::io::duplex(64);
+        let mut reader = PacketReader::new(rx, c);
+        let send = async {
+            c.write_packet_async(&mut tx, &p).await.unwrap();
+            c.write_packet_async(&mut tx, &p).await.unwrap();
+            drop(tx);
+        };
+        let recv = async {
+            let mut got = Vec::new();
+            while let Some(packet) = reader.read_packet_async().await.unwrap() {
+                got.push(packet);
+            }
+            got
+        };
+        let ((), got) = tokio::join!(send, recv);
+        assert_eq!(got, [p.clone(), p]);
+    }
+}